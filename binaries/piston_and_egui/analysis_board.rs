@@ -0,0 +1,359 @@
+use crate::practice::{check_offline_move, make_offline_move};
+use anyhow::{Context, Result};
+use async_chess_client::{
+    chess::{
+        boards::{
+            board::{Board, CanMovePiece},
+            board_container::BoardContainer,
+        },
+        history::{HistoryMove, MoveGlyph, MoveHistory, MovePath},
+    },
+    prelude::{Coords, Either},
+};
+use epac_utils::error_ext::ToAnyhowNotErr;
+
+///A way to move around the variations of an [`AnalysisBoard`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisStep {
+    ///Go back one move
+    Back,
+    ///Go forward one move, along the first variation
+    Forward,
+    ///Switch to the previous variation of the last move
+    PreviousVariation,
+    ///Switch to the next variation of the last move
+    NextVariation,
+}
+
+///Number of moves shown by [`AnalysisBoard::describe`] - the most recent ones
+const DESCRIBED_MOVES: usize = 8;
+
+///One position in an [`AnalysisBoard`]
+struct AnalysisNode {
+    ///The board in this position
+    board: BoardContainer,
+    ///The move which led here from the parent, as `(from, to)` - `None` for the starting position
+    m: Option<(Coords, Coords)>,
+    ///The index of the node this move was made from - `None` for the starting position
+    parent: Option<usize>,
+    ///The indices of the moves made from here, in the order they were first played
+    children: Vec<usize>,
+    ///How good the move which led here was, if it has been annotated
+    glyph: Option<MoveGlyph>,
+    ///A comment about the move which led here, if one has been written
+    comment: Option<String>,
+}
+
+///A board for exploring a position, separate from the live board.
+///
+/// Either colour can be moved in any order, and each different move from a position starts a new variation, so lines can be compared and gone back to. Nothing here is ever sent to the server. The moves explored form a variation tree, which can be turned into a [`MoveHistory`] to be kept or exported.
+pub struct AnalysisBoard {
+    ///Every position reached, with the starting position first
+    nodes: Vec<AnalysisNode>,
+    ///The index of the position being shown
+    current: usize,
+    ///Whether white moves first from the starting position
+    white_first: bool,
+    ///Whether this was forked from the game's own move history, so the variations explored can be kept in it
+    from_game: bool,
+}
+
+impl AnalysisBoard {
+    ///Creates an analysis board starting from a copy of a board, without any moves before it
+    #[must_use]
+    pub fn new(board: Board<CanMovePiece>, white_first: bool) -> Self {
+        Self {
+            nodes: vec![AnalysisNode {
+                board: Either::Left(board),
+                m: None,
+                parent: None,
+                children: vec![],
+                glyph: None,
+                comment: None,
+            }],
+            current: 0,
+            white_first,
+            from_game: false,
+        }
+    }
+
+    ///Creates an analysis board with the moves and variations from a [`MoveHistory`].
+    ///
+    /// If `from_game` is set, it is the game's own history - the end of the game is shown, and [`AnalysisBoard::is_from_game`] says the variations should be kept. Otherwise, eg. for an imported PGN game, the starting position is shown.
+    ///
+    /// # Errors
+    /// - The starting position isn't valid
+    /// - A move in the history can't be made - see [`AnalysisBoard::make_move`]
+    pub fn from_history(history: &MoveHistory, from_game: bool) -> Result<Self> {
+        let mut analysis = Self::new(
+            history.start_board().context("reading start")?,
+            history.white_first(),
+        );
+        analysis.from_game = from_game;
+        let end = analysis.add_line(0, history.moves())?;
+        if from_game {
+            analysis.current = end;
+        }
+        Ok(analysis)
+    }
+
+    ///Adds a line of moves, and recursively their variations, after a node. Returns the index of the node at the end of the line.
+    ///
+    /// # Errors
+    /// - A move can't be made - see [`AnalysisBoard::make_move`]
+    fn add_line(&mut self, from: usize, line: &[HistoryMove]) -> Result<usize> {
+        let mut at = from;
+        for m in line {
+            let before = at;
+            at = self
+                .add_child(before, m.from, m.to)
+                .with_context(|| format!("making move {:?} to {:?}", m.from, m.to))?;
+            self.nodes[at].glyph = m.glyph;
+            self.nodes[at].comment = m.comment.clone();
+
+            for variation in &m.variations {
+                self.add_line(before, variation)?;
+            }
+        }
+        Ok(at)
+    }
+
+    ///Gets whether this was forked from the game's own move history - see [`AnalysisBoard::from_history`]
+    #[must_use]
+    pub const fn is_from_game(&self) -> bool {
+        self.from_game
+    }
+
+    ///Turns the moves explored into a [`MoveHistory`] - the first move made from each position is the main line, and the rest are its variations
+    #[must_use]
+    pub fn to_history(&self) -> MoveHistory {
+        let mut moves = vec![];
+        self.continue_line(0, &mut moves);
+        match &self.nodes[0].board {
+            Either::Left(b) => MoveHistory::from_moves(b, self.white_first, moves),
+            Either::Right(b) => MoveHistory::from_moves(b, self.white_first, moves),
+        }
+    }
+
+    ///Adds the moves after a node to a line, following the first move made from each position
+    fn continue_line(&self, mut at: usize, line: &mut Vec<HistoryMove>) {
+        while let Some((&first, others)) = self.nodes[at].children.split_first() {
+            let variations = others.iter().filter_map(|o| self.line_from(*o)).collect();
+            line.extend(self.history_move(first, variations));
+            at = first;
+        }
+    }
+
+    ///Gets the line starting with the move which led to a node
+    fn line_from(&self, index: usize) -> Option<Vec<HistoryMove>> {
+        let mut line = vec![self.history_move(index, vec![])?];
+        self.continue_line(index, &mut line);
+        Some(line)
+    }
+
+    ///Gets the move which led to a node, with its annotations. Returns `None` for the starting position.
+    fn history_move(&self, index: usize, variations: Vec<Vec<HistoryMove>>) -> Option<HistoryMove> {
+        let node = &self.nodes[index];
+        let (from, to) = node.m?;
+        Some(HistoryMove {
+            from,
+            to,
+            glyph: node.glyph,
+            comment: node.comment.clone(),
+            variations,
+        })
+    }
+
+    ///Gets where the position being shown is in the [`AnalysisBoard::to_history`]. Returns `None` for the starting position.
+    #[must_use]
+    pub fn current_path(&self) -> Option<MovePath> {
+        let mut chain = vec![];
+        let mut at = self.current;
+        while let Some(parent) = self.nodes[at].parent {
+            chain.push((parent, at));
+            at = parent;
+        }
+
+        let mut path: Vec<usize> = vec![];
+        for (parent, index) in chain.into_iter().rev() {
+            match path.last_mut() {
+                Some(last) => *last += 1,
+                None => path.push(0),
+            }
+            let variation = self.nodes[parent]
+                .children
+                .iter()
+                .position(|c| *c == index)?;
+            if variation > 0 {
+                path.push(variation - 1);
+                path.push(0);
+            }
+        }
+
+        (!path.is_empty()).then_some(MovePath(path))
+    }
+
+    ///Finds the node for a move in the [`AnalysisBoard::to_history`] - the opposite of [`AnalysisBoard::current_path`]
+    fn node_at(&self, path: &MovePath) -> Option<usize> {
+        let mut indices = path.0.iter();
+        let mut at = 0;
+        for _ in 0..=*indices.next()? {
+            at = *self.nodes[at].children.first()?;
+        }
+        while let Some(variation) = indices.next() {
+            let parent = self.nodes[at].parent?;
+            at = *self.nodes[parent].children.get(variation + 1)?;
+            for _ in 0..*indices.next()? {
+                at = *self.nodes[at].children.first()?;
+            }
+        }
+        Some(at)
+    }
+
+    ///Sets the annotations on a move. An empty comment removes the comment.
+    ///
+    /// # Errors
+    /// - There isn't a move at that path
+    pub fn annotate(
+        &mut self,
+        path: &MovePath,
+        glyph: Option<MoveGlyph>,
+        comment: Option<String>,
+    ) -> Result<()> {
+        let index = self
+            .node_at(path)
+            .ae()
+            .with_context(|| format!("no move {path} on the analysis board"))?;
+        let node = &mut self.nodes[index];
+        node.glyph = glyph;
+        node.comment = comment.filter(|c| !c.trim().is_empty());
+        Ok(())
+    }
+
+    ///Gets the board in the position being shown
+    #[must_use]
+    pub fn board(&self) -> &BoardContainer {
+        &self.nodes[self.current].board
+    }
+
+    ///Makes a move from the position being shown. If that move has been made from here before, its variation is followed rather than a new one started.
+    ///
+    /// # Errors
+    /// - There isn't a piece on `from`, or it can't move to `to` - see [`check_offline_move`]
+    pub fn make_move(&mut self, from: Coords, to: Coords) -> Result<()> {
+        self.current = self.add_child(self.current, from, to)?;
+        Ok(())
+    }
+
+    ///Makes a move from a node, or finds it if it has been made from there before. Returns the index of the node after the move.
+    ///
+    /// # Errors
+    /// - There isn't a piece on `from`, or it can't move to `to` - see [`check_offline_move`]
+    fn add_child(&mut self, parent: usize, from: Coords, to: Coords) -> Result<usize> {
+        let node = &self.nodes[parent];
+        if let Some(existing) = node
+            .children
+            .iter()
+            .copied()
+            .find(|c| self.nodes[*c].m == Some((from, to)))
+        {
+            return Ok(existing);
+        }
+
+        check_offline_move(&node.board, from, to)?;
+        let board = make_offline_move(&node.board, from, to)?;
+
+        let index = self.nodes.len();
+        self.nodes.push(AnalysisNode {
+            board,
+            m: Some((from, to)),
+            parent: Some(parent),
+            children: vec![],
+            glyph: None,
+            comment: None,
+        });
+        self.nodes[parent].children.push(index);
+        Ok(index)
+    }
+
+    ///Moves around the variations. Returns whether the position being shown changed.
+    pub fn step(&mut self, step: AnalysisStep) -> bool {
+        let node = &self.nodes[self.current];
+        let next = match step {
+            AnalysisStep::Back => node.parent,
+            AnalysisStep::Forward => node.children.first().copied(),
+            AnalysisStep::PreviousVariation | AnalysisStep::NextVariation => {
+                node.parent.and_then(|parent| {
+                    let siblings = &self.nodes[parent].children;
+                    let at = siblings.iter().position(|s| *s == self.current)?;
+                    let to = if step == AnalysisStep::NextVariation {
+                        at.checked_add(1)
+                    } else {
+                        at.checked_sub(1)
+                    }?;
+                    siblings.get(to).copied()
+                })
+            }
+        };
+
+        match next {
+            Some(next) => {
+                self.current = next;
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///Describes the position being shown for the status line - the last few moves from the start, which variation of the last move it is, and its annotations
+    #[must_use]
+    pub fn describe(&self) -> String {
+        let mut moves = vec![];
+        let mut at = self.current;
+        while let Some(node) = self.nodes.get(at) {
+            if let Some((from, to)) = node.m {
+                moves.push(format!(
+                    "{}{}",
+                    from.to_algebraic().unwrap_or_default(),
+                    to.to_algebraic().unwrap_or_default()
+                ));
+            }
+            match node.parent {
+                Some(parent) => at = parent,
+                None => break,
+            }
+        }
+        moves.reverse();
+        if moves.len() > DESCRIBED_MOVES {
+            moves.drain(..moves.len() - DESCRIBED_MOVES);
+            moves.insert(0, "...".into());
+        }
+
+        let variation = self.nodes[self.current]
+            .parent
+            .map(|parent| {
+                let siblings = &self.nodes[parent].children;
+                let at = siblings
+                    .iter()
+                    .position(|s| *s == self.current)
+                    .unwrap_or_default();
+                (at + 1, siblings.len())
+            })
+            .filter(|(_, count)| *count > 1)
+            .map(|(at, count)| format!(" (variation {at} of {count})"))
+            .unwrap_or_default();
+        let node = &self.nodes[self.current];
+        let annotation = match (node.glyph, &node.comment) {
+            (Some(glyph), Some(comment)) => format!(" {} - {comment}", glyph.symbol()),
+            (Some(glyph), None) => format!(" {}", glyph.symbol()),
+            (None, Some(comment)) => format!(" - {comment}"),
+            (None, None) => String::new(),
+        };
+
+        if moves.is_empty() {
+            "Analysis - starting position".into()
+        } else {
+            format!("Analysis - {}{variation}{annotation}", moves.join(" "))
+        }
+    }
+}
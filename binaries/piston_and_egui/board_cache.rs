@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use async_chess_client::{
+    net::server_interface::JSONPieceList,
+    util::lru_cache::{CacheGauges, LruCache},
+};
+use directories::ProjectDirs;
+use epac_utils::error_ext::{ErrorExt, MutexExt, ToAnyhowNotErr};
+use serde_json::{from_str, to_string};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_dir, read_to_string},
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Sender},
+        Mutex, OnceLock,
+    },
+    thread::JoinHandle,
+    time::SystemTime,
+};
+
+///Number of boards kept in memory by the [`snapshots`] store
+pub const SNAPSHOT_ENTRIES: usize = 32;
+///Gauges the [`snapshots`] store publishes to
+pub const SNAPSHOT_GAUGES: CacheGauges = CacheGauges {
+    hits: "cache.board_snapshot_hits",
+    misses: "cache.board_snapshot_misses",
+    entries: "memory.board_snapshots",
+};
+
+///Gets the boards most recently saved or loaded, kept in memory so they don't have to be read from disk again
+fn snapshots() -> &'static Mutex<LruCache<u32, JSONPieceList>> {
+    ///The store, made on first use
+    static SNAPSHOTS: OnceLock<Mutex<LruCache<u32, JSONPieceList>>> = OnceLock::new();
+    SNAPSHOTS.get_or_init(|| Mutex::new(LruCache::new(SNAPSHOT_ENTRIES, SNAPSHOT_GAUGES)))
+}
+
+///Publishes the hits, misses and number of boards held by the in-memory snapshot store to the metrics registry - see [`SNAPSHOT_GAUGES`]
+pub fn publish_snapshot_metrics() {
+    snapshots().lock_panic("board snapshots").publish_metrics();
+}
+
+///Saves the last board fetched for a game, so it can be shown when the server can't be reached.
+///
+/// # Errors
+/// - Fail to get the path using [`board_path`]
+/// - Fail to convert the list to JSON
+/// - Fail to write to the file
+#[tracing::instrument(skip(list))]
+pub fn save_board(id: u32, list: &JSONPieceList) -> Result<()> {
+    let path = board_path(id)?;
+    let st = to_string(list).context("turning board to string")?;
+    std::fs::write(&path, st).context("Write to file")?;
+
+    snapshots()
+        .lock_panic("board snapshots")
+        .insert(id, list.clone());
+    Ok(())
+}
+
+///Saves boards with [`save_board`] on one long-lived thread, so a burst of refreshes doesn't start a thread for each, and an older board can't be written over a newer one
+#[derive(Debug)]
+pub struct BoardSaver {
+    ///Sender for the boards to save - taken on drop, so the thread ends
+    tx: Option<Sender<(u32, JSONPieceList)>>,
+    ///The thread saving the boards
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BoardSaver {
+    ///Creates a new `BoardSaver`, and starts its thread
+    #[must_use]
+    pub fn new() -> Self {
+        let (tx, rx) = channel::<(u32, JSONPieceList)>();
+        let handle = std::thread::spawn(move || {
+            while let Ok(first) = rx.recv() {
+                //boards are sent in order, so only the newest waiting for each game needs writing
+                let mut newest = BTreeMap::from([first]);
+                newest.extend(rx.try_iter());
+                for (id, list) in newest {
+                    save_board(id, &list)
+                        .context("saving last known board")
+                        .warn();
+                }
+            }
+        });
+        Self {
+            tx: Some(tx),
+            handle: Some(handle),
+        }
+    }
+
+    ///Queues a board to be saved - if a newer one for the same game is queued before it is written, only the newer one is
+    pub fn save(&self, id: u32, list: JSONPieceList) {
+        if let Some(tx) = &self.tx {
+            tx.send((id, list))
+                .context("sending board to saver")
+                .warn();
+        }
+    }
+}
+
+impl Default for BoardSaver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for BoardSaver {
+    ///Waits for the boards still queued to be written
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("Board saver thread panicked");
+            }
+        }
+    }
+}
+
+///Loads the last board saved for a game, from memory if it has been saved or loaded recently. Returns `None` if there isn't one.
+///
+/// # Errors
+/// - Fail to get the path using [`board_path`]
+/// - Fail to read in the file
+/// - Fail to parse the contents into a [`JSONPieceList`]
+#[tracing::instrument]
+pub fn load_board(id: u32) -> Result<Option<JSONPieceList>> {
+    if let Some(list) = snapshots().lock_panic("board snapshots").get(&id) {
+        return Ok(Some(list.clone()));
+    }
+
+    let path = board_path(id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+    let list: JSONPieceList =
+        from_str(&cntnts).with_context(|| format!("reading contents {cntnts}"))?;
+    snapshots()
+        .lock_panic("board snapshots")
+        .insert(id, list.clone());
+    Ok(Some(list))
+}
+
+///Lists the ids of all of the games with a saved board, most recently saved first
+///
+/// # Errors
+/// - Fail to get the [`boards_dir`]
+/// - Fail to read the boards directory
+#[tracing::instrument]
+pub fn cached_games() -> Result<Vec<u32>> {
+    let mut games = vec![];
+    for entry in read_dir(boards_dir()?).context("reading boards directory")? {
+        let path = entry.context("reading boards directory entry")?.path();
+        let Some(id) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let modified = path
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        games.push((modified, id));
+    }
+
+    games.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(games.into_iter().map(|(_, id)| id).collect())
+}
+
+///Gets the path of the saved board for a game - `{id}.json` in the [`boards_dir`]
+///
+/// # Errors
+/// - Fail to get the [`boards_dir`]
+fn board_path(id: u32) -> Result<PathBuf> {
+    Ok(boards_dir()?.join(format!("{id}.json")))
+}
+
+///Gets the directory that boards are saved to - `boards` in the project data directory, creating it if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the boards directory
+fn boards_dir() -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dir = pd.data_dir().join("boards");
+    create_dir_all(&dir).context("creating boards directory")?;
+    Ok(dir)
+}
@@ -0,0 +1,64 @@
+use crate::{piston::PistonConfig, stats::ResultsStore};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string_pretty};
+use std::{fs::read_to_string, path::Path};
+
+///The current version of the [`ConfigBundle`] format
+const BUNDLE_VERSION: u32 = 1;
+
+///Everything needed to set the client up on another machine, in one file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    ///The version of the format, so that bundles from newer clients are rejected rather than half-read
+    pub version: u32,
+    ///The full configuration, including the servers
+    pub config: PistonConfig,
+    ///The names of the installed themes - the sprites aren't included, so they need to be imported separately
+    pub themes: Vec<String>,
+    ///The results of previous games
+    pub results: ResultsStore,
+}
+
+impl ConfigBundle {
+    ///Creates a new `ConfigBundle` at the current version
+    #[must_use]
+    pub fn new(config: PistonConfig, themes: Vec<String>, results: ResultsStore) -> Self {
+        Self {
+            version: BUNDLE_VERSION,
+            config,
+            themes,
+            results,
+        }
+    }
+
+    ///Writes the bundle to a file
+    ///
+    /// # Errors
+    /// - Fail to convert the bundle to JSON
+    /// - Fail to write to the file
+    #[tracing::instrument(skip(self))]
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let st = to_string_pretty(self).context("turning bundle to string")?;
+        std::fs::write(path, st).with_context(|| format!("writing bundle to {path:?}"))
+    }
+
+    ///Reads a bundle from a file
+    ///
+    /// # Errors
+    /// - Fail to read in the file
+    /// - Fail to parse the contents into a `ConfigBundle`
+    /// - The bundle is from a newer version of the client
+    #[tracing::instrument]
+    pub fn import(path: &Path) -> Result<Self> {
+        let cntnts = read_to_string(path).with_context(|| format!("reading path {path:?}"))?;
+        let bundle: Self = from_str(&cntnts).context("parsing bundle")?;
+        if bundle.version > BUNDLE_VERSION {
+            bail!(
+                "bundle is version {}, but only up to {BUNDLE_VERSION} is supported",
+                bundle.version
+            );
+        }
+        Ok(bundle)
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result};
+use async_chess_client::util::error_ext::ToAnyhowNotErr;
+use piston_window::{
+    clear, rectangle, BufferSwap, Event, Filter, G2dTexture, ImageSize, PistonWindow, RenderEvent,
+    Texture, TextureSettings, Window,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::read_dir,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, TryRecvError},
+    thread::{available_parallelism, Builder},
+    time::Instant,
+};
+
+///Finds the folder that the default assets are loaded from - `assets`, in or near the working directory
+///
+/// # Errors
+/// - The folder can't be found
+pub fn assets_folder() -> Result<PathBuf> {
+    find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets")
+        .context("finding assets folder")
+}
+
+///How textures are sampled when they are drawn at a different size to the image
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureFilter {
+    ///Uses the nearest pixel - crisp, best for pixel art being made bigger
+    Nearest,
+    ///Blends neighbouring pixels - smoother, and less shimmery when being made smaller
+    Linear,
+    ///Uses [`TextureFilter::Nearest`] when the window is bigger than the board sprite, and [`TextureFilter::Linear`] when it is smaller
+    Auto,
+}
+
+///Configuration for loading and scaling textures
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureConfig {
+    ///The filter to use for all textures
+    pub filter: TextureFilter,
+    ///Whether or not to only scale by whole numbers when the window is bigger than the board sprite, so every pixel is the same size
+    pub integer_scaling: bool,
+}
+
+impl Default for TextureConfig {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Auto,
+            integer_scaling: false,
+        }
+    }
+}
+
+impl TextureConfig {
+    ///Gets the [`TextureSettings`] to use, given the scale the window will start at
+    #[must_use]
+    pub fn settings(&self, window_scale: f64) -> TextureSettings {
+        let filter = match self.filter {
+            TextureFilter::Nearest => Filter::Nearest,
+            TextureFilter::Linear => Filter::Linear,
+            TextureFilter::Auto if window_scale >= 1.0 => Filter::Nearest,
+            TextureFilter::Auto => Filter::Linear,
+        };
+        TextureSettings::new().filter(filter)
+    }
+
+    ///Applies [`TextureConfig::integer_scaling`] to a window scale
+    #[must_use]
+    pub fn snap_scale(&self, window_scale: f64) -> f64 {
+        if self.integer_scaling && window_scale >= 1.0 {
+            window_scale.floor()
+        } else {
+            window_scale
+        }
+    }
+}
+
+///Struct to load and hold all of the textures in the assets folder, keyed by file name
+pub struct Cacher {
+    ///The textures, keyed by file name eg. `white_pawn.png`
+    textures: HashMap<String, G2dTexture>,
+}
+
+impl Cacher {
+    ///Loads every `png` in the [`assets_folder`] using the given [`TextureSettings`], and every `png` in the theme folder if there is one - the theme's sprites replace the defaults with the same name.
+    ///
+    /// The images are decoded in parallel on worker threads, and uploaded to the GPU on this thread as each one is ready. Meanwhile, the window shows a splash with a bar which fills in as each texture is loaded, so everything is ready by the time the board is first drawn.
+    ///
+    /// # Errors
+    /// - Fail to find or read the assets or theme folder
+    /// - Fail to decode or upload any of the textures
+    /// - The window is closed while loading
+    #[tracing::instrument(skip(win, settings))]
+    pub fn new(
+        win: &mut PistonWindow,
+        settings: &TextureSettings,
+        theme: Option<&Path>,
+    ) -> Result<Self> {
+        let mut paths = BTreeMap::new();
+        let assets = assets_folder()?;
+        for folder in std::iter::once(assets.as_path()).chain(theme) {
+            for entry in read_dir(folder).with_context(|| format!("reading {folder:?}"))? {
+                let path = entry.context("reading folder entry")?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("png") {
+                    continue;
+                }
+                let Some(name) = path.file_name().and_then(|n| n.to_str()).map(String::from) else {
+                    continue;
+                };
+                paths.insert(name, path);
+            }
+        }
+        let paths: Vec<(String, PathBuf)> = paths.into_iter().collect();
+
+        let start = Instant::now();
+        let mut ctx = win.create_texture_context();
+        let mut textures = HashMap::with_capacity(paths.len());
+        let workers = available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(paths.len())
+            .max(1);
+
+        std::thread::scope(|s| -> Result<()> {
+            let (tx, rx) = channel();
+            for i in 0..workers {
+                let (tx, paths) = (tx.clone(), &paths);
+                Builder::new()
+                    .name(format!("texture decode {i}"))
+                    .spawn_scoped(s, move || {
+                        for (name, path) in paths.iter().skip(i).step_by(workers) {
+                            let img = image::open(path)
+                                .map(|img| img.to_rgba8())
+                                .with_context(|| format!("decoding texture {path:?}"));
+                            //if loading has failed, the rest aren't needed
+                            if tx.send((name, img)).is_err() {
+                                return;
+                            }
+                        }
+                    })
+                    .context("starting decode thread")?;
+            }
+            drop(tx);
+
+            while textures.len() < paths.len() {
+                loop {
+                    let (name, img) = match rx.try_recv() {
+                        Ok(decoded) => decoded,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => bail!("decode threads stopped early"),
+                    };
+                    let tex = Texture::from_image(&mut ctx, &img?, settings)
+                        .map_err(|e| anyhow!("{e}"))
+                        .with_context(|| format!("uploading texture {name:?}"))?;
+                    debug!(%name, "Loaded texture");
+                    textures.insert(name.clone(), tex);
+                }
+
+                if textures.len() < paths.len() {
+                    let e = win
+                        .next()
+                        .ae()
+                        .context("window closed while loading textures")?;
+                    if e.render_args().is_some() {
+                        draw_splash(win, &e, textures.len(), paths.len());
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        info!(n=%textures.len(), %workers, elapsed=?start.elapsed(), "Loaded textures");
+        Ok(Self { textures })
+    }
+
+    ///Makes a cacher with no textures, for a game which is never drawn - eg. when replaying a recording
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            textures: HashMap::new(),
+        }
+    }
+
+    ///Gets a texture by file name
+    ///
+    /// # Errors
+    /// - There is no texture with that name
+    pub fn get(&self, name: &str) -> Result<&G2dTexture> {
+        self.textures
+            .get(name)
+            .ok_or_else(|| anyhow!("missing texture {name:?}"))
+    }
+
+    ///Gets the number of textures loaded
+    #[must_use]
+    pub fn texture_count(&self) -> usize {
+        self.textures.len()
+    }
+
+    ///Gets roughly how many bytes the textures take up - 4 for each pixel
+    #[must_use]
+    pub fn texture_bytes(&self) -> u64 {
+        self.textures
+            .values()
+            .map(|t| {
+                let (w, h) = t.get_size();
+                u64::from(w) * u64::from(h) * 4
+            })
+            .sum()
+    }
+}
+
+///Draws the splash shown while textures load - a bar with a segment for each texture, which fills in as they are loaded
+#[allow(clippy::cast_precision_loss)]
+fn draw_splash(win: &mut PistonWindow, e: &Event, done: usize, total: usize) {
+    ///Colour behind the bar
+    const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+    ///Colour of the segments for textures which have been loaded
+    const DONE_COLOUR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+    ///Colour of the segments for textures still loading
+    const WAITING_COLOUR: [f32; 4] = [0.3, 0.3, 0.3, 1.0];
+    ///Gap in pixels between segments
+    const GAP: f64 = 1.0;
+
+    let size = win.size();
+    win.draw_2d(e, |c, g, _| {
+        clear(BACKGROUND, g);
+
+        let (w, h) = (size.width * 0.6, size.height * 0.03);
+        let (x, y) = ((size.width - w) / 2.0, (size.height - h) / 2.0);
+        let segment = w / total.max(1) as f64;
+        for i in 0..total {
+            let colour = if i < done {
+                DONE_COLOUR
+            } else {
+                WAITING_COLOUR
+            };
+            rectangle(
+                colour,
+                [x + segment * i as f64, y, (segment - GAP).max(GAP), h],
+                c.transform,
+                g,
+            );
+        }
+    });
+    //buffers aren't swapped automatically - see `piston_main`
+    win.window.swap_buffers();
+}
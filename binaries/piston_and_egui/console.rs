@@ -0,0 +1,647 @@
+use crate::read_config;
+use anyhow::{Context as _, Result};
+use async_chess_client::{
+    chess::{
+        history::{MoveGlyph, MoveHistory, MovePath},
+        opening_book::BookMove,
+    },
+    metrics::metrics,
+    net::{game_import::ExternalGame, request_stats::RequestStats},
+    prelude::ErrorExt,
+};
+use graphics::Text;
+use piston_window::{rectangle, Context, G2d, Glyphs, Key, Transformed};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context as LayerContext, reload, EnvFilter, Layer, Registry};
+
+///Maximum number of log lines kept for the console
+const MAX_LINES: usize = 200;
+///Size in pixels of the console text
+const TEXT_S: f64 = 12.0;
+///Padding in pixels around the console text
+const CONSOLE_PADDING: f64 = 4.0;
+///Maximum number of moves shown in the move list panel - around the position being shown, or the most recent ones
+const MAX_MOVE_LINES: usize = 20;
+
+///A log line captured for the console
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    ///The level it was logged at
+    pub level: Level,
+    ///The module it was logged from
+    pub target: String,
+    ///The message, followed by any other fields
+    pub message: String,
+}
+
+///The most recent [`MAX_LINES`] log lines, shared between the [`RingBufferLayer`] and the [`Console`]
+pub type LogBuffer = Arc<Mutex<VecDeque<LogLine>>>;
+
+///Gets the [`LogBuffer`] shared by the whole program
+pub fn log_buffer() -> &'static LogBuffer {
+    ///The buffer, made on first use
+    static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+    BUFFER.get_or_init(LogBuffer::default)
+}
+
+///Handle to swap out the [`EnvFilter`] used for logging, set once the subscriber is made
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+///Stores the handle to the reloadable [`EnvFilter`], so that [`set_log_filter`] can change it. Only the first handle is kept.
+pub fn set_log_filter_handle(handle: reload::Handle<EnvFilter, Registry>) {
+    if LOG_FILTER.set(handle).is_err() {
+        warn!("Log filter handle already set");
+    }
+}
+
+///Changes which events are logged without restarting, using `RUST_LOG`-style directives like `info,async_chess_client::net=debug`. `None` goes back to `RUST_LOG`.
+///
+/// # Errors
+/// - The directives aren't valid
+/// - The handle hasn't been set with [`set_log_filter_handle`]
+/// - Fail to swap the filter
+pub fn set_log_filter(directives: Option<&str>) -> Result<()> {
+    let filter = match directives {
+        Some(d) => EnvFilter::try_new(d).with_context(|| format!("parsing filter {d:?}"))?,
+        None => EnvFilter::builder()
+            .from_env()
+            .context("reading filter from RUST_LOG")?,
+    };
+    LOG_FILTER
+        .get()
+        .context("log filter handle not set")?
+        .reload(filter)
+        .context("swapping log filter")?;
+    info!(?directives, "Changed log filter");
+    Ok(())
+}
+
+///Layer for the tracing subscriber which keeps recent events in the [`log_buffer`]
+#[derive(Debug, Default)]
+pub struct RingBufferLayer;
+
+impl<S: Subscriber> Layer<S> for RingBufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let mut message = visitor.message;
+        for field in visitor.fields {
+            message += " ";
+            message += &field;
+        }
+
+        let line = LogLine {
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message,
+        };
+
+        //can't log an error here, as it would come straight back to this layer
+        if let Ok(mut lines) = log_buffer().lock() {
+            if lines.len() == MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+}
+
+///Visitor to turn the fields of an [`Event`] into strings
+#[derive(Debug, Default)]
+struct MessageVisitor {
+    ///The `message` field
+    message: String,
+    ///All of the other fields, as `name=value`
+    fields: Vec<String>,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields.push(format!("{}={value:?}", field.name()));
+        }
+    }
+}
+
+///A command typed into the [`Console`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleCommand {
+    ///Ask the server for the board straight away
+    Refresh,
+    ///Log the board in Forsyth-Edwards Notation
+    Fen,
+    ///Toggle the threats overlay
+    Threats,
+    ///Toggle the evaluation bar
+    Eval,
+    ///Show a suggested move
+    Hint,
+    ///Act as though the server can't be reached
+    Disconnect,
+    ///Stop acting as though the server can't be reached
+    Reconnect,
+    ///Save the network session recording, for a bug report
+    SaveNetworkLog,
+    ///Save the input recording so far, to be replayed with `--replay`
+    SaveInputRecording,
+    ///Show the move log of the game, with when each move was sent and acknowledged
+    MoveLog,
+    ///Start capturing a trace of the frame and update loop, or stop and save it
+    Trace,
+    ///Toggle the request statistics panel
+    Stats,
+    ///Toggle the opening book panel
+    Book,
+    ///Start practising from the current position, against the engine if `true`, or go back to the live game
+    Practice(bool),
+    ///Open the analysis board, or close it and throw away what was explored
+    Analysis,
+    ///Toggle the move list panel
+    Moves,
+    ///Annotate a move in the move list - `None` removes the glyph or comment
+    Annotate {
+        ///Where the move is, as shown in the move list
+        path: MovePath,
+        ///How good the move was
+        glyph: Option<MoveGlyph>,
+        ///A comment about the move
+        comment: Option<String>,
+    },
+    ///Export the move history as PGN, or import a PGN file into the analysis board
+    Pgn(Option<PathBuf>),
+    ///Import a game from Lichess or Chess.com into the analysis board
+    Import(ExternalGame),
+    ///Export the move history as a printable scoresheet
+    Scoresheet,
+    ///Only show log lines at this level or more severe
+    Level(Level),
+    ///Change which events are logged at all, using `RUST_LOG`-style directives
+    Filter(String),
+    ///Go back to the log filter from the configuration, read in again
+    ReloadFilter,
+    ///Clear the log lines
+    Clear,
+    ///Log the list of commands
+    Help,
+}
+
+impl ConsoleCommand {
+    ///Description of every command, for [`ConsoleCommand::Help`]
+    pub const HELP: &'static str = "refresh | fen | threats | eval | hint | disconnect | reconnect | network | record | movelog | trace | stats | book | practice [engine] | analysis | moves | annotate <move> <!!/!/!?/?!/?/??/-> [comment] | pgn [file to import] | scoresheet | import lichess <url> | import chesscom <player> <yyyy/mm> <url> | level <error/warn/info/debug/trace> | filter <directives> | reload | clear | help";
+}
+
+impl FromStr for ConsoleCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let cmd = match (words.next(), words.next()) {
+            (Some("refresh"), None) => Self::Refresh,
+            (Some("fen"), None) => Self::Fen,
+            (Some("threats"), None) => Self::Threats,
+            (Some("eval"), None) => Self::Eval,
+            (Some("hint"), None) => Self::Hint,
+            (Some("disconnect"), None) => Self::Disconnect,
+            (Some("reconnect"), None) => Self::Reconnect,
+            (Some("network"), None) => Self::SaveNetworkLog,
+            (Some("record"), None) => Self::SaveInputRecording,
+            (Some("movelog"), None) => Self::MoveLog,
+            (Some("trace"), None) => Self::Trace,
+            (Some("stats"), None) => Self::Stats,
+            (Some("book"), None) => Self::Book,
+            (Some("practice"), None) => Self::Practice(false),
+            (Some("practice"), Some("engine")) => Self::Practice(true),
+            (Some("analysis"), None) => Self::Analysis,
+            (Some("moves"), None) => Self::Moves,
+            (Some("annotate"), Some(path)) => {
+                let path = path.parse().context("reading move")?;
+                let glyph = match words.next() {
+                    None | Some("-") => None,
+                    Some(glyph) => Some(
+                        MoveGlyph::from_symbol(glyph)
+                            .ok_or_else(|| anyhow!("unknown annotation {glyph:?}"))?,
+                    ),
+                };
+                let comment = words.collect::<Vec<_>>().join(" ");
+                Self::Annotate {
+                    path,
+                    glyph,
+                    comment: Some(comment).filter(|c| !c.is_empty()),
+                }
+            }
+            (Some("pgn"), None) => Self::Pgn(None),
+            (Some("pgn"), Some(path)) => {
+                //paths can have spaces in
+                let path: Vec<&str> = std::iter::once(path).chain(words).collect();
+                Self::Pgn(Some(path.join(" ").into()))
+            }
+            (Some("scoresheet"), None) => Self::Scoresheet,
+            (Some("import"), Some("lichess")) => {
+                let url = words.next().context("missing game url")?;
+                Self::Import(ExternalGame::lichess(url)?)
+            }
+            (Some("import"), Some("chesscom")) => {
+                let (Some(player), Some(month), Some(url)) =
+                    (words.next(), words.next(), words.next())
+                else {
+                    bail!("expected import chesscom <player> <yyyy/mm> <url>");
+                };
+                Self::Import(ExternalGame::chess_com(player, month, url)?)
+            }
+            (Some("level"), Some(level)) => Self::Level(
+                level
+                    .parse()
+                    .map_err(|_| anyhow!("unknown level {level:?}"))?,
+            ),
+            (Some("filter"), Some(directives)) => Self::Filter(directives.to_string()),
+            (Some("reload"), None) => Self::ReloadFilter,
+            (Some("clear"), None) => Self::Clear,
+            (Some("help"), None) => Self::Help,
+            _ => bail!("unknown command {s:?} - try help"),
+        };
+        Ok(cmd)
+    }
+}
+
+///Groups the gauges in the [`metrics`] registry into one line per group, eg. `queue: to_game=0 to_worker=1`
+fn metrics_lines() -> Vec<String> {
+    let mut lines: Vec<(&str, String)> = vec![];
+    for (name, value) in metrics().gauges() {
+        let (group, rest) = name.split_once('.').unwrap_or(("", name));
+        match lines.last_mut() {
+            Some((g, line)) if *g == group => *line += &format!(" {rest}={value}"),
+            _ => lines.push((group, format!("{group}: {rest}={value}"))),
+        }
+    }
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+///A toggleable overlay showing recent log lines, with a line to type [`ConsoleCommand`]s into
+#[derive(Debug)]
+pub struct Console {
+    ///Whether or not the console is shown
+    open: bool,
+    ///The least severe level of log lines shown
+    min_level: Level,
+    ///The command being typed
+    input: String,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            open: false,
+            min_level: Level::INFO,
+            input: String::new(),
+        }
+    }
+}
+
+impl Console {
+    ///Whether or not the console is shown - while it is, keyboard input should go to it rather than the game
+    #[must_use]
+    pub const fn is_open(&self) -> bool {
+        self.open
+    }
+
+    ///Shows or hides the console
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.input.clear();
+    }
+
+    ///Adds typed text to the command
+    pub fn text(&mut self, text: &str) {
+        //the key that opens the console also types a backtick
+        self.input.extend(text.chars().filter(|c| *c != '`'));
+    }
+
+    ///Deals with a key press while the console is open. Returns the command if one was entered.
+    ///
+    /// The logging commands - [`ConsoleCommand::Level`], [`ConsoleCommand::Filter`], [`ConsoleCommand::ReloadFilter`], [`ConsoleCommand::Clear`] and [`ConsoleCommand::Help`] - are dealt with here, and the rest are for the caller.
+    pub fn key_press(&mut self, key: Key) -> Option<ConsoleCommand> {
+        match key {
+            Key::Backspace => {
+                self.input.pop();
+                None
+            }
+            Key::Return => {
+                let input = std::mem::take(&mut self.input);
+                info!(%input, "Console command");
+                match input.parse() {
+                    Ok(ConsoleCommand::Level(level)) => {
+                        self.min_level = level;
+                        None
+                    }
+                    Ok(ConsoleCommand::Filter(directives)) => {
+                        set_log_filter(Some(&directives))
+                            .context("setting log filter")
+                            .warn();
+                        None
+                    }
+                    Ok(ConsoleCommand::ReloadFilter) => {
+                        read_config()
+                            .context("reading config")
+                            .and_then(|pc| set_log_filter(pc.log_filter.as_deref()))
+                            .context("reloading log filter")
+                            .warn();
+                        None
+                    }
+                    Ok(ConsoleCommand::Clear) => {
+                        log_buffer()
+                            .lock()
+                            .map_err(|e| anyhow!("{e}"))
+                            .context("locking log buffer")
+                            .map(|mut l| l.clear())
+                            .warn();
+                        None
+                    }
+                    Ok(ConsoleCommand::Help) => {
+                        info!("{}", ConsoleCommand::HELP);
+                        None
+                    }
+                    Ok(cmd) => Some(cmd),
+                    Err(e) => {
+                        warn!(%e, "Invalid console command");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    ///Renders the console over the top half of the window, with the [`metrics`] at the top and the newest log lines at the bottom. Should be given a [`Context`] which isn't zoomed or panned.
+    ///
+    /// # Errors
+    /// - Fail to lock the [`log_buffer`]
+    /// - Fail to render the text
+    pub fn render(
+        &self,
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        (width, height): (f64, f64),
+    ) -> Result<()> {
+        if !self.open {
+            return Ok(());
+        }
+
+        let console_h = height / 2.0;
+        rectangle(
+            [0.0, 0.0, 0.0, 0.85],
+            [0.0, 0.0, width, console_h],
+            ctx.transform,
+            graphics,
+        );
+
+        let line_h = TEXT_S + CONSOLE_PADDING;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let font_size = TEXT_S as u32;
+
+        let mut top = 0.0;
+        for line in metrics_lines() {
+            top += line_h;
+            Text::new_color([0.5, 0.9, 1.0, 1.0], font_size)
+                .draw(
+                    &line,
+                    glyphs,
+                    &ctx.draw_state,
+                    ctx.transform.trans(CONSOLE_PADDING, top),
+                    graphics,
+                )
+                .map_err(|e| anyhow!("{e:?}"))
+                .context("drawing console metrics")?;
+        }
+
+        let mut y = console_h - CONSOLE_PADDING;
+        Text::new_color([1.0, 1.0, 0.5, 1.0], font_size)
+            .draw(
+                &format!("> {}_", self.input),
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform.trans(CONSOLE_PADDING, y),
+                graphics,
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("drawing console input")?;
+
+        let lines = log_buffer()
+            .lock()
+            .map_err(|e| anyhow!("{e}"))
+            .context("locking log buffer")?;
+        for line in lines.iter().rev().filter(|l| l.level <= self.min_level) {
+            y -= line_h;
+            if y < top + line_h {
+                break;
+            }
+
+            let colour = match line.level {
+                Level::ERROR => [1.0, 0.3, 0.3, 1.0],
+                Level::WARN => [1.0, 0.7, 0.2, 1.0],
+                Level::INFO => [0.9, 0.9, 0.9, 1.0],
+                _ => [0.6, 0.6, 0.6, 1.0],
+            };
+            Text::new_color(colour, font_size)
+                .draw(
+                    &format!("{:>5} {}: {}", line.level, line.target, line.message),
+                    glyphs,
+                    &ctx.draw_state,
+                    ctx.transform.trans(CONSOLE_PADDING, y),
+                    graphics,
+                )
+                .map_err(|e| anyhow!("{e:?}"))
+                .context("drawing console line")?;
+        }
+
+        Ok(())
+    }
+}
+
+///Renders a panel in the bottom left of the window with a line for each endpoint in the [`RequestStats`] - the number of requests, the share which failed, and the latency percentiles. Should be given a [`Context`] which isn't zoomed or panned.
+///
+/// # Errors
+/// - Fail to render the text
+pub fn render_request_stats(
+    stats: &RequestStats,
+    ctx: Context,
+    graphics: &mut G2d,
+    glyphs: &mut Glyphs,
+    (width, height): (f64, f64),
+) -> Result<()> {
+    let fmt_ms = |ms: Option<u64>| ms.map_or_else(|| "-".into(), |ms| format!("{ms}ms"));
+    let mut lines = vec![format!(
+        "{:<10} {:>5} {:>6} {:>7} {:>7} {:>7}",
+        "endpoint", "reqs", "errors", "p50", "p90", "p99"
+    )];
+    for (endpoint, s) in stats.endpoints() {
+        lines.push(format!(
+            "{endpoint:<10} {:>5} {:>5.1}% {:>7} {:>7} {:>7}",
+            s.requests,
+            s.error_rate() * 100.0,
+            fmt_ms(s.latency.percentile(0.5)),
+            fmt_ms(s.latency.percentile(0.9)),
+            fmt_ms(s.latency.percentile(0.99)),
+        ));
+    }
+
+    render_panel(&lines, ctx, graphics, glyphs, (width, height)).context("drawing request stats")
+}
+
+///Renders a panel in the bottom right of the window with the [`BookMove`]s for the current position - the move, its weight and its share of the weight. `None` means there isn't an opening book. Should be given a [`Context`] which isn't zoomed or panned.
+///
+/// # Errors
+/// - Fail to render the text
+pub fn render_book_moves(
+    moves: Option<&[BookMove]>,
+    ctx: Context,
+    graphics: &mut G2d,
+    glyphs: &mut Glyphs,
+    (width, height): (f64, f64),
+) -> Result<()> {
+    let lines = match moves {
+        None => vec!["No opening book - set one in the launcher".to_string()],
+        Some([]) => vec!["Position not in the opening book".to_string()],
+        Some(moves) => std::iter::once(format!("{:<6} {:>6} {:>6}", "move", "weight", "share"))
+            .chain(moves.iter().map(|b| {
+                format!(
+                    "{:<6} {:>6} {:>5.1}%",
+                    format!(
+                        "{}{}",
+                        b.m.0.to_algebraic().unwrap_or_default(),
+                        b.m.1.to_algebraic().unwrap_or_default()
+                    ),
+                    b.weight,
+                    b.share * 100.0
+                )
+            }))
+            .collect(),
+    };
+
+    let panel_w = width.min(TEXT_S * 20.0);
+    render_panel(
+        &lines,
+        ctx.trans(width - panel_w, 0.0),
+        graphics,
+        glyphs,
+        (panel_w, height),
+    )
+    .context("drawing book moves")
+}
+
+///Renders a panel in the top right of the window with the moves in a [`MoveHistory`] - each with where it is for [`ConsoleCommand::Annotate`] and its annotations, and variations indented under the move they replace. The move at `current` is marked, and the moves around it shown - otherwise the most recent moves are. `None` means no moves have been seen yet. Should be given a [`Context`] which isn't zoomed or panned.
+///
+/// # Errors
+/// - Fail to write the moves
+/// - Fail to render the text
+pub fn render_move_list(
+    history: Option<&MoveHistory>,
+    current: Option<&MovePath>,
+    ctx: Context,
+    graphics: &mut G2d,
+    glyphs: &mut Glyphs,
+    (width, height): (f64, f64),
+) -> Result<()> {
+    let described = match history {
+        Some(history) => history.described_moves().context("writing moves")?,
+        None => vec![],
+    };
+    let lines = if described.is_empty() {
+        vec!["No moves yet".to_string()]
+    } else {
+        let skip = match described.iter().position(|m| Some(&m.path) == current) {
+            Some(at) => at.saturating_sub(MAX_MOVE_LINES / 2),
+            None => described.len().saturating_sub(MAX_MOVE_LINES),
+        };
+        described
+            .into_iter()
+            .skip(skip)
+            .take(MAX_MOVE_LINES)
+            .map(|m| {
+                let marker = if Some(&m.path) == current { ">" } else { " " };
+                let mut line = format!(
+                    "{marker}{:indent$}{}: {} {}",
+                    "",
+                    m.path,
+                    m.number,
+                    m.san,
+                    indent = m.depth * 2
+                );
+                if let Some(glyph) = m.glyph {
+                    line += glyph.symbol();
+                }
+                if let Some(comment) = &m.comment {
+                    line += &format!(" - {comment}");
+                }
+                line
+            })
+            .collect()
+    };
+
+    let line_h = TEXT_S + CONSOLE_PADDING;
+    #[allow(clippy::cast_precision_loss)]
+    let panel_h = (line_h * lines.len() as f64 + CONSOLE_PADDING).min(height);
+    let panel_w = width.min(TEXT_S * 30.0);
+    render_panel(
+        &lines,
+        ctx.trans(width - panel_w, 0.0),
+        graphics,
+        glyphs,
+        (panel_w, panel_h),
+    )
+    .context("drawing move list")
+}
+
+///Renders lines of text on a translucent panel, in the bottom left of an area of `width` by `height`
+///
+/// # Errors
+/// - Fail to render the text
+pub fn render_panel(
+    lines: &[String],
+    ctx: Context,
+    graphics: &mut G2d,
+    glyphs: &mut Glyphs,
+    (width, height): (f64, f64),
+) -> Result<()> {
+    let line_h = TEXT_S + CONSOLE_PADDING;
+    #[allow(clippy::cast_precision_loss)]
+    let panel_h = line_h * lines.len() as f64 + CONSOLE_PADDING;
+    let panel_w = width.min(TEXT_S * 30.0);
+    rectangle(
+        [0.0, 0.0, 0.0, 0.75],
+        [0.0, height - panel_h, panel_w, panel_h],
+        ctx.transform,
+        graphics,
+    );
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let font_size = TEXT_S as u32;
+    let mut y = height - panel_h;
+    for line in lines {
+        y += line_h;
+        Text::new_color([0.9, 0.9, 0.9, 1.0], font_size)
+            .draw(
+                line,
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform.trans(CONSOLE_PADDING, y),
+                graphics,
+            )
+            .map_err(|e| anyhow!("{e:?}"))?;
+    }
+
+    Ok(())
+}
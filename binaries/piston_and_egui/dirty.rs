@@ -0,0 +1,165 @@
+use async_chess_client::{net::server_interface::PresenceState, prelude::ChessPiece};
+use graphics::math::{transform_pos, Matrix2d};
+use piston_window::Context;
+use std::collections::BTreeSet;
+
+///How many buffers the window swaps between. Each one is left with the frame drawn to it last time, so a square has to be redrawn on this many frames after it changes.
+const BUFFERS: u8 = 2;
+
+///Everything which affects how one square is drawn
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct TileState {
+    ///The piece on the square
+    pub piece: Option<ChessPiece>,
+    ///Whether the square is highlighted, from the mouse being over it or it being part of the pending move
+    pub highlighted: bool,
+    ///Whether the piece on the square is selected
+    pub selected: bool,
+    ///Whether the piece on the square is threatened, and threats are shown
+    pub threatened: bool,
+    ///Whether the selected piece can move to the square
+    pub legal_move: bool,
+    ///Whether the controller's cursor is on the square
+    pub cursor: bool,
+}
+
+///The state of all of the squares, indexed by column then row
+pub type Tiles = [[TileState; 8]; 8];
+
+///Everything drawn outside of the squares, or over more than one of them. If any of it changes, the whole window is redrawn.
+#[derive(Clone, PartialEq)]
+pub struct Scene {
+    ///The transform the board is drawn with, from the zoom and pan
+    pub transform: Matrix2d,
+    ///The size of the window in pixels
+    pub draw_size: Option<[u32; 2]>,
+    ///How much bigger than the sprites everything is drawn
+    pub window_scale: f64,
+    ///Whether black is at the bottom
+    pub is_flipped: bool,
+    ///The generation of the pieces which have been taken, shown beside the board, from [`TakenCache::update`](crate::taken::TakenCache::update)
+    pub taken: u64,
+    ///The evaluation bar, to the nearest tenth of a percent, if it is shown
+    pub eval: Option<i32>,
+    ///Whether the board is dimmed as it might be out of date
+    pub stale: bool,
+    ///Whether the board is framed as one which is never sent to the server
+    pub offline: bool,
+    ///The opponent presence indicator
+    pub presence: Option<PresenceState>,
+    ///The reason in the banner saying requests can't be made
+    pub unavailable: Option<String>,
+    ///Whether anything is animating, or drawn on top of the squares - a sliding or floating piece, a hint arrow, a tooltip or the board updated notice
+    pub overlays: bool,
+}
+
+///What to redraw for a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redraw {
+    ///Clear the window and redraw everything
+    Full,
+    ///Only redraw these squares, as `(column, row)`, and the info strip below the board. Everything else is left from the last frame drawn to this buffer.
+    Tiles(BTreeSet<(u8, u8)>),
+}
+
+///Keeps track of what was drawn on the last frames, to work out which squares need redrawing on the next one.
+///
+/// As the buffers are swapped between, the buffer being drawn to has the frame from [`BUFFERS`] frames ago - so the squares which changed on the last frame are redrawn as well as those which changed on this one, and a full redraw is done on each buffer.
+#[derive(Default)]
+pub struct DirtyTracker {
+    ///Whether or not only the squares which changed are redrawn - if not, every frame is [`Redraw::Full`]
+    enabled: bool,
+    ///What was drawn on the last frame
+    last: Option<(Scene, Tiles)>,
+    ///The squares which changed on the last frame
+    last_changed: BTreeSet<(u8, u8)>,
+    ///How many more frames need to be full, so that every buffer has had one
+    full_left: u8,
+}
+
+impl DirtyTracker {
+    ///Makes a new tracker - if not `enabled`, every frame is redrawn in full
+    #[must_use]
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    ///Works out what needs to be redrawn for a frame showing this scene and these squares
+    pub fn plan(&mut self, scene: Scene, tiles: Tiles) -> Redraw {
+        let changed: BTreeSet<(u8, u8)> = match &self.last {
+            Some((last_scene, last_tiles)) if self.enabled && *last_scene == scene => (0..8_u8)
+                .flat_map(|col| (0..8_u8).map(move |row| (col, row)))
+                .filter(|&(col, row)| {
+                    last_tiles[usize::from(col)][usize::from(row)]
+                        != tiles[usize::from(col)][usize::from(row)]
+                })
+                .collect(),
+            _ => {
+                self.full_left = BUFFERS;
+                BTreeSet::new()
+            }
+        };
+        self.last = Some((scene, tiles));
+
+        let redraw = if self.full_left > 0 {
+            self.full_left -= 1;
+            Redraw::Full
+        } else {
+            Redraw::Tiles(changed.union(&self.last_changed).copied().collect())
+        };
+        self.last_changed = changed;
+        redraw
+    }
+
+    ///Makes the next frames full redraws, eg. after something was drawn over the board which the tracker doesn't know about
+    pub fn invalidate(&mut self) {
+        self.last = None;
+    }
+}
+
+///Works out the rectangle of the window in pixels which a rectangle is drawn to with a [`Context`], for clipping drawing to it with [`DrawState::scissor`](graphics::DrawState::scissor)
+///
+/// The edges are rounded, so rectangles which touch are clipped to rectangles which touch. Returns `None` if the [`Context`] has no viewport.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn scissor_rect(ctx: &Context, [x, y, w, h]: [f64; 4]) -> Option<[u32; 4]> {
+    let [draw_w, draw_h] = ctx.viewport?.draw_size;
+    let (draw_w, draw_h) = (f64::from(draw_w), f64::from(draw_h));
+
+    //the transform goes to -1..1 with y up, rather than pixels
+    let to_pixels = |px: f64, py: f64| {
+        let [nx, ny] = transform_pos(ctx.transform, [px, py]);
+        (
+            ((nx + 1.0) / 2.0 * draw_w).round().clamp(0.0, draw_w),
+            ((1.0 - ny) / 2.0 * draw_h).round().clamp(0.0, draw_h),
+        )
+    };
+    let (x0, y0) = to_pixels(x, y);
+    let (x1, y1) = to_pixels(x + w, y + h);
+    let (left, top) = (x0.min(x1), y0.min(y1));
+
+    Some([
+        left as u32,
+        top as u32,
+        (x0.max(x1) - left) as u32,
+        (y0.max(y1) - top) as u32,
+    ])
+}
+
+///Works out the rectangle of the window in the coordinates of a [`Context`], so everything which can be seen is inside it. The transform should only be scaled and moved, not rotated.
+#[must_use]
+pub fn visible_rect(ctx: &Context) -> [f64; 4] {
+    let [[sx, _, tx], [_, sy, ty]] = ctx.transform;
+    //the edges of the window are at -1 and 1, with y up
+    let (left, right) = ((-1.0 - tx) / sx, (1.0 - tx) / sx);
+    let (top, bottom) = ((1.0 - ty) / sy, (-1.0 - ty) / sy);
+    [
+        left.min(right),
+        top.min(bottom),
+        (right - left).abs(),
+        (bottom - top).abs(),
+    ]
+}
@@ -1,13 +1,56 @@
 use anyhow::{Context, Result};
-use async_chess_client::{prelude::ErrorExt, util::error_ext::ToAnyhowNotErr};
+use async_chess_client::{
+    chess::{
+        boards::board::{Board, CanMovePiece},
+        engine::HintEngine,
+    },
+    net::{
+        invite::{Invite, InviteRole},
+        rate_limiter::RateLimit,
+        server_client::{ClientConfig, ServerClient},
+        server_interface::fetch_boards,
+    },
+    prelude::{Coords, ErrorExt},
+    util::error_ext::ToAnyhowNotErr,
+};
 use directories::ProjectDirs;
-use eframe::{egui, App};
+use eframe::{
+    egui::{self, Align2, Color32, FontId, Rect, Sense, Stroke},
+    App,
+};
+use qrcode::{Color, QrCode};
 use serde_json::to_string;
 use std::{
+    collections::BTreeMap,
     fs::{create_dir_all},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+};
+
+use crate::{
+    board_cache::{cached_games, load_board, save_board},
+    bundle::ConfigBundle,
+    cacher::{TextureConfig, TextureFilter},
+    hints::HintConfig,
+    i18n::{Language, Translations},
+    latency::{LatencyMonitor, Reachability},
+    piston::{FrameConfig, InputConfig, PistonConfig},
+    stats::{GameResult, ResultsStore},
+    telemetry::{discard_queue, TelemetryQueue},
+    themes::{import_theme, list_themes, remove_theme},
+    update_check::{check_in_background, Release},
 };
 
-use crate::piston::PistonConfig;
+///Number of recent results shown in the statistics view
+const RECENT_RESULTS: usize = 10;
+///Number of games shown in the game history
+const HISTORY_GAMES: usize = 10;
+///Size in points of each square of a board preview
+const PREVIEW_SQUARE: f32 = 12.0;
+///The smallest loss in centipawns which counts as a blunder, when blunder warnings are first turned on
+const DEFAULT_BLUNDER_THRESHOLD: i32 = 200;
+///How long a UCI engine gets to think for a hint, in milliseconds, unless it has been set
+const DEFAULT_UCI_MOVETIME_MS: u64 = 500;
 
 ///Function to start up an [`AsyncChessLauncher`] using [`eframe::run_native`]
 #[tracing::instrument]
@@ -28,6 +71,78 @@ struct AsyncChessLauncher {
     id: String,
     ///The width/height of the to-be-opened window
     res: String,
+    ///The limit on requests to the server - not editable here, but kept so it isn't lost on saving
+    rate_limit: RateLimit,
+    ///Where the server is and how to identify to it - only the server is editable here, but the rest is kept so it isn't lost on saving
+    client: ClientConfig,
+    ///Pings the server to show its latency - started on the first frame, as it needs the [`egui::Context`]
+    latency: Option<LatencyMonitor>,
+    ///Whether moves need to be confirmed before being sent
+    confirm_moves: bool,
+    ///Misclick protection settings
+    input: InputConfig,
+    ///Texture filtering and scaling settings
+    textures: TextureConfig,
+    ///Frame rate settings
+    frames: FrameConfig,
+    ///The name of the theme to use, or `None` for the default sprites
+    theme: Option<String>,
+    ///The names of the installed themes
+    installed_themes: Vec<String>,
+    ///The path of a zip to import a theme from
+    theme_zip: String,
+    ///The outcome of the last theme import or removal, to show to the user
+    theme_message: Option<String>,
+    ///Whether or not to check for a new version on startup
+    check_for_updates: bool,
+    ///`RUST_LOG`-style directives for which events are logged - empty to use `RUST_LOG`
+    log_filter: String,
+    ///Whether or not to send anonymous usage statistics
+    telemetry: bool,
+    ///The usage statistics waiting to be sent - `None` if they couldn't be loaded
+    telemetry_queue: Option<TelemetryQueue>,
+    ///The most hints per game - see [`HintConfig::max_per_game`]
+    max_hints: Option<u32>,
+    ///Whether hints come from a UCI engine rather than the built-in one
+    use_uci: bool,
+    ///The path of the UCI engine
+    uci_path: String,
+    ///How long the UCI engine gets to think, in milliseconds
+    uci_movetime_ms: u64,
+    ///The path of the Polyglot opening book - empty for none
+    book_path: String,
+    ///The path of the Polyglot keys for the opening book
+    book_keys_path: String,
+    ///The directory of Syzygy endgame tablebases - empty for none
+    tablebase_path: String,
+    ///Receiver for the result of the update check, if it is still running
+    update_rx: Option<Receiver<Option<Release>>>,
+    ///The newer release, if there is one
+    update: Option<Release>,
+    ///The path to export a [`ConfigBundle`] to, or import one from
+    bundle_path: String,
+    ///The outcome of the last bundle import or export, to show to the user
+    bundle_message: Option<String>,
+    ///What the player being invited should join as
+    invite_role: Option<InviteRole>,
+    ///What this player plays as - `None` to play either colour
+    role: Option<InviteRole>,
+    ///An invite link to join a game from
+    join_link: String,
+    ///Receiver for the checked invite, if the check is still running
+    join_rx: Option<Receiver<Result<Invite>>>,
+    ///Why the last attempt to join from a link failed, to show to the user
+    join_message: Option<String>,
+    ///The language to show the launcher in
+    language: Language,
+    ///The translations for the `language`
+    i18n: Translations,
+    ///The results of previous games - `None` if they couldn't be loaded
+    results: Option<ResultsStore>,
+    ///The most recently played games, with their last known boards if they could be loaded
+    history: Vec<(u32, Option<Board<CanMovePiece>>)>,
+    ///Receiver for the boards of the `history` fetched from the server, if the fetch is still running
+    history_rx: Option<Receiver<BTreeMap<u32, Board<CanMovePiece>>>>,
 }
 
 impl Default for AsyncChessLauncher {
@@ -35,32 +150,390 @@ impl Default for AsyncChessLauncher {
         Self {
             id: "0".into(),
             res: "600".into(),
+            rate_limit: RateLimit::default(),
+            client: ClientConfig::default(),
+            latency: None,
+            confirm_moves: false,
+            input: InputConfig::default(),
+            textures: TextureConfig::default(),
+            frames: FrameConfig::default(),
+            theme: None,
+            installed_themes: load_themes(),
+            theme_zip: String::new(),
+            theme_message: None,
+            check_for_updates: false,
+            log_filter: String::new(),
+            telemetry: false,
+            telemetry_queue: load_telemetry_queue(),
+            max_hints: None,
+            use_uci: false,
+            uci_path: String::new(),
+            uci_movetime_ms: DEFAULT_UCI_MOVETIME_MS,
+            book_path: String::new(),
+            book_keys_path: String::new(),
+            tablebase_path: String::new(),
+            update_rx: None,
+            update: None,
+            bundle_path: String::new(),
+            bundle_message: None,
+            invite_role: None,
+            role: None,
+            join_link: String::new(),
+            join_rx: None,
+            join_message: None,
+            language: Language::default(),
+            i18n: Translations::default(),
+            results: load_results(),
+            history: load_history(),
+            history_rx: None,
+        }
+    }
+}
+
+///Loads the [`ResultsStore`], logging any errors
+fn load_results() -> Option<ResultsStore> {
+    match ResultsStore::load() {
+        Ok(r) => Some(r),
+        Err(e) => {
+            warn!(%e, "Unable to load results");
+            None
         }
     }
 }
 
+///Loads the [`HISTORY_GAMES`] most recently played games, and their last known boards, logging any errors
+fn load_history() -> Vec<(u32, Option<Board<CanMovePiece>>)> {
+    let ids = cached_games().unwrap_or_else(|e| {
+        warn!(%e, "Unable to list cached games");
+        vec![]
+    });
+
+    ids.into_iter()
+        .take(HISTORY_GAMES)
+        .map(|id| {
+            let board = load_board(id)
+                .and_then(|l| l.map(Board::new_json).transpose())
+                .unwrap_or_else(|e| {
+                    warn!(%e, %id, "Unable to load last known board");
+                    None
+                });
+            (id, board)
+        })
+        .collect()
+}
+
+///Loads the [`TelemetryQueue`], logging any errors
+fn load_telemetry_queue() -> Option<TelemetryQueue> {
+    match TelemetryQueue::load() {
+        Ok(q) => Some(q),
+        Err(e) => {
+            warn!(%e, "Unable to load telemetry queue");
+            None
+        }
+    }
+}
+
+///Loads the [`Translations`] for a language, logging any errors and falling back to English
+fn load_translations(language: Language) -> Translations {
+    Translations::load(language).unwrap_or_else(|e| {
+        warn!(%e, ?language, "Unable to load translations");
+        Translations::default()
+    })
+}
+
+///Lists the installed themes, logging any errors
+fn load_themes() -> Vec<String> {
+    list_themes().unwrap_or_else(|e| {
+        warn!(%e, "Unable to list themes");
+        vec![]
+    })
+}
+
 impl AsyncChessLauncher {
     ///Function to create a new `AsyncChessLauncher`.
     ///
     ///If `start_uc` is [`Some`], then it uses those values, and if not then it uses the [`AsyncChessLauncher::default`] values - `id: 0, res: 600`
     pub fn new(start_uc: Option<PistonConfig>) -> Self {
-        start_uc
-            .map(|PistonConfig { id, res }| Self {
-                id: id.to_string(),
-                res: res.to_string(),
-            })
-            .unwrap_or_default()
+        let mut launcher = Self::default();
+        if let Some(pc) = start_uc {
+            launcher.apply_config(pc);
+        }
+
+        if launcher.check_for_updates {
+            launcher.update_rx = Some(check_in_background());
+        }
+        launcher.refresh_history();
+        launcher
+    }
+
+    ///Starts fetching the current boards of the `history` on a new thread, in one request using [`fetch_boards`]. The fetched boards are also saved to the cache.
+    fn refresh_history(&mut self) {
+        if self.history.is_empty() || self.client.low_bandwidth {
+            return;
+        }
+
+        let ids: Vec<u32> = self.history.iter().map(|(id, _)| *id).collect();
+        let (tx, rx) = channel();
+        let config = self.client.clone();
+        std::thread::spawn(move || {
+            let boards = ServerClient::new(&config)
+                .and_then(|client| fetch_boards(&client, &ids))
+                .unwrap_or_else(|e| {
+                    warn!(%e, "Unable to fetch boards for the game history");
+                    None
+                })
+                .unwrap_or_default();
+
+            let boards = boards
+                .into_iter()
+                .filter_map(|(id, state)| {
+                    if let Err(e) = save_board(id, state.pieces()) {
+                        warn!(%e, %id, "Unable to cache fetched board");
+                    }
+                    match state.try_into() {
+                        Ok(board) => Some((id, board)),
+                        Err(e) => {
+                            warn!(%e, %id, "Unable to use fetched board");
+                            None
+                        }
+                    }
+                })
+                .collect();
+            //if the launcher has closed, nobody needs to know
+            let _ = tx.send(boards);
+        });
+        self.history_rx = Some(rx);
+    }
+
+    ///Sets all of the configurable values from a [`PistonConfig`]
+    fn apply_config(
+        &mut self,
+        PistonConfig {
+            id,
+            res,
+            rate_limit,
+            client,
+            confirm_moves,
+            input,
+            textures,
+            frames,
+            theme,
+            check_for_updates,
+            role,
+            language,
+            log_filter,
+            telemetry,
+            hints,
+            record_input: _,
+            review: _,
+        }: PistonConfig,
+    ) {
+        self.id = id.to_string();
+        self.res = res.to_string();
+        self.rate_limit = rate_limit;
+        self.client = client;
+        self.confirm_moves = confirm_moves;
+        self.input = input;
+        self.textures = textures;
+        self.frames = frames;
+        self.theme = theme;
+        self.check_for_updates = check_for_updates;
+        self.log_filter = log_filter.unwrap_or_default();
+        self.telemetry = telemetry;
+        self.max_hints = hints.max_per_game;
+        let path_string = |p: Option<PathBuf>| {
+            p.map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        self.book_path = path_string(hints.book);
+        self.book_keys_path = path_string(hints.book_keys);
+        self.tablebase_path = path_string(hints.tablebase);
+        match hints.engine {
+            HintEngine::BuiltIn => self.use_uci = false,
+            HintEngine::Uci { path, movetime_ms } => {
+                self.use_uci = true;
+                self.uci_path = path.to_string_lossy().to_string();
+                self.uci_movetime_ms = movetime_ms;
+            }
+        }
+        self.role = role;
+        if self.language != language {
+            self.language = language;
+            self.i18n = load_translations(language);
+        }
+    }
+
+    ///Makes a [`PistonConfig`] from the configurable values
+    ///
+    /// # Errors
+    /// - The game id or resolution aren't valid numbers
+    fn to_config(&self) -> Result<PistonConfig> {
+        Ok(PistonConfig {
+            id: self.id.parse().context("parsing game id")?,
+            res: self.res.parse().context("parsing resolution")?,
+            rate_limit: self.rate_limit,
+            client: self.client.clone(),
+            confirm_moves: self.confirm_moves,
+            input: self.input,
+            textures: self.textures,
+            frames: self.frames,
+            theme: self.theme.clone(),
+            check_for_updates: self.check_for_updates,
+            role: self.role,
+            language: self.language,
+            log_filter: Some(self.log_filter.trim())
+                .filter(|f| !f.is_empty())
+                .map(String::from),
+            telemetry: self.telemetry,
+            hints: HintConfig {
+                engine: if self.use_uci {
+                    HintEngine::Uci {
+                        path: self.uci_path.trim().into(),
+                        movetime_ms: self.uci_movetime_ms,
+                    }
+                } else {
+                    HintEngine::BuiltIn
+                },
+                max_per_game: self.max_hints,
+                book: Some(self.book_path.trim())
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from),
+                book_keys: Some(self.book_keys_path.trim())
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from),
+                tablebase: Some(self.tablebase_path.trim())
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from),
+            },
+            record_input: false,
+            review: None,
+        })
+    }
+
+    ///Parses the `join_link`, and starts checking the game exists on a new thread
+    fn start_join(&mut self) {
+        match Invite::parse(&self.join_link) {
+            Ok(invite) => {
+                self.join_message = Some(self.i18n.fill("Checking {}...", &[&invite]));
+                let (tx, rx) = channel();
+                let client = self.client.clone();
+                std::thread::spawn(move || {
+                    let checked = invite.verify(&client).map(|()| invite);
+                    //if the launcher has closed, nobody needs to know
+                    let _ = tx.send(checked);
+                });
+                self.join_rx = Some(rx);
+            }
+            Err(e) => {
+                let e = format!("{e:#}");
+                self.join_message = Some(self.i18n.fill("Invalid invite link: {}", &[&e]));
+            }
+        }
+    }
+
+    ///Switches to the game from a checked [`Invite`], keeping the rest of the configuration - see [`PistonConfig::join`]
+    fn join(&mut self, invite: Invite) {
+        info!(%invite, "Joining from invite");
+        if self.client.server.trim_end_matches('/') != invite.server {
+            self.client.server = invite.server;
+            self.client.backup_servers.clear();
+        }
+        self.id = invite.id.to_string();
+        self.role = invite.role;
     }
 }
 
 impl App for AsyncChessLauncher {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(received) = self.update_rx.as_ref().map(Receiver::try_recv) {
+            match received {
+                Ok(update) => {
+                    self.update = update;
+                    self.update_rx = None;
+                }
+                //egui only repaints on input otherwise, so the banner wouldn't show up
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Disconnected) => self.update_rx = None,
+            }
+        }
+
+        if let Some(received) = self.history_rx.as_ref().map(Receiver::try_recv) {
+            match received {
+                Ok(mut boards) => {
+                    self.history_rx = None;
+                    for (id, board) in &mut self.history {
+                        if let Some(fetched) = boards.remove(id) {
+                            *board = Some(fetched);
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Disconnected) => self.history_rx = None,
+            }
+        }
+
+        if let Some(received) = self.join_rx.as_ref().map(Receiver::try_recv) {
+            match received {
+                Ok(Ok(invite)) => {
+                    self.join_rx = None;
+                    self.join(invite);
+                    //saves the config, so the game is opened next time
+                    frame.quit();
+                }
+                Ok(Err(e)) => {
+                    warn!(%e, "Unable to join from invite link");
+                    self.join_rx = None;
+                    let e = format!("{e:#}");
+                    self.join_message = Some(self.i18n.fill("Unable to join: {}", &[&e]));
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Disconnected) => self.join_rx = None,
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.label("Asynchronous Chess!");
-            ui.label("To play, enter the configuration and press start game, then re-open the app");
+            if let Some(release) = &self.update {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        self.i18n
+                            .fill("Version {} is available - ", &[&release.tag_name]),
+                    );
+                    ui.hyperlink_to(self.i18n.tr("release page"), &release.html_url);
+                });
+                if let Some(changelog) = &release.body {
+                    ui.collapsing(self.i18n.tr("Changelog"), |ui| ui.label(changelog));
+                }
+                ui.separator();
+            }
+
+            ui.label(self.i18n.tr("Asynchronous Chess!"));
+            ui.label(
+                self.i18n.tr(
+                    "To play, enter the configuration and press start game, then re-open the app",
+                ),
+            );
             ui.separator();
             ui.horizontal(|ui| {
-                ui.label("Game ID: ");
+                ui.label(self.i18n.tr("Language: "));
+                let before = self.language;
+                egui::ComboBox::from_id_source("language")
+                    .selected_text(self.language.name())
+                    .show_ui(ui, |ui| {
+                        for language in Language::ALL {
+                            ui.selectable_value(&mut self.language, language, language.name());
+                        }
+                    });
+                if self.language != before {
+                    self.i18n = load_translations(self.language);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Server: "));
+                ui.text_edit_singleline(&mut self.client.server);
+                self.show_latency(ui, ctx);
+            });
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Game ID: "));
                 ui.text_edit_singleline(&mut self.id);
 
                 if self.id.parse::<u32>().is_err() {
@@ -68,33 +541,676 @@ impl App for AsyncChessLauncher {
                 }
             });
             ui.horizontal(|ui| {
-                ui.label("Screen Width/Height: ");
+                ui.label(self.i18n.tr("Play as: "));
+                ui.radio_value(&mut self.role, None, self.i18n.tr("Either"));
+                ui.radio_value(
+                    &mut self.role,
+                    Some(InviteRole::White),
+                    self.i18n.tr("White"),
+                );
+                ui.radio_value(
+                    &mut self.role,
+                    Some(InviteRole::Black),
+                    self.i18n.tr("Black"),
+                );
+                ui.radio_value(
+                    &mut self.role,
+                    Some(InviteRole::Spectator),
+                    self.i18n.tr("Spectator"),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Join from invite link: "));
+                ui.text_edit_singleline(&mut self.join_link);
+                if ui
+                    .add_enabled(
+                        self.join_rx.is_none(),
+                        egui::Button::new(self.i18n.tr("Join")),
+                    )
+                    .clicked()
+                {
+                    self.start_join();
+                }
+            });
+            if let Some(msg) = &self.join_message {
+                ui.label(msg);
+            }
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Screen Width/Height: "));
                 ui.text_edit_singleline(&mut self.res);
 
                 if self.res.parse::<u32>().is_err() {
                     self.res.clear();
                 }
             });
+            ui.checkbox(
+                &mut self.confirm_moves,
+                self.i18n
+                    .tr("Confirm moves before sending (Enter to confirm, right click to cancel)"),
+            );
+            ui.checkbox(
+                &mut self.input.confirm_captures,
+                self.i18n.tr("Always confirm moves which take a piece"),
+            );
+            ui.horizontal(|ui| {
+                let mut warn_blunders = self.input.blunder_threshold.is_some();
+                ui.checkbox(
+                    &mut warn_blunders,
+                    self.i18n
+                        .tr("Warn before blunders losing at least (centipawns): "),
+                );
+                match (warn_blunders, &mut self.input.blunder_threshold) {
+                    (true, Some(threshold)) => {
+                        ui.add(egui::DragValue::new(threshold).clamp_range(50..=2000));
+                    }
+                    (true, threshold @ None) => *threshold = Some(DEFAULT_BLUNDER_THRESHOLD),
+                    (false, threshold) => *threshold = None,
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Texture filtering: "));
+                ui.radio_value(
+                    &mut self.textures.filter,
+                    TextureFilter::Auto,
+                    self.i18n.tr("Auto"),
+                );
+                ui.radio_value(
+                    &mut self.textures.filter,
+                    TextureFilter::Nearest,
+                    self.i18n.tr("Crisp"),
+                );
+                ui.radio_value(
+                    &mut self.textures.filter,
+                    TextureFilter::Linear,
+                    self.i18n.tr("Smooth"),
+                );
+            });
+            ui.checkbox(
+                &mut self.textures.integer_scaling,
+                self.i18n.tr("Only scale by whole numbers"),
+            );
+
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Max FPS: "));
+                ui.add(egui::DragValue::new(&mut self.frames.max_fps).clamp_range(1..=240));
+                ui.label(self.i18n.tr("Updates per second: "));
+                ui.add(egui::DragValue::new(&mut self.frames.ups).clamp_range(1..=120));
+            });
+            ui.checkbox(&mut self.frames.vsync, self.i18n.tr("VSync"));
+            ui.checkbox(
+                &mut self.check_for_updates,
+                self.i18n.tr("Check for updates on startup"),
+            );
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Log filter: "));
+                ui.text_edit_singleline(&mut self.log_filter).on_hover_text(
+                    self.i18n
+                        .tr("Which logs to show, like info,async_chess_client::net=debug - empty to use RUST_LOG"),
+                );
+            });
+            ui.checkbox(
+                &mut self.client.record_session,
+                self.i18n.tr(
+                    "Record network traffic for bug reports (save with `network` in the console)",
+                ),
+            );
+            ui.checkbox(
+                &mut self.client.low_bandwidth,
+                self.i18n
+                    .tr("Low-bandwidth mode (refreshes the board less often and turns off previews)"),
+            );
+            ui.checkbox(
+                &mut self.telemetry,
+                self.i18n
+                    .tr("Send anonymous usage statistics (see Usage Statistics below)"),
+            );
+            ui.horizontal(|ui| {
+                let mut power_saver = self.frames.idle_fps.is_some();
+                ui.checkbox(
+                    &mut power_saver,
+                    self.i18n.tr("Power saver - lower FPS when idle: "),
+                );
+                match (power_saver, &mut self.frames.idle_fps) {
+                    (true, Some(idle_fps)) => {
+                        ui.add(egui::DragValue::new(idle_fps).clamp_range(1..=240));
+                    }
+                    (true, idle_fps @ None) => *idle_fps = FrameConfig::default().idle_fps,
+                    (false, idle_fps) => *idle_fps = None,
+                }
+            });
+            ui.checkbox(
+                &mut self.frames.dirty_regions,
+                self.i18n
+                    .tr("Only redraw squares which changed (turn off if old squares are left on the board)"),
+            );
 
             ui.separator();
 
-            if ui.button("Save and Exit.").clicked() {
+            if ui.button(self.i18n.tr("Save and Exit.")).clicked() {
                 frame.quit();
             }
+
+            ui.separator();
+            ui.collapsing(self.i18n.tr("Your Games"), |ui| {
+                if let Some(id) = self.show_history(ui) {
+                    info!(%id, "Resuming game");
+                    self.id = id.to_string();
+                    //saves the config, so the game is opened next time
+                    frame.quit();
+                }
+            });
+            ui.collapsing(self.i18n.tr("Themes"), |ui| self.show_themes(ui));
+            ui.collapsing(self.i18n.tr("Import/Export"), |ui| self.show_bundle(ui));
+            ui.collapsing(self.i18n.tr("Invite"), |ui| self.show_invite(ui));
+            ui.collapsing(self.i18n.tr("Hints"), |ui| self.show_hints(ui));
+            ui.collapsing(self.i18n.tr("Usage Statistics"), |ui| self.show_telemetry(ui));
+            ui.collapsing(self.i18n.tr("Statistics"), |ui| {
+                if let Some(results) = &self.results {
+                    show_stats(ui, results, &self.i18n);
+                } else {
+                    ui.label(
+                        self.i18n
+                            .tr("Unable to load previous results - check the logs."),
+                    );
+                }
+            });
         });
     }
 
     #[tracing::instrument]
     fn on_exit(&mut self, gl: &eframe::glow::Context) {
-        let pc = PistonConfig {
-            //PANICS - we parse ^
-            id: self.id.parse().unwrap(),
-            res: self.res.parse().unwrap(),
+        if !self.telemetry {
+            //opting out means nothing collected before gets sent either
+            discard_queue().context("discarding telemetry").warn();
+        }
+
+        match self.to_config() {
+            Ok(pc) => {
+                std::thread::spawn(move || {
+                    write_conf_to_file(pc).error();
+                });
+            }
+            Err(e) => error!(%e, "Unable to save config"),
+        }
+    }
+}
+
+///Number of empty modules around a QR code, so it can be scanned
+const QR_QUIET_ZONE: usize = 2;
+///Size in points of each module of a QR code
+const QR_MODULE_SIZE: f32 = 4.0;
+
+impl AsyncChessLauncher {
+    ///Shows the most recently played games with a preview of their last known boards, unless [`ClientConfig::low_bandwidth`] is on. Returns the id of the game to resume, if one was picked.
+    fn show_history(&self, ui: &mut egui::Ui) -> Option<u32> {
+        if self.history.is_empty() {
+            ui.label(self.i18n.tr("No games played yet"));
+            return None;
+        }
+
+        let mut resume = None;
+        for (id, board) in &self.history {
+            ui.horizontal(|ui| {
+                match board.as_ref().filter(|_| !self.client.low_bandwidth) {
+                    Some(board) => show_board_preview(ui, board),
+                    None => {
+                        ui.label(self.i18n.tr("No preview"));
+                    }
+                }
+                ui.vertical(|ui| {
+                    ui.label(self.i18n.fill("Game {}", &[id]));
+                    let last_result = self
+                        .results
+                        .as_ref()
+                        .and_then(|r| r.recent(usize::MAX).find(|r| r.id == *id));
+                    if let Some(r) = last_result {
+                        let result = self.i18n.tr(result_name(r.result));
+                        ui.label(self.i18n.fill("Last result: {}", &[&result]));
+                    }
+                    if ui.button(self.i18n.tr("Resume")).clicked() {
+                        resume = Some(*id);
+                    }
+                });
+            });
+        }
+        resume
+    }
+
+    ///Shows the latency of the server, starting the [`LatencyMonitor`] if needed
+    fn show_latency(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let monitor = self
+            .latency
+            .get_or_insert_with(|| LatencyMonitor::start(self.client.clone(), ctx.clone()));
+        monitor.set_config(&self.client);
+
+        match monitor.latest() {
+            Reachability::Checking => {
+                ui.label(self.i18n.tr("Checking..."));
+            }
+            Reachability::Reachable(d) => {
+                ui.colored_label(Color32::GREEN, format!("{}ms", d.as_millis()));
+            }
+            Reachability::Unreachable(e) => {
+                ui.colored_label(Color32::RED, self.i18n.tr("Unreachable"))
+                    .on_hover_text(e);
+            }
+        }
+    }
+
+    ///Shows an invite link to the current game, with a button to copy it and a QR code of it
+    fn show_invite(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(self.i18n.tr("Invite as: "));
+            ui.radio_value(&mut self.invite_role, None, self.i18n.tr("Either"));
+            ui.radio_value(
+                &mut self.invite_role,
+                Some(InviteRole::White),
+                self.i18n.tr("White"),
+            );
+            ui.radio_value(
+                &mut self.invite_role,
+                Some(InviteRole::Black),
+                self.i18n.tr("Black"),
+            );
+            ui.radio_value(
+                &mut self.invite_role,
+                Some(InviteRole::Spectator),
+                self.i18n.tr("Spectator"),
+            );
+        });
+
+        let Ok(id) = self.id.parse() else {
+            ui.label(self.i18n.tr("Enter a game ID to make an invite"));
+            return;
+        };
+        let invite = Invite {
+            server: self.client.server.clone(),
+            id,
+            role: self.invite_role,
+        };
+        let link = match invite.to_link() {
+            Ok(l) => l,
+            Err(e) => {
+                let e = format!("{e:#}");
+                ui.label(self.i18n.fill("Unable to make invite: {}", &[&e]));
+                return;
+            }
         };
 
-        std::thread::spawn(move || {
-            write_conf_to_file(pc).error();
+        ui.horizontal(|ui| {
+            ui.label(&link);
+            if ui.button(self.i18n.tr("Copy")).clicked() {
+                ui.output().copied_text = link.clone();
+            }
+        });
+
+        match QrCode::new(link.as_bytes()) {
+            Ok(code) => show_qr(ui, &code),
+            Err(e) => {
+                ui.label(self.i18n.fill("Unable to make QR code: {}", &[&e]));
+            }
+        }
+    }
+
+    ///Shows a way to export everything to a [`ConfigBundle`], or import everything from one
+    fn show_bundle(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(self.i18n.tr("Bundle file: "));
+            ui.text_edit_singleline(&mut self.bundle_path);
+        });
+        ui.horizontal(|ui| {
+            if ui.button(self.i18n.tr("Export")).clicked() {
+                self.bundle_message = Some(match self.export_bundle() {
+                    Ok(()) => self.i18n.tr("Exported"),
+                    Err(e) => {
+                        warn!(%e, "Unable to export bundle");
+                        self.i18n.fill("Unable to export: {}", &[&format!("{e:#}")])
+                    }
+                });
+            }
+            if ui.button(self.i18n.tr("Import")).clicked() {
+                self.bundle_message = Some(match self.import_bundle() {
+                    Ok(missing) if missing.is_empty() => self.i18n.tr("Imported"),
+                    Ok(missing) => self.i18n.fill(
+                        "Imported - these themes need to be imported separately: {}",
+                        &[&missing.join(", ")],
+                    ),
+                    Err(e) => {
+                        warn!(%e, "Unable to import bundle");
+                        self.i18n.fill("Unable to import: {}", &[&format!("{e:#}")])
+                    }
+                });
+            }
+        });
+
+        if let Some(msg) = &self.bundle_message {
+            ui.label(msg);
+        }
+    }
+
+    ///Shows the settings for the hint key - which engine suggests moves, and how many hints can be used in a game
+    fn show_hints(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(self.i18n.tr("Hint engine: "));
+            ui.radio_value(&mut self.use_uci, false, self.i18n.tr("Built-in"));
+            ui.radio_value(&mut self.use_uci, true, self.i18n.tr("UCI engine"));
+        });
+        if self.use_uci {
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Engine path: "));
+                ui.text_edit_singleline(&mut self.uci_path);
+            });
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Thinking time (ms): "));
+                ui.add(egui::DragValue::new(&mut self.uci_movetime_ms).clamp_range(50..=10_000));
+            });
+        }
+
+        ui.horizontal(|ui| {
+            let mut limited = self.max_hints.is_some();
+            ui.checkbox(&mut limited, self.i18n.tr("Limit hints per game: "))
+                .on_hover_text(
+                    self.i18n
+                        .tr("Set to 0 to turn hints off, eg. for rated games"),
+                );
+            match (limited, &mut self.max_hints) {
+                (true, Some(max)) => {
+                    ui.add(egui::DragValue::new(max).clamp_range(0..=100));
+                }
+                (true, max @ None) => *max = Some(0),
+                (false, max) => *max = None,
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(self.i18n.tr("Opening book: ")).on_hover_text(
+                self.i18n
+                    .tr("A Polyglot book - its moves are suggested while the position is in it"),
+            );
+            ui.text_edit_singleline(&mut self.book_path);
         });
+        if !self.book_path.trim().is_empty() {
+            ui.horizontal(|ui| {
+                ui.label(self.i18n.tr("Polyglot keys: "))
+                    .on_hover_text(self.i18n.tr(
+                        "A file with the 781 Random64 numbers from the Polyglot specification, in hexadecimal",
+                    ));
+                ui.text_edit_singleline(&mut self.book_keys_path);
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(self.i18n.tr("Endgame tablebase: ")).on_hover_text(
+                self.i18n
+                    .tr("A directory of Syzygy tables, for perfect hints and exact results"),
+            );
+            ui.text_edit_singleline(&mut self.tablebase_path);
+        });
+        if !cfg!(feature = "syzygy") && !self.tablebase_path.trim().is_empty() {
+            ui.label(
+                self.i18n
+                    .tr("This build can't read tablebases - it needs the syzygy feature."),
+            );
+        }
+    }
+
+    ///Shows what is collected when sending usage statistics, and exactly what is waiting to be sent
+    fn show_telemetry(&mut self, ui: &mut egui::Ui) {
+        ui.label(self.i18n.tr(
+            "When turned on, the client counts how long games are open, which features are used, and which kinds of errors happen. Nothing identifies you or your games. They are sent the next time a game starts and the server can be reached.",
+        ));
+
+        let Some(queue) = &self.telemetry_queue else {
+            ui.label(
+                self.i18n
+                    .tr("Unable to load usage statistics - check the logs."),
+            );
+            return;
+        };
+
+        if queue.events().is_empty() {
+            ui.label(self.i18n.tr("Nothing waiting to be sent"));
+        } else {
+            ui.label(
+                self.i18n
+                    .fill("Waiting to be sent ({} events):", &[&queue.events().len()]),
+            );
+            match queue.to_pretty_json() {
+                Ok(json) => {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| ui.monospace(json));
+                }
+                Err(e) => {
+                    warn!(%e, "Unable to show telemetry queue");
+                }
+            }
+            if ui.button(self.i18n.tr("Remove")).clicked() {
+                match discard_queue() {
+                    Ok(()) => self.telemetry_queue = Some(TelemetryQueue::default()),
+                    Err(e) => warn!(%e, "Unable to discard telemetry"),
+                }
+            }
+        }
+    }
+
+    ///Exports everything to a [`ConfigBundle`] at the `bundle_path`
+    ///
+    /// # Errors
+    /// - The current config isn't valid
+    /// - Fail to load the results
+    /// - Fail to write the bundle
+    fn export_bundle(&self) -> Result<()> {
+        let bundle = ConfigBundle::new(
+            self.to_config()?,
+            self.installed_themes.clone(),
+            ResultsStore::load().context("loading results")?,
+        );
+        bundle.export(Path::new(self.bundle_path.trim()))
+    }
+
+    ///Imports everything from a [`ConfigBundle`] at the `bundle_path`, replacing the current config and results.
+    ///
+    /// Returns the themes in the bundle which aren't installed.
+    ///
+    /// # Errors
+    /// - Fail to read the bundle
+    /// - Fail to save the results
+    fn import_bundle(&mut self) -> Result<Vec<String>> {
+        let bundle = ConfigBundle::import(Path::new(self.bundle_path.trim()))?;
+        bundle.results.save().context("saving results")?;
+
+        self.apply_config(bundle.config);
+        self.results = load_results();
+
+        if self
+            .theme
+            .as_ref()
+            .is_some_and(|t| !self.installed_themes.contains(t))
+        {
+            self.theme = None;
+        }
+        Ok(bundle
+            .themes
+            .into_iter()
+            .filter(|t| !self.installed_themes.contains(t))
+            .collect())
+    }
+
+    ///Shows the installed themes to choose from or remove, and a way to import new ones
+    fn show_themes(&mut self, ui: &mut egui::Ui) {
+        ui.radio_value(&mut self.theme, None, self.i18n.tr("Default"));
+
+        let mut to_remove = None;
+        for name in &self.installed_themes {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.theme, Some(name.clone()), name);
+                if ui.button(self.i18n.tr("Remove")).clicked() {
+                    to_remove = Some(name.clone());
+                }
+            });
+        }
+
+        if let Some(name) = to_remove {
+            self.theme_message = Some(match remove_theme(&name) {
+                Ok(()) => self.i18n.fill("Removed {}", &[&name]),
+                Err(e) => {
+                    warn!(%e, %name, "Unable to remove theme");
+                    self.i18n.fill("Unable to remove {}: {}", &[&name, &e])
+                }
+            });
+            if self.theme.as_ref() == Some(&name) {
+                self.theme = None;
+            }
+            self.installed_themes = load_themes();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(self.i18n.tr("Import from zip: "));
+            ui.text_edit_singleline(&mut self.theme_zip);
+            if ui.button(self.i18n.tr("Import")).clicked() {
+                self.theme_message = Some(match import_theme(Path::new(self.theme_zip.trim())) {
+                    Ok(name) => {
+                        self.theme_zip.clear();
+                        self.i18n.fill("Imported {}", &[&name])
+                    }
+                    Err(e) => {
+                        warn!(%e, "Unable to import theme");
+                        self.i18n
+                            .fill("Unable to import theme: {}", &[&format!("{e:#}")])
+                    }
+                });
+                self.installed_themes = load_themes();
+            }
+        });
+
+        if let Some(msg) = &self.theme_message {
+            ui.label(msg);
+        }
+    }
+}
+
+///Draws a miniature of a board, with each piece as a circle of its colour and the letter of its kind
+fn show_board_preview(ui: &mut egui::Ui, board: &Board<CanMovePiece>) {
+    let size = PREVIEW_SQUARE * 8.0;
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(size, size), Sense::hover());
+    let painter = ui.painter();
+
+    for x in 0..8_u8 {
+        for y in 0..8_u8 {
+            let min = rect.min + egui::vec2(f32::from(x), f32::from(y)) * PREVIEW_SQUARE;
+            let square = Rect::from_min_size(min, egui::vec2(PREVIEW_SQUARE, PREVIEW_SQUARE));
+            let fill = if (x + y) % 2 == 0 {
+                Color32::from_rgb(240, 217, 181)
+            } else {
+                Color32::from_rgb(181, 136, 99)
+            };
+            painter.rect_filled(square, 0.0, fill);
+
+            if let Some(p) = board[Coords::OnBoard(x, y)] {
+                let (fill, ink) = if p.is_white {
+                    (Color32::WHITE, Color32::BLACK)
+                } else {
+                    (Color32::BLACK, Color32::WHITE)
+                };
+                painter.circle(
+                    square.center(),
+                    PREVIEW_SQUARE * 0.45,
+                    fill,
+                    Stroke::new(1.0, ink),
+                );
+                painter.text(
+                    square.center(),
+                    Align2::CENTER_CENTER,
+                    p.kind.letter(),
+                    FontId::monospace(PREVIEW_SQUARE * 0.7),
+                    ink,
+                );
+            }
+        }
+    }
+}
+
+///Draws a QR code, black on white
+fn show_qr(ui: &mut egui::Ui, code: &QrCode) {
+    let width = code.width();
+    let modules = width + QR_QUIET_ZONE * 2;
+    #[allow(clippy::cast_precision_loss)]
+    let size = modules as f32 * QR_MODULE_SIZE;
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(size, size), Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, Color32::WHITE);
+
+    for (i, colour) in code.to_colors().into_iter().enumerate() {
+        if colour != Color::Dark {
+            continue;
+        }
+        let (x, y) = (i % width + QR_QUIET_ZONE, i / width + QR_QUIET_ZONE);
+        #[allow(clippy::cast_precision_loss)]
+        let min = rect.min + egui::vec2(x as f32, y as f32) * QR_MODULE_SIZE;
+        painter.rect_filled(
+            Rect::from_min_size(min, egui::vec2(QR_MODULE_SIZE, QR_MODULE_SIZE)),
+            0.0,
+            Color32::BLACK,
+        );
+    }
+}
+
+///Shows summary statistics and the most recent results from a [`ResultsStore`]
+fn show_stats(ui: &mut egui::Ui, results: &ResultsStore, i18n: &Translations) {
+    let summary = results.summary();
+    ui.label(i18n.fill(
+        "Wins: {}, Losses: {}, Draws: {}, Unfinished: {}",
+        &[
+            &summary.wins,
+            &summary.losses,
+            &summary.draws,
+            &summary.unfinished,
+        ],
+    ));
+    ui.label(i18n.fill(
+        "Average game length: {} moves, {} minutes",
+        &[
+            &format!("{:.1}", summary.average_moves),
+            &format!("{:.0}", summary.average_duration_secs / 60.0),
+        ],
+    ));
+
+    ui.separator();
+    ui.label(i18n.tr("Recent results:"));
+    egui::Grid::new("recent_results")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label(i18n.tr("Game ID"));
+            ui.label(i18n.tr("Colour"));
+            ui.label(i18n.tr("Result"));
+            ui.label(i18n.tr("Moves"));
+            ui.label(i18n.tr("Minutes"));
+            ui.end_row();
+
+            for r in results.recent(RECENT_RESULTS) {
+                ui.label(r.id.to_string());
+                ui.label(i18n.tr(match r.is_white {
+                    Some(true) => "White",
+                    Some(false) => "Black",
+                    None => "-",
+                }));
+                ui.label(i18n.tr(result_name(r.result)));
+                ui.label(r.moves.to_string());
+                ui.label((r.duration_secs / 60).to_string());
+                ui.end_row();
+            }
+        });
+}
+
+///Gets the English name of a [`GameResult`], to be translated
+const fn result_name(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Win => "Win",
+        GameResult::Loss => "Loss",
+        GameResult::Draw => "Draw",
+        GameResult::Unfinished => "Unfinished",
     }
 }
 
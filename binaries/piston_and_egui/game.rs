@@ -1,131 +1,2084 @@
 use crate::{
-    piston::{mp_valid, to_board_pixels},
-    pixel_size_consts::{BOARD_S, BOARD_TILE_S, LEFT_BOUND_PADDING, RIGHT_BOUND, TILE_S},
+    analysis_board::{AnalysisBoard, AnalysisStep},
+    board_cache::{load_board, publish_snapshot_metrics, BoardSaver},
+    cacher::Cacher,
+    dirty::{scissor_rect, visible_rect, DirtyTracker, Redraw, Scene, TileState, Tiles},
+    hints::{hints_used, set_hints_used, HintConfig, HintSource, HINT_DURATION},
+    identity::player_identity,
+    input_recording::{recorded_message, InputRecorder, RecordedInput},
+    ipc::IpcState,
+    move_history::{export_pgn, import_pgn, load_moves, save_moves},
+    move_log::{append_move_log, describe_entry, load_move_log, rebuild_history, MoveLogEvent},
+    pending::{clear_pending, load_pending, save_pending, PendingMove},
+    piston::{mp_valid, to_board_pixels, InputConfig, PistonConfig},
+    pixel_size_consts::{
+        BOARD_S, BOARD_TILE_S, LEFT_BOUND, LEFT_BOUND_PADDING, RIGHT_BOUND, TILE_S,
+    },
+    practice::{Practice, PracticeOpponent},
+    scorecard::{export_scorecard, render_scorecard},
+    scoresheet::export_scoresheet,
+    startup_profile::{startup_profiler, StartupPhase},
+    stats::{record_result, GameRecord, GameResult},
+    taken::TakenCache,
+    telemetry::{error_category, telemetry},
+    themes::theme_dir,
 };
 use anyhow::{Context as _, Result};
 use async_chess_client::{
-    chess::boards::{board::Board, board_container::BoardContainer},
+    chess::{
+        analysis::{find_blunder, white_share, Blunder},
+        attacks::AttackMap,
+        boards::{
+            board::{Board, BoardMoveState, CanMovePiece, FIFTY_MOVE_HALFMOVES},
+            board_container::BoardContainer,
+        },
+        engine::eval_cache,
+        history::{infer_moves, MoveGlyph, MoveHistory, MovePath},
+        opening_book::{BookMove, OpeningBook},
+        san::{to_san, to_san_promoting},
+        tablebase::{Tablebase, TablebaseOutcome, TablebaseProbe},
+        zobrist::zobrist_hash,
+    },
+    metrics::metrics,
     net::{
+        events::{game_events, GameEvent, GameEventKind},
+        game_import::{ExternalGame, GameImporter},
+        invite::InviteRole,
         list_refresher::{
-            BoardMessage, ListRefresher, MessageToGame, MessageToWorker, MoveOutcome,
+            BoardMessage, ConnectionState, DrawClaimOutcome, ListRefresher, MessageToGame,
+            MessageToWorker, MoveOutcome,
+        },
+        request_stats::RequestStats,
+        server_client::ClientConfig,
+        server_interface::{
+            JSONMove, JSONPieceList, JSONResult, PresenceState, ResponseDiagnostic,
         },
-        server_interface::{no_connection_list, JSONMove},
     },
-    prelude::{Coords, Either, ErrorExt},
-    util::{cacher::Cacher, error_ext::ToAnyhowErr},
+    prelude::{ChessPiece, ChessPieceKind, Coords, Either, ErrorExt},
+    util::{
+        error_ext::{ToAnyhowErr, ToAnyhowNotErr},
+        event_bus::Subscription,
+    },
+};
+use graphics::{character::CharacterCache, DrawState, Ellipse, ImageSize, Line, Rectangle, Text};
+use piston_window::{
+    clear, ellipse, math::Matrix2d, rectangle, rectangle::square, Context, G2d, Glyphs, Image,
+    PistonWindow, Transformed,
 };
-use graphics::{DrawState, ImageSize};
-use piston_window::{clear, rectangle::square, Context, G2d, Image, PistonWindow, Transformed};
-use std::sync::mpsc::TryRecvError;
-use std::time::Duration;
+use directories::ProjectDirs;
+use std::collections::BTreeSet;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use async_chess_client::prelude::DoOnInterval;
 use async_chess_client::util::time_based_structs::do_on_interval::UpdateOnCheck;
 use crate::pixel_size_consts::TOP_SPACE;
 
-///Struct to hold Game of Chess
-pub struct ChessGame {
-    ///The id of the game being played
-    id: u32,
-    ///The cacher of all the assets
-    cache: Cacher,
-    ///The Chess Board
-    board: BoardContainer,
-    ///The coordinates of the piece last pressed. Used for selected sprite location.
-    last_pressed: Coords,
-    ///The coordinates before - useful for rolling back invalid moves.
-    ex_last_pressed: Coords,
-    ///The refresher for making server requests
-    refresher: ListRefresher,
-    ///Whenever we get an update, display a message for an interval, timed by this
-    show_board_update: Option<DoOnInterval<UpdateOnCheck>>
-}
-impl ChessGame {
-    ///Create a new `ChessGame`f
+///How often to publish the memory metrics
+const MEMORY_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+///How long the board is refreshed quickly for after the window regains focus
+const FOCUS_BURST_LENGTH: Duration = Duration::from_secs(10);
+///How long the mouse has to stay over a square before a tooltip is shown
+const TOOLTIP_DELAY: Duration = Duration::from_millis(600);
+///How long a move from a delta takes to slide across the board
+const SLIDE_TIME: Duration = Duration::from_millis(250);
+///Colour over pieces which are threatened, when threats are shown
+const THREAT_COLOUR: [f32; 4] = [1.0, 0.0, 0.0, 0.35];
+///Colour over the board while it might be out of date
+const STALE_COLOUR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+///Colour of the dots on the squares the selected piece can move to
+const LEGAL_MOVE_COLOUR: [f32; 4] = [0.1, 0.1, 0.1, 0.35];
+///Colour of the controller's cursor
+const CURSOR_COLOUR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+///Colour of the frame around a board which is never sent to the server, so it can't be mistaken for the live one
+const OFFLINE_FRAME_COLOUR: [f32; 4] = [0.2, 0.5, 0.9, 1.0];
+///Colour behind the pieces in the promotion picker
+const PROMOTION_PICKER_COLOUR: [f32; 4] = [0.95, 0.95, 0.95, 0.9];
+///The pieces a pawn can become, in the order they are shown in the promotion picker
+const PROMOTION_KINDS: [ChessPieceKind; 4] = [
+    ChessPieceKind::Queen,
+    ChessPieceKind::Rook,
+    ChessPieceKind::Knight,
+    ChessPieceKind::Bishop,
+];
+///Colour behind the claim draw button
+const CLAIM_DRAW_COLOUR: [f32; 4] = [0.2, 0.5, 0.9, 1.0];
+///Width in pixels of the claim draw button
+const CLAIM_DRAW_W: f64 = 40.0;
+///Height in pixels of the claim draw button
+const CLAIM_DRAW_H: f64 = 10.0;
+///Where the claim draw button is, in pixels - above the right edge of the board
+const CLAIM_DRAW_RECT: [f64; 4] = [
+    RIGHT_BOUND - CLAIM_DRAW_W,
+    (TOP_SPACE - CLAIM_DRAW_H) / 2.0,
+    CLAIM_DRAW_W,
+    CLAIM_DRAW_H,
+];
+
+///Struct to hold Game of Chess
+pub struct ChessGame {
+    ///The id of the game being played
+    id: u32,
+    ///The cacher of all the assets
+    cache: Cacher,
+    ///The folder of the theme the sprites came from, if there is one
+    theme: Option<PathBuf>,
+    ///The Chess Board
+    board: BoardContainer,
+    ///The coordinates of the piece last pressed. Used for selected sprite location.
+    last_pressed: Coords,
+    ///The coordinates before - useful for rolling back invalid moves.
+    ex_last_pressed: Coords,
+    ///The refresher for making server requests - `None` when reviewing a PGN with `--review`, as there is no server to talk to
+    refresher: Option<ListRefresher>,
+    ///Whenever we get an update, display a message for an interval, timed by this
+    show_board_update: Option<DoOnInterval<UpdateOnCheck>>,
+    ///When this game was opened, for the results store
+    started: Instant,
+    ///Number of moves made from this client which the server accepted
+    moves_made: u32,
+    ///The colour of the first piece moved from this client, or the colour from the invite - used as the player's colour for the results store
+    player_is_white: Option<bool>,
+    ///What the player joined as from an invite, if they did - only pieces of that colour can be moved, and spectators can't move any
+    role: Option<InviteRole>,
+    ///Whether or not the opponent is around, if the server has told us
+    opponent_presence: Option<PresenceState>,
+    ///The most recent response from the server which failed validation, kept so it can be saved on request
+    last_diagnostic: Option<ResponseDiagnostic>,
+    ///A new status message to show the player, eg. why a move was rejected. An empty string clears the status.
+    status: Option<String>,
+    ///The last board received from the server, shown instead of the [`OfflineBoard`](async_chess_client::chess::boards::pattern::OfflineBoard) board when offline
+    last_known: Option<Board<CanMovePiece>>,
+    ///Saves each board from the server in turn, so the newest one is what is kept on disk
+    board_saver: BoardSaver,
+    ///Whether or not the board being shown is the last known board rather than a fresh one
+    is_stale: bool,
+    ///Why requests can't be made at all, if the client to make them with couldn't be built - shown in a banner until it is
+    unavailable: Option<String>,
+    ///Whether or not moves need to be confirmed before being sent
+    confirm_moves: bool,
+    ///A move waiting to be confirmed, if [`ChessGame::confirm_moves`] is on, or it is a capture and [`InputConfig::confirm_captures`] is on
+    pending_move: Option<JSONMove>,
+    ///A move which promotes a pawn, waiting for what it becomes to be picked from the promotion picker
+    promotion: Option<JSONMove>,
+    ///Misclick protection settings
+    input: InputConfig,
+    ///Where the mouse was pressed to select a piece, to check whether the release is a drag
+    drag_start: Option<(f64, f64)>,
+    ///The square picked out by a controller's cursor, once one has been used
+    cursor: Option<(u8, u8)>,
+    ///Whether or not to mark the player's pieces which are attacked and undefended
+    show_threats: bool,
+    ///The square the mouse is over, and when it got there - used to show a tooltip after hovering for a bit
+    hover: Option<((u32, u32), Instant)>,
+    ///If the evaluation bar is shown, how much of it is currently white and when that was last animated
+    eval_bar: Option<(f64, Instant)>,
+    ///Number of times the board has changed since the game was opened, used for the move number
+    board_changes: u32,
+    ///When a new board was last received from the server
+    last_refresh: Option<Instant>,
+    ///When the server says the last move was made, in milliseconds since the unix epoch by its clock
+    moved_at: Option<u64>,
+    ///The result of the game, once the server says it is over or the board shows checkmate or a draw
+    result: Option<JSONResult>,
+    ///Whether this game has been saved to the results store yet - see [`ChessGame::unrecorded_game`]
+    result_recorded: bool,
+    ///How much time white and black have left, if the server keeps clocks
+    server_clocks: Option<(Duration, Duration)>,
+    ///The moves from the last delta, as where each piece started and finished, which slide across the board from when they were applied
+    slides: Option<(Vec<((u8, u8), (u8, u8))>, Instant)>,
+    ///Whether or not anything has changed which needs the window to be redrawn
+    redraw: bool,
+    ///Which squares were drawn on the last frames, so only the ones which changed are redrawn
+    dirty: DirtyTracker,
+    ///The taken pieces as drawn beside the board, worked out again only when they change
+    taken: TakenCache,
+    ///Timer for publishing the memory metrics
+    memory_metrics_timer: DoOnInterval,
+    ///Counts, error rates and latencies of the requests made to the server, for the stats panel
+    request_stats: RequestStats,
+    ///Which engine suggests hints, and how many can be used
+    hints: HintConfig,
+    ///Number of hints used in this game, including in previous runs
+    hints_used: u32,
+    ///Receiver for the suggested move, if the engine is still thinking
+    hint_rx: Option<Receiver<Result<Option<((Coords, Coords), HintSource)>>>>,
+    ///The move being suggested as `(from, to)`, and when it was first shown
+    hint: Option<((Coords, Coords), Instant)>,
+    ///The opening book from [`HintConfig::book`], if one is set and could be loaded
+    book: Option<OpeningBook>,
+    ///The endgame tablebase from [`HintConfig::tablebase`], if one is set and could be opened
+    tablebase: Option<Arc<Tablebase>>,
+    ///The exact result of the current position from the tablebase, if it is in there - shown on the evaluation bar
+    tablebase_outcome: Option<TablebaseOutcome>,
+    ///An offline board forked from the live one, if the player is practising - it is shown and moved instead of the live board
+    practice: Option<Practice>,
+    ///A board for exploring variations, if the player has opened one - it is shown and moved instead of the live or practice board
+    analysis: Option<AnalysisBoard>,
+    ///The moves seen in this game, with the player's annotations - `None` until a board has been received, or one was saved last time
+    history: Option<MoveHistory>,
+    ///How to identify the client, used to import games from other sites
+    client: ClientConfig,
+    ///Receiver for a game being imported from another site, if it hasn't arrived yet
+    import_rx: Option<Receiver<Result<MoveHistory>>>,
+    ///The [`GameEvent`]s the game reacts to, from the [`game_events`] bus
+    events: Subscription<GameEvent>,
+    ///Records the input to the game, if it is being recorded
+    input_recorder: Option<InputRecorder>,
+}
+impl ChessGame {
+    ///Create a new `ChessGame`f
+    ///
+    /// # Errors
+    /// - Can fail if the cacher incorrectly populates
+    pub fn new(win: &mut PistonWindow, pc: &PistonConfig) -> Result<Self> {
+        let window_scale = f64::from(pc.res) / BOARD_S;
+        let mut game = Self::with_cache(pc, |theme| {
+            let _timer = startup_profiler().time(StartupPhase::CacherInit);
+            Cacher::new(win, &pc.textures.settings(window_scale), theme)
+        })?;
+        if pc.record_input {
+            game.start_input_recording(window_scale);
+        }
+        if let Some(path) = &pc.review {
+            info!(?path, "Reviewing PGN");
+            game.open_pgn(path);
+        }
+        Ok(game)
+    }
+
+    ///Create a new `ChessGame` without a window, which is never drawn - eg. to replay a recording of the input to a game
+    ///
+    /// # Errors
+    /// Never fails - it returns a [`Result`] to match [`ChessGame::new`]
+    pub fn headless(pc: &PistonConfig) -> Result<Self> {
+        Self::with_cache(pc, |_| Ok(Cacher::empty()))
+    }
+
+    ///Create a new `ChessGame`, with the [`Cacher`] made from the theme folder if there is one
+    ///
+    /// # Errors
+    /// - Fail to make the cacher
+    fn with_cache(
+        pc: &PistonConfig,
+        make_cache: impl FnOnce(Option<&Path>) -> Result<Cacher>,
+    ) -> Result<Self> {
+        let id = pc.id;
+        //before the refresher starts, so nothing it publishes is missed
+        let events = game_events().subscribe(&[GameEventKind::ConnectionChanged]);
+        //nothing from the games the player is really playing is picked up by a played back session - see `ChessGame::persists`
+        let persists = pc.client.playback.is_none();
+
+        //only sent in heartbeats, which a played back session answers whoever sends them
+        let player = if persists {
+            player_identity().unwrap_or_else(|e| {
+                warn!(%e, "Unable to get player identity, using an anonymous one");
+                "anonymous".into()
+            })
+        } else {
+            "anonymous".into()
+        };
+
+        let theme = pc.theme.as_deref().and_then(|name| match theme_dir(name) {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                warn!(%e, "Unable to find theme, using default sprites");
+                None
+            }
+        });
+
+        let last_known = match persists
+            .then(|| load_board(id))
+            .transpose()
+            .map(Option::flatten)
+            .and_then(|l| l.map(Board::new_json).transpose())
+        {
+            Ok(b) => b,
+            Err(e) => {
+                warn!(%e, "Unable to load last known board");
+                None
+            }
+        };
+        let board = last_known
+            .clone()
+            .map_or_else(BoardContainer::default, Either::Left);
+
+        let recovered = persists
+            .then(|| load_pending(id))
+            .transpose()
+            .unwrap_or_else(|e| {
+                warn!(%e, "Unable to load pending move");
+                None
+            })
+            .flatten();
+        let status = match recovered {
+            Some(PendingMove { m, sent }) => {
+                info!(?m, %sent, "Recovered pending move from last time");
+                Some(format!(
+                    "Recovered {} move ({}, {}) to ({}, {}) - Enter to resubmit, right click to discard",
+                    if sent { "unacknowledged" } else { "unconfirmed" },
+                    m.x,
+                    m.y,
+                    m.nx,
+                    m.ny
+                ))
+            }
+            None => last_known
+                .as_ref()
+                .map(|_| "Showing last known board".to_string()),
+        };
+
+        let game = Self {
+            id,
+            cache: make_cache(theme.as_deref()).context("making cacher")?,
+            theme,
+            is_stale: last_known.is_some(),
+            unavailable: None,
+            board,
+            last_known,
+            board_saver: BoardSaver::new(),
+            refresher: pc
+                .review
+                .is_none()
+                .then(|| ListRefresher::with_config(id, player, pc.rate_limit, pc.client.clone())),
+            last_pressed: Coords::OffBoard,
+            ex_last_pressed: Coords::OffBoard,
+            show_board_update: None,
+            started: Instant::now(),
+            moves_made: 0,
+            player_is_white: pc.role.and_then(InviteRole::is_white),
+            role: pc.role,
+            opponent_presence: None,
+            last_diagnostic: None,
+            status,
+            confirm_moves: pc.confirm_moves,
+            pending_move: recovered.map(|p| p.m),
+            promotion: None,
+            input: pc.input,
+            drag_start: None,
+            cursor: None,
+            show_threats: false,
+            hover: None,
+            eval_bar: None,
+            board_changes: 0,
+            last_refresh: None,
+            moved_at: None,
+            server_clocks: None,
+            slides: None,
+            redraw: true,
+            dirty: DirtyTracker::new(pc.frames.dirty_regions),
+            taken: TakenCache::default(),
+            memory_metrics_timer: DoOnInterval::new(MEMORY_METRICS_INTERVAL),
+            request_stats: RequestStats::default(),
+            hints: pc.hints.clone(),
+            hints_used: persists
+                .then(|| hints_used(id))
+                .transpose()
+                .unwrap_or_else(|e| {
+                    warn!(%e, "Unable to load number of hints used");
+                    None
+                })
+                .unwrap_or_default(),
+            hint_rx: None,
+            hint: None,
+            book: pc.hints.load_book().unwrap_or_else(|e| {
+                warn!(%e, "Unable to load opening book");
+                None
+            }),
+            tablebase: pc
+                .hints
+                .load_tablebase()
+                .unwrap_or_else(|e| {
+                    warn!(%e, "Unable to open tablebase");
+                    None
+                })
+                .map(Arc::new),
+            tablebase_outcome: None,
+            practice: None,
+            analysis: None,
+            history: persists.then(|| load_history(id)).flatten(),
+            client: pc.client.clone(),
+            import_rx: None,
+            events,
+            result: None,
+            result_recorded: false,
+            input_recorder: None,
+        };
+        //joining a game in progress, so only the current position will be known
+        if game.history.is_none() {
+            game.fetch_history();
+        }
+        Ok(game)
+    }
+
+    ///Handles mouse input
+    ///
+    /// If [`ChessGame::confirm_moves`] is on (or [`InputConfig::confirm_captures`] is on and the move takes a piece), the second press makes the move pending, rather than sending it. Pressing again while a move is pending cancels it.
+    ///
+    /// If the second press moves a pawn to the far side, the promotion picker is shown - see [`ChessGame::promotion_input`].
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    #[tracing::instrument(skip(self))]
+    pub fn mouse_input(&mut self, mouse_pos: (f64, f64), mult: f64) -> Result<()> {
+        if let Some(m) = self.promotion.take() {
+            return self.promotion_input(m, mouse_pos, mult);
+        }
+        if self.pending_move.is_some() {
+            self.cancel_move();
+        }
+        if self.analysis.is_some() {
+            return self.analysis_input(mouse_pos, mult);
+        }
+        if self.practice.is_some() {
+            return self.practice_input(mouse_pos, mult);
+        }
+        if let Some(result) = self.result {
+            self.status = Some(format!(
+                "Game over - {result} - S to save a scorecard, or C to restart"
+            ));
+            return Ok(());
+        }
+
+        match std::mem::take(&mut self.last_pressed) {
+            Coords::OffBoard => {
+                let lp_x = to_board_coord(mouse_pos.0, mult);
+                let lp_y = to_board_coord(mouse_pos.1, mult);
+
+                let coord = (lp_x, lp_y).try_into()?;
+
+                if self.role == Some(InviteRole::Spectator) {
+                    self.status = Some("Spectating - moves are disabled".into());
+                    return Ok(());
+                }
+                let is_own = match (self.role.and_then(InviteRole::is_white), self.board[coord]) {
+                    (Some(is_white), Some(p)) => p.is_white == is_white,
+                    _ => true,
+                };
+
+                if is_own && self.board.piece_exists_at_location(coord) {
+                    self.last_pressed = coord;
+                    self.drag_start = Some(mouse_pos);
+                }
+            }
+            Coords::OnBoard(x, y) => {
+                self.drag_start = None;
+
+                //Deal with second press
+                let current_press = {
+                    let lp_x = to_board_coord(mouse_pos.0, mult);
+                    let lp_y = to_board_coord(mouse_pos.1, mult);
+                    (lp_x, lp_y)
+                };
+
+                let m = JSONMove::new(
+                    self.id,
+                    u32::from(x),
+                    u32::from(y),
+                    current_press.0,
+                    current_press.1,
+                );
+                self.ex_last_pressed = Coords::OnBoard(x, y);
+
+                //a server which doesn't take promotions always queens, so there is nothing to pick
+                let can_pick = !self
+                    .refresher
+                    .as_ref()
+                    .and_then(ListRefresher::capabilities)
+                    .is_some_and(|c| !c.promotion);
+                //offered while other moves are waiting on the server too, as they are already on the board
+                if can_pick && self.board.is_promotion(m.current_coords(), m.new_coords()) {
+                    info!(?m, "Waiting for promotion choice");
+                    self.status =
+                        Some("Pick what the pawn becomes, or click elsewhere to cancel".into());
+                    self.promotion = Some(m);
+                    return Ok(());
+                }
+
+                self.submit_move(m)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    ///Handles a press while the promotion picker is open - pressing one of the pieces in it makes the move with the pawn becoming that piece, and pressing anywhere else cancels the move
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    fn promotion_input(&mut self, m: JSONMove, mouse_pos: (f64, f64), mult: f64) -> Result<()> {
+        self.status = Some(String::new());
+
+        let pressed = (
+            to_board_coord(mouse_pos.0, mult),
+            to_board_coord(mouse_pos.1, mult),
+        );
+        match promotion_options(&m).find(|(_, at)| *at == pressed) {
+            Some((kind, _)) => {
+                if kind != ChessPieceKind::Queen {
+                    telemetry().feature("under_promotion");
+                }
+                self.submit_move(m.with_promotion(kind))
+            }
+            None => {
+                info!(?m, "Cancelled promotion");
+                Ok(())
+            }
+        }
+    }
+
+    ///Sends a move which has been picked on the board - or holds it to be confirmed, if it looks like a blunder, or [`ChessGame::confirm_moves`] is on (or [`InputConfig::confirm_captures`] is on and the move takes a piece)
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    fn submit_move(&mut self, m: JSONMove) -> Result<()> {
+        let is_capture = matches!(
+            (self.board[m.current_coords()], self.board[m.new_coords()]),
+            (Some(moving), Some(taken)) if moving.is_white != taken.is_white
+        );
+
+        let blunder = self.input.blunder_threshold.and_then(|threshold| {
+            let m = (m.current_coords(), m.new_coords());
+            match &self.board {
+                Either::Left(b) => find_blunder(b, m, threshold),
+                Either::Right(b) => find_blunder(b, m, threshold),
+            }
+        });
+
+        if let Some(blunder) = blunder {
+            info!(?m, ?blunder, "Waiting for blunder confirmation");
+            telemetry().feature("blunder_warning");
+            let mv = (m.current_coords(), m.new_coords());
+            self.status = Some(match &self.board {
+                Either::Left(b) => describe_blunder(blunder, &b.with_move(mv.0, mv.1)),
+                Either::Right(b) => describe_blunder(blunder, &b.with_move(mv.0, mv.1)),
+            });
+            self.wait_for_confirmation(m);
+        } else if self.confirm_moves || (is_capture && self.input.confirm_captures) {
+            info!(?m, %is_capture, "Waiting for move confirmation");
+            self.status = Some(format!(
+                "{} {}? Enter to confirm, right click to cancel",
+                if is_capture { "Capture" } else { "Move" },
+                self.move_name(m)
+            ));
+            self.wait_for_confirmation(m);
+        } else {
+            self.send_move(m)?;
+        }
+        Ok(())
+    }
+
+    ///Handles mouse input while practising - like [`ChessGame::mouse_input`], but either colour can be picked up, and moves are made on the [`Practice`] board rather than sent
+    ///
+    /// # Errors
+    /// - Can fail if the mouse isn't over the board
+    fn practice_input(&mut self, mouse_pos: (f64, f64), mult: f64) -> Result<()> {
+        let Some(practice) = &mut self.practice else {
+            return Ok(());
+        };
+        let coord: Coords = (
+            to_board_coord(mouse_pos.0, mult),
+            to_board_coord(mouse_pos.1, mult),
+        )
+            .try_into()?;
+
+        match std::mem::take(&mut self.last_pressed) {
+            Coords::OffBoard => {
+                if practice.board().piece_exists_at_location(coord) {
+                    self.last_pressed = coord;
+                    self.drag_start = Some(mouse_pos);
+                }
+            }
+            from @ Coords::OnBoard(..) => {
+                self.drag_start = None;
+                match practice.make_move(from, coord) {
+                    Ok(()) => {
+                        telemetry().feature("practice_move");
+                        self.status = Some(practice_status(practice));
+                    }
+                    Err(e) => self.status = Some(e.to_string()),
+                }
+                self.ex_last_pressed = from;
+            }
+        }
+
+        Ok(())
+    }
+
+    ///Handles mouse input on the [`AnalysisBoard`] - either colour can be moved at any time, and nothing is sent
+    ///
+    /// # Errors
+    /// - Can fail if the mouse isn't over the board
+    fn analysis_input(&mut self, mouse_pos: (f64, f64), mult: f64) -> Result<()> {
+        let Some(analysis) = &mut self.analysis else {
+            return Ok(());
+        };
+        let coord: Coords = (
+            to_board_coord(mouse_pos.0, mult),
+            to_board_coord(mouse_pos.1, mult),
+        )
+            .try_into()?;
+
+        match std::mem::take(&mut self.last_pressed) {
+            Coords::OffBoard => {
+                if analysis.board().piece_exists_at_location(coord) {
+                    self.last_pressed = coord;
+                    self.drag_start = Some(mouse_pos);
+                }
+            }
+            from @ Coords::OnBoard(..) => {
+                self.drag_start = None;
+                self.status = Some(match analysis.make_move(from, coord) {
+                    Ok(()) => analysis.describe(),
+                    Err(e) => e.to_string(),
+                });
+                self.ex_last_pressed = from;
+            }
+        }
+
+        Ok(())
+    }
+
+    ///Holds a move until it is confirmed with [`ChessGame::confirm_move`], saving it in case of a crash
+    fn wait_for_confirmation(&mut self, m: JSONMove) {
+        self.pending_move = Some(m);
+        if self.persists() {
+            save_pending(self.id, PendingMove { m, sent: false })
+                .context("saving unconfirmed move")
+                .warn();
+        }
+    }
+
+    ///Handles the mouse being released.
+    ///
+    /// If a piece was selected by the matching press and the mouse has moved at least [`InputConfig::min_drag_distance`] onto a different square, it is treated as a drag and the release acts like a second press. Otherwise it is a click, and the piece stays selected.
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    #[tracing::instrument(skip(self))]
+    pub fn mouse_release(&mut self, mouse_pos: (f64, f64), mult: f64) -> Result<()> {
+        let Some(start) = std::mem::take(&mut self.drag_start) else {
+            return Ok(());
+        };
+
+        let distance = (mouse_pos.0 - start.0).hypot(mouse_pos.1 - start.1);
+        if distance < self.input.min_drag_distance * mult {
+            return Ok(());
+        }
+
+        let release_square = (
+            to_board_coord(mouse_pos.0, mult),
+            to_board_coord(mouse_pos.1, mult),
+        );
+        let start_square = (to_board_coord(start.0, mult), to_board_coord(start.1, mult));
+        if release_square == start_square {
+            return Ok(());
+        }
+
+        info!(?start, ?mouse_pos, %distance, "Treating release as a drag");
+        self.mouse_input(mouse_pos, mult)
+    }
+
+    ///Gets the piece which has been picked up, if there is one. While there is, the piece floats at the cursor and the OS cursor is hidden.
+    #[must_use]
+    pub fn held_piece(&self) -> Option<ChessPiece> {
+        if self.last_pressed.is_on_board() {
+            self.shown_board()[self.last_pressed]
+        } else {
+            None
+        }
+    }
+
+    ///Moves the controller's cursor by a number of squares, staying on the board. The cursor starts on the selected piece if there is one, or in front of the king's pawn.
+    pub fn move_cursor(&mut self, (dx, dy): (i8, i8)) {
+        let (x, y) = self.cursor.unwrap_or_else(|| {
+            self.last_pressed.to_option().unwrap_or_else(|| {
+                if self.player_is_white == Some(false) {
+                    (4, 2)
+                } else {
+                    (4, 5)
+                }
+            })
+        });
+        let step = |v: u8, by: i8| v.saturating_add_signed(by).min(7);
+        self.cursor = Some((step(x, dx), step(y, dy)));
+        self.redraw = true;
+    }
+
+    ///Acts like a click on the square under the controller's cursor - see [`ChessGame::mouse_input`]
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    pub fn cursor_select(&mut self, mult: f64) -> Result<()> {
+        let Some((x, y)) = self.cursor else {
+            self.move_cursor((0, 0));
+            return Ok(());
+        };
+        let board_pos = square_centre((x, y), mult);
+
+        self.mouse_input(board_pos, mult)?;
+        self.mouse_release(board_pos, mult)
+    }
+
+    ///Gets the board and the colour to move for the [`crate::move_entry::MoveEntry`] - `None` if either colour can move, like on the analysis board, or it isn't known yet
+    #[must_use]
+    pub fn move_entry_position(&self) -> (&BoardContainer, Option<bool>) {
+        let white = if self.analysis.is_some() {
+            None
+        } else if let Some(practice) = &self.practice {
+            Some(practice.white_to_move())
+        } else {
+            self.history
+                .as_ref()
+                .filter(|h| !h.moves().is_empty())
+                .map(MoveHistory::white_to_move)
+                .or(self.player_is_white)
+        };
+        (self.shown_board(), white)
+    }
+
+    ///Makes a move typed into the [`crate::move_entry::MoveEntry`] as if its two squares had been clicked, so it is checked and confirmed like any other move
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    #[tracing::instrument(skip(self))]
+    pub fn typed_move(&mut self, (from, to): (Coords, Coords), mult: f64) -> Result<()> {
+        let (Some(from), Some(to)) = (from.to_option(), to.to_option()) else {
+            bail!("typed move is off the board");
+        };
+
+        self.clear_mouse_input();
+        self.mouse_input(square_centre(from, mult), mult)?;
+        if self.last_pressed.is_on_board() {
+            self.mouse_input(square_centre(to, mult), mult)?;
+        }
+        self.redraw = true;
+        Ok(())
+    }
+
+    ///Shows what the piece at a position can do in the status - how many moves it has, and how many pieces attack and defend it. Used for long presses on touch screens.
+    #[tracing::instrument(skip(self))]
+    pub fn piece_info(&mut self, mouse_pos: (f64, f64), mult: f64) {
+        let Ok(coord) = Coords::try_from((
+            to_board_coord(mouse_pos.0, mult),
+            to_board_coord(mouse_pos.1, mult),
+        )) else {
+            return;
+        };
+        let square = coord.to_algebraic().unwrap_or_default();
+
+        let board = self.shown_board();
+        self.status = Some(match board[coord] {
+            Some(piece) => {
+                let (moves, attackers, defenders) = match board {
+                    Either::Left(b) => piece_summary(b, coord, piece.is_white),
+                    Either::Right(b) => piece_summary(b, coord, piece.is_white),
+                };
+                format!("{piece} on {square} - {moves} moves, attacked by {attackers}, defended by {defenders}")
+            }
+            None => format!("Nothing on {square}"),
+        });
+        self.redraw = true;
+    }
+
+    ///Toggles showing the evaluation bar beside the board
+    pub fn toggle_eval_bar(&mut self) {
+        telemetry().feature("eval_bar");
+        self.eval_bar = match self.eval_bar {
+            Some(_) => None,
+            None => Some((0.5, Instant::now())),
+        };
+        if self.eval_bar.is_some() {
+            self.probe_tablebase();
+        }
+    }
+
+    ///Looks the current board up in the endgame tablebase, for the evaluation bar, and puts the result in the status when it changes.
+    ///
+    /// Like hints, the position is looked up with the player to move, or white until their colour is known.
+    fn probe_tablebase(&mut self) {
+        let Some(tablebase) = &self.tablebase else {
+            return;
+        };
+        let white_to_move = self.player_is_white.unwrap_or(true);
+        let probed = match &self.board {
+            Either::Left(b) => tablebase.probe(b, white_to_move),
+            Either::Right(b) => tablebase.probe(b, white_to_move),
+        };
+        let outcome = probed
+            .unwrap_or_else(|e| {
+                warn!(%e, "Unable to probe tablebase");
+                None
+            })
+            .map(|p| p.outcome);
+
+        if outcome != self.tablebase_outcome {
+            if let Some(outcome) = outcome {
+                info!(%outcome, "Position is in the tablebase");
+                self.status = Some(format!("Tablebase: {outcome}"));
+            }
+            self.tablebase_outcome = outcome;
+        }
+    }
+
+    ///Toggles marking the player's pieces which are attacked and undefended.
+    ///
+    /// The player's colour is only known once they have moved a piece, so until then nothing is marked.
+    pub fn toggle_threats(&mut self) {
+        telemetry().feature("threats");
+        self.show_threats = !self.show_threats;
+        if self.show_threats && self.player_is_white.is_none() {
+            self.status = Some("Threats will be shown once you have moved a piece".into());
+        }
+    }
+
+    ///Asks the [`HintConfig::engine`] for a move for the player on a new thread - it is shown as an arrow once [`ChessGame::update_list`] picks it up. While the position is in the opening book, the book move is suggested instead.
+    ///
+    /// Hints count towards [`HintConfig::max_per_game`] once they are shown. Like threats, the player's colour is only known once they have moved a piece. While practising, the hint is for whichever colour moves next on the practice board, and on the analysis board it is for the player's colour.
+    #[tracing::instrument(skip(self))]
+    pub fn request_hint(&mut self) {
+        if !self.hints.allows(self.hints_used) {
+            self.status = Some(if self.hints.max_per_game == Some(0) {
+                "Hints are turned off".into()
+            } else {
+                format!("No hints left - {} used in this game", self.hints_used)
+            });
+            return;
+        }
+        if self.hint_rx.is_some() {
+            return;
+        }
+        let (board, is_white) = if let Some(analysis) = &self.analysis {
+            //either colour can move on the analysis board, so the hint is for the player
+            (
+                analysis.board().clone(),
+                self.player_is_white.unwrap_or(true),
+            )
+        } else if let Some(practice) = &self.practice {
+            (practice.board().clone(), practice.white_to_move())
+        } else {
+            let Some(is_white) = self.player_is_white else {
+                self.status = Some("Hints will be available once you have moved a piece".into());
+                return;
+            };
+            (self.board.clone(), is_white)
+        };
+        let Either::Left(board) = board else {
+            self.status = Some("Wait for the server to accept the last move".into());
+            return;
+        };
+
+        telemetry().feature("hint");
+        let (tx, rx) = channel();
+        let book_move = self
+            .book
+            .as_ref()
+            .and_then(|book| book.best_move(&board, is_white));
+        if let Some(m) = book_move {
+            //looking up the book is quick, so there's no need for a thread
+            let _ = tx.send(Ok(Some((m, HintSource::Book))));
+        } else {
+            let engine = self.hints.engine.clone();
+            let tablebase = self.tablebase.clone();
+            std::thread::spawn(move || {
+                let hint = match tablebase.map(|tb| tb.probe(&board, is_white)) {
+                    Some(Ok(Some(TablebaseProbe { best: Some(m), .. }))) => {
+                        Ok(Some((m, HintSource::Tablebase)))
+                    }
+                    probed => {
+                        if let Some(Err(e)) = probed {
+                            warn!(%e, "Unable to probe tablebase");
+                        }
+                        engine
+                            .suggest(&board, is_white)
+                            .map(|m| m.map(|m| (m, HintSource::Engine)))
+                    }
+                };
+                //if the game has closed, nobody needs to know
+                let _ = tx.send(hint);
+            });
+            self.status = Some("Thinking of a hint...".into());
+        }
+        self.hint_rx = Some(rx);
+    }
+
+    ///Picks up the suggested move if the engine has finished, and stops showing it after [`HINT_DURATION`]
+    fn poll_hint(&mut self) {
+        if let Some(received) = self.hint_rx.as_ref().map(Receiver::try_recv) {
+            match received {
+                Ok(Ok(Some(((from, to), source)))) => {
+                    self.hints_used += 1;
+                    if self.persists() {
+                        set_hints_used(self.id, self.hints_used)
+                            .context("saving hints used")
+                            .warn();
+                    }
+                    info!(?from, ?to, hints_used=%self.hints_used, "Showing hint");
+
+                    let name = container_san(self.shown_board(), (from, to), ChessPieceKind::Queen)
+                        .unwrap_or_else(|_| {
+                            format!(
+                                "{} to {}",
+                                from.to_algebraic().unwrap_or_default(),
+                                to.to_algebraic().unwrap_or_default()
+                            )
+                        });
+                    self.status = Some(format!(
+                        "Hint {}{}: {name}",
+                        self.hints_used,
+                        source.label()
+                    ));
+                    self.hint = Some(((from, to), Instant::now()));
+                }
+                Ok(Ok(None)) => self.status = Some("No moves to suggest".into()),
+                Ok(Err(e)) => {
+                    warn!(%e, "Unable to get hint");
+                    telemetry().error("hint");
+                    self.status = Some("Unable to get a hint - check the logs".into());
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {}
+            }
+            self.hint_rx = None;
+            self.redraw = true;
+        }
+
+        if matches!(self.hint, Some((_, shown)) if shown.elapsed() >= HINT_DURATION) {
+            self.hint = None;
+            self.redraw = true;
+        }
+    }
+
+    ///Sends the pending move to the server, if there is one
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    #[tracing::instrument(skip(self))]
+    pub fn confirm_move(&mut self) -> Result<()> {
+        if let Some(m) = std::mem::take(&mut self.pending_move) {
+            telemetry().feature("confirm_move");
+            self.status = Some(String::new());
+            self.send_move(m)?;
+        }
+        Ok(())
+    }
+
+    ///Cancels the pending move, if there is one
+    pub fn cancel_move(&mut self) {
+        if std::mem::take(&mut self.pending_move).is_some() {
+            info!("Cancelled pending move");
+            self.status = Some(String::new());
+            if self.persists() {
+                clear_pending(self.id)
+                    .context("clearing cancelled move")
+                    .warn();
+            }
+        }
+    }
+
+    ///Sends a move to the [`ListRefresher`], saving it first so it can be resubmitted if the client crashes before the server responds
+    ///
+    /// # Errors
+    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
+    fn send_move(&mut self, m: JSONMove) -> Result<()> {
+        info!(?m, san = %self.move_name(m), "Starting moving");
+        if self.persists() {
+            save_pending(self.id, PendingMove { m, sent: true })
+                .context("saving sent move")
+                .warn();
+        }
+
+        if self.player_is_white.is_none() {
+            self.player_is_white = self.board[m.current_coords()].map(|p| p.is_white);
+        }
+        self.log_move(MoveLogEvent::Sent { m, fen: self.fen() });
+
+        self.send_to_worker(MessageToWorker::MakeMove(m))
+            .context("sending a message to the worker re moving")
+    }
+
+    ///Names a move on the board in Standard Algebraic Notation, eg. `Nxf3+`, or by its coordinates if it can't be written that way
+    fn move_name(&self, m: JSONMove) -> String {
+        let mv = (m.current_coords(), m.new_coords());
+        container_san(&self.board, mv, m.promotion_kind())
+            .unwrap_or_else(|_| format!("({}, {}) to ({}, {})", m.x, m.y, m.nx, m.ny))
+    }
+
+    ///Gets whether a board has arrived from the server yet
+    #[must_use]
+    pub const fn has_fetched(&self) -> bool {
+        self.last_refresh.is_some()
+    }
+
+    ///Gets the board in Forsyth-Edwards Notation - see [`Board::to_fen`]
+    #[must_use]
+    pub fn fen(&self) -> String {
+        self.board.to_fen()
+    }
+
+    ///Gets what the [`crate::ipc::IpcServer`] tells other programs about the game
+    #[must_use]
+    pub fn ipc_state(&self) -> IpcState {
+        IpcState {
+            id: self.id,
+            fen: self.fen(),
+            white_to_move: self.move_entry_position().1,
+            online: !self.is_stale,
+            server: self.client.server.clone(),
+        }
+    }
+
+    ///Tells the [`ListRefresher`] to act as though the server can't be reached, or to stop doing so
+    ///
+    /// # Errors
+    /// - If there is an error sending the message
+    pub fn simulate_offline(&self, offline: bool) -> Result<()> {
+        self.send_to_worker(MessageToWorker::SimulateOffline(offline))
+            .context("sending simulate offline msg")
+    }
+
+    ///Refreshes the board straight away when the window regains focus, then keeps refreshing quickly for a little while - coming back to the window is usually to see whether the opponent has moved
+    ///
+    /// # Errors
+    /// - If there is an error sending the messages
+    #[tracing::instrument(skip(self))]
+    pub fn focus_regained(&mut self) -> Result<()> {
+        self.send_to_worker(MessageToWorker::PollBurst(FOCUS_BURST_LENGTH))
+            .context("sending poll burst msg")?;
+        self.update_list(true)
+    }
+
+    ///Asks the [`ListRefresher`] for the whole history of the game from the server, to fill in the [`MoveHistory`] when it can't be worked out from the boards
+    fn fetch_history(&self) {
+        self.send_to_worker(MessageToWorker::FetchHistory)
+            .context("sending fetch history msg")
+            .warn();
+    }
+
+    ///Sends a message to the [`ListRefresher`] - when reviewing a PGN there isn't one, so nothing is sent
+    fn send_to_worker(&self, m: MessageToWorker) -> Result<(), TrySendError<MessageToWorker>> {
+        self.refresher.as_ref().map_or(Ok(()), |r| r.send_msg(m))
+    }
+
+    ///Gets whether a PGN is being reviewed with `--review`, rather than a game being played on the server
+    #[must_use]
+    pub const fn is_reviewing(&self) -> bool {
+        self.refresher.is_none()
+    }
+
+    ///Checks the board against the checksum the server sent with it, and fetches the whole board again if they differ - eg. if an update was missed
+    fn verify_checksum(&mut self, expected: u64) {
+        //the board is waiting on a move, so will differ from the server's until the move is done
+        let Either::Left(board) = &self.board else {
+            return;
+        };
+        if self.is_stale {
+            return;
+        }
+
+        let actual = zobrist_hash(board, true);
+        if actual == expected {
+            return;
+        }
+
+        warn!(
+            expected = %format_args!("{expected:016x}"),
+            actual = %format_args!("{actual:016x}"),
+            "Board out of sync with the server"
+        );
+        telemetry().error("board_desync");
+        self.status = Some("Board out of sync with the server - fetching it again".into());
+        self.send_to_worker(MessageToWorker::Resync)
+            .context("sending resync msg")
+            .warn();
+    }
+
+    ///Uses a new board from the server - saves it, and works out the moves made to reach it
+    fn use_new_board(&mut self, mut board: Board<CanMovePiece>, to_save: JSONPieceList) {
+        if self.persists() {
+            self.board_saver.save(self.id, to_save);
+        }
+
+        let changed = self.last_known.as_ref() != Some(&board);
+        let mut white_to_move = None;
+        if changed {
+            self.board_changes += 1;
+            let moves = self.record_moves(&board);
+            white_to_move = self
+                .history
+                .as_ref()
+                .filter(|h| !h.moves().is_empty())
+                .map(MoveHistory::white_to_move);
+            //the server only sends where the pieces are, so castling rights lost, positions reached and the half-move clock have to be carried over
+            match (moves, &self.last_known) {
+                (Some(moves), Some(before)) => {
+                    board.keep_castling_from(before);
+                    board.keep_positions_from(before, &moves, white_to_move.unwrap_or(true));
+                    board.keep_halfmove_clock_from(before, &moves);
+                }
+                (None, Some(_)) => {
+                    warn!("Couldn't follow the moves to the new board, so repetitions are only counted from here");
+                }
+                (_, None) => {}
+            }
+            board.record_position(white_to_move.unwrap_or(true));
+            game_events().publish(GameEvent::BoardChanged { id: self.id });
+        }
+        self.last_refresh = Some(Instant::now());
+        self.last_known = Some(board.clone());
+        self.board = Either::Left(board);
+        if changed && self.eval_bar.is_some() {
+            self.probe_tablebase();
+        }
+        if self.is_stale {
+            self.is_stale = false;
+            self.status = Some(String::new());
+        }
+        //the server's result is more trustworthy than working it out
+        if changed && self.result.is_none() {
+            let was_check = self
+                .status
+                .as_deref()
+                .is_some_and(|s| s.starts_with("Check") || s.starts_with("Draw by"));
+            if let Some(board) = &self.last_known {
+                if let Some((result, why)) = board_result(board, white_to_move) {
+                    info!(%result, %why, "Game finished on the board");
+                    self.clear_mouse_input();
+                    self.status = Some(format!("Game over - {why} - S to save a scorecard"));
+                    self.result = Some(result);
+                    self.record_game();
+                } else {
+                    match (check_status(board), board.claimable_draw()) {
+                        (Some(status), _) => self.status = Some(status),
+                        (None, Some(reason)) => {
+                            self.status =
+                                Some(format!("Draw by {reason} can be claimed - R to claim it"));
+                        }
+                        (None, None) if was_check => self.status = Some(String::new()),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    ///Applies the moves from a delta to the current board, sliding each piece across.
+    ///
+    /// If the board is waiting on a move, is stale, or doesn't have a piece where a move starts, the delta can't be trusted, so the whole board is fetched again instead.
+    ///
+    /// Returns whether or not the moves were applied.
+    fn apply_moves(&mut self, moves: &[JSONMove]) -> bool {
+        let mut board = match (&self.board, self.is_stale) {
+            (Either::Left(board), false) => Some(board.clone()),
+            _ => None,
+        };
+        for m in moves {
+            board = board
+                .filter(|b| b.piece_exists_at_location(m.current_coords()))
+                .map(|b| {
+                    let taken = b.piece_exists_at_location(m.new_coords());
+                    b.make_move(*m).move_worked(taken)
+                });
+        }
+
+        let Some(board) = board else {
+            warn!(
+                ?moves,
+                "Unable to apply delta from server, fetching the whole board"
+            );
+            self.send_to_worker(MessageToWorker::Resync)
+                .context("sending resync msg")
+                .warn();
+            return false;
+        };
+
+        #[allow(clippy::cast_possible_truncation)]
+        let slides = moves
+            .iter()
+            .map(|m| ((m.x as u8, m.y as u8), (m.nx as u8, m.ny as u8)))
+            .collect();
+        self.slides = Some((slides, Instant::now()));
+        let to_save = board.to_json();
+        self.use_new_board(board, to_save);
+        true
+    }
+
+    ///Uses the history of the game sent by the server, if it ends at the last known board, keeping the variations from the old history
+    fn use_server_history(&mut self, mut history: MoveHistory) {
+        let matches = match (history.end_board(), &self.last_known) {
+            (Ok(end), Some(last_known)) => end.same_pieces(last_known),
+            (Ok(_), None) => true,
+            (Err(e), _) => {
+                warn!(%e, "Unable to play through the server's move history");
+                false
+            }
+        };
+        if !matches {
+            info!("Server's move history doesn't end at the current board, ignoring it");
+            return;
+        }
+
+        if let Some(old) = &self.history {
+            history.keep_variations(old);
+        }
+        info!(
+            moves = history.moves().len(),
+            "Using move history from server"
+        );
+        match history.start_board() {
+            Ok(start) => {
+                self.log_move(MoveLogEvent::Start {
+                    fen: start.to_fen(),
+                    white_first: history.white_first(),
+                    reason: "history from the server".into(),
+                });
+                for m in history.moves() {
+                    self.log_move(MoveLogEvent::Played {
+                        from: m.from,
+                        to: m.to,
+                    });
+                }
+            }
+            Err(e) => warn!(%e, "Unable to log move history from server"),
+        }
+        self.history = Some(history);
+        self.save_history();
+        self.redraw = true;
+    }
+
+    ///Sends a message to the [`ListRefresher`] to clear the board for a new game, and then fetch the new board - as one [`MessageToWorker::Batch`], so the refresh can't start before the restart has finished.
+    ///
+    /// Before restarting, the outcome of the current game is saved to the results store, if it hasn't been already.
+    ///
+    /// # Errors:
+    /// - If there is an error sending the message
+    #[tracing::instrument(skip(self))]
+    pub fn restart_board(&mut self) -> Result<()> {
+        if self.practice.is_some() || self.analysis.is_some() {
+            self.status = Some("Go back to the live game before restarting it".into());
+            return Ok(());
+        }
+        telemetry().feature("restart");
+        self.record_game();
+
+        self.started = Instant::now();
+        self.moves_made = 0;
+        self.board_changes = 0;
+        self.player_is_white = self.role.and_then(InviteRole::is_white);
+        self.hints_used = 0;
+        self.hint = None;
+        self.result = None;
+        self.result_recorded = false;
+        if self.persists() {
+            set_hints_used(self.id, 0)
+                .context("resetting hints used")
+                .warn();
+        }
+
+        self.send_to_worker(MessageToWorker::Batch(vec![
+            MessageToWorker::RestartBoard,
+            MessageToWorker::UpdateNOW,
+        ]))
+        .context("sending restart msg to board")
+    }
+
+    ///Sends a message to the [`ListRefresher`] to tell the server we're done
+    ///
+    /// # Errors:
+    /// - If there is an error sending the message
+    #[tracing::instrument(skip(self))]
+    pub fn exit(mut self) -> Result<()> {
+        //a replay is recorded to compare against the original, not to be saved
+        if self.input_recorder.is_some() && self.client.playback.is_none() {
+            self.save_input_recording().warn();
+        }
+        //saved here rather than on another thread, as the client is about to close
+        if let Some(record) = self.unrecorded_game() {
+            record_result(record).context("recording result").error();
+        }
+        self.send_to_worker(MessageToWorker::InvalidateKill)
+            .context("sending invalidatekill msg to board")
+    }
+
+    ///Saves the most recent [`ResponseDiagnostic`] into the `diagnostics` folder in the project data directory.
+    ///
+    /// # Errors:
+    /// - Fail to get [`ProjectDirs`]
+    /// - Fail to [`create_dir_all`] on the diagnostics directory
+    /// - Fail to save the diagnostic
+    #[tracing::instrument(skip(self))]
+    pub fn save_diagnostic(&self) -> Result<()> {
+        let Some(diag) = &self.last_diagnostic else {
+            info!("No invalid responses to save");
+            return Ok(());
+        };
+        telemetry().feature("save_diagnostic");
+
+        let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+            .ae()
+            .context("getting project dirs")?;
+        let dir = pd.data_dir().join("diagnostics");
+        create_dir_all(&dir).context("creating diagnostics directory")?;
+
+        let path = diag.save(&dir).context("saving diagnostic")?;
+        info!(?path, "Saved diagnostic");
+        Ok(())
+    }
+
+    ///Saves the network session recording into the `network` folder in the project data directory, to be attached to bug reports.
+    ///
+    /// # Errors:
+    /// - Fail to get [`ProjectDirs`]
+    /// - Fail to [`create_dir_all`] on the network directory
+    /// - Fail to save the recording
+    #[tracing::instrument(skip(self))]
+    pub fn save_network_log(&self) -> Result<()> {
+        let Some(recorder) = self.refresher.as_ref().and_then(ListRefresher::recorder) else {
+            info!("Network recording is off - turn it on in the launcher");
+            return Ok(());
+        };
+        telemetry().feature("save_network_log");
+
+        let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+            .ae()
+            .context("getting project dirs")?;
+        let dir = pd.data_dir().join("network");
+        create_dir_all(&dir).context("creating network directory")?;
+
+        let path = recorder.save(&dir).context("saving network log")?;
+        info!(?path, "Saved network log");
+        Ok(())
+    }
+
+    ///Starts recording the input to the game, along with the board messages from the worker, to be replayed with [`replay`](crate::input_recording::replay). Any recording already going is thrown away.
+    pub fn start_input_recording(&mut self, window_scale: f64) {
+        self.input_recorder = Some(InputRecorder::new(self.id, window_scale));
+    }
+
+    ///Records an input, if the input is being recorded
+    pub fn record_input(&mut self, input: RecordedInput) {
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.record(input);
+        }
+    }
+
+    ///Gets the input recorder, if the input is being recorded
+    #[must_use]
+    pub const fn input_recorder(&self) -> Option<&InputRecorder> {
+        self.input_recorder.as_ref()
+    }
+
+    ///Saves the input recording so far, along with the network session it was played against, into the `recordings` folder in the project data directory, and shows where in the status.
+    ///
+    /// # Errors:
+    /// - Fail to save the recording
+    #[tracing::instrument(skip(self))]
+    pub fn save_input_recording(&mut self) -> Result<()> {
+        let Some(recorder) = &self.input_recorder else {
+            info!("Input recording is off - start with --record-input");
+            return Ok(());
+        };
+        let Some(network) = self.refresher.as_ref().and_then(ListRefresher::recorder) else {
+            info!("Network recording is off, so the input can't be replayed");
+            return Ok(());
+        };
+        telemetry().feature("save_input_recording");
+
+        let path = recorder
+            .save(network.session())
+            .context("saving input recording")?;
+        info!(?path, "Saved input recording");
+        self.status = Some(format!("Saved input recording to {}", path.display()));
+        Ok(())
+    }
+
+    ///Gets the statistics for the requests made to the server so far
+    #[must_use]
+    pub const fn request_stats(&self) -> &RequestStats {
+        &self.request_stats
+    }
+
+    ///Gets the board being shown - the [`AnalysisBoard`] if it is open, the [`Practice`] board while practising, and the live board otherwise
+    fn shown_board(&self) -> &BoardContainer {
+        self.analysis
+            .as_ref()
+            .map(AnalysisBoard::board)
+            .or_else(|| self.practice.as_ref().map(Practice::board))
+            .unwrap_or(&self.board)
+    }
+
+    ///Starts practising from the current position on an offline board, or goes back to the live game if already practising. Nothing done while practising is sent to the server.
+    ///
+    /// The player moves first, as whichever colour they are playing - or white until that is known. Against the engine, it plays the other colour with the [`HintConfig::engine`].
+    #[tracing::instrument(skip(self))]
+    pub fn toggle_practice(&mut self, against_engine: bool) {
+        if self.analysis.is_some() {
+            self.status = Some("Close the analysis board first".into());
+            return;
+        }
+        self.last_pressed = Coords::OffBoard;
+        self.drag_start = None;
+        self.hint = None;
+
+        if self.practice.take().is_some() {
+            info!("Back to the live game");
+            self.status = Some("Back to the live game".into());
+            self.redraw = true;
+            return;
+        }
+
+        if self.pending_move.is_some() {
+            self.cancel_move();
+        }
+        let Either::Left(board) = self.board.clone() else {
+            self.status = Some("Wait for the server to accept the last move".into());
+            return;
+        };
+
+        telemetry().feature("practice");
+        let player_is_white = self.player_is_white.unwrap_or(true);
+        let opponent = if against_engine {
+            PracticeOpponent::Engine {
+                is_white: !player_is_white,
+            }
+        } else {
+            PracticeOpponent::Nobody
+        };
+        info!(?opponent, "Practising from the current position");
+        let practice = Practice::new(board, player_is_white, opponent, self.hints.engine.clone());
+        self.status = Some(practice_status(&practice));
+        self.practice = Some(practice);
+        self.redraw = true;
+    }
+
+    ///Opens an [`AnalysisBoard`] starting from the board being shown, or closes it. Nothing on it is ever sent to the server.
+    ///
+    /// On the live game, the analysis board has the whole [`MoveHistory`], so any earlier move can be gone back to and explored - and when it is closed, the variations of the game's moves are kept in the history. Anything explored after the last move of the game, or from a practice board, is thrown away.
+    #[tracing::instrument(skip(self))]
+    pub fn toggle_analysis(&mut self) {
+        self.last_pressed = Coords::OffBoard;
+        self.drag_start = None;
+        self.hint = None;
+        self.redraw = true;
+
+        if self.is_reviewing() && self.analysis.is_some() {
+            self.status = Some("Reviewing a PGN, so there is no live game to go back to".into());
+            return;
+        }
+        if let Some(analysis) = self.close_analysis() {
+            info!("Closed analysis board");
+            self.status = Some(if self.practice.is_some() {
+                "Analysis discarded - back to practice".into()
+            } else if analysis.is_from_game() {
+                "Variations kept in the move history - back to the live game".into()
+            } else {
+                "Analysis discarded - back to the live game".into()
+            });
+            return;
+        }
+
+        if self.pending_move.is_some() {
+            self.cancel_move();
+        }
+        let Either::Left(board) = self.shown_board().clone() else {
+            self.status = Some("Wait for the server to accept the last move".into());
+            return;
+        };
+
+        telemetry().feature("analysis");
+        info!("Opened analysis board");
+        let analysis = match (&self.practice, &self.history) {
+            (Some(practice), _) => AnalysisBoard::new(board, practice.white_to_move()),
+            (None, Some(history)) => {
+                //the history could have missed the latest board if it couldn't follow the moves
+                let up_to_date = history
+                    .end_board()
+                    .is_ok_and(|end| end.to_fen() == board.to_fen());
+                match AnalysisBoard::from_history(history, true) {
+                    Ok(analysis) if up_to_date => analysis,
+                    Ok(_) => AnalysisBoard::new(board, history.white_to_move()),
+                    Err(e) => {
+                        warn!(%e, "Unable to fork analysis from the move history");
+                        AnalysisBoard::new(board, history.white_to_move())
+                    }
+                }
+            }
+            (None, None) => AnalysisBoard::new(board, self.player_is_white.unwrap_or(true)),
+        };
+        self.status = Some(format!(
+            "{} - arrows to move between variations, A to close",
+            analysis.describe()
+        ));
+        self.analysis = Some(analysis);
+    }
+
+    ///Moves around the variations on the [`AnalysisBoard`], if it is open
+    pub fn step_analysis(&mut self, step: AnalysisStep) {
+        let Some(analysis) = &mut self.analysis else {
+            return;
+        };
+        if analysis.step(step) {
+            self.last_pressed = Coords::OffBoard;
+            self.status = Some(analysis.describe());
+            self.redraw = true;
+        }
+    }
+
+    ///Picks up the engine's reply while practising
+    fn poll_practice(&mut self) {
+        let Some(practice) = &mut self.practice else {
+            return;
+        };
+        match practice.poll() {
+            Ok(false) => {}
+            Ok(true) => {
+                self.status = Some(practice_status(practice));
+                self.redraw = true;
+            }
+            Err(e) => {
+                warn!(%e, "Engine reply failed in practice");
+                self.status = Some(format!("Practice: {e}"));
+                self.redraw = true;
+            }
+        }
+    }
+
+    ///Gets the opening book moves for the current position, for the book panel. Returns `None` if there isn't a book.
     ///
-    /// # Errors
-    /// - Can fail if the cacher incorrectly populates
-    pub fn new(win: &mut PistonWindow, id: u32) -> Result<Self> {
-        Ok(Self {
-            id,
-            cache: Cacher::new(win).context("making cacher")?,
-            board: BoardContainer::default(),
-            refresher: ListRefresher::new(id),
-            last_pressed: Coords::OffBoard,
-            ex_last_pressed: Coords::OffBoard,
-            show_board_update: None,
+    /// The board doesn't say whose turn it is, so the position is looked up with the player to move if their colour is known, and otherwise with whichever side has moves in the book.
+    #[must_use]
+    pub fn book_moves(&self) -> Option<Vec<BookMove>> {
+        let book = self.book.as_ref()?;
+        let moves = |white: bool| match &self.board {
+            Either::Left(b) => book.moves(b, white),
+            Either::Right(b) => book.moves(b, white),
+        };
+        Some(match self.player_is_white {
+            Some(is_white) => moves(is_white),
+            None => {
+                let white = moves(true);
+                if white.is_empty() {
+                    moves(false)
+                } else {
+                    white
+                }
+            }
         })
     }
 
-    ///Handles mouse input
-    ///
-    /// # Errors
-    /// - Can fail if there is an error sending the message to the [`ListRefresher`]
-    #[tracing::instrument(skip(self))]
-    pub fn mouse_input(&mut self, mouse_pos: (f64, f64), mult: f64) -> Result<()> {
-        match std::mem::take(&mut self.last_pressed) {
-            Coords::OffBoard => {
-                let lp_x = to_board_coord(mouse_pos.0, mult);
-                let lp_y = to_board_coord(mouse_pos.1, mult);
+    ///Closes the [`AnalysisBoard`], if it is open. If it was forked from the game's [`MoveHistory`], the variations explored are kept in it.
+    fn close_analysis(&mut self) -> Option<AnalysisBoard> {
+        let analysis = self.analysis.take()?;
+        if analysis.is_from_game() {
+            if let Some(history) = &mut self.history {
+                history.keep_variations(&analysis.to_history());
+                self.save_history();
+            }
+        }
+        Some(analysis)
+    }
 
-                let coord = (lp_x, lp_y).try_into()?;
+    ///Gets the moves for the move list panel, and where the position being shown is in them - the variations on the [`AnalysisBoard`] if it is open, and otherwise the moves seen in this game
+    #[must_use]
+    pub fn move_list(&self) -> Option<(MoveHistory, Option<MovePath>)> {
+        match &self.analysis {
+            Some(analysis) => Some((analysis.to_history(), analysis.current_path())),
+            None => self.history.clone().map(|h| (h, None)),
+        }
+    }
 
-                if self.board.piece_exists_at_location(coord) {
-                    self.last_pressed = coord;
+    ///Appends an event to the move log of the game, if it [persists](ChessGame::persists) - see [`append_move_log`]
+    fn log_move(&self, event: MoveLogEvent) {
+        if self.persists() {
+            append_move_log(self.id, event)
+                .context("appending to move log")
+                .warn();
+        }
+    }
+
+    ///Whether the game saves to and loads from the project data directory - not while playing back a recorded session, eg. in a [replay](crate::input_recording::replay), so the games the player is really playing are neither read nor changed by it
+    fn persists(&self) -> bool {
+        self.client.playback.is_none()
+    }
+
+    ///Shows the move log of the game in the console, to check what was played when
+    pub fn show_move_log(&self) {
+        match load_move_log(self.id) {
+            Ok(log) if log.is_empty() => info!("No moves logged yet"),
+            Ok(log) => {
+                for entry in &log {
+                    info!("{}", describe_entry(entry));
                 }
             }
-            Coords::OnBoard(x, y) => {
-                //Deal with second press
-                let current_press = {
-                    let lp_x = to_board_coord(mouse_pos.0, mult);
-                    let lp_y = to_board_coord(mouse_pos.1, mult);
-                    (lp_x, lp_y)
+            Err(e) => warn!(%e, "Unable to load move log"),
+        }
+    }
+
+    ///Saves the [`MoveHistory`] on another thread, if there is one and the game [persists](ChessGame::persists)
+    fn save_history(&self) {
+        let Some(history) = self.history.clone().filter(|_| self.persists()) else {
+            return;
+        };
+        let id = self.id;
+        std::thread::spawn(move || {
+            save_moves(id, &history)
+                .context("saving move history")
+                .warn();
+        });
+    }
+
+    ///Adds the moves which turned the last known board into a new one to the [`MoveHistory`], and saves it.
+    ///
+    /// The server only sends boards, so the moves have to be worked out - if no moves explain the change, eg. the game was restarted or several moves were made while the client was closed, a new history is started from the new board.
+    ///
+    /// Returns the moves, as `(from, to)` pairs, if they were worked out.
+    fn record_moves(&mut self, after: &Board<CanMovePiece>) -> Option<Vec<(Coords, Coords)>> {
+        let white_first = self
+            .history
+            .as_ref()
+            .filter(|h| !h.moves().is_empty())
+            .map(MoveHistory::white_to_move);
+        let inferred = self
+            .last_known
+            .as_ref()
+            .and_then(|before| Some((before, infer_moves(before, after, white_first)?)));
+
+        let mut log = vec![];
+        let followed = inferred.as_ref().map(|(_, (_, moves))| moves.clone());
+        match inferred {
+            Some((before, (white, moves))) => {
+                //the colour which moved first is only known once a move has been seen
+                let history = if white_first.is_none() {
+                    log.push(MoveLogEvent::Start {
+                        fen: before.to_fen(),
+                        white_first: white,
+                        reason: "first move seen".into(),
+                    });
+                    self.history.insert(MoveHistory::new(before, white))
+                } else {
+                    self.history
+                        .get_or_insert_with(|| MoveHistory::new(before, white))
                 };
+                for (from, to) in moves {
+                    log.push(MoveLogEvent::Played { from, to });
+                    history.push(from, to);
+                }
+            }
+            None => {
+                info!("Unable to work out the moves made, starting a new move history");
+                log.push(MoveLogEvent::Start {
+                    fen: after.to_fen(),
+                    white_first: true,
+                    reason: "moves to the new board couldn't be worked out".into(),
+                });
+                self.history = Some(MoveHistory::new(after, true));
+                self.fetch_history();
+            }
+        }
+        for event in log {
+            self.log_move(event);
+        }
+        self.save_history();
+        followed
+    }
 
-                info!(last_pos=?(x, y), new_pos=?current_press, "Starting moving");
+    ///Sets the annotations on a move in the move list - on the [`AnalysisBoard`] if it is open, and otherwise in the [`MoveHistory`], which is saved
+    #[tracing::instrument(skip(self))]
+    pub fn annotate_move(
+        &mut self,
+        path: &MovePath,
+        glyph: Option<MoveGlyph>,
+        comment: Option<String>,
+    ) {
+        let annotated = match (&mut self.analysis, &mut self.history) {
+            (Some(analysis), _) => analysis.annotate(path, glyph, comment),
+            (None, Some(history)) => history.annotate(path, glyph, comment),
+            (None, None) => {
+                self.status = Some("No moves to annotate yet".into());
+                return;
+            }
+        };
+        if let Err(e) = annotated {
+            warn!(%e, "Unable to annotate move");
+            self.status = Some(format!("Unable to annotate move: {e}"));
+            return;
+        }
 
-                self.refresher
-                    .send_msg(MessageToWorker::MakeMove(JSONMove::new(
-                        self.id,
-                        u32::from(x),
-                        u32::from(y),
-                        current_press.0,
-                        current_press.1,
-                    )))
-                    .context("sending a message to the worker re moving")?;
+        telemetry().feature("annotate");
+        self.status = Some(format!("Annotated move {path}"));
+        self.redraw = true;
+        if self.analysis.is_none() {
+            self.save_history();
+        }
+    }
 
-                self.ex_last_pressed = Coords::OnBoard(x, y);
+    ///Exports the moves as PGN, with the annotations and variations, and says where it was saved - the [`AnalysisBoard`] if it is open, and otherwise the [`MoveHistory`]
+    #[tracing::instrument(skip(self))]
+    pub fn save_pgn(&mut self) {
+        let history = match &self.analysis {
+            Some(analysis) => analysis.to_history(),
+            None => {
+                let Some(history) = self.history.clone() else {
+                    self.status = Some("No moves to export yet".into());
+                    return;
+                };
+                history
+            }
+        };
+        match export_pgn(self.id, &history) {
+            Ok(path) => {
+                info!(?path, "Exported PGN");
+                self.status = Some(format!("Saved PGN to {}", path.display()));
+            }
+            Err(e) => {
+                warn!(%e, "Unable to export PGN");
+                self.status = Some(format!("Unable to export PGN: {e}"));
             }
         }
+    }
 
-        Ok(())
+    ///Describes how close the live game is to a draw by the fifty-move rule or by repetition, for the info strip - eg. ` | 50-move rule: 37/100 | Position repeated 2×`
+    fn draw_rule_counters(&self) -> String {
+        let Some(board) = &self.last_known else {
+            return String::new();
+        };
+        let claim = if self.can_claim_draw() {
+            " | R or the button above to claim a draw"
+        } else {
+            ""
+        };
+        format!(
+            " | 50-move rule: {}/{FIFTY_MOVE_HALFMOVES} | Position repeated {}×{claim}",
+            board.halfmove_clock(),
+            board.repetition_count()
+        )
     }
 
-    ///Sends a message to the [`ListRefresher`] to clear the board for a new game.
-    ///
-    /// # Errors:
-    /// - If there is an error sending the message
+    ///Gets whether a draw can be claimed in the live game right now, so the claim draw button is shown
+    fn can_claim_draw(&self) -> bool {
+        self.practice.is_none()
+            && self.analysis.is_none()
+            && self.result.is_none()
+            && self
+                .last_known
+                .as_ref()
+                .is_some_and(|b| b.claimable_draw().is_some())
+    }
+
+    ///Claims a draw if the press at `scene_pos` (in window pixels, before the board is flipped) is on the claim draw button, and it is shown. Returns whether the press was on the button.
     #[tracing::instrument(skip(self))]
-    pub fn restart_board(&mut self) -> Result<()> {
-        self.refresher
-            .send_msg(MessageToWorker::RestartBoard)
-            .context("sending restart msg to board")
+    pub fn press_claim_draw_button(&mut self, scene_pos: (f64, f64), window_scale: f64) -> bool {
+        let [x, y, w, h] = CLAIM_DRAW_RECT.map(|p| p * window_scale);
+        let on_button = (x..x + w).contains(&scene_pos.0) && (y..y + h).contains(&scene_pos.1);
+        if !on_button || !self.can_claim_draw() {
+            return false;
+        }
+        self.claim_draw();
+        true
     }
 
-    ///Sends a message to the [`ListRefresher`] to tell the server we're done
+    ///Claims a draw by repetition or the fifty-move rule, if either can be claimed in the live game - see [`Board::claimable_draw`]
     ///
-    /// # Errors:
-    /// - If there is an error sending the message
+    /// The claim is sent to the server if it takes them, and the game only ends once it agrees - see [`ChessGame::use_draw_claim`]. Otherwise, the player is only told that a draw could be claimed, and the game carries on, as the opponent would still see it going.
     #[tracing::instrument(skip(self))]
-    pub fn exit(self) -> Result<()> {
-        self.refresher
-            .send_msg(MessageToWorker::InvalidateKill)
-            .context("sending invalidatekill msg to board")
+    pub fn claim_draw(&mut self) {
+        if self.practice.is_some() || self.analysis.is_some() {
+            self.status = Some("Go back to the live game to claim a draw".into());
+            return;
+        }
+        if self.result.is_some() {
+            return;
+        }
+
+        let Some(board) = &self.last_known else {
+            return;
+        };
+        let Some(reason) = board.claimable_draw() else {
+            self.status = Some(format!(
+                "Can't claim a draw - the position has come up {} time(s), and it has been {} half-moves since a capture or pawn move",
+                board.repetition_count(),
+                board.halfmove_clock()
+            ));
+            return;
+        };
+
+        telemetry().feature("claim_draw");
+        let server_takes_claims = self
+            .refresher
+            .as_ref()
+            .and_then(ListRefresher::capabilities)
+            .is_some_and(|c| c.draw_claims);
+        if server_takes_claims {
+            info!(%reason, "Claiming a draw");
+            self.status = Some(format!("Claiming a draw by {reason}..."));
+            self.send_to_worker(MessageToWorker::ClaimDraw(reason))
+                .context("sending a message to the worker re claiming a draw")
+                .warn();
+            return;
+        }
+
+        //the server can't be told, so the game isn't ended or recorded here either
+        info!(%reason, "Server doesn't take draw claims, so not claiming");
+        self.status = Some(format!(
+            "A draw by {reason} could be claimed here, but the server doesn't take draw claims"
+        ));
+    }
+
+    ///Acts on the server's answer to a draw claimed with [`ChessGame::claim_draw`]
+    fn use_draw_claim(&mut self, outcome: DrawClaimOutcome) {
+        match outcome {
+            DrawClaimOutcome::Accepted(reason) => {
+                info!(%reason, "Server accepted draw claim");
+                self.clear_mouse_input();
+                self.status = Some(format!(
+                    "Game over - Draw by {reason} - S to save a scorecard"
+                ));
+                self.result = Some(JSONResult::Draw);
+                self.record_game();
+            }
+            DrawClaimOutcome::Rejected(e) => {
+                info!(%e, "Server rejected draw claim");
+                self.status = Some(format!("Couldn't claim a draw - {e}"));
+            }
+            DrawClaimOutcome::CouldntProcessClaim => {
+                telemetry().error("couldnt_process_draw_claim");
+                self.status = Some("Couldn't reach the server to claim a draw".into());
+            }
+        }
+    }
+
+    ///Saves an image of the live board with the moves of the game beside it, for sharing, and says where it was saved
+    #[tracing::instrument(skip(self))]
+    pub fn save_scorecard(&mut self) {
+        let moves = self
+            .history
+            .as_ref()
+            .map(MoveHistory::described_moves)
+            .transpose()
+            .unwrap_or_else(|e| {
+                warn!(%e, "Unable to write out moves for scorecard");
+                None
+            })
+            .unwrap_or_default();
+        let mut heading = vec![format!("Async Chess game {}", self.id)];
+        heading.extend(self.result.map(|r| r.to_string()));
+
+        let flipped = self.player_is_white == Some(false);
+        let theme = self.theme.as_deref();
+        let card = match &self.board {
+            Either::Left(b) => render_scorecard(b, &moves, &heading, flipped, theme),
+            Either::Right(b) => render_scorecard(b, &moves, &heading, flipped, theme),
+        };
+        match card.and_then(|c| export_scorecard(self.id, &c)) {
+            Ok(path) => {
+                info!(?path, "Saved scorecard");
+                telemetry().feature("scorecard");
+                self.status = Some(format!("Saved scorecard to {}", path.display()));
+            }
+            Err(e) => {
+                warn!(%e, "Unable to save scorecard");
+                self.status = Some(format!("Unable to save scorecard: {e}"));
+            }
+        }
+    }
+
+    ///Exports the main line of the [`MoveHistory`] as a printable scoresheet, with diagrams of the annotated positions, and says where it was saved
+    #[tracing::instrument(skip(self))]
+    pub fn save_scoresheet(&mut self) {
+        let Some(history) = &self.history else {
+            self.status = Some("No moves to export yet".into());
+            return;
+        };
+        match export_scoresheet(self.id, history, self.result) {
+            Ok(path) => {
+                info!(?path, "Exported scoresheet");
+                telemetry().feature("scoresheet");
+                self.status = Some(format!("Saved scoresheet to {}", path.display()));
+            }
+            Err(e) => {
+                warn!(%e, "Unable to export scoresheet");
+                self.status = Some(format!("Unable to export scoresheet: {e}"));
+            }
+        }
+    }
+
+    ///Imports a PGN game into a new [`AnalysisBoard`], replacing any which is open, so its moves, annotations and variations can be stepped through
+    #[tracing::instrument(skip(self))]
+    pub fn open_pgn(&mut self, path: &Path) {
+        telemetry().feature("import_pgn");
+        self.open_imported(import_pgn(path), "PGN");
+    }
+
+    ///Starts importing a game from Lichess or Chess.com on another thread. When it arrives, it is opened in a new [`AnalysisBoard`] like [`ChessGame::open_pgn`].
+    #[tracing::instrument(skip(self))]
+    pub fn import_game(&mut self, game: ExternalGame) {
+        if self.import_rx.is_some() {
+            self.status = Some("Already importing a game".into());
+            return;
+        }
+
+        telemetry().feature("import_game");
+        let (tx, rx) = channel();
+        let client = self.client.clone();
+        std::thread::spawn(move || {
+            let history = GameImporter::new(&client).and_then(|i| i.import(&game));
+            //if the game has closed, nobody needs to know
+            let _ = tx.send(history);
+        });
+        self.import_rx = Some(rx);
+        self.status = Some("Importing game...".into());
+    }
+
+    ///Picks up a game being imported from another site, if it has arrived
+    fn poll_import(&mut self) {
+        let Some(received) = self.import_rx.as_ref().map(Receiver::try_recv) else {
+            return;
+        };
+        let history = match received {
+            Ok(history) => history,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(anyhow!("import thread stopped")),
+        };
+        self.import_rx = None;
+        self.open_imported(history, "game");
+    }
+
+    ///Opens an imported game in a new [`AnalysisBoard`], replacing any which is open, or says why it couldn't be imported
+    fn open_imported(&mut self, history: Result<MoveHistory>, what: &str) {
+        match history.and_then(|history| AnalysisBoard::from_history(&history, false)) {
+            Ok(analysis) => {
+                self.close_analysis();
+                info!("Imported {what}");
+                self.last_pressed = Coords::OffBoard;
+                self.drag_start = None;
+                self.hint = None;
+                let close = if self.is_reviewing() {
+                    ""
+                } else {
+                    ", A to close"
+                };
+                self.status = Some(format!(
+                    "{} - arrows to play through the game and its variations{close}",
+                    analysis.describe()
+                ));
+                self.analysis = Some(analysis);
+            }
+            Err(e) => {
+                warn!(%e, "Unable to import {what}");
+                self.status = Some(format!("Unable to import {what}: {e}"));
+            }
+        }
+        self.redraw = true;
+    }
+
+    ///Publishes how much the game is holding on to into the [`metrics`] registry, to catch leaks in clients left open for a long time.
+    ///
+    /// The queue depths and thread counts are kept up to date by the [`ListRefresher`] itself.
+    fn publish_memory_metrics(&mut self) {
+        let to_gauge = |n: usize| i64::try_from(n).unwrap_or(i64::MAX);
+
+        let m = metrics();
+        m.set_gauge("memory.textures", to_gauge(self.cache.texture_count()));
+        m.set_gauge(
+            "memory.texture_bytes",
+            i64::try_from(self.cache.texture_bytes()).unwrap_or(i64::MAX),
+        );
+        m.set_gauge(
+            "memory.taken_pieces",
+            to_gauge(self.board.get_taken().len()),
+        );
+        m.set_gauge("memory.board_changes", i64::from(self.board_changes));
+        eval_cache().publish_metrics();
+        publish_snapshot_metrics();
+    }
+
+    ///Saves the outcome of the game to the results store on another thread, if it hasn't been already - see [`ChessGame::unrecorded_game`]
+    fn record_game(&mut self) {
+        if let Some(record) = self.unrecorded_game() {
+            std::thread::spawn(move || {
+                record_result(record).context("recording result").error();
+            });
+        }
+    }
+
+    ///Gets the [`GameRecord`] to save for the current game, marking it as saved so each game is only saved once.
+    ///
+    /// Nothing is saved for a game which doesn't [persist](ChessGame::persists), a board which is only being reviewed, or a game which hasn't finished and which no moves have been made in from this client.
+    fn unrecorded_game(&mut self) -> Option<GameRecord> {
+        if self.result_recorded
+            || !self.persists()
+            || self.is_reviewing()
+            || (self.result.is_none() && self.moves_made == 0)
+        {
+            return None;
+        }
+        self.result_recorded = true;
+        Some(self.current_record())
+    }
+
+    ///Works out a [`GameRecord`] for the current game.
+    ///
+    /// The result is the one in [`ChessGame::result`] if the game is over - otherwise it is found using which kings have been taken or checkmated. Either way, it is from the perspective of [`ChessGame::player_is_white`]
+    fn current_record(&mut self) -> GameRecord {
+        let (mut white_king_taken, mut black_king_taken) = (false, false);
+        for p in self.board.get_taken() {
+            if p.kind == ChessPieceKind::King {
+                if p.is_white {
+                    white_king_taken = true;
+                } else {
+                    black_king_taken = true;
+                }
+            }
+        }
+        //a checkmated king is as good as taken
+        if let Some(board) = &self.last_known {
+            white_king_taken |= board.is_checkmate(true);
+            black_king_taken |= board.is_checkmate(false);
+        }
+
+        let white_won = match (self.result, white_king_taken, black_king_taken) {
+            (Some(JSONResult::WhiteWins), _, _) | (None, false, true) => Some(true),
+            (Some(JSONResult::BlackWins), _, _) | (None, true, false) => Some(false),
+            _ => None,
+        };
+
+        let result = match (self.result, self.player_is_white, white_won) {
+            (Some(JSONResult::Draw), _, _) => GameResult::Draw,
+            (_, Some(is_white), Some(white_won)) => {
+                if is_white == white_won {
+                    GameResult::Win
+                } else {
+                    GameResult::Loss
+                }
+            }
+            _ => GameResult::Unfinished,
+        };
+
+        GameRecord::new(
+            self.id,
+            self.player_is_white,
+            result,
+            self.moves_made,
+            self.started.elapsed().as_secs(),
+        )
+    }
+
+    ///Takes the new status message, if there is one. An empty string means the status should be cleared.
+    pub fn take_status(&mut self) -> Option<String> {
+        std::mem::take(&mut self.status)
     }
 
-    ///Clears the mouse input - means that a different piece can be selected.
+    ///Clears the mouse input - means that a different piece can be selected. Also cancels any pending move, or promotion being picked.
     pub fn clear_mouse_input(&mut self) {
         self.last_pressed = Coords::OffBoard;
         self.ex_last_pressed = Coords::OffBoard;
+        self.drag_start = None;
+        if self.promotion.take().is_some() {
+            self.status = Some(String::new());
+        }
+        self.cancel_move();
     }
 
     // #[tracing::instrument(skip(self, ctx, graphics, _device))]
@@ -133,14 +2086,22 @@ impl ChessGame {
     ///
     /// # Errors
     /// - Can fail if piece sprites aren't found in the [`Cacher`]. However, will still render all other sprites
+    /// - Can fail if the tooltip text can't be rendered
     pub fn render(
         &mut self,
         ctx: Context,
         graphics: &mut G2d,
+        glyphs: &mut Glyphs,
         raw_mouse_coords: (f64, f64),
         window_scale: f64,
         is_flipped: bool
     ) -> Result<()> {
+        let board = self
+            .analysis
+            .as_ref()
+            .map(AnalysisBoard::board)
+            .or_else(|| self.practice.as_ref().map(Practice::board))
+            .unwrap_or(&self.board);
         let board_coords = if mp_valid(raw_mouse_coords, window_scale) {
             let bps = to_board_pixels(raw_mouse_coords, window_scale);
             Some((
@@ -150,6 +2111,82 @@ impl ChessGame {
         } else {
             None
         };
+        //the mouse coordinates are flipped to match the board, so flip them back for drawing things at the cursor
+        let screen_mouse = if is_flipped {
+            (raw_mouse_coords.0, BOARD_S * window_scale - raw_mouse_coords.1)
+        } else {
+            raw_mouse_coords
+        };
+
+        let held = self.held_piece().is_some();
+        let show_tooltip = if held {
+            false
+        } else {
+            match (board_coords, self.hover) {
+                (Some(bc), Some((hc, since))) if bc == hc => since.elapsed() >= TOOLTIP_DELAY,
+                (bc, _) => {
+                    self.hover = bc.map(|bc| (bc, Instant::now()));
+                    false
+                }
+            }
+        };
+
+        let mut eval_moving = false;
+        if let Some((share, last_frame)) = &mut self.eval_bar {
+            ///How quickly the bar moves towards a new evaluation - the fraction of the gap closed per second
+            const EVAL_BAR_SPEED: f64 = 4.0;
+
+            let target = self.tablebase_outcome.map_or_else(
+                || white_share(board.evaluate()),
+                TablebaseOutcome::white_share,
+            );
+            let dt = last_frame.elapsed().as_secs_f64();
+            *share += (target - *share) * (dt * EVAL_BAR_SPEED).min(1.0);
+            *last_frame = Instant::now();
+            eval_moving = (target - *share).abs() > 0.001;
+        }
+
+        let is_offline_board = self.analysis.is_some() || self.practice.is_some();
+        let tiles = self.tile_states(board, board_coords);
+        //rounded, as it only needs to be as exact as the pixels
+        #[allow(clippy::cast_possible_truncation)]
+        let scene = Scene {
+            transform: ctx.transform,
+            draw_size: ctx.viewport.map(|v| v.draw_size),
+            window_scale,
+            is_flipped,
+            taken: self.taken.update(board.taken()),
+            eval: self
+                .eval_bar
+                .map(|(share, _)| (share * 1000.0).round() as i32),
+            stale: self.is_stale && !is_offline_board,
+            offline: is_offline_board,
+            presence: self.opponent_presence,
+            unavailable: self.unavailable.clone(),
+            overlays: held
+                || show_tooltip
+                || eval_moving
+                || self.slides.is_some()
+                || self.hint.is_some()
+                || self.show_board_update.is_some()
+                || self.promotion.is_some(),
+        };
+        if let Redraw::Tiles(dirty) = self.dirty.plan(scene, tiles) {
+            let result = self.render_tiles(
+                &dirty,
+                &tiles,
+                ctx,
+                graphics,
+                glyphs,
+                window_scale,
+                is_flipped,
+            );
+            //keep drawing until the tooltip is shown
+            self.redraw |= self
+                .hover
+                .is_some_and(|(_, since)| since.elapsed() < TOOLTIP_DELAY);
+            return result;
+        }
 
         clear([0.0; 4], graphics);
         let t = ctx.transform;
@@ -188,13 +2225,35 @@ impl ChessGame {
                     graphics,
                 );
             }
+
+            if let Some(m) = &self.pending_move {
+                for (px, py) in [(m.x, m.y), (m.nx, m.ny)] {
+                    let x = f64::from(px) * BOARD_TILE_S * window_scale;
+                    let y = if is_flipped {
+                        f64::from(7 - py)
+                    } else {
+                        f64::from(py)
+                    } * BOARD_TILE_S * window_scale;
+                    let image = Image::new().rect(square(x, y, TILE_S * window_scale));
+
+                    image.draw(
+                        self.cache
+                            .get("highlight.png")
+                            .context("getting hightlight.png")
+                            .unwrap_log_error(),
+                        &DrawState::default(),
+                        trans,
+                        graphics,
+                    );
+                }
+            }
         }
         let mut errs = vec![];
 
         for col in 0..8_u8 {
             for row in 0..8_u8 {
-                if let Some(piece) = self.board[(col, row).into()] {
-                    match self.cache.get(&piece.to_file_name()) {
+                if let Some(piece) = board[(col, row).into()] {
+                    match self.cache.get(piece.to_file_name()) {
                         Err(e) => {
                             errs.push(e.context(format!(
                                 "cacher doesn't contain: {:?} at ({col}, {row})",
@@ -202,12 +2261,26 @@ impl ChessGame {
                             )));
                         }
                         Ok(tex) => {
-                            let x = f64::from(col) * BOARD_TILE_S * window_scale;
-                            let y = if is_flipped {
-                                f64::from(7 - row)
-                            } else {
-                                f64::from(row)
-                            } * BOARD_TILE_S * window_scale;
+                            //pieces from a delta slide from where they started
+                            let (draw_col, draw_row) = self
+                                .slides
+                                .as_ref()
+                                .filter(|_| std::ptr::eq(board, &self.board))
+                                .and_then(|(slides, since)| {
+                                    let (from, _) =
+                                        slides.iter().rev().find(|(_, to)| *to == (col, row))?;
+                                    let t =
+                                        since.elapsed().as_secs_f64() / SLIDE_TIME.as_secs_f64();
+                                    let lerp = |a: u8, b: u8| {
+                                        f64::from(a) + (f64::from(b) - f64::from(a)) * t.min(1.0)
+                                    };
+                                    Some((lerp(from.0, col), lerp(from.1, row)))
+                                })
+                                .unwrap_or((f64::from(col), f64::from(row)));
+                            let x = draw_col * BOARD_TILE_S * window_scale;
+                            let y = if is_flipped { 7.0 - draw_row } else { draw_row }
+                                * BOARD_TILE_S
+                                * window_scale;
                             let image = Image::new().rect(square(x, y, TILE_S * window_scale));
 
                             let mut draw =
@@ -229,60 +2302,213 @@ impl ChessGame {
             }
         }
 
+        //over the pieces, so the rings around pieces which can be taken aren't hidden - the same order as in `render_tiles`
+        for (px, py) in self.legal_move_squares(board) {
+            let x = f64::from(px) * BOARD_TILE_S * window_scale;
+            let y = if is_flipped {
+                f64::from(7 - py)
+            } else {
+                f64::from(py)
+            } * BOARD_TILE_S * window_scale;
+            let capture = board[(px, py).into()].is_some();
+            draw_legal_move(
+                (x, y),
+                capture,
+                window_scale,
+                &DrawState::default(),
+                trans,
+                graphics,
+            );
+        }
+
+        if let (true, Some(is_white)) = (self.show_threats, self.player_is_white) {
+            for (x, y) in board
+                .threatened_pieces(is_white)
+                .into_iter()
+                .filter_map(|c| c.to_option())
+            {
+                let x = f64::from(x) * BOARD_TILE_S * window_scale;
+                let y = if is_flipped {
+                    f64::from(7 - y)
+                } else {
+                    f64::from(y)
+                } * BOARD_TILE_S * window_scale;
+
+                rectangle(
+                    THREAT_COLOUR,
+                    square(x, y, TILE_S * window_scale),
+                    trans,
+                    graphics,
+                );
+            }
+        }
+
+        if let Some(((from, to), _)) = self.hint {
+            ///Colour of the hint arrow - translucent, so the pieces underneath can still be seen
+            const HINT_COLOUR: [f32; 4] = [0.2, 0.6, 1.0, 0.6];
+
+            //from the top left of the board, to the middle of a square
+            let centre = |c: Coords| {
+                c.to_option().map(|(x, y)| {
+                    let y = if is_flipped { 7 - y } else { y };
+                    (
+                        (f64::from(x) * BOARD_TILE_S + TILE_S / 2.0) * window_scale,
+                        (f64::from(y) * BOARD_TILE_S + TILE_S / 2.0) * window_scale,
+                    )
+                })
+            };
+
+            if let (Some((fx, fy)), Some((tx, ty))) = (centre(from), centre(to)) {
+                Line::new(HINT_COLOUR, 1.5 * window_scale).draw_arrow(
+                    [fx, fy, tx, ty],
+                    5.0 * window_scale,
+                    &DrawState::default(),
+                    trans,
+                    graphics,
+                );
+            }
+        }
+
+        if let Some((share, _)) = self.eval_bar {
+            ///Width in pixels of the evaluation bar
+            const EVAL_BAR_W: f64 = 4.0;
+
+            let x = (LEFT_BOUND - EVAL_BAR_W * 1.5) * window_scale;
+            let height = (RIGHT_BOUND - LEFT_BOUND) * window_scale;
+            let white_height = height * share;
+            //white is at the bottom unless the board is flipped
+            let white_y = if is_flipped {
+                LEFT_BOUND * window_scale
+            } else {
+                LEFT_BOUND * window_scale + height - white_height
+            };
+
+            rectangle(
+                [0.1, 0.1, 0.1, 1.0],
+                [x, LEFT_BOUND * window_scale, EVAL_BAR_W * window_scale, height],
+                t,
+                graphics,
+            );
+            rectangle(
+                [0.95, 0.95, 0.95, 1.0],
+                [x, white_y, EVAL_BAR_W * window_scale, white_height],
+                t,
+                graphics,
+            );
+        }
+
+        if self.is_stale && !is_offline_board {
+            //dim the board so it's clear it might be out of date
+            rectangle(
+                STALE_COLOUR,
+                square(
+                    LEFT_BOUND * window_scale,
+                    LEFT_BOUND * window_scale,
+                    (RIGHT_BOUND - LEFT_BOUND) * window_scale,
+                ),
+                t,
+                graphics,
+            );
+        }
+        if let Some((x, y)) = self.cursor {
+            let y = if is_flipped { 7 - y } else { y };
+            Rectangle::new_border(CURSOR_COLOUR, window_scale).draw(
+                square(
+                    f64::from(x) * BOARD_TILE_S * window_scale,
+                    f64::from(y) * BOARD_TILE_S * window_scale,
+                    TILE_S * window_scale,
+                ),
+                &DrawState::default(),
+                trans,
+                graphics,
+            );
+        }
+        if is_offline_board {
+            Rectangle::new_border(OFFLINE_FRAME_COLOUR, window_scale).draw(
+                square(
+                    LEFT_BOUND * window_scale,
+                    LEFT_BOUND * window_scale,
+                    (RIGHT_BOUND - LEFT_BOUND) * window_scale,
+                ),
+                &DrawState::default(),
+                t,
+                graphics,
+            );
+        }
+
         {
             ///Size in pixels for pieces which have been taken
             const TAKEN_TILE_SIZE: f64 = TILE_S * 0.75;
-            ///Starting Y for Taken tiles, such that when all pieces are taken, it it centred
-            const START_Y: f64 = (BOARD_S - (TAKEN_TILE_SIZE * 16.0)) / 2.0; //16 pieces
-
-            let mut pieces = self.board.get_taken();
-            pieces.sort();
+            ///Size in pixels of the counts and totals next to taken pieces
+            const TAKEN_TEXT_S: f64 = 6.0;
+            ///Gap in pixels between the edge of the window or board and the taken pieces
+            const TAKEN_PADDING: f64 = 2.0;
+            ///Starting Y for Taken tiles, such that when one of every kind and the total are shown, it is centred
+            const START_Y: f64 = (BOARD_S - (TAKEN_TILE_SIZE * 7.0)) / 2.0; //6 kinds + total
 
-            let white_trans = t.trans(TAKEN_TILE_SIZE * window_scale, START_Y * window_scale);
-            let black_trans = t.trans(
-                (RIGHT_BOUND + TAKEN_TILE_SIZE) * window_scale,
-                START_Y * window_scale,
-            );
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let font_size = (TAKEN_TEXT_S * window_scale) as u32;
 
-            let mut white_dy = 0.0;
-            let mut black_dy = 0.0;
+            //white pieces on the left, black on the right
+            let columns = [TAKEN_PADDING, RIGHT_BOUND + TAKEN_PADDING]
+                .into_iter()
+                .zip(self.taken.columns());
+            for (x, column) in columns {
+                let Some(column) = column else {
+                    continue;
+                };
 
-            for p in pieces {
-                match self.cache.get(&p.to_file_name()) {
-                    Err(e) => errs
-                        .push(e.context(format!("cacher doesn't contain: {:?}", p.to_file_name()))),
-                    Ok(tex) => {
-                        if p.is_white {
-                            let img = Image::new().rect(square(
-                                0.0,
-                                white_dy * window_scale,
-                                TAKEN_TILE_SIZE * window_scale,
-                            ));
-                            white_dy += TAKEN_TILE_SIZE;
-                            img.draw(tex, &DrawState::default(), white_trans, graphics);
-                        } else {
+                let mut y = START_Y;
+                for (p, label) in &column.kinds {
+                    match self.cache.get(p.to_file_name()) {
+                        Err(e) => errs.push(
+                            e.context(format!("cacher doesn't contain: {:?}", p.to_file_name())),
+                        ),
+                        Ok(tex) => {
                             let img = Image::new().rect(square(
-                                0.0,
-                                black_dy * window_scale,
+                                x * window_scale,
+                                y * window_scale,
                                 TAKEN_TILE_SIZE * window_scale,
                             ));
-                            black_dy += TAKEN_TILE_SIZE;
-                            img.draw(tex, &DrawState::default(), black_trans, graphics);
+                            img.draw(tex, &DrawState::default(), t, graphics);
+                        }
+                    }
+
+                    if let Some(label) = label {
+                        let text_x = x + TAKEN_TILE_SIZE + TAKEN_PADDING / 2.0;
+                        let text_y = y + (TAKEN_TILE_SIZE + TAKEN_TEXT_S) / 2.0;
+                        if let Err(e) = Text::new_color([1.0; 4], font_size).draw(
+                            label,
+                            glyphs,
+                            &ctx.draw_state,
+                            t.trans(text_x * window_scale, text_y * window_scale),
+                            graphics,
+                        ) {
+                            errs.push(anyhow!("{e:?}").context("drawing taken count"));
                         }
                     }
+
+                    y += TAKEN_TILE_SIZE;
+                }
+
+                let text_y = y + (TAKEN_TILE_SIZE + TAKEN_TEXT_S) / 2.0;
+                if let Err(e) = Text::new_color([1.0; 4], font_size).draw(
+                    &column.total,
+                    glyphs,
+                    &ctx.draw_state,
+                    t.trans(x * window_scale, text_y * window_scale),
+                    graphics,
+                ) {
+                    errs.push(anyhow!("{e:?}").context("drawing taken total"));
                 }
             }
         }
 
         {
-            let (raw_x, raw_y) = if is_flipped {
-                (raw_mouse_coords.0, BOARD_S * window_scale - raw_mouse_coords.1)
-            } else {
-                raw_mouse_coords
-            };
+            let (raw_x, raw_y) = screen_mouse;
             if self.last_pressed.is_on_board() {
-                if let Some(piece) = self.board[self.last_pressed] {
-                    match self.cache.get(&piece.to_file_name()) {
+                if let Some(piece) = board[self.last_pressed] {
+                    match self.cache.get(piece.to_file_name()) {
                         Ok(tex) => {
                             let s = TILE_S * window_scale / 1.5;
                             let image =
@@ -333,6 +2559,88 @@ impl ChessGame {
             }
         }
 
+        if let Err(e) = self.render_promotion_picker(trans, graphics, window_scale, is_flipped) {
+            errs.push(e);
+        }
+
+        if let Some(presence) = self.opponent_presence {
+            ///Size in pixels of the opponent presence indicator
+            const PRESENCE_S: f64 = 6.0;
+
+            let colour = match presence {
+                PresenceState::Active => [0.2, 0.8, 0.2, 1.0],
+                PresenceState::Idle => [0.9, 0.7, 0.1, 1.0],
+                PresenceState::Gone => [0.5, 0.5, 0.5, 1.0],
+            };
+            let y_pos = (TOP_SPACE - PRESENCE_S) / 2.0;
+            ellipse(
+                colour,
+                square(
+                    LEFT_BOUND_PADDING * window_scale,
+                    y_pos * window_scale,
+                    PRESENCE_S * window_scale,
+                ),
+                t,
+                graphics,
+            );
+        }
+
+        if held {
+            ///Size in pixels of the grab point drawn instead of the OS cursor
+            const GRAB_S: f64 = 2.0;
+
+            //the OS cursor is hidden while holding a piece, so mark exactly where it is on top of the floating piece
+            let s = GRAB_S * window_scale;
+            ellipse(
+                [0.1, 0.1, 0.1, 1.0],
+                square(screen_mouse.0 - s, screen_mouse.1 - s, s * 2.0),
+                t,
+                graphics,
+            );
+            ellipse(
+                [1.0; 4],
+                square(screen_mouse.0 - s / 2.0, screen_mouse.1 - s / 2.0, s),
+                t,
+                graphics,
+            );
+        } else {
+            if let Err(e) = self.render_info_strip(ctx, graphics, glyphs, window_scale) {
+            errs.push(e);
+        }
+
+            if let (true, Some(bc)) = (show_tooltip, board_coords) {
+                if let Err(e) =
+                    self.render_tooltip(bc, screen_mouse, ctx, graphics, glyphs, window_scale)
+                {
+                    errs.push(e);
+                }
+            }
+        }
+
+        if let Err(e) = self.render_unavailable_banner(ctx, graphics, glyphs, window_scale) {
+            errs.push(e);
+        }
+
+        if let Err(e) = self.render_claim_draw_button(ctx, graphics, glyphs, window_scale) {
+            errs.push(e);
+        }
+
+        if self
+            .slides
+            .as_ref()
+            .is_some_and(|(_, since)| since.elapsed() >= SLIDE_TIME)
+        {
+            self.slides = None;
+        }
+
+        //keep drawing while anything is animating
+        self.redraw |= eval_moving
+            || self.slides.is_some()
+            || self.show_board_update.is_some()
+            || self
+                .hover
+                .is_some_and(|(_, since)| since.elapsed() < TOOLTIP_DELAY);
+
         if !errs.is_empty() {
             bail!("{errs:?}");
         }
@@ -340,6 +2648,488 @@ impl ChessGame {
         Ok(())
     }
 
+    ///Checks whether anything has changed which needs the window to be redrawn, and resets it.
+    ///
+    /// Input isn't tracked here, so the window should also be redrawn after any input.
+    pub fn take_redraw(&mut self) -> bool {
+        std::mem::take(&mut self.redraw)
+    }
+
+    ///Makes the next frames redraw the whole window - should be called when something is drawn over the game which it doesn't know about
+    pub fn invalidate_frame(&mut self) {
+        self.dirty.invalidate();
+    }
+
+    ///Gets the squares the selected piece can move to, or nothing if no piece is selected or the game is over
+    fn legal_move_squares(&self, board: &BoardContainer) -> Vec<(u8, u8)> {
+        if self.result.is_some() {
+            return vec![];
+        }
+        match self.last_pressed {
+            at @ Coords::OnBoard(_, _) => board
+                .legal_moves_from(at)
+                .into_iter()
+                .filter_map(|c| c.to_option())
+                .collect(),
+            Coords::OffBoard => vec![],
+        }
+    }
+
+    ///Works out how each square of the board is drawn, for the [`DirtyTracker`]
+    fn tile_states(&self, board: &BoardContainer, board_coords: Option<(u32, u32)>) -> Tiles {
+        ///Gets a square, if the coordinates are on the board
+        fn tile(tiles: &mut Tiles, (x, y): (u32, u32)) -> Option<&mut TileState> {
+            tiles
+                .get_mut(usize::try_from(x).ok()?)?
+                .get_mut(usize::try_from(y).ok()?)
+        }
+
+        let mut tiles = Tiles::default();
+        if let Some(t) = board_coords.and_then(|c| tile(&mut tiles, c)) {
+            t.highlighted = true;
+        }
+        if let Some(m) = &self.pending_move {
+            for c in [(m.x, m.y), (m.nx, m.ny)] {
+                if let Some(t) = tile(&mut tiles, c) {
+                    t.highlighted = true;
+                }
+            }
+        }
+        if let (true, Some(is_white)) = (self.show_threats, self.player_is_white) {
+            for (x, y) in board
+                .threatened_pieces(is_white)
+                .into_iter()
+                .filter_map(|c| c.to_option())
+            {
+                if let Some(t) = tile(&mut tiles, (u32::from(x), u32::from(y))) {
+                    t.threatened = true;
+                }
+            }
+        }
+        if let Some((x, y)) = self.cursor {
+            if let Some(t) = tile(&mut tiles, (u32::from(x), u32::from(y))) {
+                t.cursor = true;
+            }
+        }
+        for (x, y) in self.legal_move_squares(board) {
+            if let Some(t) = tile(&mut tiles, (u32::from(x), u32::from(y))) {
+                t.legal_move = true;
+            }
+        }
+
+        for col in 0..8_u8 {
+            for row in 0..8_u8 {
+                let t = &mut tiles[usize::from(col)][usize::from(row)];
+                t.piece = board[(col, row).into()];
+                t.selected = self.last_pressed == Coords::OnBoard(col, row);
+            }
+        }
+        tiles
+    }
+
+    ///Redraws only some of the squares, and the info strip below the board, clipped to each of them so the rest of the last frame drawn to the buffer is left as it is - see [`DirtyTracker`]
+    ///
+    /// # Errors
+    /// - Can fail if sprites aren't found in the [`Cacher`]
+    /// - Can fail if the info strip can't be rendered
+    #[allow(clippy::too_many_arguments)]
+    fn render_tiles(
+        &self,
+        dirty: &BTreeSet<(u8, u8)>,
+        tiles: &Tiles,
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        window_scale: f64,
+        is_flipped: bool,
+    ) -> Result<()> {
+        let t = ctx.transform;
+        let trans = t.trans(
+            LEFT_BOUND_PADDING * window_scale,
+            LEFT_BOUND_PADDING * window_scale,
+        );
+        let board_image = Image::new().rect(square(0.0, 0.0, BOARD_S * window_scale));
+        let board_tex = self
+            .cache
+            .get("board_alt.png")
+            .context("getting board_alt.png")?;
+        let is_offline_board = self.analysis.is_some() || self.practice.is_some();
+        let board_rect = square(
+            LEFT_BOUND * window_scale,
+            LEFT_BOUND * window_scale,
+            (RIGHT_BOUND - LEFT_BOUND) * window_scale,
+        );
+
+        for &(col, row) in dirty {
+            let tile = tiles[usize::from(col)][usize::from(row)];
+            let shown_row = if is_flipped { 7 - row } else { row };
+            let x = f64::from(col) * BOARD_TILE_S * window_scale;
+            let y = f64::from(shown_row) * BOARD_TILE_S * window_scale;
+
+            //the padding around the square is included, as the cursor is drawn over it
+            let cell = square(
+                LEFT_BOUND * window_scale + x,
+                LEFT_BOUND * window_scale + y,
+                BOARD_TILE_S * window_scale,
+            );
+            let ds = DrawState::default().scissor(
+                scissor_rect(&ctx, cell)
+                    .ae()
+                    .context("clipping to square")?,
+            );
+            board_image.draw(board_tex, &ds, t, graphics);
+
+            let image = Image::new().rect(square(x, y, TILE_S * window_scale));
+            if tile.highlighted {
+                let tex = self
+                    .cache
+                    .get("highlight.png")
+                    .context("getting highlight.png")?;
+                image.draw(tex, &ds, trans, graphics);
+            }
+            if let Some(piece) = tile.piece {
+                let name = if tile.selected {
+                    "selected.png"
+                } else {
+                    piece.to_file_name()
+                };
+                let tex = self
+                    .cache
+                    .get(&name)
+                    .with_context(|| format!("cacher doesn't contain: {name:?}"))?;
+                image.draw(tex, &ds, trans, graphics);
+            }
+            if tile.legal_move {
+                draw_legal_move(
+                    (x, y),
+                    tile.piece.is_some(),
+                    window_scale,
+                    &ds,
+                    trans,
+                    graphics,
+                );
+            }
+            if tile.threatened {
+                Rectangle::new(THREAT_COLOUR).draw(
+                    square(x, y, TILE_S * window_scale),
+                    &ds,
+                    trans,
+                    graphics,
+                );
+            }
+            if self.is_stale && !is_offline_board {
+                Rectangle::new(STALE_COLOUR).draw(board_rect, &ds, t, graphics);
+            }
+            if tile.cursor {
+                Rectangle::new_border(CURSOR_COLOUR, window_scale).draw(
+                    square(x, y, TILE_S * window_scale),
+                    &ds,
+                    trans,
+                    graphics,
+                );
+            }
+            if is_offline_board {
+                Rectangle::new_border(OFFLINE_FRAME_COLOUR, window_scale)
+                    .draw(board_rect, &ds, t, graphics);
+            }
+        }
+
+        //the times in the info strip change, and it can run past the edge of the board
+        let [left, _, width, _] = visible_rect(&ctx);
+        let strip = [
+            left,
+            RIGHT_BOUND * window_scale,
+            width,
+            (BOARD_S - RIGHT_BOUND) * window_scale,
+        ];
+        let mut strip_ctx = ctx;
+        strip_ctx.draw_state = DrawState::default().scissor(
+            scissor_rect(&ctx, strip)
+                .ae()
+                .context("clipping to info strip")?,
+        );
+        Rectangle::new([0.0, 0.0, 0.0, 1.0]).draw(strip, &strip_ctx.draw_state, t, graphics);
+        board_image.draw(board_tex, &strip_ctx.draw_state, t, graphics);
+        self.render_info_strip(strip_ctx, graphics, glyphs, window_scale)
+    }
+
+    ///Renders the promotion picker over the board while a promotion is being picked - the pieces the pawn can become, in a column from the square it is moving to
+    ///
+    /// # Errors
+    /// - Can fail if piece sprites aren't found in the [`Cacher`]
+    fn render_promotion_picker(
+        &self,
+        trans: Matrix2d,
+        graphics: &mut G2d,
+        window_scale: f64,
+        is_flipped: bool,
+    ) -> Result<()> {
+        let Some(m) = self.promotion else {
+            return Ok(());
+        };
+        //white pawns promote on the top rank
+        let is_white = m.ny == 0;
+
+        for (kind, (px, py)) in promotion_options(&m) {
+            let x = f64::from(px) * BOARD_TILE_S * window_scale;
+            let y = if is_flipped { 7 - py } else { py };
+            let y = f64::from(y) * BOARD_TILE_S * window_scale;
+            rectangle(
+                PROMOTION_PICKER_COLOUR,
+                square(x, y, TILE_S * window_scale),
+                trans,
+                graphics,
+            );
+
+            let piece = ChessPiece { kind, is_white };
+            let tex = self
+                .cache
+                .get(piece.to_file_name())
+                .with_context(|| format!("getting {:?}", piece.to_file_name()))?;
+            let image = Image::new().rect(square(x, y, TILE_S * window_scale));
+            image.draw(tex, &DrawState::default(), trans, graphics);
+        }
+        Ok(())
+    }
+
+    ///Renders a banner across the top of the window while the client to make requests with can't be built, saying why - the board underneath is the last known or offline one
+    ///
+    /// # Errors
+    /// - Can fail if the text can't be rendered
+    fn render_unavailable_banner(
+        &self,
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        window_scale: f64,
+    ) -> Result<()> {
+        ///Size in pixels of the banner text
+        const TEXT_S: f64 = 6.0;
+        ///Colour behind the banner text
+        const BANNER_COLOUR: [f32; 4] = [0.6, 0.1, 0.1, 0.9];
+
+        let Some(reason) = &self.unavailable else {
+            return Ok(());
+        };
+
+        rectangle(
+            BANNER_COLOUR,
+            [
+                0.0,
+                0.0,
+                BOARD_S * window_scale,
+                TEXT_S * 2.0 * window_scale,
+            ],
+            ctx.transform,
+            graphics,
+        );
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let font_size = (TEXT_S * window_scale) as u32;
+        Text::new_color([1.0; 4], font_size)
+            .draw(
+                &format!("Can't make requests, retrying: {reason}"),
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform
+                    .trans(TEXT_S / 2.0 * window_scale, TEXT_S * 1.5 * window_scale),
+                graphics,
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("drawing unavailable banner")
+    }
+
+    ///Renders the claim draw button above the board, if a draw can be claimed - see [`ChessGame::press_claim_draw_button`]
+    ///
+    /// # Errors
+    /// - Can fail if the text can't be rendered
+    fn render_claim_draw_button(
+        &self,
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        window_scale: f64,
+    ) -> Result<()> {
+        ///Size in pixels of the button text
+        const TEXT_S: f64 = 6.0;
+
+        if !self.can_claim_draw() {
+            return Ok(());
+        }
+
+        let [x, y, _, h] = CLAIM_DRAW_RECT;
+        rectangle(
+            CLAIM_DRAW_COLOUR,
+            CLAIM_DRAW_RECT.map(|p| p * window_scale),
+            ctx.transform,
+            graphics,
+        );
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let font_size = (TEXT_S * window_scale) as u32;
+        Text::new_color([1.0; 4], font_size)
+            .draw(
+                "Claim draw",
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform.trans(
+                    (x + TEXT_S / 3.0) * window_scale,
+                    (y + (h + TEXT_S) / 2.0) * window_scale,
+                ),
+                graphics,
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("drawing claim draw button")
+    }
+
+    ///Renders a line of information about the game below the board - the game id, the move number, the player's colour, and how long ago the board was updated.
+    ///
+    /// The move number is counted from when the game was opened, as the server doesn't send it. If the server says when the last move was made, that is shown instead of when the board was updated, corrected for the skew between the clocks.
+    ///
+    /// # Errors
+    /// - Can fail if the text can't be rendered
+    fn render_info_strip(
+        &self,
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        window_scale: f64,
+    ) -> Result<()> {
+        ///Size in pixels of the info strip text
+        const TEXT_S: f64 = 6.0;
+
+        let colour = match self.player_is_white {
+            Some(true) => "White",
+            Some(false) => "Black",
+            None => "?",
+        };
+        let ago = |d: Duration| match d.as_secs() {
+            s @ 0..=59 => format!("{s}s ago"),
+            s @ 60..=3599 => format!("{}m ago", s / 60),
+            s => format!("{}h ago", s / 3600),
+        };
+        let updated = match (self.moved_at, self.last_refresh) {
+            (Some(ms), _) => {
+                let skew = self
+                    .refresher
+                    .as_ref()
+                    .and_then(ListRefresher::clock_skew)
+                    .unwrap_or_default();
+                format!("Moved {}", ago(skew.since_server_millis(ms)))
+            }
+            (None, Some(i)) => format!("Updated {}", ago(i.elapsed())),
+            (None, None) => "Updated never".to_string(),
+        };
+        //only shown if the server says it keeps clocks, so a stale time isn't left up
+        let capabilities = self
+            .refresher
+            .as_ref()
+            .and_then(ListRefresher::capabilities);
+        let clocks = match (capabilities, self.server_clocks) {
+            (Some(capabilities), Some((white, black))) if capabilities.clocks => {
+                let (white, black) = (white.as_secs(), black.as_secs());
+                format!(
+                    " | White {}:{:02} Black {}:{:02}",
+                    white / 60,
+                    white % 60,
+                    black / 60,
+                    black % 60
+                )
+            }
+            _ => String::new(),
+        };
+        let rules = self.draw_rule_counters();
+        let text = match &self.practice {
+            _ if self.analysis.is_some() => format!(
+                "Analysing from game {} | Nothing is sent | A to close",
+                self.id
+            ),
+            Some(practice) => format!(
+                "Practising from game {} | {} practice moves | Nothing is sent",
+                self.id,
+                practice.moves()
+            ),
+            None => format!(
+                "Game {} | Move {} | You: {colour} | {updated}{clocks}{rules}",
+                self.id,
+                self.board_changes / 2 + 1
+            ),
+        };
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let font_size = (TEXT_S * window_scale) as u32;
+        let y = RIGHT_BOUND + (BOARD_S - RIGHT_BOUND + TEXT_S) / 2.0;
+
+        Text::new_color([0.8, 0.8, 0.8, 1.0], font_size)
+            .draw(
+                &text,
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform
+                    .trans(LEFT_BOUND_PADDING * window_scale, y * window_scale),
+                graphics,
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("drawing info strip")
+    }
+
+    ///Renders a tooltip next to the mouse with the name of a square, and the piece on it if there is one
+    ///
+    /// # Errors
+    /// - Can fail if the text can't be rendered or measured
+    fn render_tooltip(
+        &self,
+        (x, y): (u32, u32),
+        mouse: (f64, f64),
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        window_scale: f64,
+    ) -> Result<()> {
+        ///Size in pixels of the tooltip text
+        const TEXT_S: f64 = 6.0;
+        ///Padding in pixels around the tooltip text
+        const TOOLTIP_PADDING: f64 = 2.0;
+
+        let coords: Coords = (x, y).try_into()?;
+        let mut text = coords.to_algebraic().ae().context("naming square")?;
+        if let Some(p) = self.shown_board()[coords] {
+            text = format!("{text} - {}", p.description());
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let font_size = (TEXT_S * window_scale) as u32;
+        let width = glyphs
+            .width(font_size, &text)
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("measuring tooltip")?;
+        let padding = TOOLTIP_PADDING * window_scale;
+        let height = f64::from(font_size) + 2.0 * padding;
+
+        //keep the tooltip below and to the right of the cursor, unless it would go off the window
+        let max = BOARD_S * window_scale;
+        let tx = (mouse.0 + padding * 4.0).min(max - width - 2.0 * padding);
+        let ty = (mouse.1 + padding * 4.0).min(max - height);
+
+        rectangle(
+            [0.1, 0.1, 0.1, 0.85],
+            [tx, ty, width + 2.0 * padding, height],
+            ctx.transform,
+            graphics,
+        );
+        Text::new_color([1.0; 4], font_size)
+            .draw(
+                &text,
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform
+                    .trans(tx + padding, ty + padding + f64::from(font_size)),
+                graphics,
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("drawing tooltip")
+    }
+
     ///Updates the board using messages from the [`ListRefresher`]
     ///
     /// Should be called ASAP after instantiating game, and often afterwards.
@@ -349,26 +3139,71 @@ impl ChessGame {
     // #[tracing::instrument(skip(self))]
     #[allow(irrefutable_let_patterns)]
     pub fn update_list(&mut self, ignore_timer: bool) -> Result<()> {
+        if self
+            .refresher
+            .as_mut()
+            .is_some_and(ListRefresher::restart_if_wedged)
+        {
+            self.status = Some("Connection to the server got stuck, so it was restarted".into());
+            self.redraw = true;
+        }
+
         let mut updated = false;
-        match self.refresher.try_recv() {
+        let received = self
+            .refresher
+            .as_ref()
+            .map_or(Err(TryRecvError::Empty), ListRefresher::try_recv);
+        if let Ok(msg) = &received {
+            self.redraw = true;
+            if let Some(recorded) = recorded_message(msg) {
+                self.record_input(RecordedInput::Message(recorded));
+            }
+        }
+        match received {
             Ok(msg) => match msg {
                 MessageToGame::UpdateBoard(msg) => match msg {
                     BoardMessage::TmpMove(m) => {
-                        if let Either::Left(bo) = self.board.clone() {
-                            self.board = Either::Right(bo.make_move(m));
-                        } else {
-                            bail!("need move update before can do: {m:?}");
-                        }
+                        //a move made while others are waiting goes on top of them, so they can all be undone if the server rejects them
+                        self.board = Either::Right(match self.board.clone() {
+                            Either::Left(bo) => bo.make_move(m),
+                            Either::Right(bo) => bo.make_move(m),
+                        });
                     }
                     BoardMessage::Move(outcome) => {
+                        //whatever the outcome, the player has seen it
+                        if self.persists() {
+                            clear_pending(self.id)
+                                .context("clearing acknowledged move")
+                                .warn();
+                        }
                         if let Either::Right(bo) = self.board.clone() {
+                            //the worker sends queued moves in the order they were made, so the server is answering about the oldest one waiting
+                            let m = bo.unconfirmed_moves().first().map(|made| made.m);
                             match outcome {
                                 MoveOutcome::Worked(taken) => {
-                                    self.board = Either::Left(bo.move_worked(taken));
+                                    self.log_move(MoveLogEvent::Acknowledged { m, taken });
+                                    self.moves_made += 1;
+                                    self.status = Some(String::new());
+                                    self.board = bo.confirm_move(taken);
+                                }
+                                MoveOutcome::Invalid(e) => {
+                                    self.log_move(MoveLogEvent::Rejected {
+                                        m,
+                                        reason: e.to_string(),
+                                    });
+                                    telemetry().error(error_category(&e));
+                                    updated = true;
+                                    info!(%e, "Resetting pieces");
+                                    self.status = Some(e.to_string());
+                                    //anything made after it was on a board with it, so it all goes
+                                    self.board = Either::Left(bo.undo_move());
                                 }
-                                MoveOutcome::Invalid | MoveOutcome::CouldntProcessMove => {
+                                MoveOutcome::CouldntProcessMove => {
+                                    self.log_move(MoveLogEvent::Unreachable { m });
+                                    telemetry().error("couldnt_process_move");
                                     updated = true;
                                     info!("Resetting pieces");
+                                    self.status = Some("Couldn't reach the server".into());
                                     self.board = Either::Left(bo.undo_move());
                                 }
                             }
@@ -376,15 +3211,66 @@ impl ChessGame {
                             bail!("need move to update with outcome: {outcome:?}");
                         }
                     }
-                    BoardMessage::NoConnectionList => {
-                        self.board = Either::Left(no_connection_list());
+                    BoardMessage::Offline(offline) => {
+                        if let Some(last_known) = self.last_known.clone() {
+                            self.board = Either::Left(last_known);
+                            self.is_stale = true;
+                            self.status = Some("Offline - showing last known board".into());
+                        } else {
+                            self.board = Either::Left(offline.board());
+                        }
                     }
                     BoardMessage::NewList(l) => {
                         updated = true;
-                        self.board = Either::Left(Board::new_json(l)?);
+
+                        let to_save = l.pieces().clone();
+                        let (board, info) = l.into_parts()?;
+                        self.moved_at = info.moved_at.or(self.moved_at);
+                        self.server_clocks = info.clocks.or(self.server_clocks);
+                        if let Some(result) = info.result {
+                            self.status =
+                                Some(format!("Game over - {result} - S to save a scorecard"));
+                            self.result = Some(result);
+                            self.record_game();
+                        }
+                        self.slides = None;
+                        self.use_new_board(board, to_save);
                     },
+                    BoardMessage::ApplyMoves(moves) => {
+                        updated |= self.apply_moves(&moves);
+                    }
+                    BoardMessage::Dropped(moves) => {
+                        info!(?moves, "Moves dropped after an earlier one failed");
+                        self.status = Some(format!(
+                            "{} queued move(s) weren't sent, as an earlier move failed",
+                            moves.len()
+                        ));
+                        for m in moves {
+                            self.log_move(MoveLogEvent::Dropped { m });
+                        }
+                    }
                     BoardMessage::UseExisting => {}
                 },
+                MessageToGame::OpponentPresence(presence) => {
+                    self.opponent_presence = Some(presence);
+                }
+                MessageToGame::InvalidResponse(diag) => {
+                    telemetry().error("invalid_response");
+                    self.last_diagnostic = Some(diag);
+                }
+                MessageToGame::RequestFinished(sample) => {
+                    self.request_stats.record(&sample);
+                }
+                MessageToGame::History(history) => self.use_server_history(history),
+                MessageToGame::Checksum(expected) => self.verify_checksum(expected),
+                MessageToGame::DrawClaim(outcome) => self.use_draw_claim(outcome),
+                MessageToGame::Connection(state) => {
+                    self.unavailable = match state {
+                        ConnectionState::Available => None,
+                        ConnectionState::Unavailable(reason) => Some(reason),
+                    };
+                    self.redraw = true;
+                }
             },
             Err(e) => {
                 if e != TryRecvError::Empty {
@@ -398,16 +3284,195 @@ impl ChessGame {
             self.show_board_update = Some(DoOnInterval::new(Duration::from_millis(1_500)));
         }
 
-        self.refresher
-            .send_msg(if ignore_timer {
-                MessageToWorker::UpdateNOW
-            } else {
-                MessageToWorker::UpdateList
-            })
-            .ae()
+        for event in self.events.drain() {
+            if let GameEvent::ConnectionChanged { id, server } = event {
+                if id == self.id {
+                    self.status = Some(format!("Using server {server}"));
+                    self.redraw = true;
+                }
+            }
+        }
+
+        if let Some(_doiu) = self.memory_metrics_timer.get_updater() {
+            self.publish_memory_metrics();
+        }
+        self.poll_hint();
+        self.poll_practice();
+        self.poll_import();
+
+        self.send_to_worker(if ignore_timer {
+            MessageToWorker::UpdateNOW
+        } else {
+            MessageToWorker::UpdateList
+        })
+        .ae()
+    }
+}
+
+///Gets the pieces shown in the promotion picker for a move, with the square each is drawn on - running from the square the pawn is moving to, towards the middle of the board
+fn promotion_options(m: &JSONMove) -> impl Iterator<Item = (ChessPieceKind, (u32, u32))> {
+    let (nx, ny) = (m.nx, m.ny);
+    PROMOTION_KINDS
+        .into_iter()
+        .zip(0..)
+        .map(move |(kind, i)| (kind, (nx, if ny == 0 { i } else { ny - i })))
+}
+
+///Describes whether either side is in check for the status line, or `None` if neither is
+fn check_status(board: &Board<CanMovePiece>) -> Option<String> {
+    [true, false]
+        .into_iter()
+        .find(|white| board.is_in_check(*white))
+        .map(|white| format!("Check! {} is in check", colour_name(white)))
+}
+
+///Loads the [`MoveHistory`] of a game, rebuilding it from the move log if it wasn't saved
+fn load_history(id: u32) -> Option<MoveHistory> {
+    load_moves(id)
+        .unwrap_or_else(|e| {
+            warn!(%e, "Unable to load move history");
+            None
+        })
+        .or_else(|| {
+            let rebuilt = load_move_log(id)
+                .map(|log| rebuild_history(&log))
+                .unwrap_or_else(|e| {
+                    warn!(%e, "Unable to load move log");
+                    None
+                });
+            if let Some(history) = &rebuilt {
+                info!(
+                    moves = history.moves().len(),
+                    "Rebuilt move history from move log"
+                );
+            }
+            rebuilt
+        })
+}
+
+///Works out whether the board alone says the game is over - by checkmate, stalemate, or neither side having the material to mate - with a description of why.
+///
+/// Stalemate is only looked for if `white_to_move` is known.
+fn board_result(
+    board: &Board<CanMovePiece>,
+    white_to_move: Option<bool>,
+) -> Option<(JSONResult, String)> {
+    if let Some(white) = [true, false]
+        .into_iter()
+        .find(|white| board.is_checkmate(*white))
+    {
+        let result = if white {
+            JSONResult::BlackWins
+        } else {
+            JSONResult::WhiteWins
+        };
+        return Some((result, format!("Checkmate - {} wins", colour_name(!white))));
+    }
+    board
+        .draw_reason(white_to_move)
+        .map(|reason| (JSONResult::Draw, format!("Draw by {reason}")))
+}
+
+///Gets the name of a colour, for the status line
+const fn colour_name(white: bool) -> &'static str {
+    if white {
+        "White"
+    } else {
+        "Black"
+    }
+}
+
+///Describes a [`Practice`] for the status line - who moves next, and how to go back to the live game
+fn practice_status(practice: &Practice) -> String {
+    let opponent = match practice.opponent() {
+        PracticeOpponent::Nobody => "both sides",
+        PracticeOpponent::Engine { .. } => "the engine",
+    };
+    let secs = |white| practice.thinking_time(white).as_secs();
+    let (white, black) = (secs(true), secs(false));
+    format!(
+        "Practice against {opponent}, {} moves (white {}:{:02}, black {}:{:02}) - nothing is sent. P to go back",
+        practice.moves(),
+        white / 60,
+        white % 60,
+        black / 60,
+        black % 60
+    )
+}
+
+///Describes a [`Blunder`] for the status line, asking whether to send the move anyway. The refutation is written in Standard Algebraic Notation, on the board `after` the move.
+fn describe_blunder<S: BoardMoveState>(blunder: Blunder, after: &Board<S>) -> String {
+    let refutation = blunder
+        .refutation
+        .and_then(|m| to_san(after, m).ok())
+        .map(|san| format!(" after {san}"))
+        .unwrap_or_default();
+    let consequence = if blunder.loses_king {
+        "This lets your king be taken".to_string()
+    } else {
+        format!(
+            "This loses about {:.1} pawns",
+            f64::from(blunder.loss) / 100.0
+        )
+    };
+
+    format!("Are you sure? {consequence}{refutation} - Enter to send anyway, right click to cancel")
+}
+
+///Writes a move, as `(from, to)`, on either kind of board in a [`BoardContainer`] - see [`to_san_promoting`]
+fn container_san(
+    board: &BoardContainer,
+    mv: (Coords, Coords),
+    promotion: ChessPieceKind,
+) -> Result<String> {
+    match board {
+        Either::Left(b) => to_san_promoting(b, mv, promotion),
+        Either::Right(b) => to_san_promoting(b, mv, promotion),
+    }
+}
+
+///Marks a square the selected piece can move to, at `(x, y)` from the top left of the board - a dot on an empty square, or a ring around a piece which would be taken
+fn draw_legal_move(
+    (x, y): (f64, f64),
+    capture: bool,
+    window_scale: f64,
+    ds: &DrawState,
+    trans: Matrix2d,
+    graphics: &mut G2d,
+) {
+    let tile = TILE_S * window_scale;
+    if capture {
+        Ellipse::new_border(LEGAL_MOVE_COLOUR, tile / 16.0).draw(
+            square(x, y, tile),
+            ds,
+            trans,
+            graphics,
+        );
+    } else {
+        let dot = tile / 3.0;
+        Ellipse::new(LEGAL_MOVE_COLOUR).draw(
+            square(x + (tile - dot) / 2.0, y + (tile - dot) / 2.0, dot),
+            ds,
+            trans,
+            graphics,
+        );
     }
 }
 
+///Gets the middle of a square in board pixels, as if the mouse was there
+fn square_centre((x, y): (u8, u8), mult: f64) -> (f64, f64) {
+    let centre = |v: u8| (f64::from(v) + 0.5) * BOARD_TILE_S * mult;
+    (centre(x), centre(y))
+}
+
+///Counts the legal moves the piece at `at` could make, and how many pieces attack and defend it, for [`ChessGame::piece_info`]
+fn piece_summary<S: BoardMoveState>(board: &Board<S>, at: Coords, white: bool) -> (usize, u8, u8) {
+    let moves = board.legal_moves_from(at).len();
+    let attackers = AttackMap::new(board, !white).attackers(at);
+    let defenders = AttackMap::new(board, white).attackers(at);
+    (moves, attackers, defenders)
+}
+
 ///Converts a pixel to a board coordinate, assuming that the mouse cursor is on the board
 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 pub fn to_board_coord(p: f64, mult: f64) -> u32 {
@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use async_chess_client::chess::{
+    engine::HintEngine, opening_book::OpeningBook, tablebase::Tablebase,
+};
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string},
+    path::PathBuf,
+    time::Duration,
+};
+
+///How long a hint arrow is shown for
+pub const HINT_DURATION: Duration = Duration::from_secs(4);
+
+///Settings for the hint key
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HintConfig {
+    ///The engine which suggests the moves
+    pub engine: HintEngine,
+    ///The most hints which can be used in one game - `Some(0)` turns hints off, eg. for rated play, and `None` is no limit
+    pub max_per_game: Option<u32>,
+    ///A Polyglot opening book - while the position is in it, hints suggest book moves without asking the engine
+    pub book: Option<PathBuf>,
+    ///The file with the Polyglot keys the book is looked up with - see [`async_chess_client::chess::opening_book::PolyglotKeys`]
+    pub book_keys: Option<PathBuf>,
+    ///A directory of Syzygy endgame tablebases - positions with few enough pieces get a perfect move and an exact result. Needs the `syzygy` feature.
+    pub tablebase: Option<PathBuf>,
+}
+
+///Where a hint came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintSource {
+    ///The [`HintConfig::engine`]
+    Engine,
+    ///The opening book
+    Book,
+    ///The endgame tablebase
+    Tablebase,
+}
+
+impl HintSource {
+    ///Gets the label shown after the hint number in the status
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Engine => "",
+            Self::Book => " (book)",
+            Self::Tablebase => " (tablebase)",
+        }
+    }
+}
+
+impl HintConfig {
+    ///Checks whether another hint can be used, after `used` hints in this game
+    #[must_use]
+    pub fn allows(&self, used: u32) -> bool {
+        self.max_per_game.map_or(true, |max| used < max)
+    }
+
+    ///Loads the opening book, if one has been set
+    ///
+    /// # Errors
+    /// - The book is set without the keys
+    /// - Fail to load the book or keys
+    #[tracing::instrument]
+    pub fn load_book(&self) -> Result<Option<OpeningBook>> {
+        let Some(book) = &self.book else {
+            return Ok(None);
+        };
+        let keys = self
+            .book_keys
+            .as_ref()
+            .ae()
+            .context("opening book set without polyglot keys")?;
+        OpeningBook::load(book, keys).map(Some)
+    }
+
+    ///Opens the endgame tablebase, if one has been set
+    ///
+    /// # Errors
+    /// - Fail to open the tablebase - see [`Tablebase::open`]
+    #[tracing::instrument]
+    pub fn load_tablebase(&self) -> Result<Option<Tablebase>> {
+        self.tablebase
+            .as_deref()
+            .map(Tablebase::open)
+            .transpose()
+    }
+}
+
+///Loads the number of hints used in each game, by game id
+///
+/// # Errors
+/// - Fail to get the path using [`counts_path`]
+/// - Fail to read in the file
+/// - Fail to parse the contents
+fn load_counts() -> Result<BTreeMap<u32, u32>> {
+    let path = counts_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+    from_str(&cntnts).with_context(|| format!("reading contents {cntnts}"))
+}
+
+///Gets the number of hints used in a game, so that closing the window doesn't reset the limit
+///
+/// # Errors
+/// - Fail to load the counts
+#[tracing::instrument]
+pub fn hints_used(id: u32) -> Result<u32> {
+    Ok(load_counts()?.get(&id).copied().unwrap_or_default())
+}
+
+///Sets the number of hints used in a game - `0` removes the game, eg. when the board is restarted for a new game
+///
+/// # Errors
+/// - Fail to load the counts
+/// - Fail to convert the counts to JSON
+/// - Fail to write to the file
+#[tracing::instrument]
+pub fn set_hints_used(id: u32, used: u32) -> Result<()> {
+    let mut counts = load_counts()?;
+    if used == 0 {
+        counts.remove(&id);
+    } else {
+        counts.insert(id, used);
+    }
+
+    let path = counts_path()?;
+    let st = to_string(&counts).context("turning hint counts to string")?;
+    std::fs::write(&path, st).with_context(|| format!("writing to {path:?}"))
+}
+
+///Gets the path of the hint counts - `hints.json` in the project data directory, creating the directory if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the data directory
+fn counts_path() -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dd = pd.data_dir();
+    create_dir_all(dd).context("creating data directory")?;
+    Ok(dd.join("hints.json"))
+}
@@ -0,0 +1,93 @@
+use crate::cacher::assets_folder;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::from_str;
+use std::{collections::HashMap, fmt::Display, fs::read_to_string};
+
+///A language the launcher can be shown in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Language {
+    ///English - the strings in the code, so it needs no locale file
+    #[default]
+    English,
+    ///French
+    French,
+}
+
+impl Language {
+    ///All of the languages, in the order they are shown in the selector
+    pub const ALL: [Language; 2] = [Language::English, Language::French];
+
+    ///Gets the code used for the locale file - `assets/locales/{code}.json`
+    #[must_use]
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::French => "fr",
+        }
+    }
+
+    ///Gets the name of the language, in that language
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::English => "English",
+            Self::French => "Français",
+        }
+    }
+}
+
+///Translations of the English strings into one language.
+///
+/// Locale files are JSON objects from each English string to its translation. Any string without a translation is shown in English, so locale files can be partial.
+#[derive(Debug, Default)]
+pub struct Translations {
+    ///Translations keyed by the English string
+    strings: HashMap<String, String>,
+}
+
+impl Translations {
+    ///Loads the translations for a language from its locale file in the [`assets_folder`]
+    ///
+    /// # Errors
+    /// - Fail to find the assets folder
+    /// - Fail to read in the locale file
+    /// - Fail to parse the contents
+    #[tracing::instrument]
+    pub fn load(language: Language) -> Result<Self> {
+        if language == Language::English {
+            return Ok(Self::default());
+        }
+
+        let path = assets_folder()?
+            .join("locales")
+            .join(format!("{}.json", language.code()));
+        let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+        let strings = from_str(&cntnts).context("parsing locale file")?;
+        Ok(Self { strings })
+    }
+
+    ///Translates a string, or returns it as is if there is no translation
+    #[must_use]
+    pub fn tr(&self, english: &str) -> String {
+        self.strings
+            .get(english)
+            .map_or_else(|| english.to_string(), Clone::clone)
+    }
+
+    ///Translates a string with `{}` placeholders, then fills them in order with the arguments
+    #[must_use]
+    pub fn fill(&self, english: &str, args: &[&dyn Display]) -> String {
+        let mut args = args.iter();
+        let translated = self.tr(english);
+        let mut parts = translated.split("{}");
+        let mut filled = parts.next().unwrap_or_default().to_string();
+        for part in parts {
+            if let Some(arg) = args.next() {
+                filled += &arg.to_string();
+            }
+            filled += part;
+        }
+        filled
+    }
+}
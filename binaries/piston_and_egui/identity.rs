@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use std::{
+    fs::{create_dir_all, read_to_string},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+///Gets the identity of this player, used to tell the server who is sending heartbeats.
+///
+/// The identity is generated on first use and then stored as `player_id` in the project data directory, so it stays the same between runs.
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the data directory
+/// - Fail to read in an existing identity, or write a new one
+#[tracing::instrument]
+pub fn player_identity() -> Result<String> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dd = pd.data_dir();
+    create_dir_all(dd).context("creating data directory")?;
+    let path = dd.join("player_id");
+
+    if path.exists() {
+        let existing =
+            read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+        let existing = existing.trim();
+        if !existing.is_empty() {
+            return Ok(existing.to_string());
+        }
+    }
+
+    //doesn't need to be cryptographically random, just unlikely to collide with other players
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let identity = format!("{nanos:x}{:x}", std::process::id());
+    info!(%identity, "Generated new player identity");
+
+    std::fs::write(&path, &identity).context("Write to file")?;
+    Ok(identity)
+}
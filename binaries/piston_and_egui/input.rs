@@ -0,0 +1,151 @@
+use piston_window::{ControllerAxisArgs, ControllerButton, ControllerHat, HatState};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+///How long the stick has to be held over before the cursor starts repeating
+const STICK_REPEAT_DELAY: Duration = Duration::from_millis(400);
+///How often the cursor moves while the stick is held over, once it has started repeating
+const STICK_REPEAT: Duration = Duration::from_millis(150);
+
+///Something the player wants to do on the board, whichever device it came from - so the piston loop can deal with controllers the same way, alongside the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardAction {
+    ///Moves the square cursor by this many squares, as seen on the screen - negative `y` is up
+    Cursor((i8, i8)),
+    ///Picks up the piece under the cursor, or drops the held one there
+    Select,
+    ///Drops the held piece back, and cancels any move waiting to be confirmed
+    Cancel,
+    ///Goes back a move in the history
+    HistoryBack,
+    ///Goes forward a move in the history
+    HistoryForward,
+}
+
+///Which controller buttons do what. The defaults follow the SDL game controller layout.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerConfig {
+    ///The button for [`BoardAction::Select`] - A
+    pub select: u8,
+    ///The button for [`BoardAction::Cancel`] - B
+    pub cancel: u8,
+    ///The button for [`BoardAction::HistoryBack`] - the left shoulder
+    pub history_back: u8,
+    ///The button for [`BoardAction::HistoryForward`] - the right shoulder
+    pub history_forward: u8,
+    ///The d-pad buttons, for controllers which send it as buttons rather than a hat - up, down, left and right
+    pub dpad: [u8; 4],
+    ///How far the stick has to be pushed, from `0.0` to `1.0`, before it moves the cursor
+    pub dead_zone: f64,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        Self {
+            select: 0,
+            cancel: 1,
+            history_back: 9,
+            history_forward: 10,
+            dpad: [11, 12, 13, 14],
+            dead_zone: 0.5,
+        }
+    }
+}
+
+///Turns controller events from piston into [`BoardAction`]s using a [`ControllerConfig`].
+///
+/// Holding the stick over moves the cursor once, then keeps moving it every [`STICK_REPEAT`] after [`STICK_REPEAT_DELAY`].
+#[derive(Debug)]
+pub struct ControllerInput {
+    ///Which buttons do what
+    config: ControllerConfig,
+    ///Which way the stick is pushed past the dead zone, on each axis
+    stick: (i8, i8),
+    ///When the cursor should next move from the stick, if it is pushed over
+    next_repeat: Option<Instant>,
+}
+
+impl ControllerInput {
+    ///Creates a new `ControllerInput`, with the stick in the middle
+    #[must_use]
+    pub const fn new(config: ControllerConfig) -> Self {
+        Self {
+            config,
+            stick: (0, 0),
+            next_repeat: None,
+        }
+    }
+
+    ///Gets the action for a button press, if it has one
+    #[must_use]
+    pub fn button(&self, b: ControllerButton) -> Option<BoardAction> {
+        let c = &self.config;
+        let [up, down, left, right] = c.dpad;
+        Some(match b.button {
+            x if x == c.select => BoardAction::Select,
+            x if x == c.cancel => BoardAction::Cancel,
+            x if x == c.history_back => BoardAction::HistoryBack,
+            x if x == c.history_forward => BoardAction::HistoryForward,
+            x if x == up => BoardAction::Cursor((0, -1)),
+            x if x == down => BoardAction::Cursor((0, 1)),
+            x if x == left => BoardAction::Cursor((-1, 0)),
+            x if x == right => BoardAction::Cursor((1, 0)),
+            _ => return None,
+        })
+    }
+
+    ///Gets the cursor movement for the d-pad, for controllers which send it as a hat
+    #[must_use]
+    pub const fn hat(h: ControllerHat) -> Option<BoardAction> {
+        let by = match h.state {
+            HatState::Centered => return None,
+            HatState::Up => (0, -1),
+            HatState::Down => (0, 1),
+            HatState::Left => (-1, 0),
+            HatState::Right => (1, 0),
+            HatState::LeftUp => (-1, -1),
+            HatState::LeftDown => (-1, 1),
+            HatState::RightUp => (1, -1),
+            HatState::RightDown => (1, 1),
+        };
+        Some(BoardAction::Cursor(by))
+    }
+
+    ///Deals with the stick moving. Only the first two axes are used - the left stick on most controllers.
+    ///
+    /// Returns a cursor movement if the stick has just been pushed over.
+    pub fn axis(&mut self, args: &ControllerAxisArgs) -> Option<BoardAction> {
+        let pushed = if args.position >= self.config.dead_zone {
+            1
+        } else if args.position <= -self.config.dead_zone {
+            -1
+        } else {
+            0
+        };
+
+        let before = self.stick;
+        match args.axis {
+            0 => self.stick.0 = pushed,
+            1 => self.stick.1 = pushed,
+            _ => return None,
+        }
+
+        if self.stick == (0, 0) {
+            self.next_repeat = None;
+            None
+        } else if self.stick == before {
+            None
+        } else {
+            self.next_repeat = Some(Instant::now() + STICK_REPEAT_DELAY);
+            Some(BoardAction::Cursor(self.stick))
+        }
+    }
+
+    ///Checks whether the cursor should move again from the stick being held over. Should be called often - eg. on every update tick.
+    pub fn poll(&mut self) -> Option<BoardAction> {
+        let due = self.next_repeat.filter(|at| Instant::now() >= *at)?;
+        self.next_repeat = Some(due + STICK_REPEAT);
+        Some(BoardAction::Cursor(self.stick))
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{game::ChessGame, piston::PistonConfig};
+use anyhow::{Context, Result};
+use async_chess_client::{
+    net::{
+        list_refresher::{BoardMessage, MessageToGame},
+        recorder::NetworkSession,
+    },
+    util::error_ext::ToAnyhowNotErr,
+};
+use directories::ProjectDirs;
+use piston_window::{Key, MouseButton};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+///How long a replay waits for the messages recorded after an input before it counts as diverged
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+///How long a replay sleeps between checks for messages from the worker
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+///Something the player did to the game, or a message the game dealt with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedInput {
+    ///A mouse button was pressed over the board, at this position in board pixels
+    Press(MouseButton, (f64, f64)),
+    ///A mouse button was released over the board, at this position in board pixels
+    Release(MouseButton, (f64, f64)),
+    ///A key which acts on the game was pressed
+    Key(Key),
+    ///A message from the worker changed the board - its debug form, from [`recorded_message`]
+    Message(String),
+}
+
+///One [`RecordedInput`], and when it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    ///When it happened, in milliseconds since the recording started
+    pub after_ms: u64,
+    ///What happened
+    pub input: RecordedInput,
+}
+
+///A saved recording of the input to a game, along with the network session it was played against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputRecording {
+    ///The game id
+    pub id: u32,
+    ///The window scale the positions were recorded at
+    pub window_scale: f64,
+    ///When the recording started, in seconds since the unix epoch
+    pub started_at: u64,
+    ///The events, oldest first
+    pub events: Vec<RecordedEvent>,
+    ///The exchanges with the server, played back in place of it when replaying
+    pub session: NetworkSession,
+}
+
+impl InputRecording {
+    ///Loads a recording saved by [`InputRecorder::save`]
+    ///
+    /// # Errors
+    /// - Fail to read in the file
+    /// - Fail to parse the contents into an `InputRecording`
+    #[tracing::instrument]
+    pub fn load(path: &Path) -> Result<Self> {
+        let cntnts = read_to_string(path).with_context(|| format!("reading path {path:?}"))?;
+        serde_json::from_str(&cntnts).context("parsing input recording")
+    }
+
+    ///Gets the recorded messages, in order
+    fn messages(&self) -> impl Iterator<Item = &str> {
+        self.events.iter().filter_map(|e| match &e.input {
+            RecordedInput::Message(m) => Some(m.as_str()),
+            _ => None,
+        })
+    }
+}
+
+///Records the input to a game, from when the game starts so that the network session lines up with it
+#[derive(Debug)]
+pub struct InputRecorder {
+    ///The game id
+    id: u32,
+    ///The window scale, which the positions are relative to
+    window_scale: f64,
+    ///When the recording started
+    started: Instant,
+    ///When the recording started, in seconds since the unix epoch
+    started_at: u64,
+    ///The events so far, oldest first
+    events: Vec<RecordedEvent>,
+}
+
+impl InputRecorder {
+    ///Starts a new recording for a game
+    #[must_use]
+    pub fn new(id: u32, window_scale: f64) -> Self {
+        Self {
+            id,
+            window_scale,
+            started: Instant::now(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            events: vec![],
+        }
+    }
+
+    ///Records an input
+    pub fn record(&mut self, input: RecordedInput) {
+        //rounded, as a recording won't run for 500 million years
+        #[allow(clippy::cast_possible_truncation)]
+        let after_ms = self.started.elapsed().as_millis() as u64;
+        self.events.push(RecordedEvent { after_ms, input });
+    }
+
+    ///Gets the number of messages recorded so far
+    fn message_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|e| matches!(e.input, RecordedInput::Message(_)))
+            .count()
+    }
+
+    ///Gets the recorded messages, in order
+    fn messages(&self) -> impl Iterator<Item = &str> {
+        self.events.iter().filter_map(|e| match &e.input {
+            RecordedInput::Message(m) => Some(m.as_str()),
+            _ => None,
+        })
+    }
+
+    ///Saves the recording so far with the network session into the `recordings` folder in the project data directory, named after the game and when the recording started
+    ///
+    /// # Errors
+    /// - Fail to get [`ProjectDirs`]
+    /// - Fail to create the directory, or write the file
+    #[tracing::instrument(skip(self, session))]
+    pub fn save(&self, session: NetworkSession) -> Result<PathBuf> {
+        let recording = InputRecording {
+            id: self.id,
+            window_scale: self.window_scale,
+            started_at: self.started_at,
+            events: self.events.clone(),
+            session,
+        };
+
+        let dir = ProjectDirs::from("com", "jackmaguire", "async_chess")
+            .ae()
+            .context("getting project dirs")?
+            .data_dir()
+            .join("recordings");
+        create_dir_all(&dir).context("creating recordings directory")?;
+
+        let path = dir.join(format!("{}-{}.json", self.id, self.started_at));
+        let json = serde_json::to_string_pretty(&recording).context("serialising recording")?;
+        write(&path, json).with_context(|| format!("writing to {path:?}"))?;
+        Ok(path)
+    }
+}
+
+///Gets the form of a message which is recorded, or `None` if it isn't recorded.
+///
+/// Only messages which change the board are recorded - how many refreshes find the board unchanged, and how long requests take, depend on timing, so they would never line up in a replay.
+#[must_use]
+pub fn recorded_message(msg: &MessageToGame) -> Option<String> {
+    match msg {
+        MessageToGame::UpdateBoard(BoardMessage::UseExisting) => None,
+        MessageToGame::UpdateBoard(m) => Some(format!("{m:?}")),
+        _ => None,
+    }
+}
+
+///What happened when a recording was replayed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    ///The number of inputs replayed
+    pub inputs: usize,
+    ///The number of inputs which were skipped, as replays can't act on them
+    pub skipped: usize,
+    ///The number of messages the replayed game dealt with
+    pub messages: usize,
+    ///The first message which was different from the recording, as its index then the recorded message and the replayed one - `None` if there wasn't one at that index
+    pub divergence: Option<(usize, Option<String>, Option<String>)>,
+    ///The board at the end, in Forsyth-Edwards Notation
+    pub fen: String,
+}
+
+///Replays a recording headlessly - a [`ChessGame`] without a window is driven by the recorded inputs, with the recorded network session played back in place of the server.
+///
+/// After each input, the replay waits for the game to deal with as many messages as had been recorded by the next input, so the worker's timing can't change the order things happen in. The messages are then compared with the recorded ones, so a change in how the game reacts to a sequence of inputs shows up as a divergence.
+///
+/// # Errors
+/// - Fail to load the recording, or write out its session to be played back
+/// - Fail to make the game
+/// - Fail to act on an input, or deal with a message
+#[tracing::instrument]
+pub fn replay(path: &Path) -> Result<ReplayReport> {
+    let recording = InputRecording::load(path)?;
+
+    let session_path = std::env::temp_dir().join(format!(
+        "async-chess-replay-{}-{}.json",
+        recording.id, recording.started_at
+    ));
+    let session = serde_json::to_string(&recording.session).context("serialising session")?;
+    write(&session_path, session).with_context(|| format!("writing to {session_path:?}"))?;
+
+    let mut pc = PistonConfig {
+        id: recording.id,
+        ..PistonConfig::default()
+    };
+    pc.client.playback = Some(session_path);
+    pc.client.record_session = false;
+
+    let mut game = ChessGame::headless(&pc).context("making headless game")?;
+    game.start_input_recording(recording.window_scale);
+
+    let scale = recording.window_scale;
+    let (mut inputs, mut skipped, mut expected) = (0, 0, 0);
+    for event in &recording.events {
+        match &event.input {
+            RecordedInput::Message(_) => {
+                expected += 1;
+                continue;
+            }
+            _ if !settle(&mut game, expected)? => break,
+            RecordedInput::Press(MouseButton::Left, pos) => {
+                game.mouse_input(*pos, scale)
+                    .context("replaying mouse input")?;
+                game.update_list(true).context("updating after input")?;
+            }
+            RecordedInput::Press(MouseButton::Right, _) => game.clear_mouse_input(),
+            RecordedInput::Release(MouseButton::Left, pos) => {
+                game.mouse_release(*pos, scale)
+                    .context("replaying mouse release")?;
+                game.update_list(true).context("updating after release")?;
+            }
+            RecordedInput::Key(Key::Return) => {
+                game.confirm_move().context("replaying confirm")?;
+                game.update_list(true).context("updating after confirm")?;
+            }
+            RecordedInput::Key(Key::C) => game.restart_board().context("replaying restart")?,
+            input => {
+                debug!(?input, "Can't replay input");
+                skipped += 1;
+                continue;
+            }
+        }
+        inputs += 1;
+    }
+    let total = recording.messages().count();
+    settle(&mut game, total)?;
+
+    let replayed = game
+        .input_recorder()
+        .ae()
+        .context("getting replay recorder")?;
+    let divergence = recording
+        .messages()
+        .map(Some)
+        .chain(std::iter::repeat(None))
+        .zip(replayed.messages().map(Some).chain(std::iter::repeat(None)))
+        .take(total.max(replayed.message_count()))
+        .enumerate()
+        .find(|(_, (recorded, replayed))| recorded != replayed)
+        .map(|(i, (recorded, replayed))| {
+            (
+                i,
+                recorded.map(ToString::to_string),
+                replayed.map(ToString::to_string),
+            )
+        });
+
+    let report = ReplayReport {
+        inputs,
+        skipped,
+        messages: replayed.message_count(),
+        divergence,
+        fen: game.fen(),
+    };
+    game.exit().context("clearing up replay")?;
+    Ok(report)
+}
+
+///Deals with messages from the worker until the game has recorded `expected` of them. Returns `false` if they didn't all arrive within the [`STEP_TIMEOUT`].
+///
+/// # Errors
+/// - Fail to deal with a message
+fn settle(game: &mut ChessGame, expected: usize) -> Result<bool> {
+    let start = Instant::now();
+    loop {
+        game.update_list(false).context("dealing with messages")?;
+        let count = game
+            .input_recorder()
+            .map_or(0, InputRecorder::message_count);
+        if count >= expected {
+            return Ok(true);
+        }
+        if start.elapsed() >= STEP_TIMEOUT {
+            warn!(%count, %expected, "Timed out waiting for recorded messages");
+            return Ok(false);
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Gets the path of a recording in the `fixtures` folder
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("binaries/piston_and_egui/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn rejected_move_is_undone_in_replay() {
+        let report = replay(&fixture("undo_after_invalid_move.json")).unwrap();
+
+        assert_eq!(report.divergence, None);
+        assert_eq!((report.inputs, report.skipped), (4, 0));
+        assert_eq!(report.messages, 3);
+        assert_eq!(report.fen, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+    }
+}
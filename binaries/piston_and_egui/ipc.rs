@@ -0,0 +1,184 @@
+use crate::tray::open_game;
+use anyhow::{Context, Result};
+use epac_utils::error_ext::MutexExt;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Write},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{Builder, JoinHandle},
+};
+
+///What the game window tells other programs about its game, from the `state` command
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IpcState {
+    ///The game id
+    pub id: u32,
+    ///The board in Forsyth-Edwards Notation
+    pub fen: String,
+    ///Whether white is to move - `None` if it isn't known
+    pub white_to_move: Option<bool>,
+    ///Whether the server can be reached - if not, the board is the last known one
+    pub online: bool,
+    ///The base URL of the server
+    pub server: String,
+}
+
+///A command from another program, for the piston loop to act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcCommand {
+    ///Ask the server for the board now, rather than waiting for the timer
+    Refresh,
+}
+
+///A local socket - a Unix socket in the runtime directory, or a named pipe on Windows - which other programs can use to ask the game window about its game and send it commands, eg. for status bar widgets.
+///
+/// Each request is one line, and gets one line of JSON back, with `ok` and an `error` if it failed:
+/// - `state` - the [`IpcState`], as `state`
+/// - `refresh` - refreshes the board, as an [`IpcCommand::Refresh`]
+/// - `open <game id>` - opens another game in a new window
+///
+/// Only one window can have the socket - any others run without it.
+#[derive(Debug)]
+pub struct IpcServer {
+    ///The state to answer `state` with, updated by the piston loop
+    state: Arc<Mutex<IpcState>>,
+    ///Receiver for commands from other programs
+    commands: Receiver<IpcCommand>,
+    ///Handle for the thread accepting connections. It blocks waiting for them, so it is left running until the process exits.
+    _handle: JoinHandle<()>,
+}
+
+impl IpcServer {
+    ///Makes the socket and starts accepting connections on another thread
+    ///
+    /// # Errors
+    /// - Fail to make the socket, eg. if another window already has it
+    /// - Fail to start the thread
+    #[tracing::instrument]
+    pub fn start() -> Result<Self> {
+        let name = socket_name()?;
+        let listener = LocalSocketListener::bind(name.as_str())
+            .with_context(|| format!("binding local socket {name}"))?;
+        info!(%name, "Listening for local connections");
+
+        let state = Arc::new(Mutex::new(IpcState::default()));
+        let (tx, commands) = channel();
+        let thread_state = state.clone();
+        let handle = Builder::new()
+            .name("ipc".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let (state, tx) = (thread_state.clone(), tx.clone());
+                            //a slow client shouldn't hold up the others
+                            std::thread::spawn(move || {
+                                if let Err(e) = serve(stream, &state, &tx) {
+                                    warn!(%e, "Local connection ended with an error");
+                                }
+                            });
+                        }
+                        Err(e) => warn!(%e, "Unable to accept local connection"),
+                    }
+                }
+            })
+            .context("starting ipc thread")?;
+
+        Ok(Self {
+            state,
+            commands,
+            _handle: handle,
+        })
+    }
+
+    ///Sets the state to answer `state` with
+    pub fn set_state(&self, state: IpcState) {
+        *self.state.lock_panic("ipc state") = state;
+    }
+
+    ///Gets the next command from another program, if there is one
+    #[must_use]
+    pub fn try_recv(&self) -> Option<IpcCommand> {
+        self.commands.try_recv().ok()
+    }
+}
+
+///Answers the requests on one connection until it is closed
+///
+/// # Errors
+/// - Fail to read or write to the connection
+fn serve(
+    stream: LocalSocketStream,
+    state: &Mutex<IpcState>,
+    tx: &Sender<IpcCommand>,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).context("reading request")? > 0 {
+        let reply = respond(line.trim(), state, tx);
+        writeln!(reader.get_mut(), "{reply}").context("writing reply")?;
+        line.clear();
+    }
+    Ok(())
+}
+
+///Works out the reply to a request
+fn respond(request: &str, state: &Mutex<IpcState>, tx: &Sender<IpcCommand>) -> Value {
+    debug!(%request, "Local request");
+    let mut words = request.split_whitespace();
+    let done = match (words.next(), words.next(), words.next()) {
+        (Some("state"), None, None) => {
+            return json!({ "ok": true, "state": &*state.lock_panic("ipc state") });
+        }
+        (Some("refresh"), None, None) => tx
+            .send(IpcCommand::Refresh)
+            .context("sending refresh to game"),
+        (Some("open"), Some(id), None) => id
+            .parse()
+            .with_context(|| format!("reading game id {id:?}"))
+            .and_then(open_game),
+        _ => Err(anyhow!(
+            "unknown request {request:?} - try state, refresh or open <game id>"
+        )),
+    };
+
+    match done {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => json!({ "ok": false, "error": format!("{e:#}") }),
+    }
+}
+
+///Gets the name of the socket - `async-chess.sock` in the user's runtime directory, or the temporary directory if there isn't one. Any socket file left there by a window which has closed is removed.
+///
+/// # Errors
+/// - Fail to remove an old socket file
+#[cfg(unix)]
+fn socket_name() -> Result<String> {
+    use directories::BaseDirs;
+
+    let dir = BaseDirs::new()
+        .and_then(|b| b.runtime_dir().map(ToOwned::to_owned))
+        .unwrap_or_else(std::env::temp_dir);
+    let path = dir.join("async-chess.sock");
+    let name = path.to_string_lossy().into_owned();
+
+    if path.exists() && LocalSocketStream::connect(name.as_str()).is_err() {
+        std::fs::remove_file(&path).with_context(|| format!("removing old socket {path:?}"))?;
+    }
+    Ok(name)
+}
+
+///Gets the name of the named pipe - `\\.\pipe\async-chess`
+///
+/// # Errors
+/// Never fails - it returns a [`Result`] to match other platforms
+#[cfg(windows)]
+#[allow(clippy::unnecessary_wraps)]
+fn socket_name() -> Result<String> {
+    Ok("async-chess".into())
+}
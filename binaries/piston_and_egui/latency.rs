@@ -0,0 +1,111 @@
+use async_chess_client::net::server_client::{ClientConfig, ServerClient};
+use eframe::egui;
+use std::{
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    time::Duration,
+};
+
+///How long to wait between pings
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+///How long to wait for the server before counting it as unreachable
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+///Whether or not the server could be reached, and how quickly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reachability {
+    ///No ping has finished yet
+    Checking,
+    ///The server responded after this long
+    Reachable(Duration),
+    ///The server couldn't be reached, for this reason
+    Unreachable(String),
+}
+
+///Pings the server on a background thread every [`PING_INTERVAL`], for as long as it is kept around
+#[derive(Debug)]
+pub struct LatencyMonitor {
+    ///Sends the config of the server to ping whenever it changes - dropping it stops the thread
+    config_tx: Sender<ClientConfig>,
+    ///Receives the result of each ping
+    result_rx: Receiver<Reachability>,
+    ///The config last sent to the thread
+    config: ClientConfig,
+    ///The result of the latest ping
+    latest: Reachability,
+}
+
+impl LatencyMonitor {
+    ///Starts pinging the server in the given [`ClientConfig`]. The [`egui::Context`] is asked to repaint whenever a ping finishes.
+    #[must_use]
+    pub fn start(config: ClientConfig, ctx: egui::Context) -> Self {
+        let (config_tx, config_rx) = channel();
+        let (result_tx, result_rx) = channel();
+
+        let thread_config = config.clone();
+        std::thread::spawn(move || ping_loop(thread_config, &config_rx, &result_tx, &ctx));
+
+        Self {
+            config_tx,
+            result_rx,
+            config,
+            latest: Reachability::Checking,
+        }
+    }
+
+    ///Switches to pinging a different server, if the config has changed
+    pub fn set_config(&mut self, config: &ClientConfig) {
+        if &self.config == config {
+            return;
+        }
+
+        self.config = config.clone();
+        self.latest = Reachability::Checking;
+        if self.config_tx.send(config.clone()).is_err() {
+            warn!("Latency thread has stopped");
+        }
+    }
+
+    ///Gets the result of the latest ping
+    pub fn latest(&mut self) -> &Reachability {
+        while let Ok(r) = self.result_rx.try_recv() {
+            self.latest = r;
+        }
+        &self.latest
+    }
+}
+
+///Pings the server until the [`LatencyMonitor`] is dropped, switching to new configs as they come in
+fn ping_loop(
+    mut config: ClientConfig,
+    config_rx: &Receiver<ClientConfig>,
+    result_tx: &Sender<Reachability>,
+    ctx: &egui::Context,
+) {
+    loop {
+        let result = match ServerClient::new(&config).and_then(|c| c.ping(PING_TIMEOUT)) {
+            Ok(d) => Reachability::Reachable(d),
+            Err(e) => Reachability::Unreachable(format!("{e:#}")),
+        };
+
+        //a new config might have arrived while pinging, so this result would be for the old server
+        let mut changed = false;
+        while let Ok(c) = config_rx.try_recv() {
+            config = c;
+            changed = true;
+        }
+        if changed {
+            continue;
+        }
+
+        if result_tx.send(result).is_err() {
+            return;
+        }
+        ctx.request_repaint();
+
+        match config_rx.recv_timeout(PING_INTERVAL) {
+            Ok(c) => config = c,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
@@ -17,27 +17,99 @@
 
 //TODO: Fix rooks
 
-use crate::{egui_launcher::egui_main, piston::piston_main};
+use crate::{
+    console::{set_log_filter_handle, RingBufferLayer},
+    egui_launcher::egui_main,
+    piston::piston_main,
+    startup_profile::{startup_profiler, StartupPhase},
+    trace_export::{trace_capture, ChromeTraceLayer},
+};
 use anyhow::{Context, Result};
-use async_chess_client::{prelude::ErrorExt, util::error_ext::ToAnyhowNotErr};
+use async_chess_client::{
+    net::{invite::Invite, recorder::NetworkSession},
+    prelude::ErrorExt,
+    util::error_ext::ToAnyhowNotErr,
+};
 use directories::ProjectDirs;
 use piston::PistonConfig;
 use serde_json::from_str;
 use std::{
     env::{args, set_var, var},
     fs::read_to_string,
+    path::{Path, PathBuf},
 };
 use tracing_subscriber::{
-    prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry,
+    filter::filter_fn, prelude::__tracing_subscriber_SubscriberExt, reload,
+    util::SubscriberInitExt, EnvFilter, Layer, Registry,
 };
 use tracing_tree::HierarchicalLayer;
 
+///Module to hold the [`analysis_board::AnalysisBoard`], for exploring variations away from the live board
+mod analysis_board;
+///Module to save and load the last known board for each game
+mod board_cache;
+///Module to export and import all of the configuration in one file
+mod bundle;
+///Module to load and hold textures
+mod cacher;
+///Module to hold the in-game debug [`console::Console`], and the tracing layer which feeds it
+mod console;
+///Module to work out which squares of the board need redrawing each frame
+mod dirty;
 ///Module to deal with configurator
 mod egui_launcher;
+///Module to hold the settings for hints, and count how many have been used in each game
+mod hints;
+///Module to translate the launcher into other languages
+mod i18n;
+///Module to turn input from devices other than the mouse into [`input::BoardAction`]s
+mod input;
+///Module to record the input to a game, and replay it headlessly against the recorded network session
+mod input_recording;
+///Module to let other programs query and control the game window over a local socket
+mod ipc;
+///Module to work out the identity of the player for the server
+mod identity;
+///Module to ping the server in the background, to show its latency in the launcher
+mod latency;
 ///Module to hold the [`game::ChessGame`] struct and deal with its logic
 mod game;
+///Module to save the move history of each game, and export and import it as PGN
+mod move_history;
+///Module to hold the [`move_entry::MoveEntry`] box, for typing moves in SAN or UCI
+mod move_entry;
+///Module to keep an append-only log of the moves made in each game, to rebuild the history from and check what was played when
+mod move_log;
+///Module to save moves the server hasn't acknowledged, so they can be resubmitted after a crash
+mod pending;
 ///Module to hold windowing/rendering logic for the [`game::ChessGame`]
 mod piston;
+///Module to practise from the current position on an offline board, which is never sent to the server
+mod practice;
+///Module to draw the board and move list of a game into a shareable image
+mod scorecard;
+///Module to write out a game as a printable scoresheet
+mod scoresheet;
+///Module to time the phases of starting up, and report the time to the first playable frame
+mod startup_profile;
+///Module to hold the local store of game results, and summary statistics over them
+mod stats;
+///Module to work out how the taken pieces are drawn beside the board, and cache it
+mod taken;
+///Module to collect anonymous usage statistics, if the player opts in, and queue them to be uploaded
+mod telemetry;
+///Module to install, list and remove themes of sprites
+mod themes;
+///Module to turn touch screen events into gestures, with the [`touch::TouchTracker`]
+mod touch;
+///Module to capture tracing spans on demand, and export them for chrome://tracing
+mod trace_export;
+///Module to show a tray icon which follows the recent games, and shows when it is the player's turn
+mod tray;
+///Module to check whether there is a newer release
+mod update_check;
+///Module to register the binary as the handler for invite links
+mod url_scheme;
 ///Module to hold useful constants for pixel sizes
 pub mod pixel_size_consts {
     ///The size in pixels of the length/width of a chess piece sprite
@@ -67,6 +139,8 @@ extern crate tracing;
 extern crate anyhow;
 
 fn main() {
+    //so the startup report is timed from here
+    startup_profiler();
     setup_logging_tracing().eprint_exit();
 
     info!("Thanks to Devil's Workshop for the Chess Assets!");
@@ -76,6 +150,16 @@ fn main() {
 
 ///Function to run the game.
 ///
+/// - If `--replay <recording file>` was passed, it replays that input recording headlessly with [`input_recording::replay`] and exits - with a non-zero code if the replay diverged from the recording
+/// - If `--record-input` was passed, any window started straight from the command line below records its input and network session, saved on exit or from the console
+/// - If `--review <PGN file>` was passed, it starts up the [`piston_main`] stepping through that game on an analysis board, without talking to the server
+/// - If `--playback <session file>` was passed, it starts up the [`piston_main`] playing back that recorded session instead of talking to the server, using [`playback_config`]
+/// - If `--play <invite link or game id>` was passed, it skips reading the configuration and starts up the [`piston_main`] straight away, using [`quick_play_config`]
+/// - If `--profile-startup` was passed, it starts up the [`piston_main`] with the existing configuration (or the defaults), prints the [`startup_profile::StartupReport`] once the first playable frame is drawn, and exits
+/// - If `--tray` was passed, it runs the [`tray::tray_main`] icon instead of a window
+/// - If `--open <game id>` was passed, it starts up the [`piston_main`] on that game, using the rest of the existing configuration - this is how the tray opens games
+/// - If `--register-url-scheme` was passed, it registers the binary as the handler for invite links with [`url_scheme::register_url_scheme`]
+/// - If `--join <invite link>` was passed, or an invite link on its own (as the OS does when one is clicked), it checks the game exists and starts up the [`piston_main`] on that game, using the rest of the existing configuration
 /// - It checks whether or not the conf argument was passed, and if so it starts up the [`egui_main`] which launches an `AsyncChessLauncher`
 /// - If not, then it checks if a configuration exists (and is valid), and if so it starts up the [`piston_main`] with the found configuration.
 /// - If not, then it goes for the [`egui_main`]
@@ -83,11 +167,76 @@ fn main() {
 /// When launching [`egui_main`] an Optional [`PistonConfig`] is passed in, and if it is `Some`, then the default values in the window are set to that of the [`PistonConfig`]
 #[tracing::instrument]
 fn start() {
+    if let Some(path) = args().skip_while(|a| a != "--replay").nth(1) {
+        match input_recording::replay(Path::new(&path)) {
+            Ok(report) if report.divergence.is_none() => {
+                info!(?report, "Replay matched the recording");
+                return;
+            }
+            Ok(report) => error!(?report, "Replay diverged from the recording"),
+            Err(e) => error!(%e, "Unable to replay recording"),
+        }
+        std::process::exit(1);
+    }
+
+    let record_input = args().any(|a| a == "--record-input");
+    let piston_main = |mut pc: PistonConfig| {
+        if record_input {
+            //the network session is needed to replay the input against
+            pc.record_input = true;
+            pc.client.record_session = true;
+        }
+        piston_main(pc);
+    };
+
+    if let Some(path) = args().skip_while(|a| a != "--review").nth(1) {
+        //the rest of the configuration is still used for the window, theme and hints
+        let pc = PistonConfig {
+            review: Some(PathBuf::from(path)),
+            ..read_config().unwrap_or_default()
+        };
+        piston_main(pc);
+        return;
+    }
+
+    if let Some(path) = args().skip_while(|a| a != "--playback").nth(1) {
+        match playback_config(PathBuf::from(path)) {
+            Ok(pc) => {
+                piston_main(pc);
+                return;
+            }
+            Err(e) => error!(%e, "Unable to play back session"),
+        }
+    }
+
+    if let Some(target) = args().skip_while(|a| a != "--play").nth(1) {
+        match quick_play_config(&target) {
+            Ok(pc) => {
+                piston_main(pc);
+                return;
+            }
+            Err(e) => error!(%e, "Unable to quick play"),
+        }
+    }
+
+    if args().any(|a| a == "--register-url-scheme") {
+        if let Err(e) = url_scheme::register_url_scheme() {
+            error!(%e, "Unable to register URL scheme");
+        }
+        return;
+    }
+
+    if args().any(|a| a == "--tray") {
+        tray::tray_main().context("running tray").error();
+        return;
+    }
+
     let user_wants_conf = args()
         .nth(1)
         .and_then(|s| s.chars().next())
         .map_or(false, |c| c == 'c');
 
+    let config_timer = startup_profiler().time(StartupPhase::ConfigRead);
     let uc = match read_config() {
         Ok(c) => Some(c),
         Err(e) => {
@@ -95,16 +244,92 @@ fn start() {
             None
         }
     };
+    drop(config_timer);
     info!(%user_wants_conf, ?uc);
 
-    if let Some(uc) = uc {
-        if !user_wants_conf {
-            piston_main(uc);
-            return;
+    if args().any(|a| a == "--profile-startup") {
+        startup_profiler().set_exit_after_report();
+        piston_main(uc.unwrap_or_default());
+        return;
+    }
+
+    let link = args()
+        .skip_while(|a| a != "--join")
+        .nth(1)
+        .or_else(|| url_scheme::link_from_args(args().skip(1)));
+    if let Some(link) = link {
+        match join_from_link(&link, uc.clone().unwrap_or_default()) {
+            Ok(pc) => {
+                piston_main(pc);
+                return;
+            }
+            Err(e) => error!(%e, "Unable to join from invite link"),
+        }
+    }
+
+    if let Some(id) = args().skip_while(|a| a != "--open").nth(1) {
+        match id.trim().parse() {
+            Ok(id) => {
+                let mut pc = uc.unwrap_or_default();
+                pc.id = id;
+                piston_main(pc);
+                return;
+            }
+            Err(e) => error!(%e, %id, "Unable to read game id to open"),
         }
     }
 
-    egui_main(uc);
+    match uc {
+        Some(uc) if !user_wants_conf => piston_main(uc),
+        uc => egui_main(uc),
+    }
+}
+
+///Makes a transient [`PistonConfig`] for a game - the defaults, switched to the game from either an invite link or a game id on the default server. It isn't saved, so the normal configuration is left alone.
+///
+/// # Errors
+/// - It isn't a game id or a valid [`Invite`]
+#[tracing::instrument]
+fn quick_play_config(target: &str) -> Result<PistonConfig> {
+    let mut pc = PistonConfig::default();
+    match target.trim().parse() {
+        Ok(id) => pc.id = id,
+        Err(_) => pc.join(Invite::parse(target).context("parsing game id or invite link")?),
+    }
+    Ok(pc)
+}
+
+///Makes a transient [`PistonConfig`] to play back a recorded [`NetworkSession`] - the defaults, switched to the game the session was playing, with the session played back in place of the server.
+///
+/// # Errors
+/// - Fail to load the session
+/// - There are no requests for a game in the session
+#[tracing::instrument]
+fn playback_config(path: PathBuf) -> Result<PistonConfig> {
+    let id = NetworkSession::load(&path)?
+        .game_id()
+        .context("finding the game id in the session")?;
+    let mut pc = PistonConfig {
+        id,
+        ..PistonConfig::default()
+    };
+    pc.client.playback = Some(path);
+    Ok(pc)
+}
+
+///Parses an invite link, checks the game exists, and switches the given [`PistonConfig`] to it
+///
+/// # Errors
+/// - The link isn't a valid [`Invite`]
+/// - The game can't be found on the server
+#[tracing::instrument(skip(pc))]
+fn join_from_link(link: &str, mut pc: PistonConfig) -> Result<PistonConfig> {
+    let invite = Invite::parse(link)?;
+    invite.verify(&pc.client)?;
+    info!(%invite, "Joining from invite");
+
+    pc.join(invite);
+    Ok(pc)
 }
 
 ///Function to read in the config
@@ -135,7 +360,8 @@ pub fn read_config() -> Result<PistonConfig> {
 ///Function to setup all of the logging and tracing for the program
 ///
 /// - Firstly, it sets the environment variables `RUST_LIB_BACKTRACE` to `1` and `RUST_LOG` to `info`
-/// - Then it sets up an Environment tracing logger with Tracing Tree
+/// - Then it sets up an Environment tracing logger with Tracing Tree, and a [`RingBufferLayer`] for the in-game console. The [`EnvFilter`] is reloadable, so it can be changed at runtime with [`console::set_log_filter`].
+/// - Finally, it adds a [`ChromeTraceLayer`], which only sees spans while a trace is being captured. It is filtered separately, so spans below the `RUST_LOG` level can still be captured.
 ///
 /// # Errors
 /// Can return an error if the tracing [`Registry`] fails to initialise, and this happens when:
@@ -149,14 +375,22 @@ pub fn setup_logging_tracing() -> Result<()> {
         }
     }
 
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::builder().from_env()?);
+    set_log_filter_handle(filter_handle);
+
     Registry::default()
-        .with(EnvFilter::builder().from_env()?)
         .with(
             HierarchicalLayer::new(1)
                 .with_targets(true)
                 .with_bracketed_fields(true)
                 .with_verbose_entry(true)
-                .with_ansi(true), // .with_filter(Level::INFO.into())
+                .with_ansi(true) // .with_filter(Level::INFO.into())
+                .and_then(RingBufferLayer)
+                .with_filter(filter),
+        )
+        .with(
+            ChromeTraceLayer
+                .with_filter(filter_fn(|m| m.is_span() && trace_capture().is_capturing())),
         )
         .try_init()?;
 
@@ -0,0 +1,230 @@
+use crate::console::render_panel;
+use anyhow::{Context as _, Result};
+use async_chess_client::{
+    chess::{
+        attacks::pseudo_legal_moves,
+        boards::{
+            board::{Board, BoardMoveState},
+            board_container::BoardContainer,
+        },
+        engine::parse_uci_move,
+        san::{parse_san, to_san},
+    },
+    prelude::{Coords, Either},
+};
+use piston_window::{Context, G2d, Glyphs, Key};
+
+///Most completions shown under the typed move
+const MAX_COMPLETIONS: usize = 5;
+
+///Box to type a move into, in Standard Algebraic Notation like `Nf3` or UCI like `e2e4`.
+///
+/// The move is checked as it is typed, and Tab fills in the first legal move which starts with what has been typed. Backspace on an empty box closes it.
+#[derive(Debug, Default)]
+pub struct MoveEntry {
+    ///Whether or not the box is shown - while it is, keyboard input should go to it rather than the game
+    open: bool,
+    ///The move being typed
+    input: String,
+}
+
+impl MoveEntry {
+    ///Whether or not the box is shown
+    #[must_use]
+    pub const fn is_open(&self) -> bool {
+        self.open
+    }
+
+    ///Shows the box, empty
+    pub fn open(&mut self) {
+        self.open = true;
+        self.input.clear();
+    }
+
+    ///Adds typed text to the move
+    pub fn text(&mut self, text: &str) {
+        //the keys that open the box also type a character
+        self.input.extend(
+            text.chars()
+                .filter(|c| !c.is_whitespace() && !matches!(c, '/' | ':')),
+        );
+    }
+
+    ///Deals with a key press while the box is open. Returns the move if a legal one was entered, and closes the box.
+    ///
+    /// `white` is the colour to move, or `None` if either colour can.
+    pub fn key_press(
+        &mut self,
+        key: Key,
+        board: &BoardContainer,
+        white: Option<bool>,
+    ) -> Option<(Coords, Coords)> {
+        match key {
+            Key::Backspace => {
+                if self.input.pop().is_none() {
+                    self.open = false;
+                }
+                None
+            }
+            Key::Tab => {
+                if let Some(first) = completions(board, &self.input, white).into_iter().next() {
+                    self.input = first;
+                }
+                None
+            }
+            Key::Return => match parse_entry(board, &self.input, white) {
+                Ok(m) => {
+                    info!(input = %self.input, ?m, "Typed move");
+                    self.open = false;
+                    self.input.clear();
+                    Some(m)
+                }
+                Err(e) => {
+                    warn!(%e, input = %self.input, "Invalid typed move");
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    ///Renders the box in the bottom left of the window, with whether the move is legal and the moves it could be completed to. Should be given a [`Context`] which isn't zoomed or panned.
+    ///
+    /// # Errors
+    /// - Fail to render the text
+    pub fn render(
+        &self,
+        ctx: Context,
+        graphics: &mut G2d,
+        glyphs: &mut Glyphs,
+        win_size: (f64, f64),
+        board: &BoardContainer,
+        white: Option<bool>,
+    ) -> Result<()> {
+        if !self.open {
+            return Ok(());
+        }
+
+        let check = if self.input.is_empty() {
+            "Type a move like Nf3 or e2e4 - Tab completes, Enter plays".to_string()
+        } else {
+            match parse_entry(board, &self.input, white) {
+                Ok((from, to)) => {
+                    let name = match board {
+                        Either::Left(b) => to_san(b, (from, to)),
+                        Either::Right(b) => to_san(b, (from, to)),
+                    };
+                    format!("Enter to play {}", name.unwrap_or_default())
+                }
+                Err(e) => format!("{e}"),
+            }
+        };
+        let lines = [
+            format!("Move: {}_", self.input),
+            check,
+            completions(board, &self.input, white).join("  "),
+        ];
+
+        render_panel(&lines, ctx, graphics, glyphs, win_size).context("drawing move entry")
+    }
+}
+
+///Reads a typed move, in UCI or SAN, for the colour to move - or for either colour if `white` is `None`
+///
+/// # Errors
+/// - The move isn't legal, or can't be read
+pub fn parse_entry(
+    board: &BoardContainer,
+    input: &str,
+    white: Option<bool>,
+) -> Result<(Coords, Coords)> {
+    match board {
+        Either::Left(b) => parse_move(b, input, white),
+        Either::Right(b) => parse_move(b, input, white),
+    }
+}
+
+///Does the parsing for [`parse_entry`]
+fn parse_move<S: BoardMoveState>(
+    board: &Board<S>,
+    input: &str,
+    white: Option<bool>,
+) -> Result<(Coords, Coords)> {
+    let input = input.trim_end_matches(['+', '#', '!', '?']);
+    if input.is_empty() {
+        bail!("no move typed");
+    }
+
+    if input.len() <= 5 {
+        if let Ok(m) = parse_uci_move(input) {
+            if !candidate_moves(board, white).contains(&m) {
+                bail!("{input} isn't a legal move");
+            }
+            return Ok(m);
+        }
+    }
+
+    //piece letters are upper case in SAN, but are easy to type in lower case - `b` is tried as a pawn first
+    let mut chars = input.chars();
+    let capitalised: String = chars
+        .next()
+        .map(|c| c.to_ascii_uppercase())
+        .into_iter()
+        .chain(chars)
+        .collect();
+    let mut last_error = None;
+    for san in [input, capitalised.as_str()] {
+        for colour in colours(white) {
+            match parse_san(board, san, colour) {
+                Ok(m) => return Ok(m),
+                Err(e) => last_error = Some(e),
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow!("{input} isn't a legal move")))
+}
+
+///Gets the typeable names of every move which starts with `input`, ignoring case - both SAN and UCI, sorted and without duplicates, up to [`MAX_COMPLETIONS`]
+#[must_use]
+pub fn completions(board: &BoardContainer, input: &str, white: Option<bool>) -> Vec<String> {
+    let mut names = match board {
+        Either::Left(b) => move_names(b, white),
+        Either::Right(b) => move_names(b, white),
+    };
+    let input = input.to_lowercase();
+    names.retain(|n| n.to_lowercase().starts_with(&input));
+    names.sort_unstable();
+    names.dedup();
+    names.truncate(MAX_COMPLETIONS);
+    names
+}
+
+///Gets the SAN and UCI names of every move, for [`completions`]
+fn move_names<S: BoardMoveState>(board: &Board<S>, white: Option<bool>) -> Vec<String> {
+    candidate_moves(board, white)
+        .into_iter()
+        .flat_map(|(from, to)| {
+            let uci = from
+                .to_algebraic()
+                .zip(to.to_algebraic())
+                .map(|(f, t)| format!("{f}{t}"));
+            [to_san(board, (from, to)).ok(), uci].into_iter().flatten()
+        })
+        .collect()
+}
+
+///Gets every move the colour to move could make, or both colours if `white` is `None`
+fn candidate_moves<S: BoardMoveState>(
+    board: &Board<S>,
+    white: Option<bool>,
+) -> Vec<(Coords, Coords)> {
+    colours(white)
+        .into_iter()
+        .flat_map(|w| pseudo_legal_moves(board, w))
+        .collect()
+}
+
+///Gets the colours which could be moving
+fn colours(white: Option<bool>) -> Vec<bool> {
+    white.map_or_else(|| vec![true, false], |w| vec![w])
+}
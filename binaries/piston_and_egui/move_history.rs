@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use async_chess_client::chess::history::MoveHistory;
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use serde_json::{from_str, to_string};
+use std::{
+    fs::{create_dir_all, read_to_string},
+    path::{Path, PathBuf},
+};
+
+///Saves the move history of a game, with its annotations, so it is kept between runs.
+///
+/// # Errors
+/// - Fail to get the [`data_dir`]
+/// - Fail to convert the history to JSON
+/// - Fail to write to the file
+#[tracing::instrument(skip(history))]
+pub fn save_moves(id: u32, history: &MoveHistory) -> Result<()> {
+    let path = data_dir("moves")?.join(format!("{id}.json"));
+    let st = to_string(history).context("turning history to string")?;
+    std::fs::write(&path, st).context("Write to file")
+}
+
+///Loads the move history saved for a game. Returns `None` if there isn't one.
+///
+/// # Errors
+/// - Fail to get the [`data_dir`]
+/// - Fail to read in the file
+/// - Fail to parse the contents into a [`MoveHistory`]
+#[tracing::instrument]
+pub fn load_moves(id: u32) -> Result<Option<MoveHistory>> {
+    let path = data_dir("moves")?.join(format!("{id}.json"));
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+    from_str(&cntnts)
+        .with_context(|| format!("reading contents {cntnts}"))
+        .map(Some)
+}
+
+///Exports the move history of a game as PGN, to `{id}.pgn` in the `pgn` directory. Returns the path written to.
+///
+/// # Errors
+/// - Fail to write the moves - see [`MoveHistory::to_pgn`]
+/// - Fail to get the [`data_dir`]
+/// - Fail to write to the file
+#[tracing::instrument(skip(history))]
+pub fn export_pgn(id: u32, history: &MoveHistory) -> Result<PathBuf> {
+    let pgn = history
+        .to_pgn(&[
+            ("Event", format!("Async Chess game {id}")),
+            ("Site", "?".into()),
+            ("White", "?".into()),
+            ("Black", "?".into()),
+            ("Result", "*".into()),
+        ])
+        .context("writing pgn")?;
+
+    let path = data_dir("pgn")?.join(format!("{id}.pgn"));
+    std::fs::write(&path, pgn).with_context(|| format!("writing to {path:?}"))?;
+    Ok(path)
+}
+
+///Imports the first game from a PGN file
+///
+/// # Errors
+/// - Fail to read the file
+/// - Fail to read the game - see [`MoveHistory::from_pgn`]
+#[tracing::instrument]
+pub fn import_pgn(path: &Path) -> Result<MoveHistory> {
+    let cntnts = read_to_string(path).with_context(|| format!("reading path {path:?}"))?;
+    MoveHistory::from_pgn(&cntnts).context("reading pgn")
+}
+
+///Gets a directory in the project data directory, creating it if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the directory
+fn data_dir(name: &str) -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dir = pd.data_dir().join(name);
+    create_dir_all(&dir).with_context(|| format!("creating {name} directory"))?;
+    Ok(dir)
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use async_chess_client::{
+    chess::{boards::board::Board, history::MoveHistory},
+    net::server_interface::JSONMove,
+    prelude::Coords,
+};
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{create_dir_all, read_to_string, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+///Something which happened to the moves of a game, as written to its move log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MoveLogEvent {
+    ///A new move history was started from a board
+    Start {
+        ///The board, in Forsyth-Edwards Notation
+        fen: String,
+        ///Whether white made the first move from it
+        white_first: bool,
+        ///Why the history was started, eg. the moves to the new board couldn't be worked out
+        reason: String,
+    },
+    ///A move was made on the board by either player, as worked out from the boards the server sent
+    Played {
+        ///The square the piece moved from
+        from: Coords,
+        ///The square the piece moved to
+        to: Coords,
+    },
+    ///The player sent a move to the server
+    Sent {
+        ///The move
+        m: JSONMove,
+        ///The board it was made on, in Forsyth-Edwards Notation
+        fen: String,
+    },
+    ///The server accepted the move which was sent
+    Acknowledged {
+        ///The move, if it was known
+        m: Option<JSONMove>,
+        ///Whether it took a piece
+        taken: bool,
+    },
+    ///The server rejected the move which was sent
+    Rejected {
+        ///The move, if it was known
+        m: Option<JSONMove>,
+        ///What the server said was wrong with it
+        reason: String,
+    },
+    ///The server couldn't be reached to make the move which was sent
+    Unreachable {
+        ///The move, if it was known
+        m: Option<JSONMove>,
+    },
+    ///A move queued to be sent was dropped, as one before it was rejected or couldn't be sent
+    Dropped {
+        ///The move
+        m: JSONMove,
+    },
+}
+
+///One line of a move log - a [`MoveLogEvent`], and when it happened
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveLogEntry {
+    ///When it happened, in milliseconds since the unix epoch
+    pub at_ms: u64,
+    ///What happened
+    #[serde(flatten)]
+    pub event: MoveLogEvent,
+}
+
+///Appends an event to the move log of a game.
+///
+/// The log is never rewritten - each entry is one line of JSON added to the end of the file, so anything written before a crash is kept, and the log can be used to check what was played when.
+///
+/// # Errors
+/// - Fail to get the path using [`log_path`]
+/// - Fail to convert the entry to JSON
+/// - Fail to open or write to the file
+#[tracing::instrument]
+pub fn append_move_log(id: u32, event: MoveLogEvent) -> Result<()> {
+    //rounded, as a game won't run for 500 million years
+    #[allow(clippy::cast_possible_truncation)]
+    let at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let mut line = serde_json::to_string(&MoveLogEntry { at_ms, event })
+        .context("turning move log entry to string")?;
+    line.push('\n');
+
+    let path = log_path(id)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening {path:?}"))?
+        //in one write, so a crash can only cut off the last line
+        .write_all(line.as_bytes())
+        .with_context(|| format!("writing to {path:?}"))
+}
+
+///Loads the move log of a game, oldest first. Returns an empty list if there isn't one.
+///
+/// Lines which can't be read are skipped, as a crash part way through a write leaves the last line cut off.
+///
+/// # Errors
+/// - Fail to get the path using [`log_path`]
+/// - Fail to read in the file
+#[tracing::instrument]
+pub fn load_move_log(id: u32) -> Result<Vec<MoveLogEntry>> {
+    let path = log_path(id)?;
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+    Ok(cntnts
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!(%e, line = i + 1, "Skipping unreadable move log line");
+                None
+            }
+        })
+        .collect())
+}
+
+///Rebuilds the move history from a move log - from the last [`MoveLogEvent::Start`], with every move played after it. Returns `None` if the log never started a history.
+///
+/// Annotations and variations aren't in the log, so only the moves are rebuilt.
+#[must_use]
+pub fn rebuild_history(entries: &[MoveLogEntry]) -> Option<MoveHistory> {
+    let mut history = None;
+    for entry in entries {
+        match &entry.event {
+            MoveLogEvent::Start {
+                fen, white_first, ..
+            } => match Board::from_fen(fen) {
+                Ok(board) => history = Some(MoveHistory::new(&board, *white_first)),
+                Err(e) => {
+                    warn!(%e, %fen, "Unable to read board in move log");
+                    history = None;
+                }
+            },
+            MoveLogEvent::Played { from, to } => {
+                if let Some(history) = &mut history {
+                    history.push(*from, *to);
+                }
+            }
+            _ => {}
+        }
+    }
+    history
+}
+
+///Describes an entry in the move log for a person to read, eg. `1712345678.901 played e2 to e4`, with the time in seconds since the unix epoch
+#[must_use]
+pub fn describe_entry(entry: &MoveLogEntry) -> String {
+    let square = |c: &Coords| c.to_algebraic().unwrap_or_else(|| "off the board".into());
+    let json_move = |m: Option<&JSONMove>| {
+        m.map_or_else(
+            || "the move".into(),
+            |m| format!("({}, {}) to ({}, {})", m.x, m.y, m.nx, m.ny),
+        )
+    };
+
+    let mut st = format!("{}.{:03} ", entry.at_ms / 1000, entry.at_ms % 1000);
+    st += &match &entry.event {
+        MoveLogEvent::Start {
+            fen,
+            white_first,
+            reason,
+        } => format!(
+            "started history from {fen} with {} to move - {reason}",
+            if *white_first { "white" } else { "black" }
+        ),
+        MoveLogEvent::Played { from, to } => {
+            format!("played {} to {}", square(from), square(to))
+        }
+        MoveLogEvent::Sent { m, fen } => format!("sent {} on {fen}", json_move(Some(m))),
+        MoveLogEvent::Acknowledged { m, taken } => format!(
+            "server accepted {}{}",
+            json_move(m.as_ref()),
+            if *taken { ", taking a piece" } else { "" }
+        ),
+        MoveLogEvent::Rejected { m, reason } => {
+            format!("server rejected {} - {reason}", json_move(m.as_ref()))
+        }
+        MoveLogEvent::Unreachable { m } => {
+            format!(
+                "couldn't reach the server to make {}",
+                json_move(m.as_ref())
+            )
+        }
+        MoveLogEvent::Dropped { m } => {
+            format!("dropped {} after an earlier move failed", json_move(Some(m)))
+        }
+    };
+    st
+}
+
+///Gets the path of the move log for a game - `{id}.jsonl` in `move_log` in the project data directory, creating the directory if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the move log directory
+fn log_path(id: u32) -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dir = pd.data_dir().join("move_log");
+    create_dir_all(&dir).context("creating move log directory")?;
+    Ok(dir.join(format!("{id}.jsonl")))
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use async_chess_client::net::server_interface::JSONMove;
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string};
+use std::{
+    fs::{create_dir_all, read_to_string, remove_file, rename},
+    path::PathBuf,
+};
+
+///A move which the server hasn't acknowledged yet
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingMove {
+    ///The move
+    pub m: JSONMove,
+    ///Whether or not it had been sent to the server, rather than waiting for confirmation
+    pub sent: bool,
+}
+
+///Saves the pending move for a game, replacing any which was there, so that it survives a crash.
+///
+/// The move is written to a temporary file which is then renamed over the old one, so a crash part way through leaves either the old move or the new one.
+///
+/// # Errors
+/// - Fail to get the path using [`pending_path`]
+/// - Fail to convert the move to JSON
+/// - Fail to write to or rename the file
+#[tracing::instrument]
+pub fn save_pending(id: u32, pending: PendingMove) -> Result<()> {
+    let path = pending_path(id)?;
+    let tmp = path.with_extension("json.tmp");
+    let st = to_string(&pending).context("turning pending move to string")?;
+    std::fs::write(&tmp, st).with_context(|| format!("writing to {tmp:?}"))?;
+    rename(&tmp, &path).with_context(|| format!("renaming {tmp:?} to {path:?}"))
+}
+
+///Loads the pending move left for a game by a previous run. Returns `None` if there isn't one.
+///
+/// # Errors
+/// - Fail to get the path using [`pending_path`]
+/// - Fail to read in the file
+/// - Fail to parse the contents into a [`PendingMove`]
+#[tracing::instrument]
+pub fn load_pending(id: u32) -> Result<Option<PendingMove>> {
+    let path = pending_path(id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+    from_str(&cntnts)
+        .with_context(|| format!("reading contents {cntnts}"))
+        .map(Some)
+}
+
+///Removes the pending move for a game, once the server has responded to it or it has been cancelled
+///
+/// # Errors
+/// - Fail to get the path using [`pending_path`]
+/// - Fail to remove the file
+#[tracing::instrument]
+pub fn clear_pending(id: u32) -> Result<()> {
+    let path = pending_path(id)?;
+    if path.exists() {
+        remove_file(&path).with_context(|| format!("removing {path:?}"))?;
+    }
+    Ok(())
+}
+
+///Gets the path of the pending move for a game - `{id}.json` in `pending` in the project data directory, creating the directory if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the pending directory
+fn pending_path(id: u32) -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dir = pd.data_dir().join("pending");
+    create_dir_all(&dir).context("creating pending directory")?;
+    Ok(dir.join(format!("{id}.json")))
+}
@@ -1,96 +1,689 @@
 use crate::{
+    analysis_board::AnalysisStep,
+    cacher::{assets_folder, TextureConfig},
+    console::{
+        render_book_moves, render_move_list, render_request_stats, set_log_filter, Console,
+        ConsoleCommand,
+    },
     game::ChessGame,
-    pixel_size_consts::{BOARD_S, LEFT_BOUND, RIGHT_BOUND},
+    hints::HintConfig,
+    i18n::Language,
+    input::{BoardAction, ControllerConfig, ControllerInput},
+    input_recording::RecordedInput,
+    ipc::{IpcCommand, IpcServer},
+    move_entry::MoveEntry,
+    pixel_size_consts::{BOARD_S, LEFT_BOUND, PADDING, RIGHT_BOUND, TILE_S},
+    startup_profile::{startup_profiler, StartupPhase, FETCH_TIMEOUT},
+    telemetry::telemetry,
+    touch::{TouchGesture, TouchTracker},
+    trace_export::trace_capture,
 };
 use anyhow::Context;
 use async_chess_client::{
-    prelude::ErrorExt, util::time_based_structs::memcache::MemoryTimedCacher,
+    metrics::metrics,
+    net::{
+        invite::{Invite, InviteRole},
+        rate_limiter::RateLimit,
+        server_client::ClientConfig,
+    },
+    prelude::ErrorExt,
+    util::{debouncer::Debouncer, rate_counter::RateCounter},
 };
 use piston_window::{
-    Button, Key, MouseButton, MouseCursorEvent, PistonWindow, PressEvent, RenderEvent, UpdateEvent,
-    Window, WindowSettings,
+    AdvancedWindow, BufferSwap, Button, Context, ControllerAxisEvent, EventLoop, FocusEvent, Key,
+    MouseButton, MouseCursorEvent, MouseScrollEvent, PistonWindow, PressEvent, ReleaseEvent,
+    RenderEvent, ResizeEvent, TextEvent, TouchEvent, Transformed, UpdateEvent, Window,
+    WindowSettings,
 };
 use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 ///Configuration for the Piston window
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PistonConfig {
     ///The game id
     pub id: u32,
     ///The width/height of the window
     pub res: u32,
+    ///The limit on requests to the server
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    ///Where the server is, and how to identify to it
+    #[serde(default)]
+    pub client: ClientConfig,
+    ///Whether or not moves need to be confirmed before being sent to the server
+    #[serde(default)]
+    pub confirm_moves: bool,
+    ///Settings to protect against misclicks
+    #[serde(default)]
+    pub input: InputConfig,
+    ///How textures are filtered and scaled
+    #[serde(default)]
+    pub textures: TextureConfig,
+    ///How often the window is drawn and updated
+    #[serde(default)]
+    pub frames: FrameConfig,
+    ///The name of the installed theme to use, or `None` for the default sprites
+    #[serde(default)]
+    pub theme: Option<String>,
+    ///Whether or not the launcher checks for a new version when it starts
+    #[serde(default)]
+    pub check_for_updates: bool,
+    ///What the player joined the game as, from an invite - `None` to play either colour
+    #[serde(default)]
+    pub role: Option<InviteRole>,
+    ///The language the launcher is shown in
+    #[serde(default)]
+    pub language: Language,
+    ///`RUST_LOG`-style directives for which events are logged, like `info,async_chess_client::net=debug` - `None` to use `RUST_LOG`
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    ///Whether or not the player has opted in to sending anonymous usage statistics - see [`crate::telemetry`]
+    #[serde(default)]
+    pub telemetry: bool,
+    ///Which engine suggests moves for hints, and how many can be used in a game
+    #[serde(default)]
+    pub hints: HintConfig,
+    ///Whether or not the input is recorded, to be replayed with `--replay` - see [`crate::input_recording`]. Only set from the command line, with `--record-input`.
+    #[serde(skip)]
+    pub record_input: bool,
+    ///A PGN file to step through instead of playing a game, without talking to the server - see [`ChessGame::is_reviewing`]. Only set from the command line, with `--review`.
+    #[serde(skip)]
+    pub review: Option<PathBuf>,
+}
+
+impl Default for PistonConfig {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            res: 600,
+            rate_limit: RateLimit::default(),
+            client: ClientConfig::default(),
+            confirm_moves: false,
+            input: InputConfig::default(),
+            textures: TextureConfig::default(),
+            frames: FrameConfig::default(),
+            theme: None,
+            check_for_updates: false,
+            role: None,
+            language: Language::default(),
+            log_filter: None,
+            telemetry: false,
+            hints: HintConfig::default(),
+            record_input: false,
+            review: None,
+        }
+    }
+}
+
+impl PistonConfig {
+    ///Switches to the game from an [`Invite`], keeping the rest of the configuration.
+    ///
+    /// If the invite is for a different server, the backup servers are dropped as they won't have the game.
+    pub fn join(&mut self, invite: Invite) {
+        if self.client.server.trim_end_matches('/') != invite.server {
+            self.client.server = invite.server;
+            self.client.backup_servers.clear();
+        }
+        self.id = invite.id;
+        self.role = invite.role;
+    }
+}
+
+///Configuration for frame pacing
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrameConfig {
+    ///The most frames drawn per second
+    pub max_fps: u64,
+    ///The number of updates per second - each one checks for messages from the server
+    pub ups: u64,
+    ///Whether or not to wait for the monitor before showing each frame
+    pub vsync: bool,
+    ///If `Some`, the most frames drawn per second when there hasn't been any input or animation for a while
+    pub idle_fps: Option<u64>,
+    ///Whether or not only the squares which changed are redrawn, rather than the whole board every frame. Relies on the buffers being kept between frames, so should be turned off if old squares are left on the board.
+    pub dirty_regions: bool,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        Self {
+            max_fps: 60,
+            ups: 20,
+            vsync: true,
+            idle_fps: Some(15),
+            dirty_regions: true,
+        }
+    }
+}
+
+///Configuration for how mouse, touch and controller input is turned into moves
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    ///How far, in board pixels, the mouse has to move between press and release for it to count as dragging a piece. Shorter drags are treated as clicks.
+    pub min_drag_distance: f64,
+    ///Whether or not moves which take a piece need to be confirmed before being sent, even if [`PistonConfig::confirm_moves`] is off
+    pub confirm_captures: bool,
+    ///If `Some`, moves are checked before being sent, and ones which leave the player at least this many centipawns worse off than their best move need to be confirmed. `None` to not check moves.
+    pub blunder_threshold: Option<i32>,
+    ///How much bigger hit targets are for fingers than for the mouse. A tap can wander this many times [`InputConfig::min_drag_distance`] before it becomes a pan, and taps up to `(touch_target_scale - 1)` half-tiles off the edge of the board land on the edge squares.
+    pub touch_target_scale: f64,
+    ///Which controller buttons do what
+    pub controller: ControllerConfig,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            min_drag_distance: TILE_S / 2.0,
+            confirm_captures: false,
+            blunder_threshold: None,
+            touch_target_scale: 2.0,
+            controller: ControllerConfig::default(),
+        }
+    }
+}
+
+///The longest the window goes without being redrawn, so that times shown stay up to date
+const MAX_REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+///How long after the last input or animation the window is counted as idle, for [`FrameConfig::idle_fps`]
+const IDLE_AFTER: Duration = Duration::from_secs(2);
+
+///The most the board can be zoomed in
+const MAX_ZOOM: f64 = 4.0;
+///How much one notch of the scroll wheel zooms by
+const ZOOM_STEP: f64 = 1.1;
+///The shortest time between presses of an input which restarts the board or submits a move, so key repeat and double-clicks only act once
+const INPUT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+///How far the board is zoomed in, and where it is panned to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct View {
+    ///The zoom multiplier - `1.0` is the whole board
+    zoom: f64,
+    ///The offset in window pixels of the top left of the scene
+    pan: (f64, f64),
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+        }
+    }
+}
+
+impl View {
+    ///Zooms by a number of scroll wheel notches, keeping the point under the cursor in the same place
+    pub fn zoom_by(&mut self, notches: f64, about: (f64, f64), win_size: (f64, f64)) {
+        self.zoom_by_ratio(ZOOM_STEP.powf(notches), about, win_size);
+    }
+
+    ///Multiplies the zoom by a ratio, keeping the point `about` in the same place - eg. for a pinch
+    pub fn zoom_by_ratio(&mut self, ratio: f64, about: (f64, f64), win_size: (f64, f64)) {
+        let scene = self.to_scene(about);
+        self.zoom = (self.zoom * ratio).clamp(1.0, MAX_ZOOM);
+        self.pan = (about.0 - scene.0 * self.zoom, about.1 - scene.1 * self.zoom);
+        self.clamp_pan(win_size);
+    }
+
+    ///Pans by a number of window pixels. Does nothing unless zoomed in.
+    pub fn pan_by(&mut self, (dx, dy): (f64, f64), win_size: (f64, f64)) {
+        self.pan = (self.pan.0 + dx, self.pan.1 + dy);
+        self.clamp_pan(win_size);
+    }
+
+    ///Makes sure the scene always covers the whole window
+    fn clamp_pan(&mut self, (w, h): (f64, f64)) {
+        self.pan = (
+            self.pan.0.clamp(w * (1.0 - self.zoom), 0.0),
+            self.pan.1.clamp(h * (1.0 - self.zoom), 0.0),
+        );
+    }
+
+    ///Applies the zoom and pan to a [`Context`] for rendering
+    #[must_use]
+    pub fn transform(&self, c: Context) -> Context {
+        c.trans(self.pan.0, self.pan.1).zoom(self.zoom)
+    }
+
+    ///Converts window pixels to the pixels the scene would have if it wasn't zoomed or panned.
+    ///
+    /// Must be used on mouse positions before [`mp_valid`] and [`to_board_pixels`]
+    #[must_use]
+    pub fn to_scene(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        ((x - self.pan.0) / self.zoom, (y - self.pan.1) / self.zoom)
+    }
 }
 
 ///Starts up a piston window using the given [`PistonConfig`]
 #[tracing::instrument(skip(pc))]
 pub fn piston_main(pc: PistonConfig) {
+    let window_timer = startup_profiler().time(StartupPhase::WindowCreation);
     let mut win: PistonWindow = WindowSettings::new("Async Chess", [pc.res, pc.res])
         .exit_on_esc(true)
         .resizable(true)
+        .vsync(pc.frames.vsync)
         .build()
         .map_err(|e| anyhow!("{e}"))
         .context("making window")
         .unwrap_log_error();
-    // win.set_ups(5);
+    drop(window_timer);
+    win.set_max_fps(pc.frames.max_fps);
+    win.set_ups(pc.frames.ups);
+    //buffers are only swapped when something was drawn - see `needs_redraw`
+    win.set_swap_buffers(false);
+
+    if let Some(directives) = &pc.log_filter {
+        set_log_filter(Some(directives))
+            .context("setting log filter from config")
+            .error();
+    }
+
+    telemetry().set_enabled(pc.telemetry);
+    telemetry().upload_in_background(pc.client.clone());
+    let session_start = Instant::now();
 
-    let mut game = ChessGame::new(&mut win, pc.id)
+    let mut game = ChessGame::new(&mut win, &pc)
         .context("new chess game")
         .unwrap_log_error();
 
+    let mut fetch_timer = Some(startup_profiler().time(StartupPhase::FirstBoardFetch));
     game.update_list(true).context("initial update").error();
 
-    let mut mouse_pos = (0.0, 0.0);
-    let mut time_since_last_frame = 0.0;
-    let mut cached_dt = MemoryTimedCacher::<_, 100>::default();
-    let mut is_flipped = false;
+    let mut glyphs = assets_folder()
+        .and_then(|a| {
+            win.load_font(a.join("DejaVuSansMono.ttf"))
+                .map_err(Into::into)
+        })
+        .context("loading font")
+        .unwrap_log_error();
+
+    let mut raw_mouse_pos = (0.0, 0.0);
+    let mut view = View::default();
+    let mut is_panning = false;
+    let mut cursor_hidden = false;
+    let mut needs_redraw = true;
+    let mut last_draw = Instant::now();
+    let mut last_active = Instant::now();
+    let mut is_idle = false;
+    let mut frame_rate = RateCounter::new(Duration::from_secs(1));
+    let mut console = Console::default();
+    let mut move_entry = MoveEntry::default();
+    let mut debouncer = Debouncer::new(INPUT_DEBOUNCE);
+    let mut touch = TouchTracker::default();
+    let mut controller = ControllerInput::new(pc.input.controller);
+    let mut show_request_stats = false;
+    let mut show_book = false;
+    let mut show_moves = false;
+    let mut had_overlays = false;
+    let ipc = match IpcServer::start() {
+        Ok(ipc) => Some(ipc),
+        Err(e) => {
+            warn!(%e, "Unable to start local socket, other programs won't be able to reach the game");
+            None
+        }
+    };
+    //black is at the bottom when flipped
+    let mut is_flipped = pc.role == Some(InviteRole::Black);
 
     while let Some(e) = win.next() {
-        let window_scale = win.size().height / BOARD_S;
+        let window_scale = pc.textures.snap_scale(win.size().height / BOARD_S);
+        let win_size = (win.size().width, win.size().height);
 
-        if time_since_last_frame == 0.0 || cached_dt.is_empty() {
-            debug!(fps=%(1.0 / time_since_last_frame), cached_fps=%(1.0 / cached_dt.average_f64()));
+        if let Some(timer) = fetch_timer.take() {
+            if game.has_fetched() {
+                drop(timer);
+                needs_redraw = true;
+            } else if timer.elapsed() >= FETCH_TIMEOUT {
+                warn!("No board from the server yet, reporting startup without it");
+                timer.cancel();
+                needs_redraw = true;
+            } else {
+                fetch_timer = Some(timer);
+            }
+        }
+
+        let mouse_pos = scene_pos(&view, raw_mouse_pos, is_flipped, window_scale);
+
+        if e.resize_args().is_some() {
+            needs_redraw = true;
         }
 
-        if let Some(r) = e.render_args() {
-            time_since_last_frame = r.ext_dt;
-            cached_dt.add(r.ext_dt);
+        if e.focus_args() == Some(true) {
+            let _span = trace_span!("focus").entered();
+            game.focus_regained().context("refreshing on focus").error();
+            needs_redraw = true;
+            last_active = Instant::now();
+        }
+
+        if e.render_args().is_some() {
+            let _span = trace_span!("render").entered();
+            frame_rate.record();
+            //rounded, as gauges are whole numbers
+            #[allow(clippy::cast_possible_truncation)]
+            let fps = frame_rate.per_second().round() as i64;
+            metrics().set_gauge("frames.fps", fps);
 
-            win.draw_2d(&e, |c, g, _device| {
-                game.render(c, g, mouse_pos, window_scale, is_flipped)
+            if game.take_redraw() {
+                needs_redraw = true;
+                last_active = Instant::now();
+            }
+            needs_redraw |= last_draw.elapsed() >= MAX_REDRAW_INTERVAL;
+            //new log lines and requests could come in at any time
+            needs_redraw |= console.is_open() || show_request_stats || show_book;
+            if needs_redraw {
+                needs_redraw = false;
+                last_draw = Instant::now();
+                //the first frame drawn once the board has arrived, or the wait for it has timed out
+                let frame_timer = (fetch_timer.is_none() && !startup_profiler().is_reported())
+                    .then(|| startup_profiler().time(StartupPhase::FirstFrame));
+
+                //the game doesn't know what is drawn over it, so redraws everything while anything is and once it's gone
+                let overlays = console.is_open()
+                    || show_request_stats
+                    || show_book
+                    || show_moves
+                    || move_entry.is_open();
+                if overlays || had_overlays {
+                    game.invalidate_frame();
+                }
+                had_overlays = overlays;
+
+                let draw_span = trace_span!("draw").entered();
+                win.draw_2d(&e, |c, g, device| {
+                    game.render(
+                        view.transform(c),
+                        g,
+                        &mut glyphs,
+                        mouse_pos,
+                        window_scale,
+                        is_flipped,
+                    )
                     .context("rendering")
                     .error();
-            });
+                    if show_request_stats {
+                        render_request_stats(game.request_stats(), c, g, &mut glyphs, win_size)
+                            .context("rendering request stats")
+                            .error();
+                    }
+                    if show_book {
+                        render_book_moves(
+                            game.book_moves().as_deref(),
+                            c,
+                            g,
+                            &mut glyphs,
+                            win_size,
+                        )
+                        .context("rendering book moves")
+                        .error();
+                    }
+                    if show_moves {
+                        let list = game.move_list();
+                        render_move_list(
+                            list.as_ref().map(|(history, _)| history),
+                            list.as_ref().and_then(|(_, current)| current.as_ref()),
+                            c,
+                            g,
+                            &mut glyphs,
+                            win_size,
+                        )
+                        .context("rendering move list")
+                        .error();
+                    }
+                    let (board, white) = game.move_entry_position();
+                    move_entry
+                        .render(c, g, &mut glyphs, win_size, board, white)
+                        .context("rendering move entry")
+                        .error();
+                    console
+                        .render(c, g, &mut glyphs, win_size)
+                        .context("rendering console")
+                        .error();
+                    glyphs.factory.encoder.flush(device);
+                });
+                drop(draw_span);
+
+                let _span = trace_span!("swap_buffers").entered();
+                win.window.swap_buffers();
+
+                if let Some(timer) = frame_timer {
+                    drop(timer);
+                    if let Some(report) = startup_profiler().report(game.has_fetched()) {
+                        info!(?report, "Startup report");
+                        if startup_profiler().exit_after_report() {
+                            match serde_json::to_string_pretty(&report) {
+                                Ok(json) => println!("{json}"),
+                                Err(e) => error!(%e, "Unable to print startup report"),
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
         }
 
         if let Some(_u) = e.update_args() {
-            game.update_list(false).context("on update args").error();
+            let _span = trace_span!("update").entered();
+            let mut refresh = false;
+            if let Some(ipc) = &ipc {
+                while let Some(command) = ipc.try_recv() {
+                    match command {
+                        IpcCommand::Refresh => refresh = true,
+                    }
+                }
+                ipc.set_state(game.ipc_state());
+            }
+            game.update_list(refresh).context("on update args").error();
+
+            if let Some(action) = controller.poll() {
+                board_action(action, &mut game, is_flipped, window_scale);
+                needs_redraw = true;
+            }
+
+            if let Some(gesture) = touch.poll_long_press() {
+                touch_gesture(
+                    gesture,
+                    &mut game,
+                    &mut view,
+                    is_flipped,
+                    window_scale,
+                    win_size,
+                    &pc.input,
+                );
+                needs_redraw = true;
+            }
+
+            if let Some(idle_fps) = pc.frames.idle_fps {
+                let should_idle = last_active.elapsed() >= IDLE_AFTER;
+                if should_idle != is_idle {
+                    debug!(%should_idle, "Changing frame rate");
+                    win.set_max_fps(if should_idle {
+                        idle_fps
+                    } else {
+                        pc.frames.max_fps
+                    });
+                    is_idle = should_idle;
+                }
+            }
+
+            let holding = game.held_piece().is_some();
+            if holding != cursor_hidden {
+                //the held piece is drawn at the cursor instead
+                win.window.ctx.window().set_cursor_visible(!holding);
+                cursor_hidden = holding;
+            }
+
+            if let Some(status) = game.take_status() {
+                if status.is_empty() {
+                    win.set_title("Async Chess".into());
+                } else {
+                    win.set_title(format!("Async Chess - {status}"));
+                }
+            }
         }
 
         if let Some(pa) = e.press_args() {
+            let _span = trace_span!("input", ?pa).entered();
+            needs_redraw = true;
+            last_active = Instant::now();
             let mut update_now = false;
 
             match pa {
+                Button::Keyboard(Key::Backquote) => {
+                    console.toggle();
+                    telemetry().feature("console");
+                }
+                Button::Keyboard(kb) if console.is_open() => match console.key_press(kb) {
+                    Some(ConsoleCommand::Refresh) => update_now = true,
+                    Some(ConsoleCommand::Fen) => info!(fen=%game.fen(), "Current board"),
+                    Some(ConsoleCommand::Threats) => game.toggle_threats(),
+                    Some(ConsoleCommand::Eval) => game.toggle_eval_bar(),
+                    Some(ConsoleCommand::Hint) => game.request_hint(),
+                    Some(ConsoleCommand::Disconnect) => {
+                        game.simulate_offline(true).error();
+                    }
+                    Some(ConsoleCommand::Reconnect) => {
+                        game.simulate_offline(false).error();
+                        update_now = true;
+                    }
+                    Some(ConsoleCommand::SaveNetworkLog) => {
+                        game.save_network_log()
+                            .context("saving network log")
+                            .error();
+                    }
+                    Some(ConsoleCommand::MoveLog) => game.show_move_log(),
+                    Some(ConsoleCommand::SaveInputRecording) => {
+                        game.save_input_recording()
+                            .context("saving input recording")
+                            .error();
+                    }
+                    Some(ConsoleCommand::Stats) => {
+                        show_request_stats = !show_request_stats;
+                        telemetry().feature("request_stats");
+                    }
+                    Some(ConsoleCommand::Practice(against_engine)) => {
+                        game.toggle_practice(against_engine);
+                    }
+                    Some(ConsoleCommand::Analysis) => game.toggle_analysis(),
+                    Some(ConsoleCommand::Book) => {
+                        show_book = !show_book;
+                        telemetry().feature("book");
+                    }
+                    Some(ConsoleCommand::Moves) => {
+                        show_moves = !show_moves;
+                        telemetry().feature("moves");
+                    }
+                    Some(ConsoleCommand::Annotate {
+                        path,
+                        glyph,
+                        comment,
+                    }) => game.annotate_move(&path, glyph, comment),
+                    Some(ConsoleCommand::Pgn(None)) => game.save_pgn(),
+                    Some(ConsoleCommand::Pgn(Some(path))) => game.open_pgn(&path),
+                    Some(ConsoleCommand::Scoresheet) => game.save_scoresheet(),
+                    Some(ConsoleCommand::Import(external)) => game.import_game(external),
+                    Some(ConsoleCommand::Trace) => {
+                        trace_capture().toggle().context("toggling trace").error();
+                        telemetry().feature("trace");
+                    }
+                    _ => {}
+                },
+                Button::Keyboard(kb) if move_entry.is_open() => {
+                    let (board, white) = game.move_entry_position();
+                    if let Some(m) = move_entry.key_press(kb, board, white) {
+                        game.typed_move(m, window_scale)
+                            .context("making typed move")
+                            .error();
+                        update_now = true;
+                    }
+                }
+                Button::Keyboard(Key::Slash | Key::Colon | Key::Semicolon) => {
+                    move_entry.open();
+                    telemetry().feature("move_entry");
+                }
                 Button::Keyboard(kb) => {
                     info!(?kb, "Keyboard Input");
 
                     match kb {
                         Key::C => {
-                            //Clear
-                            game.restart_board().context("restart on c key").error();
-                            update_now = true;
+                            //Clear - held keys repeat, which would restart again and again
+                            if debouncer.press(pa) {
+                                game.record_input(RecordedInput::Key(Key::C));
+                                game.restart_board().context("restart on c key").error();
+                            }
                         },
-                        Key::F =>  is_flipped = !is_flipped,
+                        Key::F => {
+                            is_flipped = !is_flipped;
+                            telemetry().feature("flip");
+                        }
+                        Key::D => {
+                            game.save_diagnostic().context("saving diagnostic").error();
+                        }
+                        Key::S => game.save_scorecard(),
+                        Key::R => game.claim_draw(),
+                        Key::T => game.toggle_threats(),
+                        Key::E => game.toggle_eval_bar(),
+                        Key::H => game.request_hint(),
+                        Key::P => game.toggle_practice(false),
+                        Key::A => game.toggle_analysis(),
+                        Key::Left => game.step_analysis(AnalysisStep::Back),
+                        Key::Right => game.step_analysis(AnalysisStep::Forward),
+                        Key::Up => game.step_analysis(AnalysisStep::PreviousVariation),
+                        Key::Down => game.step_analysis(AnalysisStep::NextVariation),
+                        Key::Z => {
+                            view = View::default();
+                            telemetry().feature("reset_view");
+                        }
+                        Key::Return => {
+                            if debouncer.press(pa) {
+                                game.record_input(RecordedInput::Key(Key::Return));
+                                game.confirm_move().context("confirming move").error();
+                                update_now = true;
+                            }
+                        }
                         _ => {}
                     }
                 }
+                Button::Controller(cb) => {
+                    let action = controller.button(cb);
+                    //select is debounced like a click, as it can send a move
+                    if action != Some(BoardAction::Select) || debouncer.press(pa) {
+                        update_now = action
+                            .is_some_and(|a| board_action(a, &mut game, is_flipped, window_scale));
+                    }
+                }
+                Button::Hat(hat) => {
+                    if let Some(action) = ControllerInput::hat(hat) {
+                        board_action(action, &mut game, is_flipped, window_scale);
+                    }
+                }
                 Button::Mouse(mb) => {
                     if mb == MouseButton::Right {
+                        game.record_input(RecordedInput::Press(mb, (0.0, 0.0)));
                         game.clear_mouse_input();
-                    } else if mp_valid(mouse_pos, window_scale) {
-                        game.mouse_input(to_board_pixels(mouse_pos, window_scale), window_scale)
-                            .context("dealing with mouse input")
-                            .error();
-                        update_now = true;
+                    } else if mb == MouseButton::Middle {
+                        is_panning = true;
+                    } else if debouncer.press(pa) {
+                        //the button is drawn above the board, which isn't flipped
+                        let scene_pos = view.to_scene(raw_mouse_pos);
+                        if game.press_claim_draw_button(scene_pos, window_scale) {
+                            update_now = true;
+                        } else if mp_valid(mouse_pos, window_scale) {
+                            let board_pos = to_board_pixels(mouse_pos, window_scale);
+                            game.record_input(RecordedInput::Press(mb, board_pos));
+                            game.mouse_input(board_pos, window_scale)
+                                .context("dealing with mouse input")
+                                .error();
+                            update_now = true;
+                        }
                     }
                 }
                 _ => {}
@@ -101,22 +694,205 @@ pub fn piston_main(pc: PistonConfig) {
                 .error();
         }
 
+        //whether the press this ends was let through by the debouncer
+        let mut press_allowed = false;
+        if let Some(ra) = e.release_args() {
+            press_allowed = debouncer.release(&ra);
+            needs_redraw = true;
+            last_active = Instant::now();
+        }
+
+        if let Some(Button::Mouse(MouseButton::Middle)) = e.release_args() {
+            is_panning = false;
+        }
+
+        e.text(|t| {
+            if console.is_open() {
+                console.text(t);
+                needs_redraw = true;
+            } else if move_entry.is_open() {
+                move_entry.text(t);
+                needs_redraw = true;
+            }
+        });
+
+        e.mouse_scroll(|d| {
+            view.zoom_by(d[1], raw_mouse_pos, win_size);
+            needs_redraw = true;
+            last_active = Instant::now();
+        });
+
+        if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+            let _span = trace_span!("input", release = "left").entered();
+            if press_allowed && mp_valid(mouse_pos, window_scale) {
+                let board_pos = to_board_pixels(mouse_pos, window_scale);
+                game.record_input(RecordedInput::Release(MouseButton::Left, board_pos));
+                game.mouse_release(board_pos, window_scale)
+                    .context("dealing with mouse release")
+                    .error();
+                game.update_list(true)
+                    .context("update on mouse release")
+                    .error();
+            }
+        }
+
+        if let Some(args) = e.controller_axis_args() {
+            if let Some(action) = controller.axis(&args) {
+                board_action(action, &mut game, is_flipped, window_scale);
+                needs_redraw = true;
+                last_active = Instant::now();
+            }
+        }
+
+        if let Some(args) = e.touch_args() {
+            //the piece held after a tap is drawn where the finger is
+            raw_mouse_pos = {
+                let [x, y] = args.position();
+                (x, y)
+            };
+            let slop = pc.input.min_drag_distance * pc.input.touch_target_scale * window_scale;
+            if let Some(gesture) = touch.touch(&args, slop) {
+                touch_gesture(
+                    gesture,
+                    &mut game,
+                    &mut view,
+                    is_flipped,
+                    window_scale,
+                    win_size,
+                    &pc.input,
+                );
+            }
+            needs_redraw = true;
+            last_active = Instant::now();
+        }
+
         e.mouse_cursor(|p| {
-            if is_flipped {
-                mouse_pos = (p[0], (BOARD_S * window_scale) - p[1]);
-            } else {
-                mouse_pos = (p[0], p[1]);
+            if is_panning {
+                view.pan_by((p[0] - raw_mouse_pos.0, p[1] - raw_mouse_pos.1), win_size);
             }
+            raw_mouse_pos = (p[0], p[1]);
+            needs_redraw = true;
+            last_active = Instant::now();
         });
     }
 
     info!("Finishing and cleaning up");
+    telemetry().session(session_start.elapsed().as_secs());
+    telemetry().flush().context("saving telemetry").warn();
     game.exit().context("clearing up").error();
 }
 
+///Converts a position in window pixels to the scene, undoing the [`View`] and flipping it if black is at the bottom
+fn scene_pos(view: &View, raw: (f64, f64), is_flipped: bool, window_scale: f64) -> (f64, f64) {
+    let p = view.to_scene(raw);
+    if is_flipped {
+        (p.0, (BOARD_S * window_scale) - p.1)
+    } else {
+        p
+    }
+}
+
+///Acts on a [`BoardAction`], from a controller. Returns whether the board should be updated straight away, as a move might have been made.
+fn board_action(
+    action: BoardAction,
+    game: &mut ChessGame,
+    is_flipped: bool,
+    window_scale: f64,
+) -> bool {
+    let _span = trace_span!("board_action", ?action).entered();
+    match action {
+        BoardAction::Cursor((dx, dy)) => {
+            //the cursor moves as seen on the screen, so up is down the board when flipped
+            game.move_cursor((dx, if is_flipped { -dy } else { dy }));
+            false
+        }
+        BoardAction::Select => {
+            game.cursor_select(window_scale)
+                .context("selecting with cursor")
+                .error();
+            true
+        }
+        BoardAction::Cancel => {
+            game.clear_mouse_input();
+            false
+        }
+        BoardAction::HistoryBack => {
+            game.step_analysis(AnalysisStep::Back);
+            false
+        }
+        BoardAction::HistoryForward => {
+            game.step_analysis(AnalysisStep::Forward);
+            false
+        }
+    }
+}
+
+///Acts on a [`TouchGesture`] - taps and long presses go to the board, and pans and pinches move the [`View`]
+fn touch_gesture(
+    gesture: TouchGesture,
+    game: &mut ChessGame,
+    view: &mut View,
+    is_flipped: bool,
+    window_scale: f64,
+    win_size: (f64, f64),
+    input: &InputConfig,
+) {
+    let _span = trace_span!("touch", ?gesture).entered();
+    let margin = (input.touch_target_scale - 1.0) * TILE_S / 2.0 * window_scale;
+    let on_board = |raw| {
+        let pos = snap_to_board(
+            scene_pos(view, raw, is_flipped, window_scale),
+            margin,
+            window_scale,
+        );
+        Some(pos).filter(|p| mp_valid(*p, window_scale))
+    };
+
+    match gesture {
+        TouchGesture::Tap(raw) => {
+            let Some(pos) = on_board(raw) else {
+                return;
+            };
+            let board_pos = to_board_pixels(pos, window_scale);
+            let board_pos = to_board_pixels(pos, window_scale);
+            game.mouse_input(board_pos, window_scale)
+                .context("dealing with tap")
+                .error();
+            game.mouse_release(board_pos, window_scale)
+                .context("dealing with end of tap")
+                .error();
+            game.update_list(true).context("update on tap").error();
+        }
+        TouchGesture::LongPress(raw) => {
+            if let Some(pos) = on_board(raw) {
+                game.piece_info(to_board_pixels(pos, window_scale), window_scale);
+                telemetry().feature("piece_info");
+            }
+        }
+        TouchGesture::Pan(by) => view.pan_by(by, win_size),
+        TouchGesture::Pinch { ratio, about } => view.zoom_by_ratio(ratio, about, win_size),
+    }
+}
+
+///Moves a position just off the edge of the board onto the edge squares, so they are as easy to tap as the others. Positions more than `margin` off the board are left alone.
+fn snap_to_board(pos: (f64, f64), margin: f64, window_scale: f64) -> (f64, f64) {
+    let (low, high) = (LEFT_BOUND * window_scale, RIGHT_BOUND * window_scale);
+    let inset = PADDING * window_scale;
+    let snap = |v: f64| {
+        if v > low - margin && v <= low {
+            low + inset
+        } else if v >= high && v < high + margin {
+            high - inset
+        } else {
+            v
+        }
+    };
+    (snap(pos.0), snap(pos.1))
+}
+
 ///Checks whether or not the mouse is on the board
 ///
-/// Must always be called BEFORE [`to_board_pixels`]
+/// Must always be called BEFORE [`to_board_pixels`], with a position already converted using [`View::to_scene`]
 pub fn mp_valid(raw_mp: (f64, f64), window_scale: f64) -> bool {
     raw_mp.0 > LEFT_BOUND * window_scale
         && raw_mp.0 < RIGHT_BOUND * window_scale
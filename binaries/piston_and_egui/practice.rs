@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use async_chess_client::{
+    chess::{
+        attacks::pseudo_legal_moves,
+        boards::{
+            board::{Board, CanMovePiece},
+            board_container::BoardContainer,
+        },
+        engine::HintEngine,
+    },
+    net::server_interface::JSONMove,
+    prelude::{Coords, Either},
+    util::time_based_structs::stopwatch::Stopwatch,
+};
+use epac_utils::error_ext::ToAnyhowNotErr;
+use std::{
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    time::Duration,
+};
+
+///Who the player practises against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PracticeOpponent {
+    ///The player moves both colours
+    Nobody,
+    ///The hint engine moves one colour
+    Engine {
+        ///Whether the engine plays white
+        is_white: bool,
+    },
+}
+
+///An offline copy of the live board, for practising from the current position.
+///
+/// Moves are checked and made locally, and nothing is ever sent to the server - the live board keeps updating separately, so the player can go back to it at any time.
+pub struct Practice {
+    ///The practice board - always able to move a piece, as nothing waits for the server
+    board: BoardContainer,
+    ///Whether white moves next
+    white_to_move: bool,
+    ///Who the player is practising against
+    opponent: PracticeOpponent,
+    ///The engine which replies, if the opponent is [`PracticeOpponent::Engine`]
+    engine: HintEngine,
+    ///Receiver for the engine's reply, if it is still thinking
+    reply_rx: Option<Receiver<Result<Option<(Coords, Coords)>>>>,
+    ///Number of moves made on the practice board
+    moves: u32,
+    ///How long each colour has spent thinking, as `(white, black)` - only the clock of the colour to move runs
+    clocks: (Stopwatch, Stopwatch),
+}
+
+impl Practice {
+    ///Forks a practice board from the live board. If the engine is to move first, it starts thinking straight away.
+    #[must_use]
+    pub fn new(
+        board: Board<CanMovePiece>,
+        white_to_move: bool,
+        opponent: PracticeOpponent,
+        engine: HintEngine,
+    ) -> Self {
+        let mut practice = Self {
+            board: Either::Left(board),
+            white_to_move,
+            opponent,
+            engine,
+            reply_rx: None,
+            moves: 0,
+            clocks: (Stopwatch::default(), Stopwatch::default()),
+        };
+        practice.clock_mut(white_to_move).resume();
+        practice.start_reply();
+        practice
+    }
+
+    ///Gets the practice board, for rendering
+    #[must_use]
+    pub const fn board(&self) -> &BoardContainer {
+        &self.board
+    }
+
+    ///Gets whether white moves next
+    #[must_use]
+    pub const fn white_to_move(&self) -> bool {
+        self.white_to_move
+    }
+
+    ///Gets who the player is practising against
+    #[must_use]
+    pub const fn opponent(&self) -> PracticeOpponent {
+        self.opponent
+    }
+
+    ///Gets the number of moves made on the practice board
+    #[must_use]
+    pub const fn moves(&self) -> u32 {
+        self.moves
+    }
+
+    ///Gets how long a colour has spent thinking on the practice board, including the current move if it is that colour's turn
+    #[must_use]
+    pub fn thinking_time(&self, white: bool) -> Duration {
+        if white {
+            self.clocks.0.elapsed()
+        } else {
+            self.clocks.1.elapsed()
+        }
+    }
+
+    ///Gets the clock for a colour
+    fn clock_mut(&mut self, white: bool) -> &mut Stopwatch {
+        if white {
+            &mut self.clocks.0
+        } else {
+            &mut self.clocks.1
+        }
+    }
+
+    ///Makes a move for the player, and starts the engine's reply if it is practising against one
+    ///
+    /// # Errors
+    /// - The engine is still thinking
+    /// - There isn't a piece of the colour to move on `from`
+    /// - The piece can't move to `to`
+    pub fn make_move(&mut self, from: Coords, to: Coords) -> Result<()> {
+        if self.reply_rx.is_some() {
+            bail!("Wait for the engine to reply");
+        }
+        let colour = if self.white_to_move { "white" } else { "black" };
+        match self.board[from] {
+            Some(p) if p.is_white == self.white_to_move => {}
+            _ => bail!("It is {colour}'s turn"),
+        }
+        check_offline_move(&self.board, from, to)?;
+
+        self.apply(from, to)?;
+        self.start_reply();
+        Ok(())
+    }
+
+    ///Picks up the engine's reply if it has finished, and makes it. Returns whether the board changed.
+    ///
+    /// # Errors
+    /// - The engine failed, or suggested a move which couldn't be made
+    pub fn poll(&mut self) -> Result<bool> {
+        let Some(received) = self.reply_rx.as_ref().map(Receiver::try_recv) else {
+            return Ok(false);
+        };
+        let reply = match received {
+            Ok(reply) => reply,
+            Err(TryRecvError::Empty) => return Ok(false),
+            Err(TryRecvError::Disconnected) => Err(anyhow!("engine thread stopped")),
+        };
+        self.reply_rx = None;
+
+        match reply.context("getting engine reply")? {
+            Some((from, to)) => {
+                info!(?from, ?to, "Engine replied in practice");
+                self.apply(from, to)?;
+                Ok(true)
+            }
+            None => bail!("The engine has no moves"),
+        }
+    }
+
+    ///Starts the engine thinking on a new thread, if it is the engine's turn
+    fn start_reply(&mut self) {
+        let PracticeOpponent::Engine { is_white } = self.opponent else {
+            return;
+        };
+        let Either::Left(board) = self.board.clone() else {
+            return;
+        };
+        if is_white != self.white_to_move {
+            return;
+        }
+
+        let (tx, rx) = channel();
+        let (engine, white) = (self.engine.clone(), self.white_to_move);
+        std::thread::spawn(move || {
+            //if practice has ended, nobody needs to know
+            let _ = tx.send(engine.suggest(&board, white));
+        });
+        self.reply_rx = Some(rx);
+    }
+
+    ///Moves a piece on the practice board, and passes the turn - and the running clock - over
+    ///
+    /// # Errors
+    /// - Fail to make the move - see [`make_offline_move`]
+    fn apply(&mut self, from: Coords, to: Coords) -> Result<()> {
+        self.board = make_offline_move(&self.board, from, to)?;
+        self.clock_mut(self.white_to_move).pause();
+        self.white_to_move = !self.white_to_move;
+        self.clock_mut(self.white_to_move).resume();
+        self.moves += 1;
+        Ok(())
+    }
+}
+
+///Checks a move on a board which is never sent to the server, as the server would - there must be a piece on `from` which could move to `to`
+///
+/// # Errors
+/// - There isn't a piece on `from`
+/// - The piece can't move to `to`
+pub fn check_offline_move(board: &BoardContainer, from: Coords, to: Coords) -> Result<()> {
+    let Some(piece) = board[from] else {
+        bail!("There isn't a piece there");
+    };
+    let possible = match board {
+        Either::Left(b) => pseudo_legal_moves(b, piece.is_white),
+        Either::Right(b) => pseudo_legal_moves(b, piece.is_white),
+    };
+    if !possible.contains(&(from, to)) {
+        bail!("That move isn't possible");
+    }
+    Ok(())
+}
+
+///Makes a move on a copy of a board which is never sent to the server, without checking it
+///
+/// # Errors
+/// - Either square isn't on the board
+/// - The board is waiting for the server to accept a move
+pub fn make_offline_move(
+    board: &BoardContainer,
+    from: Coords,
+    to: Coords,
+) -> Result<BoardContainer> {
+    let ((x, y), (nx, ny)) = (
+        from.to_option().ae().context("move from off the board")?,
+        to.to_option().ae().context("move to off the board")?,
+    );
+    let Either::Left(board) = board.clone() else {
+        bail!("board waiting for a move update");
+    };
+
+    let taken = board[to].is_some();
+    let m = JSONMove::new(0, x.into(), y.into(), nx.into(), ny.into());
+    Ok(Either::Left(board.make_move(m).move_worked(taken)))
+}
@@ -0,0 +1,192 @@
+use crate::{
+    cacher::assets_folder,
+    pixel_size_consts::{BOARD_S, BOARD_TILE_S, LEFT_BOUND_PADDING},
+};
+use anyhow::{Context, Result};
+use async_chess_client::{
+    chess::{
+        boards::board::{Board, BoardMoveState},
+        history::DescribedMove,
+    },
+    util::error_ext::ToAnyhowNotErr,
+};
+use directories::ProjectDirs;
+use image::{
+    imageops::{overlay, resize, FilterType},
+    Rgba, RgbaImage,
+};
+use imageproc::drawing::draw_text_mut;
+use rusttype::{Font, Scale};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{create_dir_all, read},
+    path::{Path, PathBuf},
+};
+
+///How many times bigger than the sprites the scorecard is drawn, so the pixel art stays crisp when shared
+const SCALE: u32 = 3;
+///Height of the text, in scorecard pixels
+const TEXT_S: f32 = 22.0;
+///Space between lines of text, and around the edges
+const PADDING: u32 = 12;
+///Width of each column of the move list
+const COLUMN_W: u32 = 280;
+///Colour behind the move list
+const BACKGROUND: Rgba<u8> = Rgba([24, 24, 24, 255]);
+///Colour of the text
+const TEXT_COLOUR: Rgba<u8> = Rgba([230, 230, 230, 255]);
+
+///One numbered pair of moves from [`move_pairs`] - the move number, then white's and black's moves in SAN. Either move can be missing at the start or end of a game.
+pub type MovePair = (String, Option<String>, Option<String>);
+
+///Pairs up the moves in the main line of a game by move number, with any annotation glyphs. Variations are left out.
+#[must_use]
+pub fn move_pairs(moves: &[DescribedMove]) -> Vec<MovePair> {
+    let mut pairs: Vec<MovePair> = vec![];
+    for m in moves.iter().filter(|m| m.depth == 0) {
+        let san = format!("{}{}", m.san, m.glyph.map_or("", |g| g.symbol()));
+        match pairs.last_mut() {
+            Some((_, Some(_), black @ None)) if !m.white => *black = Some(san),
+            _ => {
+                let number = m.number.trim_end_matches('.').to_string();
+                if m.white {
+                    pairs.push((number, Some(san), None));
+                } else {
+                    pairs.push((number, None, Some(san)));
+                }
+            }
+        }
+    }
+    pairs
+}
+
+///Writes out the main line of a game as numbered pairs of moves, eg. `1. e4 e5` or `1... e5` if black moved first
+#[must_use]
+pub fn move_lines(moves: &[DescribedMove]) -> Vec<String> {
+    move_pairs(moves)
+        .into_iter()
+        .map(|(number, white, black)| match (white, black) {
+            (Some(w), Some(b)) => format!("{number}. {w} {b}"),
+            (Some(w), None) => format!("{number}. {w}"),
+            (None, b) => format!("{number}... {}", b.unwrap_or_default()),
+        })
+        .collect()
+}
+
+///Draws a scorecard - the board with its pieces on the left, and the heading and move list beside it, in as many columns as the moves need.
+///
+/// The sprites come from the theme folder if one is given and has them, and the [`assets_folder`] if not, like the [`Cacher`](crate::cacher::Cacher). If `flipped`, black is at the bottom.
+///
+/// # Errors
+/// - Fail to find the assets folder
+/// - Fail to load a sprite or the font
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_possible_wrap
+)]
+pub fn render_scorecard<S: BoardMoveState>(
+    board: &Board<S>,
+    moves: &[DescribedMove],
+    heading: &[String],
+    flipped: bool,
+    theme: Option<&Path>,
+) -> Result<RgbaImage> {
+    let assets = assets_folder()?;
+    let sprite = |name: &str| -> Result<RgbaImage> {
+        let path = theme
+            .map(|t| t.join(name))
+            .filter(|p| p.is_file())
+            .unwrap_or_else(|| assets.join(name));
+        let img = image::open(&path)
+            .with_context(|| format!("loading sprite {path:?}"))?
+            .to_rgba8();
+        let (w, h) = img.dimensions();
+        Ok(resize(&img, w * SCALE, h * SCALE, FilterType::Nearest))
+    };
+    let font = Font::try_from_vec(read(assets.join("DejaVuSansMono.ttf")).context("reading font")?)
+        .ae()
+        .context("loading font")?;
+
+    //everything here is a few thousand pixels at most, so the casts can't overflow
+    let board_s = (BOARD_S * f64::from(SCALE)) as u32;
+    let line_h = TEXT_S as u32 + PADDING / 2;
+
+    let lines = move_lines(moves);
+    let top = PADDING + line_h * heading.len() as u32;
+    let per_column = ((board_s.saturating_sub(top + PADDING)) / line_h).max(1) as usize;
+    let columns = lines.len().div_ceil(per_column).max(1) as u32;
+
+    let mut card = RgbaImage::from_pixel(board_s + COLUMN_W * columns, board_s, BACKGROUND);
+    overlay(&mut card, &sprite("board_alt.png")?, 0, 0);
+
+    let scale = f64::from(SCALE);
+    let mut pieces = HashMap::new();
+    for row in 0..8_u8 {
+        for col in 0..8_u8 {
+            let Some(piece) = board[(col, row).into()] else {
+                continue;
+            };
+            let shown_row = if flipped { 7 - row } else { row };
+            let at = |v: u8| (LEFT_BOUND_PADDING + f64::from(v) * BOARD_TILE_S) * scale;
+            let (x, y) = (at(col) as i64, at(shown_row) as i64);
+            let tile = match pieces.entry(piece.to_file_name()) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    let tile = sprite(e.key())?;
+                    e.insert(tile)
+                }
+            };
+            overlay(&mut card, tile, x, y);
+        }
+    }
+
+    let text_scale = Scale::uniform(TEXT_S);
+    let left = (board_s + PADDING) as i32;
+    let line_y = |i: usize| (i as u32 * line_h) as i32;
+    for (i, line) in heading.iter().enumerate() {
+        draw_text_mut(
+            &mut card,
+            TEXT_COLOUR,
+            left,
+            PADDING as i32 + line_y(i),
+            text_scale,
+            &font,
+            line,
+        );
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let column = (i / per_column) as u32;
+        draw_text_mut(
+            &mut card,
+            TEXT_COLOUR,
+            left + (column * COLUMN_W) as i32,
+            top as i32 + line_y(i % per_column),
+            text_scale,
+            &font,
+            line,
+        );
+    }
+
+    Ok(card)
+}
+
+///Saves a scorecard as a PNG in the project data directory, named after the game
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to create the directory, or write the image
+#[tracing::instrument(skip(card))]
+pub fn export_scorecard(id: u32, card: &RgbaImage) -> Result<PathBuf> {
+    let dir = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?
+        .data_dir()
+        .join("scorecards");
+    create_dir_all(&dir).context("creating scorecards directory")?;
+
+    let path = dir.join(format!("{id}.png"));
+    card.save(&path)
+        .with_context(|| format!("saving scorecard to {path:?}"))?;
+    Ok(path)
+}
@@ -0,0 +1,203 @@
+use crate::scorecard::{move_pairs, MovePair};
+use anyhow::{Context, Result};
+use async_chess_client::{
+    chess::{
+        boards::board::{Board, CanMovePiece},
+        history::MoveHistory,
+    },
+    net::server_interface::JSONResult,
+    prelude::{ChessPiece, ChessPieceKind},
+    util::error_ext::ToAnyhowNotErr,
+};
+use directories::ProjectDirs;
+use std::{fs::create_dir_all, path::PathBuf};
+
+///Styles for the scoresheet - plain black on white, with each diagram kept on one page when printed
+const STYLE: &str = "body { font-family: serif; margin: 2em; color: black; background: white; }
+table.tags td { padding: 0 1em 0 0; }
+table.moves { border-collapse: collapse; margin: 1em 0; }
+table.moves td, table.moves th { border: 1px solid black; padding: 0.2em 0.6em; min-width: 4em; }
+table.moves td.gap { border: none; min-width: 1em; }
+.diagram { display: inline-block; margin: 0 1.5em 1.5em 0; page-break-inside: avoid; break-inside: avoid; }
+.diagram table { border-collapse: collapse; border: 2px solid black; }
+.diagram td { width: 1.6em; height: 1.6em; text-align: center; font-size: 1.4em; padding: 0; }
+.diagram td.dark { background: #bbb; }
+.diagram td.rank, .diagram td.file { font-size: 0.8em; background: none; }
+@media print { body { margin: 0; } }";
+
+///A position to draw as a diagram on the scoresheet
+struct Diagram {
+    ///What the diagram shows, eg. `After 12. Nf3!`
+    caption: String,
+    ///The comment on the move, if there is one
+    comment: Option<String>,
+    ///The position
+    board: Board<CanMovePiece>,
+}
+
+///Writes the main line of a game out as a printable HTML scoresheet, and saves it in the project data directory named after the game - ready to be printed, or saved as a PDF from the browser.
+///
+/// It has the tag pairs at the top, then the moves in a table of two columns, then diagrams of the key positions - after every move with an annotation or comment, and the final position.
+///
+/// # Errors
+/// - Fail to write out the moves - see [`MoveHistory::described_moves`]
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to create the directory, or write the file
+#[tracing::instrument(skip(history))]
+pub fn export_scoresheet(
+    id: u32,
+    history: &MoveHistory,
+    result: Option<JSONResult>,
+) -> Result<PathBuf> {
+    let described = history.described_moves().context("writing out moves")?;
+    let pairs = move_pairs(&described);
+
+    let mut diagrams = vec![];
+    let mut board = history.start_board().context("getting start board")?;
+    let main_line = described.iter().filter(|m| m.depth == 0);
+    for (m, d) in history.moves().iter().zip(main_line) {
+        board = board.with_move(m.from, m.to);
+        if m.glyph.is_some() || m.comment.is_some() {
+            diagrams.push(Diagram {
+                caption: format!(
+                    "After {} {}{}",
+                    d.number,
+                    d.san,
+                    d.glyph.map_or("", |g| g.symbol())
+                ),
+                comment: m.comment.clone(),
+                board: board.clone(),
+            });
+        }
+    }
+    diagrams.push(Diagram {
+        caption: "Final position".into(),
+        comment: None,
+        board,
+    });
+
+    let result = result.map_or_else(|| "*".to_string(), |r| r.to_string());
+    let html = scoresheet_html(
+        &[
+            ("Event", format!("Async Chess game {id}")),
+            ("White", "?".into()),
+            ("Black", "?".into()),
+            ("Result", result),
+        ],
+        &pairs,
+        &diagrams,
+    );
+
+    let dir = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?
+        .data_dir()
+        .join("scoresheets");
+    create_dir_all(&dir).context("creating scoresheets directory")?;
+
+    let path = dir.join(format!("{id}.html"));
+    std::fs::write(&path, html).with_context(|| format!("writing to {path:?}"))?;
+    Ok(path)
+}
+
+///Builds the HTML for [`export_scoresheet`]
+fn scoresheet_html(tags: &[(&str, String)], pairs: &[MovePair], diagrams: &[Diagram]) -> String {
+    let title = tags
+        .first()
+        .map_or_else(|| "Scoresheet".to_string(), |(_, v)| escape(v));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<table class=\"tags\">\n"
+    );
+    for (k, v) in tags {
+        html += &format!("<tr><td>{}</td><td>{}</td></tr>\n", escape(k), escape(v));
+    }
+    html += "</table>\n";
+
+    //the first half of the moves down the left, and the rest down the right
+    let half = (pairs.len() + 1) / 2;
+    html += "<table class=\"moves\">\n<tr><th>#</th><th>White</th><th>Black</th><td class=\"gap\"></td><th>#</th><th>White</th><th>Black</th></tr>\n";
+    for row in 0..half {
+        html += "<tr>";
+        html += &move_cells(pairs.get(row));
+        html += "<td class=\"gap\"></td>";
+        html += &move_cells(pairs.get(row + half));
+        html += "</tr>\n";
+    }
+    html += "</table>\n";
+
+    for diagram in diagrams {
+        html += &diagram_html(diagram);
+    }
+    html += "</body>\n</html>\n";
+    html
+}
+
+///Writes the three cells for a pair of moves in the move table - empty if there is no pair
+fn move_cells(pair: Option<&MovePair>) -> String {
+    let (number, white, black) = pair.map_or(("", None, None), |(n, w, b)| {
+        (n.as_str(), w.as_deref(), b.as_deref())
+    });
+    format!(
+        "<td>{}</td><td>{}</td><td>{}</td>",
+        escape(number),
+        escape(white.unwrap_or("")),
+        escape(black.unwrap_or(""))
+    )
+}
+
+///Draws a position as a table of squares with a chess symbol for each piece, from white's side
+fn diagram_html(diagram: &Diagram) -> String {
+    let mut html = format!(
+        "<div class=\"diagram\">\n<p><b>{}</b></p>\n<table>\n",
+        escape(&diagram.caption)
+    );
+    for row in 0..8_u8 {
+        html += &format!("<tr><td class=\"rank\">{}</td>", 8 - row);
+        for col in 0..8_u8 {
+            let shade = if (row + col) % 2 == 0 {
+                "light"
+            } else {
+                "dark"
+            };
+            let piece = diagram.board[(col, row).into()].map_or(' ', piece_symbol);
+            html += &format!("<td class=\"{shade}\">{piece}</td>");
+        }
+        html += "</tr>\n";
+    }
+    html += "<tr><td class=\"file\"></td>";
+    for file in 'a'..='h' {
+        html += &format!("<td class=\"file\">{file}</td>");
+    }
+    html += "</tr>\n</table>\n";
+    if let Some(comment) = &diagram.comment {
+        html += &format!("<p>{}</p>\n", escape(comment));
+    }
+    html += "</div>\n";
+    html
+}
+
+///Gets the chess symbol for a piece, eg. `♘` for a white knight
+const fn piece_symbol(piece: ChessPiece) -> char {
+    match (piece.is_white, piece.kind) {
+        (true, ChessPieceKind::King) => '♔',
+        (true, ChessPieceKind::Queen) => '♕',
+        (true, ChessPieceKind::Rook) => '♖',
+        (true, ChessPieceKind::Bishop) => '♗',
+        (true, ChessPieceKind::Knight) => '♘',
+        (true, ChessPieceKind::Pawn) => '♙',
+        (false, ChessPieceKind::King) => '♚',
+        (false, ChessPieceKind::Queen) => '♛',
+        (false, ChessPieceKind::Rook) => '♜',
+        (false, ChessPieceKind::Bishop) => '♝',
+        (false, ChessPieceKind::Knight) => '♞',
+        (false, ChessPieceKind::Pawn) => '♟',
+    }
+}
+
+///Escapes the characters which mean something in HTML
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
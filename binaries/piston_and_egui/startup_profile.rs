@@ -0,0 +1,131 @@
+use async_chess_client::util::time_based_structs::scoped_timer::ScopedTimer;
+use epac_utils::error_ext::MutexExt;
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+///How long to wait for the first board from the server before making the [`StartupReport`] without it
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+///A phase of starting up, timed for the [`StartupReport`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPhase {
+    ///Reading the configuration file
+    ConfigRead,
+    ///Making the game window
+    WindowCreation,
+    ///Loading the textures into the [`Cacher`](crate::cacher::Cacher)
+    CacherInit,
+    ///Waiting for the first board from the server, from when the game was made
+    FirstBoardFetch,
+    ///Drawing the first frame after the board from the server arrived
+    FirstFrame,
+}
+
+///How long one [`StartupPhase`] took
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PhaseTiming {
+    ///The phase
+    pub phase: StartupPhase,
+    ///When the phase started, in milliseconds since the process started
+    pub started_ms: u64,
+    ///How long the phase took, in milliseconds
+    pub took_ms: u64,
+}
+
+///The one-shot "time to first playable frame" report, made once the first frame with the board from the server on it has been drawn
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StartupReport {
+    ///Each phase which was timed, in the order they finished
+    pub phases: Vec<PhaseTiming>,
+    ///How long it took from the process starting to the first playable frame, in milliseconds
+    pub first_playable_frame_ms: u64,
+    ///Whether a board arrived from the server - if not, the report was made after [`FETCH_TIMEOUT`], with the frame drawn then
+    pub board_fetched: bool,
+}
+
+///Collects the timings of the [`StartupPhase`]s, to make the [`StartupReport`]
+#[derive(Debug)]
+pub struct StartupProfiler {
+    ///When the process started - or rather, when the profiler was first used, which is the first thing `main` does
+    started: Instant,
+    ///The phases timed so far
+    phases: Mutex<Vec<PhaseTiming>>,
+    ///Whether the report has been made
+    reported: AtomicBool,
+    ///Whether the game should exit once the report has been made, from `--profile-startup`
+    exit_after_report: AtomicBool,
+}
+
+///Gets the global [`StartupProfiler`]
+pub fn startup_profiler() -> &'static StartupProfiler {
+    ///The profiler, made on first use
+    static PROFILER: OnceLock<StartupProfiler> = OnceLock::new();
+    PROFILER.get_or_init(|| StartupProfiler {
+        started: Instant::now(),
+        phases: Mutex::new(vec![]),
+        reported: AtomicBool::new(false),
+        exit_after_report: AtomicBool::new(false),
+    })
+}
+
+///Converts a [`Duration`] to whole milliseconds
+fn to_ms(d: Duration) -> u64 {
+    u64::try_from(d.as_millis()).unwrap_or(u64::MAX)
+}
+
+impl StartupProfiler {
+    ///Times a phase, until the returned timer is dropped. Phases after the report has been made aren't kept.
+    pub fn time(&'static self, phase: StartupPhase) -> ScopedTimer<impl FnOnce(Duration)> {
+        ScopedTimer::new(move |took| self.record(phase, took))
+    }
+
+    ///Records a phase which just finished, having taken `took`
+    pub fn record(&self, phase: StartupPhase, took: Duration) {
+        if self.is_reported() {
+            return;
+        }
+        let ended = self.started.elapsed();
+        debug!(?phase, ?took, "Startup phase finished");
+        self.phases.lock_panic("startup phases").push(PhaseTiming {
+            phase,
+            started_ms: to_ms(ended.saturating_sub(took)),
+            took_ms: to_ms(took),
+        });
+    }
+
+    ///Makes the game exit once the report has been made, for `--profile-startup`
+    pub fn set_exit_after_report(&self) {
+        self.exit_after_report.store(true, Ordering::SeqCst);
+    }
+
+    ///Gets whether the game should exit now the report has been made
+    #[must_use]
+    pub fn exit_after_report(&self) -> bool {
+        self.exit_after_report.load(Ordering::SeqCst)
+    }
+
+    ///Gets whether the report has been made
+    #[must_use]
+    pub fn is_reported(&self) -> bool {
+        self.reported.load(Ordering::SeqCst)
+    }
+
+    ///Makes the report, the first time it is called - it is only made once, so later calls return `None`
+    pub fn report(&self, board_fetched: bool) -> Option<StartupReport> {
+        if self.reported.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        Some(StartupReport {
+            phases: self.phases.lock_panic("startup phases").clone(),
+            first_playable_frame_ms: to_ms(self.started.elapsed()),
+            board_fetched,
+        })
+    }
+}
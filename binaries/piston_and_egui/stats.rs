@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string};
+use std::{
+    fs::{create_dir_all, read_to_string},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+///Maximum number of results kept on disk - older results are dropped first
+const MAX_RECORDS: usize = 500;
+
+///The result of a game, from the perspective of the player using this client
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameResult {
+    ///The opponent's king was taken
+    Win,
+    ///The player's king was taken
+    Loss,
+    ///The game was drawn
+    Draw,
+    ///The board was restarted, or the client closed, before either side won
+    Unfinished,
+}
+
+///A record of one finished game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    ///The game id
+    pub id: u32,
+    ///The colour the player moved - `None` if it couldn't be worked out (eg. no moves made from this client)
+    pub is_white: Option<bool>,
+    ///The result of the game
+    pub result: GameResult,
+    ///The number of moves made from this client
+    pub moves: u32,
+    ///How long the game was open in this client, in seconds
+    pub duration_secs: u64,
+    ///When the game finished, in seconds since the unix epoch
+    pub finished_at: u64,
+}
+
+impl GameRecord {
+    ///Creates a new `GameRecord`, finishing now
+    #[must_use]
+    pub fn new(
+        id: u32,
+        is_white: Option<bool>,
+        result: GameResult,
+        moves: u32,
+        duration_secs: u64,
+    ) -> Self {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            id,
+            is_white,
+            result,
+            moves,
+            duration_secs,
+            finished_at,
+        }
+    }
+}
+
+///Summary statistics over all of the stored [`GameRecord`]s
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ResultsSummary {
+    ///Number of [`GameResult::Win`]s
+    pub wins: usize,
+    ///Number of [`GameResult::Loss`]es
+    pub losses: usize,
+    ///Number of [`GameResult::Draw`]s
+    pub draws: usize,
+    ///Number of [`GameResult::Unfinished`] games
+    pub unfinished: usize,
+    ///Average number of moves per game
+    pub average_moves: f64,
+    ///Average duration of a game in seconds
+    pub average_duration_secs: f64,
+}
+
+///Local store of finished game results, persisted as JSON in the project data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResultsStore {
+    ///All of the records, oldest first
+    records: Vec<GameRecord>,
+}
+
+impl ResultsStore {
+    ///Loads the store from disk. If no results have been saved yet, an empty store is returned.
+    ///
+    /// # Errors
+    /// - Fail to get the path using [`results_path`]
+    /// - Fail to read in the file
+    /// - Fail to parse the contents into a `ResultsStore`
+    #[tracing::instrument]
+    pub fn load() -> Result<Self> {
+        let path = results_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+        from_str(&cntnts).with_context(|| format!("reading contents {cntnts}"))
+    }
+
+    ///Saves the store to disk, overwriting the previous contents
+    ///
+    /// # Errors
+    /// - Fail to get the path using [`results_path`]
+    /// - Fail to convert the store to JSON
+    /// - Fail to write to the file
+    #[tracing::instrument(skip(self))]
+    pub fn save(&self) -> Result<()> {
+        let path = results_path()?;
+        let st = to_string(self).context("turning results to string")?;
+        std::fs::write(&path, st).context("Write to file")
+    }
+
+    ///Adds a new record, dropping the oldest if there are more than [`MAX_RECORDS`]
+    pub fn add(&mut self, record: GameRecord) {
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            let extra = self.records.len() - MAX_RECORDS;
+            self.records.drain(0..extra);
+        }
+    }
+
+    ///Gets the `n` most recent records, newest first
+    pub fn recent(&self, n: usize) -> impl Iterator<Item = &GameRecord> {
+        self.records.iter().rev().take(n)
+    }
+
+    ///Calculates a [`ResultsSummary`] over all of the records
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn summary(&self) -> ResultsSummary {
+        let mut summary = ResultsSummary::default();
+        if self.records.is_empty() {
+            return summary;
+        }
+
+        let (mut total_moves, mut total_duration) = (0_u64, 0_u64);
+        for r in &self.records {
+            match r.result {
+                GameResult::Win => summary.wins += 1,
+                GameResult::Loss => summary.losses += 1,
+                GameResult::Draw => summary.draws += 1,
+                GameResult::Unfinished => summary.unfinished += 1,
+            }
+            total_moves += u64::from(r.moves);
+            total_duration += r.duration_secs;
+        }
+
+        let n = self.records.len() as f64;
+        summary.average_moves = total_moves as f64 / n;
+        summary.average_duration_secs = total_duration as f64 / n;
+        summary
+    }
+}
+
+///Loads the [`ResultsStore`], adds the record and saves it again.
+///
+/// # Errors
+/// Can fail if the store can't be loaded or saved
+#[tracing::instrument]
+pub fn record_result(record: GameRecord) -> Result<()> {
+    let mut store = ResultsStore::load().context("loading results")?;
+    store.add(record);
+    store.save().context("saving results")
+}
+
+///Gets the path of the results file - `results.json` in the project data directory, creating the directory if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the data directory
+fn results_path() -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dd = pd.data_dir();
+    create_dir_all(dd).context("creating data directory")?;
+    Ok(dd.join("results.json"))
+}
@@ -0,0 +1,67 @@
+use async_chess_client::prelude::{ChessPiece, ChessPieceKind};
+use std::collections::BTreeMap;
+
+///The pieces of one colour which have been taken, as drawn beside the board
+#[derive(Debug, Clone, Default)]
+pub struct TakenColumn {
+    ///Each kind which has been taken, from least to most valuable, with the count drawn next to it if more than one has been taken, eg. `×2`
+    pub kinds: Vec<(ChessPiece, Option<String>)>,
+    ///The total value of the pieces, eg. `+5`
+    pub total: String,
+}
+
+///Caches the [`TakenColumn`]s, so they are only worked out again when the taken pieces change rather than every frame
+#[derive(Debug, Default)]
+pub struct TakenCache {
+    ///The taken pieces the columns were worked out from
+    taken: Vec<ChessPiece>,
+    ///The columns for white's pieces then black's - empty if none of that colour have been taken
+    columns: [Option<TakenColumn>; 2],
+    ///Goes up each time the taken pieces change
+    generation: u64,
+}
+
+impl TakenCache {
+    ///Works the columns out again if the taken pieces have changed since the last call, and returns the generation - which changes whenever the columns do
+    pub fn update(&mut self, taken: &[ChessPiece]) -> u64 {
+        if self.taken != taken {
+            self.taken.clear();
+            self.taken.extend_from_slice(taken);
+            self.columns = [column(taken, true), column(taken, false)];
+            self.generation += 1;
+        }
+        self.generation
+    }
+
+    ///Gets the columns as of the last [`TakenCache::update`] - white's pieces then black's
+    #[must_use]
+    pub const fn columns(&self) -> &[Option<TakenColumn>; 2] {
+        &self.columns
+    }
+}
+
+///Works out the column for one colour's taken pieces, or `None` if none have been taken
+fn column(taken: &[ChessPiece], is_white: bool) -> Option<TakenColumn> {
+    let mut counts: BTreeMap<ChessPieceKind, u32> = BTreeMap::new();
+    for p in taken.iter().filter(|p| p.is_white == is_white) {
+        *counts.entry(p.kind).or_default() += 1;
+    }
+    if counts.is_empty() {
+        return None;
+    }
+
+    let total: u32 = counts
+        .iter()
+        .map(|(kind, count)| kind.value() * count)
+        .sum();
+    Some(TakenColumn {
+        kinds: counts
+            .into_iter()
+            .map(|(kind, count)| {
+                let label = (count > 1).then(|| format!("×{count}"));
+                (ChessPiece { kind, is_white }, label)
+            })
+            .collect(),
+        total: format!("+{total}"),
+    })
+}
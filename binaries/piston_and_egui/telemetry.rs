@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use async_chess_client::net::{
+    server_client::{ClientConfig, ServerClient},
+    server_interface::ServerError,
+};
+use directories::ProjectDirs;
+use epac_utils::error_ext::{ErrorExt, MutexExt, ToAnyhowNotErr};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string, to_string_pretty};
+use std::{
+    fs::{create_dir_all, read_to_string},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+///Maximum number of events kept in the queue - older events are dropped first, so it can't grow forever while offline
+const MAX_QUEUED: usize = 1000;
+///The path on the server which the queue is uploaded to
+const UPLOAD_PATH: &str = "telemetry";
+
+///An anonymous usage event. Nothing in one can identify the player or the game - features and errors are only ever fixed names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TelemetryEvent {
+    ///A game window was closed
+    Session {
+        ///How long it was open, in seconds
+        seconds: u64,
+    },
+    ///A feature was used
+    Feature {
+        ///The name of the feature, eg. `threats`
+        name: String,
+    },
+    ///Something went wrong
+    Error {
+        ///The category of the error, eg. `illegal_move` - never the message
+        category: String,
+    },
+}
+
+///The queue of events waiting to be uploaded, kept on disk so that events from offline sessions are sent later
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TelemetryQueue {
+    ///The events, oldest first
+    events: Vec<TelemetryEvent>,
+}
+
+impl TelemetryQueue {
+    ///Loads the queue from the project data directory. Returns an empty queue if there isn't one yet.
+    ///
+    /// # Errors
+    /// - Fail to get the path using [`queue_path`]
+    /// - Fail to read in the file
+    /// - Fail to parse the contents into a `TelemetryQueue`
+    #[tracing::instrument]
+    pub fn load() -> Result<Self> {
+        let path = queue_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let cntnts = read_to_string(&path).with_context(|| format!("reading path {path:?}"))?;
+        from_str(&cntnts).with_context(|| format!("reading contents {cntnts}"))
+    }
+
+    ///Saves the queue to the project data directory, replacing what was there
+    ///
+    /// # Errors
+    /// - Fail to get the path using [`queue_path`]
+    /// - Fail to convert the queue to JSON
+    /// - Fail to write to the file
+    #[tracing::instrument(skip(self))]
+    pub fn save(&self) -> Result<()> {
+        let path = queue_path()?;
+        let st = to_string(self).context("turning telemetry queue to string")?;
+        std::fs::write(&path, st).with_context(|| format!("writing to {path:?}"))
+    }
+
+    ///Adds events to the end of the queue, dropping the oldest if there are more than [`MAX_QUEUED`]
+    pub fn extend(&mut self, events: impl IntoIterator<Item = TelemetryEvent>) {
+        self.events.extend(events);
+        let excess = self.events.len().saturating_sub(MAX_QUEUED);
+        self.events.drain(..excess);
+    }
+
+    ///Gets the queued events, oldest first
+    #[must_use]
+    pub fn events(&self) -> &[TelemetryEvent] {
+        &self.events
+    }
+
+    ///Gets exactly what would be uploaded, formatted for people to read
+    ///
+    /// # Errors
+    /// - Fail to convert the queue to JSON
+    pub fn to_pretty_json(&self) -> Result<String> {
+        to_string_pretty(self).context("turning telemetry queue to string")
+    }
+
+    ///Uploads the queue to the server, and empties it if the server accepted it.
+    ///
+    /// The API key is never sent with the queue, so it can't be tied to the player.
+    ///
+    /// # Errors
+    /// - Fail to make the [`ServerClient`]
+    /// - Fail to reach the server
+    /// - The server responds with an error
+    #[tracing::instrument(skip(self, config))]
+    pub fn upload(&mut self, config: &ClientConfig) -> Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+
+        let anonymous = ClientConfig {
+            api_key: None,
+            record_session: false,
+            ..config.clone()
+        };
+        let client = ServerClient::new(&anonymous).context("making telemetry client")?;
+        let rsp = client
+            .send(client.post(UPLOAD_PATH).json(&*self))
+            .context("uploading telemetry")?;
+        if rsp.is_error() {
+            bail!(
+                "error code {} from server on uploading telemetry",
+                rsp.status
+            );
+        }
+
+        info!(events=%self.events.len(), "Uploaded telemetry");
+        self.events.clear();
+        Ok(())
+    }
+}
+
+///Collects [`TelemetryEvent`]s for the current session, but only once the player has opted in with [`Telemetry::set_enabled`].
+///
+/// Events are kept in memory until [`Telemetry::flush`] adds them to the [`TelemetryQueue`] on disk.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    ///Whether or not the player has opted in
+    enabled: AtomicBool,
+    ///Events from this session which haven't been added to the queue yet
+    events: Mutex<Vec<TelemetryEvent>>,
+    ///Held while the queue file is being changed, so that an upload and a flush can't lose each other's changes
+    queue_file: Mutex<()>,
+}
+
+///Gets the [`Telemetry`] shared by the whole program
+pub fn telemetry() -> &'static Telemetry {
+    ///The telemetry, made on first use
+    static TELEMETRY: OnceLock<Telemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(Telemetry::default)
+}
+
+impl Telemetry {
+    ///Turns collecting events on or off. Turning it off throws away anything collected which hasn't been flushed.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.events.lock_panic("telemetry events").clear();
+        }
+    }
+
+    ///Records an event, if the player has opted in
+    fn record(&self, event: TelemetryEvent) {
+        if self.enabled.load(Ordering::Relaxed) {
+            self.events.lock_panic("telemetry events").push(event);
+        }
+    }
+
+    ///Records that a feature was used
+    pub fn feature(&self, name: &'static str) {
+        self.record(TelemetryEvent::Feature { name: name.into() });
+    }
+
+    ///Records that something went wrong
+    pub fn error(&self, category: &'static str) {
+        self.record(TelemetryEvent::Error {
+            category: category.into(),
+        });
+    }
+
+    ///Records that a game window was closed after being open for `seconds`
+    pub fn session(&self, seconds: u64) {
+        self.record(TelemetryEvent::Session { seconds });
+    }
+
+    ///Adds the events from this session to the [`TelemetryQueue`] on disk
+    ///
+    /// # Errors
+    /// - Fail to load or save the queue
+    #[tracing::instrument(skip(self))]
+    pub fn flush(&self) -> Result<()> {
+        let events = std::mem::take(&mut *self.events.lock_panic("telemetry events"));
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let _file = self.queue_file.lock_panic("telemetry queue file");
+        let mut queue = TelemetryQueue::load().context("loading telemetry queue")?;
+        queue.extend(events);
+        queue.save().context("saving telemetry queue")
+    }
+
+    ///Starts uploading the [`TelemetryQueue`] on a new thread, if the player has opted in. If the server can't be reached, the queue is kept to try again next time.
+    pub fn upload_in_background(&'static self, config: ClientConfig) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        std::thread::spawn(move || {
+            let _file = self.queue_file.lock_panic("telemetry queue file");
+            TelemetryQueue::load()
+                .and_then(|mut queue| {
+                    queue.upload(&config)?;
+                    queue.save()
+                })
+                .context("uploading telemetry queue")
+                .warn();
+        });
+    }
+}
+
+///Gets the name of the category of a [`ServerError`], for [`Telemetry::error`]
+#[must_use]
+pub const fn error_category(e: &ServerError) -> &'static str {
+    match e {
+        ServerError::IllegalMove(_) => "illegal_move",
+        ServerError::UnknownGame => "unknown_game",
+        ServerError::NotYourTurn => "not_your_turn",
+        ServerError::Other(..) => "other_server_error",
+    }
+}
+
+///Empties the [`TelemetryQueue`] on disk, so nothing collected before is ever sent
+///
+/// # Errors
+/// - Fail to save the empty queue
+pub fn discard_queue() -> Result<()> {
+    let _file = telemetry().queue_file.lock_panic("telemetry queue file");
+    TelemetryQueue::default()
+        .save()
+        .context("saving empty telemetry queue")
+}
+
+///Gets the path of the queue - `telemetry.json` in the project data directory, creating the directory if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the data directory
+fn queue_path() -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dd = pd.data_dir();
+    create_dir_all(dd).context("creating data directory")?;
+    Ok(dd.join("telemetry.json"))
+}
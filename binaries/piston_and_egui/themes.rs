@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use async_chess_client::prelude::ChessPiece;
+use directories::ProjectDirs;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use std::{
+    fs::{create_dir_all, read_dir, remove_dir_all, File},
+    io::copy,
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+///Sprites which aren't pieces that every theme needs
+const REQUIRED_BOARD_SPRITES: [&str; 4] = [
+    "board_alt.png",
+    "highlight.png",
+    "selected.png",
+    "board_updated.png",
+];
+
+///Gets the file names of all of the sprites a theme needs
+#[must_use]
+pub fn required_sprites() -> Vec<String> {
+    ChessPiece::all_variants()
+        .into_iter()
+        .map(ChessPiece::to_file_name)
+        .chain(REQUIRED_BOARD_SPRITES.into_iter().map(String::from))
+        .collect()
+}
+
+///Gets the directory that themes are installed to - `themes` in the project data directory, creating it if needed
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+/// - Fail to [`create_dir_all`] on the themes directory
+fn themes_dir() -> Result<PathBuf> {
+    let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?;
+    let dir = pd.data_dir().join("themes");
+    create_dir_all(&dir).context("creating themes directory")?;
+    Ok(dir)
+}
+
+///Gets the directory of an installed theme
+///
+/// # Errors
+/// - Fail to get the [`themes_dir`]
+/// - The theme isn't installed
+pub fn theme_dir(name: &str) -> Result<PathBuf> {
+    let dir = themes_dir()?.join(name);
+    if !dir.is_dir() {
+        bail!("theme {name:?} isn't installed");
+    }
+    Ok(dir)
+}
+
+///Lists the names of all of the installed themes, in alphabetical order
+///
+/// # Errors
+/// - Fail to get the [`themes_dir`]
+/// - Fail to read the themes directory
+pub fn list_themes() -> Result<Vec<String>> {
+    let mut themes = vec![];
+    for entry in read_dir(themes_dir()?).context("reading themes directory")? {
+        let entry = entry.context("reading themes directory entry")?;
+        if entry.path().is_dir() {
+            themes.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    themes.sort();
+    Ok(themes)
+}
+
+///Gets the required sprites which are missing from a theme directory
+#[must_use]
+pub fn missing_sprites(dir: &Path) -> Vec<String> {
+    required_sprites()
+        .into_iter()
+        .filter(|s| !dir.join(s).is_file())
+        .collect()
+}
+
+///Imports a theme from a zip file, using the name of the zip file as the name of the theme.
+///
+/// Every `png` in the zip is extracted, ignoring any folders inside it. If any of the [`required_sprites`] are missing, the theme is removed again.
+///
+/// # Errors
+/// - Fail to get the [`themes_dir`]
+/// - A theme with that name is already installed
+/// - Fail to read the zip, or extract any of the sprites
+/// - The theme is missing sprites
+#[tracing::instrument]
+pub fn import_theme(zip_path: &Path) -> Result<String> {
+    let name = zip_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .ae()
+        .context("getting theme name from zip name")?;
+    let dir = themes_dir()?.join(&name);
+    if dir.exists() {
+        bail!("theme {name:?} is already installed");
+    }
+
+    let file = File::open(zip_path).with_context(|| format!("opening {zip_path:?}"))?;
+    let mut archive = ZipArchive::new(file).context("reading zip")?;
+    create_dir_all(&dir).context("creating theme directory")?;
+
+    if let Err(e) = extract_sprites(&mut archive, &dir) {
+        remove_dir_all(&dir).context("removing invalid theme")?;
+        return Err(e);
+    }
+
+    info!(%name, "Imported theme");
+    Ok(name)
+}
+
+///Extracts every `png` from a zip into a directory, and checks that none of the [`required_sprites`] are missing
+///
+/// # Errors
+/// - Fail to read a zip entry, or write it out
+/// - The directory is missing sprites afterwards
+fn extract_sprites(archive: &mut ZipArchive<File>, dir: &Path) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).context("reading zip entry")?;
+        //only keep the file name, so paths in the zip can't escape the theme directory
+        let Some(file_name) = entry
+            .enclosed_name()
+            .and_then(Path::file_name)
+            .map(ToOwned::to_owned)
+        else {
+            continue;
+        };
+        if !entry.is_file() || Path::new(&file_name).extension() != Some("png".as_ref()) {
+            continue;
+        }
+
+        let mut out = File::create(dir.join(&file_name))
+            .with_context(|| format!("creating {file_name:?}"))?;
+        copy(&mut entry, &mut out).with_context(|| format!("extracting {file_name:?}"))?;
+    }
+
+    let missing = missing_sprites(dir);
+    if !missing.is_empty() {
+        bail!("theme is missing sprites: {}", missing.join(", "));
+    }
+    Ok(())
+}
+
+///Removes an installed theme
+///
+/// # Errors
+/// - The theme isn't installed
+/// - Fail to remove the theme directory
+#[tracing::instrument]
+pub fn remove_theme(name: &str) -> Result<()> {
+    let dir = theme_dir(name)?;
+    remove_dir_all(&dir).with_context(|| format!("removing {dir:?}"))
+}
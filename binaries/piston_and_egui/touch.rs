@@ -0,0 +1,143 @@
+use piston_window::{Touch, TouchArgs};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+///How long a finger has to stay still on the screen for it to count as a long press
+pub const LONG_PRESS: Duration = Duration::from_millis(500);
+
+///A gesture made on a touch screen, for the piston loop to act on. Positions are in window pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchGesture {
+    ///A quick tap - selects a piece, or moves the one already selected
+    Tap((f64, f64)),
+    ///A finger held still for [`LONG_PRESS`] - shows what the piece under it can do
+    LongPress((f64, f64)),
+    ///One finger dragged by this far - pans the board
+    Pan((f64, f64)),
+    ///Two fingers moved apart or together - zooms by the ratio of the distances between them, about the point halfway between them
+    Pinch {
+        ///The new distance between the fingers over the old distance
+        ratio: f64,
+        ///The point halfway between the fingers
+        about: (f64, f64),
+    },
+}
+
+///One finger on the screen
+#[derive(Debug, Clone, Copy)]
+struct Finger {
+    ///Where the finger first touched
+    start: (f64, f64),
+    ///Where the finger is now
+    at: (f64, f64),
+    ///When the finger first touched
+    since: Instant,
+    ///Whether the finger has moved far enough from `start` to stop being a tap
+    moved: bool,
+}
+
+///Turns the raw touch events from piston into [`TouchGesture`]s.
+///
+/// Once a gesture has been used for a long press or has had a second finger, lifting the fingers doesn't also count as a tap.
+#[derive(Debug, Default)]
+pub struct TouchTracker {
+    ///The fingers on the screen, keyed by the id piston gives each touch
+    fingers: BTreeMap<i64, Finger>,
+    ///Whether the current gesture has been used up, until every finger is lifted
+    spent: bool,
+}
+
+impl TouchTracker {
+    ///Deals with a touch event from piston. A finger has to move more than `slop` window pixels before a tap becomes a pan.
+    pub fn touch(&mut self, args: &TouchArgs, slop: f64) -> Option<TouchGesture> {
+        let [x, y] = args.position();
+        let at = (x, y);
+
+        match args.touch {
+            Touch::Start => {
+                self.fingers.insert(
+                    args.id,
+                    Finger {
+                        start: at,
+                        at,
+                        since: Instant::now(),
+                        moved: false,
+                    },
+                );
+                self.spent |= self.fingers.len() > 1;
+                None
+            }
+            Touch::Move => {
+                let before = self.pinch_points();
+                let finger = self.fingers.get_mut(&args.id)?;
+                let from = finger.at;
+                finger.at = at;
+                finger.moved |= distance(finger.start, at) > slop;
+                let moved = finger.moved;
+
+                match (before, self.pinch_points()) {
+                    (Some((a, b)), Some((na, nb))) if distance(a, b) > 0.0 => {
+                        Some(TouchGesture::Pinch {
+                            ratio: distance(na, nb) / distance(a, b),
+                            about: ((na.0 + nb.0) / 2.0, (na.1 + nb.1) / 2.0),
+                        })
+                    }
+                    (None, None) if moved && !self.spent => {
+                        Some(TouchGesture::Pan((at.0 - from.0, at.1 - from.1)))
+                    }
+                    _ => None,
+                }
+            }
+            Touch::End => {
+                let finger = self.fingers.remove(&args.id);
+                let gesture = finger
+                    .filter(|f| !f.moved && !self.spent && self.fingers.is_empty())
+                    .map(|f| TouchGesture::Tap(f.at));
+                self.reset_if_lifted();
+                gesture
+            }
+            Touch::Cancel => {
+                self.fingers.remove(&args.id);
+                self.reset_if_lifted();
+                None
+            }
+        }
+    }
+
+    ///Checks whether a finger has been held still for [`LONG_PRESS`]. Should be called often - eg. on every update tick.
+    pub fn poll_long_press(&mut self) -> Option<TouchGesture> {
+        if self.spent || self.fingers.len() != 1 {
+            return None;
+        }
+
+        let finger = self.fingers.values().next()?;
+        if finger.moved || finger.since.elapsed() < LONG_PRESS {
+            return None;
+        }
+        self.spent = true;
+        Some(TouchGesture::LongPress(finger.at))
+    }
+
+    ///Gets where the two fingers of a pinch are, if exactly two are on the screen
+    fn pinch_points(&self) -> Option<((f64, f64), (f64, f64))> {
+        let mut fingers = self.fingers.values();
+        match (fingers.next(), fingers.next(), fingers.next()) {
+            (Some(a), Some(b), None) => Some((a.at, b.at)),
+            _ => None,
+        }
+    }
+
+    ///Lets the next gesture be a tap again, once every finger has been lifted
+    fn reset_if_lifted(&mut self) {
+        if self.fingers.is_empty() {
+            self.spent = false;
+        }
+    }
+}
+
+///Gets the distance between two points
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
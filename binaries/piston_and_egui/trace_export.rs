@@ -0,0 +1,220 @@
+use anyhow::{Context as _, Result};
+use async_chess_client::util::error_ext::ToAnyhowNotErr;
+use directories::ProjectDirs;
+use serde::Serialize;
+use std::{
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{
+    span::{Attributes, Id},
+    Metadata, Subscriber,
+};
+use tracing_subscriber::{layer::Context as LayerContext, registry::LookupSpan, Layer};
+
+///Most spans kept in one capture, so a capture left running doesn't grow forever
+const MAX_TRACE_EVENTS: usize = 200_000;
+
+///A finished span, as a complete event in the chrome://tracing Trace Event Format
+#[derive(Debug, Clone, Serialize)]
+struct TraceEvent {
+    ///The name of the span
+    name: &'static str,
+    ///The category - the module the span is from
+    cat: &'static str,
+    ///The phase - always `X` for a complete event
+    ph: &'static str,
+    ///When the span was created, in microseconds since the capture started
+    ts: u64,
+    ///How long the span was open, in microseconds
+    dur: u64,
+    ///The process id
+    pid: u32,
+    ///The [`thread_number`] of the thread which created the span
+    tid: u64,
+}
+
+///The JSON object written out, which chrome://tracing and perfetto can open
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile {
+    ///All of the spans
+    trace_events: Vec<TraceEvent>,
+    ///The unit the viewer should show times in
+    display_time_unit: &'static str,
+}
+
+///Gets a small number for the current thread, as chrome traces need numeric thread ids
+fn thread_number() -> u64 {
+    ///The number for the next thread to ask
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        ///The number for this thread
+        static NUMBER: u64 = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    NUMBER.with(|n| *n)
+}
+
+///Converts a [`Duration`] to whole microseconds
+#[allow(clippy::cast_possible_truncation)]
+const fn micros(d: Duration) -> u64 {
+    d.as_micros() as u64
+}
+
+///A capture of spans, started and stopped on demand. Nothing is kept while it isn't capturing.
+#[derive(Debug, Default)]
+pub struct TraceCapture {
+    ///Whether or not spans are being captured
+    capturing: AtomicBool,
+    ///When the current capture started
+    started: Mutex<Option<Instant>>,
+    ///The spans which have finished during the current capture
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+///Gets the [`TraceCapture`] shared by the whole program
+pub fn trace_capture() -> &'static TraceCapture {
+    ///The capture, made on first use
+    static CAPTURE: OnceLock<TraceCapture> = OnceLock::new();
+    CAPTURE.get_or_init(TraceCapture::default)
+}
+
+impl TraceCapture {
+    ///Whether or not spans are being captured
+    #[must_use]
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.load(Ordering::Relaxed)
+    }
+
+    ///Throws away anything captured before, and starts capturing
+    fn start(&self) {
+        if let Ok(mut events) = self.events.lock() {
+            events.clear();
+        }
+        if let Ok(mut started) = self.started.lock() {
+            *started = Some(Instant::now());
+        }
+        self.capturing.store(true, Ordering::Relaxed);
+    }
+
+    ///Stops capturing, and writes everything captured to a new file in `dir`. Returns the path of that file.
+    ///
+    /// # Errors
+    /// - Fail to lock the events
+    /// - Fail to turn the events into JSON
+    /// - Fail to write to the file
+    fn stop_and_save(&self, dir: &Path) -> Result<PathBuf> {
+        self.capturing.store(false, Ordering::Relaxed);
+        let trace_events = std::mem::take(
+            &mut *self
+                .events
+                .lock()
+                .map_err(|e| anyhow!("{e}"))
+                .context("locking trace events")?,
+        );
+
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = dir.join(format!("trace-{saved_at}.json"));
+
+        let file = TraceFile {
+            trace_events,
+            display_time_unit: "ms",
+        };
+        let st = serde_json::to_string(&file).context("turning trace to string")?;
+        std::fs::write(&path, st).with_context(|| format!("writing trace to {path:?}"))?;
+        Ok(path)
+    }
+
+    ///Starts capturing if it isn't, or stops and saves the capture into the `traces` folder in the project data directory if it is.
+    ///
+    /// # Errors
+    /// - Fail to get [`ProjectDirs`]
+    /// - Fail to [`create_dir_all`] on the traces directory
+    /// - Fail to save the capture
+    #[tracing::instrument(skip(self))]
+    pub fn toggle(&self) -> Result<()> {
+        if !self.is_capturing() {
+            self.start();
+            info!("Started capturing a trace - run `trace` again to save it");
+            return Ok(());
+        }
+
+        let pd = ProjectDirs::from("com", "jackmaguire", "async_chess")
+            .ae()
+            .context("getting project dirs")?;
+        let dir = pd.data_dir().join("traces");
+        create_dir_all(&dir).context("creating traces directory")?;
+
+        let path = self.stop_and_save(&dir).context("saving trace")?;
+        info!(?path, "Saved trace for chrome://tracing");
+        Ok(())
+    }
+
+    ///Records a finished span, if it started during the current capture
+    fn record(&self, metadata: &'static Metadata<'static>, start: SpanStart) {
+        let Some(capture_start) = self.started.lock().ok().and_then(|s| *s) else {
+            return;
+        };
+        let Some(since_capture) = start.at.checked_duration_since(capture_start) else {
+            return;
+        };
+
+        let event = TraceEvent {
+            name: metadata.name(),
+            cat: metadata.target(),
+            ph: "X",
+            ts: micros(since_capture),
+            dur: micros(start.at.elapsed()),
+            pid: std::process::id(),
+            tid: start.thread,
+        };
+        if let Ok(mut events) = self.events.lock() {
+            if events.len() < MAX_TRACE_EVENTS {
+                events.push(event);
+            }
+        }
+    }
+}
+
+///Kept in the extensions of each span, to work out how long it was open for
+#[derive(Debug, Clone, Copy)]
+struct SpanStart {
+    ///When the span was created
+    at: Instant,
+    ///The [`thread_number`] of the thread which created it
+    thread: u64,
+}
+
+///Layer for the tracing subscriber which times spans for the [`trace_capture`].
+///
+/// Spans are timed from when they are created until they are closed, so it should be filtered to only enable spans while [`TraceCapture::is_capturing`], so that spans cost nothing the rest of the time.
+#[derive(Debug, Default)]
+pub struct ChromeTraceLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ChromeTraceLayer {
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart {
+                at: Instant::now(),
+                thread: thread_number(),
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let start = span.extensions().get::<SpanStart>().copied();
+        if let Some(start) = start {
+            trace_capture().record(span.metadata(), start);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    board_cache::{cached_games, load_board},
+    identity::player_identity,
+    piston::PistonConfig,
+    read_config,
+    stats::ResultsStore,
+};
+use anyhow::{Context as _, Result};
+use async_chess_client::{
+    chess::{
+        boards::board::{Board, CanMovePiece},
+        history::infer_moves,
+    },
+    net::{
+        game_manager::GameManager,
+        list_refresher::{BoardMessage, MessageToGame},
+    },
+    prelude::ErrorExt,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::current_exe,
+    process::Command,
+    sync::mpsc::{channel, Sender},
+    time::Duration,
+};
+use tray_item::TrayItem;
+
+///Most games followed from the tray - the most recently played ones
+const TRAY_GAMES: usize = 10;
+///Time between polls of the games, from the tray. Longer than in the game window, as nobody is watching the board.
+const TRAY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+///How long the tray loop sleeps between checking for messages
+const TRAY_TICK: Duration = Duration::from_millis(250);
+///Name of the tray icon when it isn't the player's turn anywhere - the icon theme name on Linux, or the resource name on Windows
+const IDLE_ICON: &str = "async-chess";
+///Name of the tray icon with a badge, shown when it is the player's turn in at least one game
+const YOUR_TURN_ICON: &str = "async-chess-your-turn";
+
+///Something picked from the tray menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrayCommand {
+    ///Open the game window on this game
+    Open(u32),
+    ///Stop the tray
+    Quit,
+}
+
+///What the tray knows about one game
+#[derive(Debug, Default)]
+struct TrayGame {
+    ///The player's colour in the game, from the results store, if it is known
+    is_white: Option<bool>,
+    ///The last board seen, to work out who moved
+    board: Option<Board<CanMovePiece>>,
+}
+
+///Runs the tray icon until Quit is picked from its menu.
+///
+/// The most recently played games are followed with a [`GameManager`], and the icon gets a badge while it is the player's turn in any of them. The menu has an entry to open each game in the game window, which is started as a new process with `--open`.
+///
+/// # Errors
+/// - Fail to work out the player's identity
+/// - Fail to make the tray icon or its menu
+#[tracing::instrument]
+pub fn tray_main() -> Result<()> {
+    let ids: Vec<u32> = cached_games()
+        .context("listing cached games")?
+        .into_iter()
+        .take(TRAY_GAMES)
+        .collect();
+    let results = match ResultsStore::load() {
+        Ok(r) => Some(r),
+        Err(e) => {
+            warn!(%e, "Unable to load results for player colours");
+            None
+        }
+    };
+
+    let mut games: BTreeMap<u32, TrayGame> = ids
+        .iter()
+        .map(|id| {
+            let is_white = results.as_ref().and_then(|r| {
+                r.recent(usize::MAX)
+                    .find(|g| g.id == *id)
+                    .and_then(|g| g.is_white)
+            });
+            let board = load_board(*id)
+                .and_then(|l| l.map(Board::new_json).transpose())
+                .unwrap_or_else(|e| {
+                    warn!(%e, %id, "Unable to load last known board");
+                    None
+                });
+            (*id, TrayGame { is_white, board })
+        })
+        .collect();
+
+    let (tx, rx) = channel();
+    let mut tray = TrayItem::new("Async Chess", IDLE_ICON)
+        .map_err(|e| anyhow!("{e:?}"))
+        .context("making tray icon")?;
+    for id in &ids {
+        add_command(
+            &mut tray,
+            &format!("Open game {id}"),
+            TrayCommand::Open(*id),
+            &tx,
+        )?;
+    }
+    add_command(&mut tray, "Quit", TrayCommand::Quit, &tx)?;
+
+    //the same server and credentials as the game window
+    let pc = read_config().unwrap_or_else(|e| {
+        warn!(%e, "Unable to read config for the tray, using the defaults");
+        PistonConfig::default()
+    });
+    let mut manager = GameManager::new(
+        player_identity().context("getting player identity")?,
+        TRAY_POLL_INTERVAL,
+        pc.rate_limit,
+        pc.client,
+    );
+    for id in &ids {
+        manager.add_game(*id);
+    }
+
+    let mut your_turn = BTreeSet::new();
+    let mut badge_shown = false;
+    loop {
+        match rx.try_recv().ok() {
+            Some(TrayCommand::Quit) => break,
+            Some(TrayCommand::Open(id)) => {
+                open_game(id).context("opening game window").error();
+                //they will see the board now
+                your_turn.remove(&id);
+            }
+            None => {}
+        }
+
+        manager.update();
+        while let Ok(message) = manager.try_recv() {
+            let MessageToGame::UpdateBoard(msg) = message.msg else {
+                continue;
+            };
+            let Some(game) = games.get_mut(&message.id) else {
+                continue;
+            };
+            if let Some(turn) = game.update(msg) {
+                if turn {
+                    info!(id = %message.id, "Player's turn");
+                    your_turn.insert(message.id);
+                } else {
+                    your_turn.remove(&message.id);
+                }
+            }
+        }
+
+        let show_badge = !your_turn.is_empty();
+        if show_badge != badge_shown {
+            let icon = if show_badge {
+                YOUR_TURN_ICON
+            } else {
+                IDLE_ICON
+            };
+            if let Err(e) = tray.set_icon(icon) {
+                warn!(?e, "Unable to set tray icon");
+            }
+            badge_shown = show_badge;
+        }
+
+        std::thread::sleep(TRAY_TICK);
+    }
+
+    info!("Closing tray");
+    Ok(())
+}
+
+impl TrayGame {
+    ///Deals with a new board or moves from the server. Returns whether it is now the player's turn, or `None` if that can't be told.
+    ///
+    /// The turn from the server is used if it sent one. If not, it is the player's turn if the last move was made with the other colour, worked out from the change to the board.
+    fn update(&mut self, msg: BoardMessage) -> Option<bool> {
+        let is_white = self.is_white;
+        match msg {
+            BoardMessage::NewList(state) => {
+                let (board, info) = match state.into_parts() {
+                    Ok(parts) => parts,
+                    Err(e) => {
+                        warn!(%e, "Unable to read board for tray");
+                        return None;
+                    }
+                };
+                let last_mover = self
+                    .board
+                    .as_ref()
+                    .filter(|before| !before.same_pieces(&board))
+                    .and_then(|before| infer_moves(before, &board, None))
+                    .map(|(white_first, moves)| white_first == (moves.len() == 1));
+                self.board = Some(board);
+
+                match (info.white_to_move, last_mover) {
+                    (Some(white_to_move), _) => is_white.map(|w| w == white_to_move),
+                    (None, Some(mover)) => is_white.map(|w| w != mover),
+                    (None, None) => None,
+                }
+            }
+            BoardMessage::ApplyMoves(moves) => {
+                let board = self.board.as_mut()?;
+                let mut mover = None;
+                for m in moves {
+                    let (from, to) = (m.current_coords(), m.new_coords());
+                    mover = board[from].map(|p| p.is_white).or(mover);
+                    *board = board.with_move(from, to);
+                }
+                mover.zip(is_white).map(|(mover, w)| mover != w)
+            }
+            _ => None,
+        }
+    }
+}
+
+///Adds an entry to the tray menu which sends a [`TrayCommand`] when picked
+///
+/// # Errors
+/// - Fail to add the entry
+fn add_command(
+    tray: &mut TrayItem,
+    label: &str,
+    command: TrayCommand,
+    tx: &Sender<TrayCommand>,
+) -> Result<()> {
+    let tx = tx.clone();
+    tray.add_menu_item(label, move || {
+        //if the tray loop has ended, there is nothing to do
+        let _ = tx.send(command);
+    })
+    .map_err(|e| anyhow!("{e:?}"))
+    .with_context(|| format!("adding {label:?} to tray menu"))
+}
+
+///Starts the game window on a game as a new process, with the rest of the saved configuration
+///
+/// # Errors
+/// - Fail to find the current executable
+/// - Fail to start it
+pub fn open_game(id: u32) -> Result<()> {
+    info!(%id, "Opening game from tray");
+    Command::new(current_exe().context("finding executable")?)
+        .args(["--open", &id.to_string()])
+        .spawn()
+        .context("starting game window")?;
+    Ok(())
+}
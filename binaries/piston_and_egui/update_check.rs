@@ -0,0 +1,79 @@
+use anyhow::{Context, Result};
+use reqwest::blocking::ClientBuilder;
+use serde::Deserialize;
+use std::{
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+///The feed of the latest release of the project
+const RELEASES_URL: &str =
+    "https://api.github.com/repos/BurntNail/async_chess_client/releases/latest";
+///How long to wait for the release feed before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+///A release from the release feed
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    ///The version tag, eg. `v0.2.0`
+    pub tag_name: String,
+    ///Link to the release page
+    pub html_url: String,
+    ///The changelog
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+///Parses a version like `v1.2.3` or `1.2` into its numbers, treating missing numbers as `0`
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = v.trim().trim_start_matches('v').split('.');
+    let mut next = || -> Option<u64> {
+        parts
+            .next()
+            .map_or(Some(0), |p| p.split('-').next()?.parse().ok())
+    };
+    Some((next()?, next()?, next()?))
+}
+
+///Fetches the latest release, and returns it if it is newer than this version
+///
+/// # Errors
+/// - Fail to reach the release feed, or parse the response
+/// - Fail to parse either version
+#[tracing::instrument]
+pub fn check_for_update() -> Result<Option<Release>> {
+    let release: Release = ClientBuilder::default()
+        .user_agent("JackyBoi/AsyncChess")
+        .timeout(TIMEOUT)
+        .build()
+        .context("building reqwest client")?
+        .get(RELEASES_URL)
+        .send()
+        .context("fetching release feed")?
+        .error_for_status()
+        .context("release feed status")?
+        .json()
+        .context("parsing release feed")?;
+
+    let latest = parse_version(&release.tag_name)
+        .with_context(|| format!("parsing latest version {:?}", release.tag_name))?;
+    let current = parse_version(env!("CARGO_PKG_VERSION")).context("parsing current version")?;
+
+    info!(?latest, ?current, "Checked for updates");
+    Ok((latest > current).then_some(release))
+}
+
+///Checks for updates on a new thread, so it never blocks. Errors are logged, and treated as there being no update.
+#[must_use]
+pub fn check_in_background() -> Receiver<Option<Release>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let update = check_for_update().unwrap_or_else(|e| {
+            warn!(%e, "Unable to check for updates");
+            None
+        });
+        //if the launcher has closed, nobody needs to know
+        let _ = tx.send(update);
+    });
+    rx
+}
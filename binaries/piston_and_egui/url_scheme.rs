@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use async_chess_client::net::invite::INVITE_PREFIX;
+use std::{env::current_exe, path::Path, process::Command};
+
+///The URL scheme invite links use, without the `://`
+pub const SCHEME: &str = "asyncchess";
+
+///Gets the invite link from the arguments the binary was launched with, if there is one - the OS passes the link on its own when one is clicked
+#[must_use]
+pub fn link_from_args(mut args: impl Iterator<Item = String>) -> Option<String> {
+    args.find(|a| a.trim().starts_with(INVITE_PREFIX))
+}
+
+///Registers this binary as the handler for [`SCHEME`] links for the current user, so clicking an invite in a chat app or email opens the game.
+///
+/// On Linux this writes a desktop entry and makes it the default with `xdg-mime`, and on Windows it adds the scheme to the user's registry classes. On macOS the scheme has to be declared in the app bundle's `Info.plist`, so this fails.
+///
+/// # Errors
+/// - Fail to find the current executable
+/// - Fail to write the desktop entry, or run the command to register it
+/// - The OS isn't supported
+#[tracing::instrument]
+pub fn register_url_scheme() -> Result<()> {
+    let exe = current_exe().context("finding executable")?;
+    info!(?exe, "Registering URL scheme");
+    register_for(&exe)
+}
+
+///Does the registering for [`register_url_scheme`] on Linux
+#[cfg(target_os = "linux")]
+fn register_for(exe: &Path) -> Result<()> {
+    use async_chess_client::util::error_ext::ToAnyhowNotErr;
+    use directories::BaseDirs;
+    use std::fs::{create_dir_all, write};
+
+    ///Name of the desktop entry for the scheme
+    const DESKTOP_FILE: &str = "async-chess-url.desktop";
+
+    let apps = BaseDirs::new()
+        .ae()
+        .context("finding base dirs")?
+        .data_dir()
+        .join("applications");
+    create_dir_all(&apps).with_context(|| format!("creating {apps:?}"))?;
+
+    let entry = format!(
+        "[Desktop Entry]\nType=Application\nName=Async Chess\nExec=\"{}\" %u\nNoDisplay=true\nMimeType=x-scheme-handler/{SCHEME};\n",
+        exe.display()
+    );
+    let path = apps.join(DESKTOP_FILE);
+    write(&path, entry).with_context(|| format!("writing desktop entry to {path:?}"))?;
+
+    run(Command::new("xdg-mime").args([
+        "default",
+        DESKTOP_FILE,
+        &format!("x-scheme-handler/{SCHEME}"),
+    ]))
+}
+
+///Does the registering for [`register_url_scheme`] on Windows
+#[cfg(target_os = "windows")]
+fn register_for(exe: &Path) -> Result<()> {
+    let key = format!(r"HKCU\Software\Classes\{SCHEME}");
+    let command = format!("\"{}\" \"%1\"", exe.display());
+
+    run(Command::new("reg").args(["add", &key, "/ve", "/d", "URL:Async Chess", "/f"]))?;
+    run(Command::new("reg").args(["add", &key, "/v", "URL Protocol", "/d", "", "/f"]))?;
+    run(Command::new("reg").args([
+        "add",
+        &format!(r"{key}\shell\open\command"),
+        "/ve",
+        "/d",
+        &command,
+        "/f",
+    ]))
+}
+
+///Does the registering for [`register_url_scheme`] on other OSes - which isn't possible from here
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn register_for(_exe: &Path) -> Result<()> {
+    bail!("the {SCHEME} scheme has to be declared in the app bundle on this OS")
+}
+
+///Runs a command for registering, and checks that it worked
+///
+/// # Errors
+/// - Fail to run the command
+/// - The command fails
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn run(command: &mut Command) -> Result<()> {
+    let status = command
+        .status()
+        .with_context(|| format!("running {command:?}"))?;
+    if !status.success() {
+        bail!("{command:?} failed with {status}");
+    }
+    Ok(())
+}
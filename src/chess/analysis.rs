@@ -0,0 +1,156 @@
+use crate::{
+    chess::{
+        attacks::{pseudo_legal_moves, AttackMap},
+        boards::board::{Board, BoardMoveState},
+        engine::{eval_cache, CachedEval},
+        zobrist::zobrist_hash,
+    },
+    prelude::{ChessPieceKind, Coords},
+};
+
+///How many centipawns each square attacked is worth
+const MOBILITY_WEIGHT: i32 = 2;
+///Score in centipawns for taking the king, which ends the game - more than any position could be worth
+const KING_SCORE: i32 = 100_000;
+
+///Evaluates a position from white's point of view, in centipawns - positive is better for white, negative better for black.
+///
+/// Only looks at the position as it is (material, and how many squares each side attacks), without searching any moves.
+#[must_use]
+pub fn evaluate<S: BoardMoveState>(board: &Board<S>) -> i32 {
+    let mut material = 0;
+    for x in 0..8 {
+        for y in 0..8 {
+            if let Some(p) = board[Coords::OnBoard(x, y)] {
+                #[allow(clippy::cast_possible_wrap)]
+                let value = p.kind.value() as i32 * 100;
+                material += if p.is_white { value } else { -value };
+            }
+        }
+    }
+
+    let mobility = AttackMap::new(board, true).attacked_squares()
+        - AttackMap::new(board, false).attacked_squares();
+
+    material + mobility * MOBILITY_WEIGHT
+}
+
+///Converts an evaluation in centipawns to how much of an evaluation bar should be white, from `0.0` (black winning) to `1.0` (white winning)
+#[must_use]
+pub fn white_share(centipawns: i32) -> f64 {
+    ///Centipawns at which the bar is about 88% one colour
+    const SCALE: f64 = 400.0;
+
+    0.5 + 0.5 * (f64::from(centipawns) / SCALE).tanh()
+}
+
+///Checks whether moving to a square takes the king, which wins the game
+fn takes_king<S: BoardMoveState>(board: &Board<S>, to: Coords) -> bool {
+    matches!(board[to], Some(p) if p.kind == ChessPieceKind::King)
+}
+
+///[`evaluate`]s a position, using the [`eval_cache`] if it has been evaluated before
+fn cached_evaluate<S: BoardMoveState>(board: &Board<S>) -> i32 {
+    //the evaluation doesn't depend on whose turn it is
+    let hash = zobrist_hash(board, true);
+    if let Some(cached) = eval_cache().get(hash, 0) {
+        return cached.score;
+    }
+
+    let score = evaluate(board);
+    eval_cache().insert(CachedEval {
+        hash,
+        depth: 0,
+        score,
+        best: None,
+    });
+    score
+}
+
+///Scores a move from the point of view of the colour making it, in centipawns, assuming the best reply - which is returned with the score, if there is one.
+///
+/// Taking the king scores [`KING_SCORE`], and letting it be taken scores `-KING_SCORE`. The best reply to each position is kept in the [`eval_cache`].
+fn score_move<S: BoardMoveState>(
+    board: &Board<S>,
+    white: bool,
+    (from, to): (Coords, Coords),
+) -> (i32, Option<(Coords, Coords)>) {
+    if takes_king(board, to) {
+        return (KING_SCORE, None);
+    }
+
+    let sign = if white { 1 } else { -1 };
+    let after = board.with_move(from, to);
+    let hash = zobrist_hash(&after, !white);
+    if let Some(cached) = eval_cache().get(hash, 1) {
+        return (sign * cached.score, cached.best);
+    }
+
+    let (score, refutation) = pseudo_legal_moves(&after, !white)
+        .into_iter()
+        .map(|(reply_from, reply_to)| {
+            let score = if takes_king(&after, reply_to) {
+                -KING_SCORE
+            } else {
+                sign * cached_evaluate(&after.with_move(reply_from, reply_to))
+            };
+            (score, Some((reply_from, reply_to)))
+        })
+        .min_by_key(|(score, _)| *score)
+        .unwrap_or_else(|| (sign * cached_evaluate(&after), None));
+
+    eval_cache().insert(CachedEval {
+        hash,
+        depth: 1,
+        score: sign * score,
+        best: refutation,
+    });
+    (score, refutation)
+}
+
+///Suggests a move for one colour, as `(from, to)`, by looking at each of its moves and the best reply to it - the move which leaves the best [`evaluate`]d position after that reply is suggested.
+///
+/// Taking the king wins, so it is always suggested if possible, and moves which let the king be taken are avoided. Returns `None` if the colour has no moves.
+#[must_use]
+pub fn suggest_move<S: BoardMoveState>(board: &Board<S>, white: bool) -> Option<(Coords, Coords)> {
+    pseudo_legal_moves(board, white)
+        .into_iter()
+        .map(|m| (m, score_move(board, white, m).0))
+        .max_by_key(|(_, score)| *score)
+        .map(|(m, _)| m)
+}
+
+///A move which leaves the player much worse off than their best move would, found by [`find_blunder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blunder {
+    ///How much worse off the move leaves the player than their best move, in centipawns
+    pub loss: i32,
+    ///The reply which punishes the move, as `(from, to)`
+    pub refutation: Option<(Coords, Coords)>,
+    ///Whether or not the refutation takes the king
+    pub loses_king: bool,
+}
+
+///Checks whether a move, as `(from, to)`, is a blunder - whether after the best reply it leaves the player at least `threshold` centipawns worse off than their best move would. Letting the king be taken always counts, unless every move does.
+///
+/// Returns `None` if it isn't a blunder, or there is no piece to move.
+#[must_use]
+pub fn find_blunder<S: BoardMoveState>(
+    board: &Board<S>,
+    m: (Coords, Coords),
+    threshold: i32,
+) -> Option<Blunder> {
+    let white = board[m.0]?.is_white;
+    let (score, refutation) = score_move(board, white, m);
+    let best = pseudo_legal_moves(board, white)
+        .into_iter()
+        .map(|other| score_move(board, white, other).0)
+        .fold(score, i32::max);
+
+    let loss = best - score;
+    (loss >= threshold || (score == -KING_SCORE && best > -KING_SCORE)).then_some(Blunder {
+        loss,
+        refutation,
+        loses_king: score == -KING_SCORE,
+    })
+}
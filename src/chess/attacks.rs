@@ -0,0 +1,257 @@
+use crate::{
+    chess::{
+        boards::board::{Board, BoardMoveState},
+        castling::{king_move, king_path, rook_move, squares_between},
+    },
+    prelude::{ChessPiece, ChessPieceKind, Coords},
+};
+
+///Directions that rooks (and queens) slide in
+const ORTHOGONALS: [(i32, i32); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+///Directions that bishops (and queens) slide in
+const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+///Offsets that knights can jump to
+const KNIGHT_JUMPS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+
+///Map of how many pieces of one colour attack each square on the board.
+///
+/// Squares with pieces of the same colour count as attacked, as that piece is defended. Doesn't account for pins or checks - it only says what could be taken, not whether taking it is legal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttackMap {
+    ///Number of attackers on each square, indexed like [`Coords::to_usize`]
+    attackers: [u8; 64],
+}
+
+impl AttackMap {
+    ///Works out the squares attacked by all of the pieces of one colour
+    #[must_use]
+    pub fn new<S: BoardMoveState>(board: &Board<S>, white: bool) -> Self {
+        let mut attackers = [0; 64];
+
+        for x in 0..8 {
+            for y in 0..8 {
+                let Some(piece) = board[Coords::OnBoard(x, y)] else {
+                    continue;
+                };
+                if piece.is_white != white {
+                    continue;
+                }
+
+                for c in piece_attacks(
+                    &|c| board[c],
+                    piece.kind,
+                    white,
+                    (i32::from(x), i32::from(y)),
+                ) {
+                    if let Some(i) = c.to_usize() {
+                        attackers[i] += 1;
+                    }
+                }
+            }
+        }
+
+        Self { attackers }
+    }
+
+    ///Gets the number of pieces attacking a square
+    #[must_use]
+    pub fn attackers(&self, c: Coords) -> u8 {
+        c.to_usize().map_or(0, |i| self.attackers[i])
+    }
+
+    ///Checks whether or not any pieces attack a square
+    #[must_use]
+    pub fn is_attacked(&self, c: Coords) -> bool {
+        self.attackers(c) > 0
+    }
+
+    ///Gets the number of squares attacked by at least one piece
+    #[must_use]
+    pub fn attacked_squares(&self) -> i32 {
+        //at most 64, so can't wrap
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let n = self.attackers.iter().filter(|a| **a > 0).count() as i32;
+        n
+    }
+}
+
+///Gets every move the pieces of one colour could make, as `(from, to)` pairs.
+///
+/// Like [`AttackMap`], it doesn't account for pins or checks - apart from castling, which is only included when the king isn't in check and doesn't pass over an attacked square. En passant isn't included, as the board doesn't track it.
+#[must_use]
+pub fn pseudo_legal_moves<S: BoardMoveState>(
+    board: &Board<S>,
+    white: bool,
+) -> Vec<(Coords, Coords)> {
+    let mut moves = vec![];
+
+    for x in 0..8_u8 {
+        for y in 0..8_u8 {
+            let from = Coords::OnBoard(x, y);
+            let Some(piece) = board[from] else {
+                continue;
+            };
+            if piece.is_white != white {
+                continue;
+            }
+
+            let at = (i32::from(x), i32::from(y));
+            let targets = if piece.kind == ChessPieceKind::Pawn {
+                pawn_moves(board, white, at)
+            } else {
+                piece_attacks(&|c| board[c], piece.kind, white, at)
+                    .into_iter()
+                    .filter(|c| !matches!(board[*c], Some(p) if p.is_white == white))
+                    .collect()
+            };
+            moves.extend(targets.into_iter().map(|to| (from, to)));
+        }
+    }
+
+    moves.extend(castles(board, white));
+    moves
+}
+
+///Gets the castles one colour can make, as the king's `(from, to)` - the castle has to be allowed by [`Board::castling`], the squares between the king and rook empty, and the king can't be in check or pass over or land on an attacked square
+fn castles<S: BoardMoveState>(board: &Board<S>, white: bool) -> Vec<(Coords, Coords)> {
+    let rights = board.castling();
+    if !rights.can_castle(white, true) && !rights.can_castle(white, false) {
+        return vec![];
+    }
+
+    let attacks = AttackMap::new(board, !white);
+    let rook = Some(ChessPiece {
+        kind: ChessPieceKind::Rook,
+        is_white: white,
+    });
+    [true, false]
+        .into_iter()
+        .filter(|king_side| {
+            rights.can_castle(white, *king_side)
+                && board[rook_move(white, *king_side).0] == rook
+                && squares_between(white, *king_side)
+                    .into_iter()
+                    .all(|c| !board.piece_exists_at_location(c))
+                && king_path(white, *king_side)
+                    .into_iter()
+                    .all(|c| !attacks.is_attacked(c))
+        })
+        .map(|king_side| king_move(white, king_side))
+        .collect()
+}
+
+///Gets the squares a pawn can move to - forwards onto empty squares (two from its starting rank), or diagonally to take a piece
+fn pawn_moves<S: BoardMoveState>(board: &Board<S>, white: bool, (x, y): (i32, i32)) -> Vec<Coords> {
+    let dy = if white { -1 } else { 1 };
+    let start_rank = if white { 6 } else { 1 };
+    let empty = |c: &Coords| c.is_on_board() && !board.piece_exists_at_location(*c);
+    let mut v = vec![];
+
+    if let Some(one) = Coords::try_from((x, y + dy)).ok().filter(empty) {
+        v.push(one);
+        if y == start_rank {
+            if let Some(two) = Coords::try_from((x, y + 2 * dy)).ok().filter(empty) {
+                v.push(two);
+            }
+        }
+    }
+
+    v.extend(
+        piece_attacks(&|c| board[c], ChessPieceKind::Pawn, white, (x, y))
+            .into_iter()
+            .filter(|c| matches!(board[*c], Some(p) if p.is_white != white)),
+    );
+    v
+}
+
+///Checks whether any piece of one colour attacks a square, going by `pieces` - indexed like [`Coords::to_usize`].
+///
+/// For checking a move on a scratch copy of a board's pieces, without building a whole [`AttackMap`] or copying the [`Board`].
+#[must_use]
+pub fn is_attacked_in(pieces: &[Option<ChessPiece>; 64], at: Coords, by_white: bool) -> bool {
+    let piece_at = |c: Coords| c.to_usize().and_then(|i| pieces[i]);
+    (0..8_u8)
+        .flat_map(|x| (0..8_u8).map(move |y| (x, y)))
+        .any(|(x, y)| {
+            piece_at(Coords::OnBoard(x, y)).is_some_and(|p| {
+                p.is_white == by_white
+                    && piece_attacks(&piece_at, p.kind, by_white, (i32::from(x), i32::from(y)))
+                        .contains(&at)
+            })
+        })
+}
+
+///Gets all of the squares attacked by one piece, with `piece_at` giving the piece on a square
+fn piece_attacks(
+    piece_at: &impl Fn(Coords) -> Option<ChessPiece>,
+    kind: ChessPieceKind,
+    white: bool,
+    (x, y): (i32, i32),
+) -> Vec<Coords> {
+    let on_board = |(dx, dy): (i32, i32)| {
+        Coords::try_from((x + dx, y + dy))
+            .ok()
+            .filter(Coords::is_on_board)
+    };
+
+    match kind {
+        ChessPieceKind::Pawn => {
+            //white starts at the bottom, so moves towards y = 0
+            let dy = if white { -1 } else { 1 };
+            [(-1, dy), (1, dy)]
+                .into_iter()
+                .filter_map(on_board)
+                .collect()
+        }
+        ChessPieceKind::Knight => KNIGHT_JUMPS.into_iter().filter_map(on_board).collect(),
+        ChessPieceKind::King => ORTHOGONALS
+            .into_iter()
+            .chain(DIAGONALS)
+            .filter_map(on_board)
+            .collect(),
+        ChessPieceKind::Rook => slide(piece_at, (x, y), &ORTHOGONALS),
+        ChessPieceKind::Bishop => slide(piece_at, (x, y), &DIAGONALS),
+        ChessPieceKind::Queen => {
+            let mut v = slide(piece_at, (x, y), &ORTHOGONALS);
+            v.append(&mut slide(piece_at, (x, y), &DIAGONALS));
+            v
+        }
+    }
+}
+
+///Gets all of the squares reachable by sliding from a square in some directions, stopping at (and including) the first piece in each direction
+fn slide(
+    piece_at: &impl Fn(Coords) -> Option<ChessPiece>,
+    (x, y): (i32, i32),
+    directions: &[(i32, i32)],
+) -> Vec<Coords> {
+    let mut v = vec![];
+
+    for (dx, dy) in directions {
+        let (mut cx, mut cy) = (x + dx, y + dy);
+        while let Ok(c) = Coords::try_from((cx, cy)) {
+            //(-1, -1) is how OffBoard is represented
+            if c.is_taken() {
+                break;
+            }
+
+            v.push(c);
+            if piece_at(c).is_some() {
+                break;
+            }
+            cx += dx;
+            cy += dy;
+        }
+    }
+
+    v
+}
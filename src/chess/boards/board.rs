@@ -1,17 +1,28 @@
 use std::{
-    marker::PhantomData,
-    ops::{Index, IndexMut},
+    fmt::{Display, Formatter},
+    ops::Index,
 };
 use anyhow::Context;
-use epac_utils::error_ext::{ErrorExt, ToAnyhowNotErr};
-use epac_utils::generic_enum;
+use epac_utils::{
+    either::Either,
+    error_ext::{ErrorExt, ToAnyhowNotErr},
+};
 use crate::{
+    chess::{
+        analysis,
+        attacks::{is_attacked_in, pseudo_legal_moves, AttackMap},
+        boards::board_container::BoardContainer,
+        castling::{castle_side, rook_move, CastlingRights},
+        zobrist::{piece_key, placement_hash, zobrist_hash},
+    },
     crate_private::Sealed,
-    net::server_interface::{JSONMove, JSONPieceList},
+    net::server_interface::{JSONMove, JSONPiece, JSONPieceList},
     prelude::{ChessPiece, ChessPieceKind, Coords, Result},
+    typestate,
+    util::typestate::StateMarker,
 };
 
-generic_enum!(Sealed, (BoardMoveState -> "Holds the current state of moving pieces in the board to ensure no logic errors") => (CanMovePiece -> "The board can currently move a new piece"), (NeedsMoveUpdate -> "The board now needs an update on what happened to the piece it moved"));
+typestate!(Sealed, (BoardMoveState -> "Holds the current state of moving pieces in the board to ensure no logic errors") => (CanMovePiece -> "The board can currently move a new piece"), (NeedsMoveUpdate -> "The board now needs an update on what happened to the pieces it moved"));
 
 ///Struct to hold a Chess Board
 #[derive(Clone, Debug)]
@@ -21,25 +32,87 @@ pub struct Board<STATE: BoardMoveState> {
     ///`None` signifies no piece, and `Some` signifies a piece
     pieces: [Option<ChessPiece>; 64],
 
+    ///The [`zobrist_hash`] of the pieces with white to move, kept up to date by [`Board::set_piece`] so positions can be compared without going over every square
+    hash: u64,
+
     ///vector to hold all the pieces which have been taken
     taken: Vec<ChessPiece>,
 
-    ///Used to hold the contents and details of the previous move, in case the move was invalid
-    ///
-    ///Holds the move made, the piece taken, and what the original kind was
-    previous: Option<(JSONMove, Option<ChessPiece>, ChessPieceKind)>,
+    ///Every move made on the board, oldest first, so they can be looked back over - and undone, if the server rejects them
+    moves: Vec<MadeMove>,
+
+    ///How many of the newest [`Board::moves`] are waiting on the server
+    unconfirmed: usize,
+
+    ///Which castling moves are still allowed
+    castling: CastlingRights,
+
+    ///The positions which have been reached so far, as their [`zobrist_hash`] and castling rights, to spot repetitions
+    positions: Vec<(u64, CastlingRights)>,
+
+    ///Number of half-moves since a piece was taken or a pawn moved, for the fifty-move rule
+    halfmove_clock: u32,
+
+    ///Which [`BoardMoveState`] the board is in
+    state: StateMarker<STATE>,
+}
 
-    ///[`PhantomData`] to make sure `STATE` isn't optimised away
-    _pd: PhantomData<STATE>,
+///A move made on a [`Board`], with what is needed to undo it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MadeMove {
+    ///The move which was made
+    pub m: JSONMove,
+    ///The piece which was on the square moved to
+    pub taken: Option<ChessPiece>,
+    ///What the piece was before it moved - only different afterwards if a pawn was promoted
+    pub old_kind: ChessPieceKind,
+    ///The castling rights before the move
+    castling: CastlingRights,
+    ///The half-move clock before the move
+    halfmove_clock: u32,
+}
+
+///How many times the same position has to come up before a draw can be claimed
+pub const REPETITIONS_FOR_DRAW: usize = 3;
+///How many half-moves without a piece being taken or a pawn moving there have to be before a draw can be claimed - fifty from each colour
+pub const FIFTY_MOVE_HALFMOVES: u32 = 100;
+
+///Why a game is drawn, going by the board - see [`Board::draw_reason`] for draws which happen straight away, and [`Board::claimable_draw`] for ones which have to be claimed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    ///The colour to move isn't in check, but has no legal moves
+    Stalemate,
+    ///Neither colour has the material left to checkmate
+    InsufficientMaterial,
+    ///The same position has come up [`REPETITIONS_FOR_DRAW`] times
+    Repetition,
+    ///No pieces have been taken and no pawns moved for [`FIFTY_MOVE_HALFMOVES`]
+    FiftyMoveRule,
+}
+
+impl Display for DrawReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stalemate => write!(f, "stalemate"),
+            Self::InsufficientMaterial => write!(f, "insufficient material"),
+            Self::Repetition => write!(f, "repetition"),
+            Self::FiftyMoveRule => write!(f, "the fifty-move rule"),
+        }
+    }
 }
 
 impl Default for Board<CanMovePiece> {
     fn default() -> Self {
         Self {
             pieces: [None; 64],
+            hash: 0,
             taken: Vec::with_capacity(32),
-            previous: None,
-            _pd: PhantomData,
+            moves: vec![],
+            unconfirmed: 0,
+            castling: CastlingRights::default(),
+            positions: vec![],
+            halfmove_clock: 0,
+            state: StateMarker::new(),
         }
     }
 }
@@ -64,26 +137,58 @@ impl<S: BoardMoveState> Index<Coords> for Board<S> {
     }
 }
 
-impl<S: BoardMoveState> IndexMut<Coords> for Board<S> {
-    ///Function to mutably index the pieces
+impl<S: BoardMoveState> PartialEq for Board<S> {
+    ///Boards are equal if they have the same pieces in the same places, and the same taken pieces. The moves made aren't compared.
     ///
-    /// # Panics
-    /// Can panic if the coords are out-of-bounds, but very unlikely
-    fn index_mut(&mut self, index: Coords) -> &mut Self::Output {
-        self.pieces
-            .get_mut(
-                index
-                    .to_usize()
-                    .ae()
-                    .context("index piece")
-                    .unwrap_log_error(),
-            )
-            .unwrap_log_error_with_context(|| format!("Getting position mutably from {index:?}"))
+    /// The hashes are compared first, so boards with different pieces are told apart without going over every square.
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.pieces == other.pieces && self.taken == other.taken
     }
 }
 
 //more like the rocket than the other examples
 impl<STATE: BoardMoveState> Board<STATE> {
+    ///Moves the board to another [`BoardMoveState`], keeping everything else.
+    ///
+    /// This doesn't check the transition makes sense - only the named transitions (eg. [`Board::make_move`]) and the [`TryFrom`] conversion back to [`CanMovePiece`] should use it.
+    pub(crate) fn into_state<T: BoardMoveState>(self) -> Board<T> {
+        Board {
+            pieces: self.pieces,
+            hash: self.hash,
+            taken: self.taken,
+            moves: self.moves,
+            unconfirmed: self.unconfirmed,
+            castling: self.castling,
+            positions: self.positions,
+            halfmove_clock: self.halfmove_clock,
+            state: self.state.retype(),
+        }
+    }
+
+    ///Sets the piece on a square, returning the piece which was there before, and updates [`Board::hash`] to match
+    ///
+    /// # Panics
+    /// Can panic if the coords are out-of-bounds, but very unlikely
+    pub fn set_piece(&mut self, at: Coords, piece: Option<ChessPiece>) -> Option<ChessPiece> {
+        let square = at.to_usize().ae().context("index piece").unwrap_log_error();
+        let old = std::mem::replace(
+            self.pieces
+                .get_mut(square)
+                .unwrap_log_error_with_context(|| format!("Getting position mutably from {at:?}")),
+            piece,
+        );
+        for p in [old, piece].into_iter().flatten() {
+            self.hash ^= piece_key(p, square);
+        }
+        old
+    }
+
+    ///Gets the [`zobrist_hash`] of the pieces with white to move. It is kept up to date as pieces move, so this is free - two boards with different hashes never have the same pieces in the same places.
+    #[must_use]
+    pub const fn hash(&self) -> u64 {
+        self.hash
+    }
+
     ///Checks whether or not a piece exists at a given set of coordinates
     #[must_use]
     pub fn piece_exists_at_location(&self, coords: Coords) -> bool {
@@ -94,11 +199,482 @@ impl<STATE: BoardMoveState> Board<STATE> {
         }
     }
 
+    ///Turns the board into a [`JSONPieceList`] like the server sends - taken pieces are at `(-1, -1)`
+    #[must_use]
+    pub fn to_json(&self) -> JSONPieceList {
+        let on_board = (0..8_u8)
+            .flat_map(|x| (0..8_u8).map(move |y| (x, y)))
+            .filter_map(|(x, y)| {
+                self[Coords::OnBoard(x, y)].map(|p| (i32::from(x), i32::from(y), p))
+            });
+        let taken = self.taken.iter().map(|p| (-1, -1, *p));
+
+        JSONPieceList(
+            on_board
+                .chain(taken)
+                .map(|(x, y, p)| JSONPiece {
+                    x,
+                    y,
+                    kind: p.kind.to_string(),
+                    is_white: p.is_white,
+                })
+                .collect(),
+        )
+    }
+
     ///Gets a clone of all the pieces which have been taken
     #[must_use]
     pub fn get_taken(&self) -> Vec<ChessPiece> {
         self.taken.clone()
     }
+
+    ///Gets all the pieces which have been taken, without cloning them
+    #[must_use]
+    pub fn taken(&self) -> &[ChessPiece] {
+        &self.taken
+    }
+
+    ///Evaluates the position using [`analysis::evaluate`] - in centipawns, positive is better for white
+    #[must_use]
+    pub fn evaluate(&self) -> i32 {
+        analysis::evaluate(self)
+    }
+
+    ///Gets the coordinates of all of the pieces of one colour which are attacked by the other colour and not defended
+    #[must_use]
+    pub fn threatened_pieces(&self, is_white: bool) -> Vec<Coords> {
+        let attacks = AttackMap::new(self, !is_white);
+        let defences = AttackMap::new(self, is_white);
+
+        (0..8_u8)
+            .flat_map(|x| (0..8_u8).map(move |y| Coords::OnBoard(x, y)))
+            .filter(|c| matches!(self[*c], Some(p) if p.is_white == is_white))
+            .filter(|c| attacks.is_attacked(*c) && !defences.is_attacked(*c))
+            .collect()
+    }
+
+    ///Checks whether moving the piece on `from` to `to` promotes it - a pawn reaching the far side
+    #[must_use]
+    pub fn is_promotion(&self, from: Coords, to: Coords) -> bool {
+        if !from.is_on_board() {
+            return false;
+        }
+        self[from].is_some_and(|p| {
+            let far_side = if p.is_white { 0 } else { 7 };
+            p.kind == ChessPieceKind::Pawn && to.y() == Some(far_side)
+        })
+    }
+
+    ///Gets which castling moves are still allowed
+    #[must_use]
+    pub const fn castling(&self) -> CastlingRights {
+        self.castling
+    }
+
+    ///Takes away any castling rights which had already been lost on the board before this one - for a board from the server, which only knows where the pieces are, when it follows on from `before`
+    pub fn keep_castling_from<T: BoardMoveState>(&mut self, before: &Board<T>) {
+        self.castling = self.castling.and(before.castling);
+    }
+
+    ///Records the position on the board as having been reached, with the given colour to move - for once a position is confirmed, like by the server
+    pub fn record_position(&mut self, white_to_move: bool) {
+        let key = (zobrist_hash(self, white_to_move), self.castling);
+        self.positions.push(key);
+    }
+
+    ///Carries over the positions recorded on the board before this one - for a board from the server, which doesn't know them, when it follows on from `before` by `moves`.
+    ///
+    /// The positions in between are recorded too, so a repetition isn't missed when several moves arrive at once. `white_to_move` is for after the last move, whose position is left to be recorded with [`Board::record_position`].
+    pub fn keep_positions_from<T: BoardMoveState>(
+        &mut self,
+        before: &Board<T>,
+        moves: &[(Coords, Coords)],
+        white_to_move: bool,
+    ) {
+        let mut board = before.clone();
+        for (i, (from, to)) in moves.iter().enumerate() {
+            board = board.with_move(*from, *to);
+            let moves_left = moves.len() - i - 1;
+            if moves_left > 0 {
+                //the colour to move swaps back with each move still to come
+                board.record_position(white_to_move != (moves_left % 2 == 1));
+            }
+        }
+        self.positions = board.positions;
+    }
+
+    ///Gets how many times the last recorded position has been reached, with the same colour to move and castling rights - [`REPETITIONS_FOR_DRAW`] or more means either colour can claim a draw. `0` if no positions have been recorded.
+    #[must_use]
+    pub fn repetition_count(&self) -> usize {
+        self.positions.last().map_or(0, |last| {
+            self.positions.iter().filter(|p| *p == last).count()
+        })
+    }
+
+    ///Gets the number of half-moves since a piece was taken or a pawn moved - [`FIFTY_MOVE_HALFMOVES`] or more means either colour can claim a draw
+    #[must_use]
+    pub const fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    ///Works out the half-move clock from the board before this one and the moves which turned it into this one - for a board from the server, which doesn't know it
+    pub fn keep_halfmove_clock_from<T: BoardMoveState>(
+        &mut self,
+        before: &Board<T>,
+        moves: &[(Coords, Coords)],
+    ) {
+        let mut board = before.clone();
+        for (from, to) in moves {
+            board = board.with_move(*from, *to);
+        }
+        self.halfmove_clock = board.halfmove_clock;
+    }
+
+    ///Gets what the half-move clock would be after moving the piece on `from` to `to` - it goes back to 0 if a pawn moves or a piece is taken
+    fn halfmove_clock_after(&self, from: Coords, to: Coords) -> u32 {
+        let pawn_moved = self[from].is_some_and(|p| p.kind == ChessPieceKind::Pawn);
+        if pawn_moved || self.piece_exists_at_location(to) {
+            0
+        } else {
+            self.halfmove_clock + 1
+        }
+    }
+
+    ///Gets a draw which either colour could claim, if there is one - by repetition or the fifty-move rule
+    #[must_use]
+    pub fn claimable_draw(&self) -> Option<DrawReason> {
+        if self.repetition_count() >= REPETITIONS_FOR_DRAW {
+            Some(DrawReason::Repetition)
+        } else if self.halfmove_clock >= FIFTY_MOVE_HALFMOVES {
+            Some(DrawReason::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+
+    ///Moves the rook too if moving `piece` from `from` to `to` is a castle, and takes away the castling rights lost by the move
+    fn castle_and_update_rights(&mut self, piece: ChessPiece, from: Coords, to: Coords) {
+        if let Some(king_side) = castle_side(piece, from, to) {
+            let (rook_from, rook_to) = rook_move(piece.is_white, king_side);
+            let rook = self.set_piece(rook_from, None);
+            self.set_piece(rook_to, rook);
+        }
+        self.castling.update_for_move(from, to);
+    }
+
+    ///Finds where a colour's king is, if it is still on the board
+    #[must_use]
+    pub fn find_king(&self, is_white: bool) -> Option<Coords> {
+        (0..8_u8)
+            .flat_map(|x| (0..8_u8).map(move |y| Coords::OnBoard(x, y)))
+            .find(|c| {
+                matches!(self[*c], Some(p) if p.kind == ChessPieceKind::King && p.is_white == is_white)
+            })
+    }
+
+    ///Checks whether a colour's king is attacked. A colour without a king on the board is never in check.
+    #[must_use]
+    pub fn is_in_check(&self, is_white: bool) -> bool {
+        self.find_king(is_white)
+            .is_some_and(|king| AttackMap::new(self, !is_white).is_attacked(king))
+    }
+
+    ///Gets the squares the piece on `from` can move to - every move it could make which doesn't leave its own king in check, including castling. Empty if there isn't a piece there.
+    ///
+    /// En passant isn't tracked, so is never included.
+    #[must_use]
+    pub fn legal_moves_from(&self, from: Coords) -> Vec<Coords> {
+        let Some(piece) = from.is_on_board().then(|| self[from]).flatten() else {
+            return vec![];
+        };
+        let from_here = pseudo_legal_moves(self, piece.is_white)
+            .into_iter()
+            .filter(|(f, _)| *f == from);
+        self.without_self_checks(from_here, piece.is_white)
+            .into_iter()
+            .map(|(_, to)| to)
+            .collect()
+    }
+
+    ///Keeps the moves which don't leave `is_white`'s king in check.
+    ///
+    /// Each move is made and unmade on one scratch copy of the pieces, rather than copying the whole board for every move. Castling moves the rook too, as it can block an attack on the king's new square.
+    fn without_self_checks(
+        &self,
+        moves: impl IntoIterator<Item = (Coords, Coords)>,
+        is_white: bool,
+    ) -> Vec<(Coords, Coords)> {
+        let Some(king) = self.find_king(is_white) else {
+            return moves.into_iter().collect();
+        };
+        let mut scratch = self.pieces;
+
+        moves
+            .into_iter()
+            .filter(|&(from, to)| {
+                let (Some(fi), Some(ti)) = (from.to_usize(), to.to_usize()) else {
+                    return false;
+                };
+                let Some(piece) = scratch[fi] else {
+                    return false;
+                };
+                let rook = castle_side(piece, from, to)
+                    .map(|king_side| rook_move(is_white, king_side))
+                    .and_then(|(rf, rt)| Some((rf.to_usize()?, rt.to_usize()?)));
+
+                let taken = scratch[ti].replace(piece);
+                scratch[fi] = None;
+                if let Some((rf, rt)) = rook {
+                    scratch[rt] = scratch[rf].take();
+                }
+
+                let king_at = if from == king { to } else { king };
+                let in_check = is_attacked_in(&scratch, king_at, !is_white);
+
+                if let Some((rf, rt)) = rook {
+                    scratch[rf] = scratch[rt].take();
+                }
+                scratch[fi] = Some(piece);
+                scratch[ti] = taken;
+
+                !in_check
+            })
+            .collect()
+    }
+
+    ///Checks whether a colour is in checkmate - it is in check, and every move it could make leaves it in check.
+    ///
+    /// En passant isn't tracked, so in the rare position where only taking en passant would get out of check, this says checkmate when it isn't.
+    #[must_use]
+    pub fn is_checkmate(&self, is_white: bool) -> bool {
+        self.is_in_check(is_white)
+            && self
+                .without_self_checks(pseudo_legal_moves(self, is_white), is_white)
+                .is_empty()
+    }
+
+    ///Checks whether a colour is in stalemate - it isn't in check, but every move it could make would leave it in check. A colour without a king on the board is never in stalemate.
+    ///
+    /// En passant isn't tracked, so in the rare position where taking en passant is the only move, this says stalemate when it isn't.
+    #[must_use]
+    pub fn is_stalemate(&self, is_white: bool) -> bool {
+        self.find_king(is_white).is_some()
+            && !self.is_in_check(is_white)
+            && self
+                .without_self_checks(pseudo_legal_moves(self, is_white), is_white)
+                .is_empty()
+    }
+
+    ///Checks whether a colour has too little material to ever checkmate on its own - just its king, or its king and one bishop or knight
+    #[must_use]
+    pub fn has_insufficient_material(&self, is_white: bool) -> bool {
+        let others: Vec<ChessPieceKind> = self
+            .pieces
+            .iter()
+            .flatten()
+            .filter(|p| p.is_white == is_white && p.kind != ChessPieceKind::King)
+            .map(|p| p.kind)
+            .collect();
+        matches!(
+            others.as_slice(),
+            [] | [ChessPieceKind::Bishop | ChessPieceKind::Knight]
+        )
+    }
+
+    ///Checks whether neither colour could ever checkmate, however badly the other plays.
+    ///
+    /// Only positions which are dead because of the material left are found - a king and at most one bishop or knight against a lone king, or kings and bishops with every bishop on the same colour of square. Positions locked up by pawns aren't found.
+    #[must_use]
+    pub fn is_dead_position(&self) -> bool {
+        let others: Vec<(usize, ChessPieceKind)> = self
+            .pieces
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.map(|p| (i, p.kind)))
+            .filter(|(_, kind)| *kind != ChessPieceKind::King)
+            .collect();
+        if others.len() <= 1 {
+            return self.has_insufficient_material(true) && self.has_insufficient_material(false);
+        }
+
+        //the index is y * 8 + x, so the square colour is the parity of x + y
+        let square_colour = |i: usize| (i % 8 + i / 8) % 2;
+        others.iter().all(|(i, kind)| {
+            *kind == ChessPieceKind::Bishop && square_colour(*i) == square_colour(others[0].0)
+        })
+    }
+
+    ///Works out whether the game is drawn, and why.
+    ///
+    /// Stalemate only counts for the colour whose turn it is, so is only checked if `white_to_move` is known.
+    #[must_use]
+    pub fn draw_reason(&self, white_to_move: Option<bool>) -> Option<DrawReason> {
+        if white_to_move.is_some_and(|white| self.is_stalemate(white)) {
+            Some(DrawReason::Stalemate)
+        } else if self.is_dead_position() {
+            Some(DrawReason::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
+    ///Describes the position in Forsyth-Edwards Notation.
+    ///
+    /// Only the piece placement comes from the board - turns and en passant aren't tracked, and the castling rights aren't written out, so the rest is always `w - - 0 1`
+    #[must_use]
+    pub fn to_fen(&self) -> String {
+        self.to_fen_with_turn(true)
+    }
+
+    ///Describes the position in Forsyth-Edwards Notation, with the given side to move. En passant isn't tracked and the castling rights aren't written out, so both are always `-`.
+    #[must_use]
+    pub fn to_fen_with_turn(&self, white_to_move: bool) -> String {
+        let mut ranks = vec![];
+        for y in 0..8_u8 {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for x in 0..8_u8 {
+                match self[Coords::OnBoard(x, y)] {
+                    Some(p) => {
+                        if empty > 0 {
+                            rank += &empty.to_string();
+                            empty = 0;
+                        }
+                        let letter = p.kind.letter();
+                        rank.push(if p.is_white {
+                            letter
+                        } else {
+                            letter.to_ascii_lowercase()
+                        });
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                rank += &empty.to_string();
+            }
+            ranks.push(rank);
+        }
+
+        format!(
+            "{} {} - - 0 1",
+            ranks.join("/"),
+            if white_to_move { 'w' } else { 'b' }
+        )
+    }
+
+    ///Gets every move made on the board, oldest first - the last [`Board::unconfirmed_moves`] of them are still waiting on the server.
+    ///
+    /// A board from the server starts without any moves, as it only knows where the pieces are.
+    #[must_use]
+    pub fn moves(&self) -> &[MadeMove] {
+        &self.moves
+    }
+
+    ///Gets the moves made on the board which are still waiting on the server, oldest first
+    #[must_use]
+    pub fn unconfirmed_moves(&self) -> &[MadeMove] {
+        &self.moves[self.moves.len() - self.unconfirmed..]
+    }
+
+    ///Makes a move which the server hasn't confirmed yet, keeping it on top of [`Board::moves`] so it can be undone
+    ///
+    /// - Firstly, finds the piece to be taken, and keeps the details of that piece
+    /// - Then, sets the piece at the new location to the piece at the current location
+    /// - Then, if a pawn reached the far side, promotes it to [`JSONMove::promotion_kind`]
+    /// - If the move is a castle, the rook is moved too
+    ///
+    /// # Panics
+    /// - Can panic if the move is OOB, or there is no piece at the current location
+    fn push_move(&mut self, m: JSONMove) {
+        let piece = self[m.current_coords()]
+            .ae()
+            .context("getting current piece")
+            .unwrap_log_error();
+        self.moves.push(MadeMove {
+            m,
+            taken: self[m.new_coords()],
+            old_kind: piece.kind,
+            castling: self.castling,
+            halfmove_clock: self.halfmove_clock,
+        });
+        self.unconfirmed += 1;
+        self.halfmove_clock = self.halfmove_clock_after(m.current_coords(), m.new_coords());
+        self.castle_and_update_rights(piece, m.current_coords(), m.new_coords());
+
+        let mut moved = piece;
+        let far_side = if moved.is_white { 0 } else { 7 };
+        if moved.kind == ChessPieceKind::Pawn && m.ny == far_side {
+            moved.kind = m.promotion_kind();
+        }
+        self.set_piece(m.current_coords(), None);
+        self.set_piece(m.new_coords(), Some(moved));
+    }
+
+    ///Undoes the newest unconfirmed move, putting back the piece it took and the rook if it castled
+    fn pop_move(&mut self) {
+        if self.unconfirmed == 0 {
+            Err::<(), _>(anyhow!("undo move without move to undo")).unwrap_log_error();
+            return;
+        }
+        let Some(made) = self.moves.pop() else {
+            return;
+        };
+        self.unconfirmed -= 1;
+
+        let m = made.m;
+        let moved = self
+            .set_piece(m.new_coords(), made.taken)
+            .map(|piece| ChessPiece {
+                kind: made.old_kind,
+                ..piece
+            });
+        self.set_piece(m.current_coords(), moved);
+
+        if let Some(piece) = moved {
+            if let Some(king_side) = castle_side(piece, m.current_coords(), m.new_coords()) {
+                let (rook_from, rook_to) = rook_move(piece.is_white, king_side);
+                let rook = self.set_piece(rook_to, None);
+                self.set_piece(rook_from, rook);
+            }
+        }
+        self.castling = made.castling;
+        self.halfmove_clock = made.halfmove_clock;
+    }
+
+    ///Makes a copy of the board with a piece moved, for looking ahead. Any piece on `to` is taken, pawns reaching the far side become queens, and castling moves the rook too.
+    ///
+    /// Nothing is checked - it is up to the caller to only pass moves from a piece which exists.
+    #[must_use]
+    pub fn with_move(&self, from: Coords, to: Coords) -> Self {
+        let mut board = self.clone();
+        board.halfmove_clock = self.halfmove_clock_after(from, to);
+        if let Some(piece) = board[from] {
+            board.castle_and_update_rights(piece, from, to);
+        }
+        if let Some(taken) = board.set_piece(to, None) {
+            board.taken.push(taken);
+        }
+        let mut moved = board.set_piece(from, None);
+
+        if let Some(p) = &mut moved {
+            let far_side = if p.is_white { 0 } else { 7 };
+            if p.kind == ChessPieceKind::Pawn && to.y() == Some(far_side) {
+                p.kind = ChessPieceKind::Queen;
+            }
+        }
+        board.set_piece(to, moved);
+
+        board
+    }
+
+    ///Checks whether two boards have the same pieces in the same places, ignoring taken pieces - eg. to compare a board worked out locally with one from the server, whose taken pieces may be in a different order
+    ///
+    /// The hashes are compared first, so this is cheap when the boards differ.
+    #[must_use]
+    pub fn same_pieces<T: BoardMoveState>(&self, other: &Board<T>) -> bool {
+        self.hash == other.hash && self.pieces == other.pieces
+    }
 }
 
 impl Board<CanMovePiece> {
@@ -108,107 +684,324 @@ impl Board<CanMovePiece> {
     /// If `into_game_list` fails, this will return that error.
     ///
     /// `into_game_list` can fail if any pieces are out-of-bounds, or there are collisions
+    ///
+    /// The castling rights are worked out from where the pieces are, using [`CastlingRights::from_placement`]
     pub fn new_json(board: JSONPieceList) -> Result<Self> {
         let (pieces, taken) = board.into_game_list()?;
-        Ok(Self {
+        let mut board = Self {
+            hash: placement_hash(&pieces),
             pieces,
             taken,
             ..Default::default()
-        })
+        };
+        board.castling = CastlingRights::from_placement(&board);
+        Ok(board)
     }
 
-    ///Makes a move using a given [`JSONMove`]
+    ///Creates a board from the piece placement in Forsyth-Edwards Notation, eg. `rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1`, with the castling rights and half-move clock if they are given. The rest is ignored, as the board doesn't track it, and no pieces start as taken.
     ///
-    /// - Firstly, finds the piece to be taken, and sets the cache to the details of that piece
-    /// - Then, sets the piece at the new location to the piece at the current location
-    /// - Then, checks for pawn promotion, and possibly promotes the pawn
+    /// # Errors
+    /// - There aren't 8 ranks, or a rank doesn't have 8 squares
+    /// - A letter isn't a piece
+    /// - The half-move clock isn't a number
+    pub fn from_fen(fen: &str) -> Result<Self> {
+        let placement = fen.split_whitespace().next().unwrap_or_default();
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            bail!("expected 8 ranks in {placement:?}, found {}", ranks.len());
+        }
+
+        let mut board = Self::default();
+        for (y, rank) in (0..8_u8).zip(ranks) {
+            let mut x = 0_u8;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    x += u8::try_from(empty).context("empty squares")?;
+                    continue;
+                }
+                let kind = ChessPieceKind::from_letter(c)
+                    .ae()
+                    .with_context(|| format!("unknown piece {c:?}"))?;
+                if x >= 8 {
+                    bail!("too many squares in rank {rank:?}");
+                }
+                board.set_piece(
+                    Coords::OnBoard(x, y),
+                    Some(ChessPiece {
+                        kind,
+                        is_white: c.is_ascii_uppercase(),
+                    }),
+                );
+                x += 1;
+            }
+            if x != 8 {
+                bail!("expected 8 squares in rank {rank:?}, found {x}");
+            }
+        }
+
+        board.castling = CastlingRights::from_placement(&board);
+        if let Some(field) = fen.split_whitespace().nth(2) {
+            board.castling = board.castling.and(CastlingRights::from_fen_field(field));
+        }
+        if let Some(clock) = fen.split_whitespace().nth(4) {
+            board.halfmove_clock = clock
+                .parse()
+                .with_context(|| format!("half-move clock {clock:?}"))?;
+        }
+        Ok(board)
+    }
+
+    ///Makes a move using a given [`JSONMove`], which then needs to be confirmed by the server - see [`Board::push_move`]
     ///
     /// # Panics
-    /// - Can panic if the move is OOB, or there is no piece at the current location, or the last move wasn't cleared
+    /// - Can panic if the move is OOB, or there is no piece at the current location
     #[tracing::instrument(skip(self))]
     pub fn make_move(mut self, m: JSONMove) -> Board<NeedsMoveUpdate> {
-        if self.previous.is_some() {
-            Err::<(), _>(anyhow!("Move made without clearing")).unwrap_log_error();
-        }
+        self.push_move(m);
+        self.into_state()
+    }
+}
 
-        self.previous = Some((
-            m,
-            self[m.new_coords()],
-            self[m.current_coords()]
-                .ae()
-                .context("getting current piece")
-                .unwrap_log_error()
-                .kind,
-        ));
-
-        let old_current = std::mem::take(&mut self[m.current_coords()]);
-        self[m.new_coords()] = old_current;
-
-        if let Some(p) = &mut self[m.new_coords()] {
-            //rather than unwrap to get a mutable reference
-            if (p.is_white && m.ny == 0) || (!p.is_white && m.ny == 7) {
-                p.kind = ChessPieceKind::Queen;
-            }
-        }
+impl TryFrom<Board<NeedsMoveUpdate>> for Board<CanMovePiece> {
+    type Error = Board<NeedsMoveUpdate>;
 
-        Board {
-            pieces: self.pieces,
-            taken: self.taken,
-            previous: self.previous,
-            _pd: PhantomData,
+    ///Goes back to moving pieces once no moves are waiting on the server - otherwise, the board is given back unchanged
+    fn try_from(board: Board<NeedsMoveUpdate>) -> Result<Self, Self::Error> {
+        if board.unconfirmed == 0 {
+            Ok(board.into_state())
+        } else {
+            Err(board)
         }
     }
 }
 
 impl Board<NeedsMoveUpdate> {
-    ///Undos the most recent move
+    ///Makes another move before the server has answered about the ones before it - it is kept on top of the others, so they can all be undone together
     ///
-    /// # Errors
-    /// Can return an error if there is no longer a piece at the coordinates the piece was moved to
+    /// # Panics
+    /// - Can panic if the move is OOB, or there is no piece at the current location
+    #[tracing::instrument(skip(self))]
+    #[must_use]
+    pub fn make_move(mut self, m: JSONMove) -> Self {
+        self.push_move(m);
+        self
+    }
+
+    ///Undoes every move still waiting on the server, newest first - eg. when the server rejects or can't be sent one of them, as the moves after it were made on a board with it
     #[must_use]
     pub fn undo_move(mut self) -> Board<CanMovePiece> {
-        if let Some((m, taken, old_kind)) = std::mem::take(&mut self.previous) {
-            self[m.current_coords()] = self[m.new_coords()];
-            self[m.new_coords()] = taken;
+        if self.unconfirmed == 0 {
+            Err::<(), _>(anyhow!("undo move without move to undo")).unwrap_log_error();
+        }
+        while self.unconfirmed > 0 {
+            self.pop_move();
+        }
 
-            if let Some(piece) = &mut self[m.current_coords()] {
-                piece.kind = old_kind;
+        Board::try_from(self)
+            .map_err(|_| anyhow!("moves left waiting after undoing them all"))
+            .unwrap_log_error()
+    }
+
+    ///Confirms the oldest move waiting on the server, adding the piece it took to the taken pieces if `taken`. The board can only move a new piece once no moves are waiting.
+    ///
+    /// # Panics
+    /// Can panic if there wasn't a move made beforehand
+    #[must_use]
+    pub fn confirm_move(mut self, taken: bool) -> BoardContainer {
+        if self.unconfirmed == 0 {
+            Err::<(), _>(anyhow!("confirming a move without a move made")).unwrap_log_error();
+        }
+        let oldest = self.moves.len() - self.unconfirmed;
+        if let Some(p) = self.moves.get(oldest).and_then(|made| made.taken) {
+            if taken {
+                self.taken.push(p);
             }
-        } else {
-            Err::<(), _>(anyhow!("undo move without move to undo")).unwrap_log_error();
         }
+        self.unconfirmed = self.unconfirmed.saturating_sub(1);
 
-        Board {
-            pieces: self.pieces,
-            taken: self.taken,
-            previous: self.previous,
-            _pd: PhantomData,
+        match Board::try_from(self) {
+            Ok(board) => Either::Left(board),
+            Err(board) => Either::Right(board),
         }
     }
 
-    ///Clears out the cache
+    ///Confirms every move waiting on the server, adding the pieces they took to the taken pieces if `taken`
     ///
     /// # Panics
     /// Can panic if there wasn't a move made beforehand
     #[must_use]
     pub fn move_worked(mut self, taken: bool) -> Board<CanMovePiece> {
-        if taken {
-            let (_, p, _) = std::mem::take(&mut self.previous)
-                .ae()
-                .context("taking previous")
-                .unwrap_log_error();
-            if let Some(p) = p {
-                self.taken.push(p);
+        loop {
+            match self.confirm_move(taken) {
+                Either::Left(board) => return board,
+                Either::Right(board) => self = board,
             }
-        } else {
-            self.previous = None;
         }
+    }
+}
 
-        Board {
-            pieces: self.pieces,
-            taken: self.taken,
-            previous: self.previous,
-            _pd: PhantomData,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///The starting position
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    ///Gets the board from `fen`, panicking if it isn't valid
+    fn board(fen: &str) -> Board<CanMovePiece> {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn confirmed_move_stays_made() {
+        let start = board(START);
+        let e4 = JSONMove::new(1, 4, 6, 4, 4);
+
+        let Either::Left(b) = start.clone().make_move(e4).confirm_move(false) else {
+            panic!("no moves should be waiting");
+        };
+        assert!(b.unconfirmed_moves().is_empty());
+        assert_eq!(b.moves().len(), 1);
+        assert_eq!(b[Coords::OnBoard(4, 6)], None);
+        assert_eq!(b[Coords::OnBoard(4, 4)], start[Coords::OnBoard(4, 6)]);
+    }
+
+    #[test]
+    fn confirmed_capture_is_taken() {
+        let start = board("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1");
+        let exd5 = JSONMove::new(1, 4, 4, 3, 3);
+
+        let b = start.clone().make_move(exd5).move_worked(true);
+        assert_eq!(b.taken(), &[start[Coords::OnBoard(3, 3)].unwrap()]);
+        assert_eq!(b.halfmove_clock(), 0);
+    }
+
+    #[test]
+    fn undo_after_invalid_move_puts_the_board_back() {
+        let start = board(START);
+        let b = start
+            .clone()
+            .make_move(JSONMove::new(1, 4, 6, 4, 4))
+            .make_move(JSONMove::new(1, 6, 7, 5, 5));
+        assert_eq!(b.unconfirmed_moves().len(), 2);
+
+        let b = b.undo_move();
+        assert!(b.same_pieces(&start));
+        assert!(b.moves().is_empty());
+        assert_eq!(b.castling(), start.castling());
+    }
+
+    #[test]
+    fn undo_keeps_confirmed_moves() {
+        let start = board(START);
+        let e4 = JSONMove::new(1, 4, 6, 4, 4);
+        let after_e4 = start.with_move(e4.current_coords(), e4.new_coords());
+
+        let Either::Right(b) = start
+            .make_move(e4)
+            .make_move(JSONMove::new(1, 6, 7, 5, 5))
+            .confirm_move(false)
+        else {
+            panic!("the second move should still be waiting");
+        };
+        assert_eq!(b.unconfirmed_moves().len(), 1);
+
+        let b = b.undo_move();
+        assert!(b.same_pieces(&after_e4));
+        assert_eq!(b.moves().len(), 1);
+    }
+
+    #[test]
+    fn undo_puts_back_taken_piece_and_castled_rook() {
+        let start = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let b = start
+            .clone()
+            .make_move(JSONMove::new(1, 4, 7, 6, 7))
+            .make_move(JSONMove::new(1, 0, 0, 0, 7))
+            .undo_move();
+        assert!(b.same_pieces(&start));
+        assert_eq!(b.castling(), start.castling());
+    }
+
+    #[test]
+    fn board_with_moves_waiting_cant_move_a_new_piece() {
+        let b = board(START).make_move(JSONMove::new(1, 4, 6, 4, 4));
+        assert!(Board::<CanMovePiece>::try_from(b).is_err());
+    }
+
+    #[test]
+    fn promotion_becomes_the_picked_piece() {
+        let start = board("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let a8 = JSONMove::new(1, 0, 1, 0, 0);
+        assert!(start.is_promotion(a8.current_coords(), a8.new_coords()));
+
+        let promoted = start
+            .make_move(a8.with_promotion(ChessPieceKind::Knight))
+            .move_worked(false);
+        assert_eq!(
+            promoted[Coords::OnBoard(0, 0)].map(|p| p.kind),
+            Some(ChessPieceKind::Knight)
+        );
+    }
+
+    #[test]
+    fn only_pawns_promote() {
+        let start = board("4k3/R7/8/8/8/8/8/4K3 w - - 0 1");
+        let a8 = JSONMove::new(1, 0, 1, 0, 0);
+        assert!(!start.is_promotion(a8.current_coords(), a8.new_coords()));
+
+        let moved = start.make_move(a8).move_worked(false);
+        assert_eq!(
+            moved[Coords::OnBoard(0, 0)].map(|p| p.kind),
+            Some(ChessPieceKind::Rook)
+        );
+    }
+
+    #[test]
+    fn back_rank_mate_is_checkmate() {
+        let mated = board("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert!(mated.is_checkmate(false));
+        assert!(!mated.is_stalemate(false));
+        assert!(!mated.is_checkmate(true));
+    }
+
+    #[test]
+    fn check_with_a_way_out_isnt_checkmate() {
+        let checked = board("R5k1/5pp1/8/8/8/8/8/6K1 b - - 0 1");
+        assert!(checked.is_in_check(false));
+        assert!(!checked.is_checkmate(false));
+    }
+
+    #[test]
+    fn no_moves_out_of_check_is_stalemate() {
+        let stuck = board("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(stuck.is_stalemate(false));
+        assert!(!stuck.is_checkmate(false));
+        assert!(!stuck.is_stalemate(true));
+    }
+
+    #[test]
+    fn pinned_piece_cant_leave_the_pin() {
+        let pinned = board("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1");
+        assert!(pinned.legal_moves_from(Coords::OnBoard(4, 6)).is_empty());
+    }
+
+    #[test]
+    fn pinned_piece_can_move_along_the_pin() {
+        let pinned = board("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1");
+        let mut moves = pinned.legal_moves_from(Coords::OnBoard(4, 6));
+        moves.sort_by_key(|c| c.y());
+        assert_eq!(
+            moves,
+            (1..6).map(|y| Coords::OnBoard(4, y)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn king_can_castle_out_of_legal_moves() {
+        let start = board("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        assert!(start
+            .legal_moves_from(Coords::OnBoard(4, 7))
+            .contains(&Coords::OnBoard(2, 7)));
     }
 }
@@ -1,4 +1,4 @@
-use std::ops::{Index, IndexMut};
+use std::ops::Index;
 use epac_utils::either::Either;
 use crate::prelude::{ChessPiece, Coords};
 use super::board::{Board, CanMovePiece, NeedsMoveUpdate};
@@ -17,7 +17,7 @@ macro_rules! method_on_original_ref {
     ($func_name:ident $func_return:ty => $($arg_name:ident $arg_type:ty),*) => {
         impl BoardContainer {
             #[must_use]
-            pub fn $func_name (&self, $($arg_name: $arg_type)*) -> $func_return {
+            pub fn $func_name (&self, $($arg_name: $arg_type),*) -> $func_return {
                 match self {
                     Self::Left(l) => l.$func_name($($arg_name,)*),
                     Self::Right(l) => l.$func_name($($arg_name),*),
@@ -30,7 +30,7 @@ macro_rules! method_on_original_ref {
 macro_rules! method_on_original_mut_ref {
     ($func_name:ident $func_return:ty => $($arg_name:ident $arg_type:ty),*) => {
         impl BoardContainer {
-            pub fn $func_name (&mut self, $($arg_name: $arg_type)*) -> $func_return {
+            pub fn $func_name (&mut self, $($arg_name: $arg_type),*) -> $func_return {
                 match self {
                     Self::Left(l) => l.$func_name($($arg_name,)*),
                     Self::Right(l) => l.$func_name($($arg_name),*),
@@ -41,6 +41,12 @@ macro_rules! method_on_original_mut_ref {
 }
 
 method_on_original_ref!(piece_exists_at_location bool => coords Coords);
+method_on_original_ref!(threatened_pieces Vec<Coords> => is_white bool);
+method_on_original_ref!(legal_moves_from Vec<Coords> => from Coords);
+method_on_original_ref!(is_promotion bool => from Coords, to Coords);
+method_on_original_ref!(evaluate i32 => );
+method_on_original_ref!(to_fen String => );
+method_on_original_ref!(taken &[ChessPiece] => );
 method_on_original_mut_ref!(get_taken Vec<ChessPiece> => );
 
 impl Index<Coords> for BoardContainer {
@@ -53,12 +59,3 @@ impl Index<Coords> for BoardContainer {
         }
     }
 }
-
-impl IndexMut<Coords> for BoardContainer {
-    fn index_mut(&mut self, index: Coords) -> &mut Self::Output {
-        match self {
-            Either::Left(b) => b.index_mut(index),
-            Either::Right(b) => b.index_mut(index),
-        }
-    }
-}
@@ -2,3 +2,5 @@
 pub mod board;
 ///Module to hold a container for the board for ease of use and utility functions
 pub mod board_container;
+///Module to lay out text as pieces with [`pattern::text_board`], for the [`pattern::OfflineBoard`]s
+pub mod pattern;
@@ -0,0 +1,142 @@
+use crate::{
+    chess::boards::board::{Board, CanMovePiece},
+    net::server_interface::{JSONPiece, JSONPieceList},
+    prelude::ChessPieceKind,
+};
+use anyhow::Context;
+use epac_utils::error_ext::ErrorExt;
+use strum::IntoEnumIterator;
+
+///Width and height of each glyph in [`glyph`]
+const GLYPH_SIZE: i32 = 3;
+///Top-left corners of the places glyphs go on the board - two lines of two glyphs
+const GLYPH_ORIGINS: [(i32, i32); 4] = [(0, 0), (5, 0), (0, 5), (5, 5)];
+
+///Gets the rows of a glyph from the font, top to bottom, with the leftmost square as the highest bit.
+///
+/// Characters without a glyph are treated as spaces.
+const fn glyph(c: char) -> [u8; 3] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b111, 0b101],
+        'B' => [0b110, 0b111, 0b111],
+        'C' => [0b111, 0b100, 0b111],
+        'D' => [0b110, 0b101, 0b110],
+        'E' => [0b111, 0b110, 0b111],
+        'F' => [0b111, 0b110, 0b100],
+        'G' => [0b110, 0b101, 0b111],
+        'H' => [0b101, 0b111, 0b101],
+        'I' => [0b111, 0b010, 0b111],
+        'J' => [0b001, 0b101, 0b111],
+        'K' => [0b101, 0b110, 0b101],
+        'L' => [0b100, 0b100, 0b111],
+        'M' => [0b111, 0b111, 0b101],
+        'N' => [0b110, 0b101, 0b101],
+        'O' | '0' => [0b111, 0b101, 0b111],
+        'P' => [0b111, 0b111, 0b100],
+        'Q' | '9' => [0b111, 0b111, 0b001],
+        'R' => [0b111, 0b100, 0b100],
+        'S' | '5' => [0b011, 0b010, 0b110],
+        'T' => [0b111, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b010],
+        'W' => [0b101, 0b111, 0b111],
+        'X' => [0b101, 0b010, 0b101],
+        'Y' => [0b101, 0b010, 0b010],
+        'Z' | '2' => [0b110, 0b010, 0b011],
+        '1' => [0b110, 0b010, 0b111],
+        '3' => [0b111, 0b011, 0b111],
+        '4' => [0b101, 0b111, 0b001],
+        '6' => [0b100, 0b111, 0b111],
+        '7' => [0b111, 0b001, 0b001],
+        '8' => [0b011, 0b111, 0b110],
+        _ => [0; 3],
+    }
+}
+
+///Lays out `text` as rooks on the board, from the font in [`glyph`].
+///
+/// The board fits two lines of two characters, so only the first four characters are shown. All of the other pieces are put in the taken list.
+#[must_use]
+pub fn text_pieces(text: &str) -> JSONPieceList {
+    let mut list = vec![];
+    for (c, (ox, oy)) in text.chars().zip(GLYPH_ORIGINS) {
+        for (dy, row) in (0..GLYPH_SIZE).zip(glyph(c)) {
+            for dx in 0..GLYPH_SIZE {
+                if row & (1 << (GLYPH_SIZE - 1 - dx)) != 0 {
+                    let (x, y) = (ox + dx, oy + dy);
+                    list.push(JSONPiece {
+                        x,
+                        y,
+                        is_white: (x + y) % 2 == 1, //why not
+                        kind: "rook".into(),
+                    });
+                }
+            }
+        }
+    }
+
+    for _ in 0..2 {
+        for kind in ChessPieceKind::iter() {
+            for is_white in [false, true] {
+                list.push(JSONPiece {
+                    x: -1,
+                    y: -1,
+                    kind: kind.to_string(),
+                    is_white,
+                });
+            }
+        }
+    }
+
+    JSONPieceList(list)
+}
+
+///Creates a board which shows `text`, laid out by [`text_pieces`]
+///
+/// # Panics
+/// Shouldn't, as the font only uses squares on the board
+#[must_use]
+pub fn text_board(text: &str) -> Board<CanMovePiece> {
+    Board::new_json(text_pieces(text))
+        .with_context(|| format!("turning text {text:?} to board"))
+        .unwrap_log_error()
+}
+
+///Why the board from the server can't be shown, each of which has its own board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfflineBoard {
+    ///The server couldn't be reached
+    NoConnection,
+    ///The server is down for maintenance
+    Maintenance,
+    ///The server sent an error, with the status code if there was one
+    Error(Option<u16>),
+}
+
+impl OfflineBoard {
+    ///Picks the board for an error status code from the server
+    #[must_use]
+    pub const fn from_status(status: u16) -> Self {
+        match status {
+            503 => Self::Maintenance,
+            s => Self::Error(Some(s)),
+        }
+    }
+
+    ///The text shown on the board
+    #[must_use]
+    pub fn text(self) -> String {
+        match self {
+            Self::NoConnection => "UHOH".into(),
+            Self::Maintenance => "WAIT".into(),
+            Self::Error(Some(status)) => format!("E{status}"),
+            Self::Error(None) => "ERR".into(),
+        }
+    }
+
+    ///Creates the board, using [`text_board`]
+    #[must_use]
+    pub fn board(self) -> Board<CanMovePiece> {
+        text_board(&self.text())
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    chess::boards::board::{Board, BoardMoveState},
+    prelude::{ChessPiece, ChessPieceKind, Coords},
+};
+
+///The file the kings start on
+const KING_FILE: u8 = 4;
+///The files the rooks start on, queen side then king side
+const ROOK_FILES: [u8; 2] = [0, 7];
+
+///Gets the rank a colour's pieces start on - white starts at the bottom
+#[must_use]
+pub const fn home_rank(white: bool) -> u8 {
+    if white {
+        7
+    } else {
+        0
+    }
+}
+
+///Which castling moves each colour could still make, going by whether their king and rooks have moved.
+///
+/// The server only sends where the pieces are, so a board from it starts with the rights its placement allows - see [`CastlingRights::from_placement`]. They are then narrowed as moves are made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CastlingRights {
+    ///Whether each castle is still allowed, indexed by [`CastlingRights::index`]
+    rights: [bool; 4],
+}
+
+impl CastlingRights {
+    ///Gets where a castle is kept in [`CastlingRights::rights`]
+    const fn index(white: bool, king_side: bool) -> usize {
+        (if white { 0 } else { 2 }) + if king_side { 1 } else { 0 }
+    }
+
+    ///Gets the rights allowed by where the pieces are - a castle is allowed if the king and that rook are on their starting squares
+    #[must_use]
+    pub fn from_placement<S: BoardMoveState>(board: &Board<S>) -> Self {
+        let mut rights = [false; 4];
+        for white in [true, false] {
+            let rank = home_rank(white);
+            let is = |x, kind| {
+                board[Coords::OnBoard(x, rank)]
+                    == Some(ChessPiece {
+                        kind,
+                        is_white: white,
+                    })
+            };
+            if !is(KING_FILE, ChessPieceKind::King) {
+                continue;
+            }
+            for king_side in [false, true] {
+                rights[Self::index(white, king_side)] =
+                    is(ROOK_FILES[usize::from(king_side)], ChessPieceKind::Rook);
+            }
+        }
+        Self { rights }
+    }
+
+    ///Reads the castling field of Forsyth-Edwards Notation, eg. `KQkq`, or `-` for none
+    #[must_use]
+    pub fn from_fen_field(field: &str) -> Self {
+        let mut rights = [false; 4];
+        for (c, white, king_side) in [
+            ('K', true, true),
+            ('Q', true, false),
+            ('k', false, true),
+            ('q', false, false),
+        ] {
+            rights[Self::index(white, king_side)] = field.contains(c);
+        }
+        Self { rights }
+    }
+
+    ///Gets whether a castle is still allowed
+    #[must_use]
+    pub const fn can_castle(&self, white: bool, king_side: bool) -> bool {
+        self.rights[Self::index(white, king_side)]
+    }
+
+    ///Gets the rights allowed by both `self` and `other`
+    #[must_use]
+    pub fn and(self, other: Self) -> Self {
+        let mut rights = self.rights;
+        for (right, other) in rights.iter_mut().zip(other.rights) {
+            *right &= other;
+        }
+        Self { rights }
+    }
+
+    ///Takes away the rights lost by a move - moving a king loses both of its castles, and moving a rook from (or taking one on) its starting square loses that one
+    pub fn update_for_move(&mut self, from: Coords, to: Coords) {
+        for white in [true, false] {
+            let rank = home_rank(white);
+            for king_side in [false, true] {
+                let touched = [KING_FILE, ROOK_FILES[usize::from(king_side)]]
+                    .into_iter()
+                    .map(|x| Coords::OnBoard(x, rank))
+                    .any(|c| c == from || c == to);
+                if touched {
+                    self.rights[Self::index(white, king_side)] = false;
+                }
+            }
+        }
+    }
+}
+
+///Gets whether a move is a castle - a king moving two squares from its starting square - and if so which side, with `Some(true)` for king side
+#[must_use]
+pub fn castle_side(piece: ChessPiece, from: Coords, to: Coords) -> Option<bool> {
+    if piece.kind != ChessPieceKind::King {
+        return None;
+    }
+    [true, false]
+        .into_iter()
+        .find(|king_side| king_move(piece.is_white, *king_side) == (from, to))
+}
+
+///Gets the move the rook makes for a castle, as `(from, to)` - it jumps to the square the king passed over
+#[must_use]
+pub fn rook_move(white: bool, king_side: bool) -> (Coords, Coords) {
+    let rank = home_rank(white);
+    let (from, to) = if king_side { (7, 5) } else { (0, 3) };
+    (Coords::OnBoard(from, rank), Coords::OnBoard(to, rank))
+}
+
+///Gets the move the king makes for a castle, as `(from, to)`
+#[must_use]
+pub fn king_move(white: bool, king_side: bool) -> (Coords, Coords) {
+    let rank = home_rank(white);
+    let to = if king_side { 6 } else { 2 };
+    (Coords::OnBoard(KING_FILE, rank), Coords::OnBoard(to, rank))
+}
+
+///Gets the squares between the king and the rook for a castle, which have to be empty
+#[must_use]
+pub fn squares_between(white: bool, king_side: bool) -> Vec<Coords> {
+    let rank = home_rank(white);
+    let files = if king_side { 5..7 } else { 1..4 };
+    files.map(|x| Coords::OnBoard(x, rank)).collect()
+}
+
+///Gets the squares the king starts on, passes over and lands on for a castle, none of which can be attacked
+#[must_use]
+pub fn king_path(white: bool, king_side: bool) -> [Coords; 3] {
+    let rank = home_rank(white);
+    let files = if king_side { [4, 5, 6] } else { [4, 3, 2] };
+    files.map(|x| Coords::OnBoard(x, rank))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::boards::board::CanMovePiece;
+
+    ///Kings and rooks on their starting squares, with nothing between them
+    const CASTLES: &str = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+    ///Gets the board from `fen`, panicking if it isn't valid
+    fn board(fen: &str) -> Board<CanMovePiece> {
+        Board::from_fen(fen).unwrap()
+    }
+
+    ///Gets a square from its name, eg. `e1`
+    fn sq(name: &str) -> Coords {
+        Coords::from_algebraic(name).unwrap()
+    }
+
+    ///Gets which castles are allowed, as `[white queen side, white king side, black queen side, black king side]`
+    fn allowed(rights: CastlingRights) -> [bool; 4] {
+        [(true, false), (true, true), (false, false), (false, true)]
+            .map(|(white, king_side)| rights.can_castle(white, king_side))
+    }
+
+    #[test]
+    fn rights_come_from_the_placement() {
+        assert_eq!(allowed(board(CASTLES).castling()), [true; 4]);
+        assert_eq!(
+            allowed(board("4k2r/8/8/8/8/8/8/R3K3 w KQkq - 0 1").castling()),
+            [true, false, false, true]
+        );
+        assert_eq!(
+            allowed(board("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1").castling()),
+            [false, true, true, false]
+        );
+    }
+
+    #[test]
+    fn king_move_loses_both_castles() {
+        let mut rights = board(CASTLES).castling();
+        rights.update_for_move(sq("e1"), sq("e2"));
+        assert_eq!(allowed(rights), [false, false, true, true]);
+
+        rights.update_for_move(sq("e8"), sq("d8"));
+        assert_eq!(allowed(rights), [false; 4]);
+    }
+
+    #[test]
+    fn rook_move_loses_its_castle() {
+        let mut rights = board(CASTLES).castling();
+        rights.update_for_move(sq("a1"), sq("a4"));
+        assert_eq!(allowed(rights), [false, true, true, true]);
+
+        rights.update_for_move(sq("h8"), sq("h5"));
+        assert_eq!(allowed(rights), [false, true, true, false]);
+    }
+
+    #[test]
+    fn taking_a_rook_loses_its_castle() {
+        let mut rights = board(CASTLES).castling();
+        rights.update_for_move(sq("a1"), sq("a8"));
+        assert_eq!(allowed(rights), [false, true, false, true]);
+
+        //the board takes the rights away too
+        let after = board(CASTLES).with_move(sq("h1"), sq("h8"));
+        assert_eq!(allowed(after.castling()), [true, false, true, false]);
+    }
+
+    #[test]
+    fn rooks_jump_over_the_king() {
+        assert_eq!(rook_move(true, true), (sq("h1"), sq("f1")));
+        assert_eq!(rook_move(true, false), (sq("a1"), sq("d1")));
+        assert_eq!(rook_move(false, true), (sq("h8"), sq("f8")));
+        assert_eq!(rook_move(false, false), (sq("a8"), sq("d8")));
+    }
+
+    #[test]
+    fn castling_moves_the_rook_and_loses_both_castles() {
+        let rook = |white| {
+            Some(ChessPiece {
+                kind: ChessPieceKind::Rook,
+                is_white: white,
+            })
+        };
+
+        let short = board(CASTLES).with_move(sq("e1"), sq("g1"));
+        assert_eq!(
+            castle_side(short[sq("g1")].unwrap(), sq("e1"), sq("g1")),
+            Some(true)
+        );
+        assert_eq!((short[sq("f1")], short[sq("h1")]), (rook(true), None));
+        assert_eq!(allowed(short.castling()), [false, false, true, true]);
+
+        let long = board(CASTLES).with_move(sq("e8"), sq("c8"));
+        assert_eq!((long[sq("d8")], long[sq("a8")]), (rook(false), None));
+        assert_eq!(allowed(long.castling()), [true, true, false, false]);
+    }
+}
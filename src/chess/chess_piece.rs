@@ -1,10 +1,14 @@
 use crate::{prelude::SError, util::error_ext::ToAnyhowNotErr};
 use std::fmt::{Debug, Formatter};
 use epac_utils::error_ext::ToAnyhowNotErr;
+use serde::{Deserialize, Serialize};
 use strum::{Display, EnumIter, IntoEnumIterator};
 
 ///Enum with all of the chess piece kinds
-#[derive(EnumIter, Display, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(
+    EnumIter, Display, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 #[repr(u8)]
 pub enum ChessPieceKind {
     ///Bishop Piece - move on diagonals
@@ -21,6 +25,39 @@ pub enum ChessPieceKind {
     Rook = 3,
 }
 
+impl ChessPieceKind {
+    ///Gets the usual point value of the piece kind - the king is worth nothing as it can't be taken
+    #[must_use]
+    pub const fn value(self) -> u32 {
+        match self {
+            Self::Pawn => 1,
+            Self::Knight | Self::Bishop => 3,
+            Self::Rook => 5,
+            Self::Queen => 9,
+            Self::King => 0,
+        }
+    }
+
+    ///Gets the letter used for the piece kind in algebraic notation - `P` for pawns
+    #[must_use]
+    pub const fn letter(self) -> char {
+        match self {
+            Self::Pawn => 'P',
+            Self::Knight => 'N',
+            Self::Bishop => 'B',
+            Self::Rook => 'R',
+            Self::Queen => 'Q',
+            Self::King => 'K',
+        }
+    }
+
+    ///Gets the piece kind for a letter in algebraic notation, in either case - the opposite of [`ChessPieceKind::letter`]
+    #[must_use]
+    pub fn from_letter(letter: char) -> Option<Self> {
+        Self::iter().find(|k| k.letter() == letter.to_ascii_uppercase())
+    }
+}
+
 ///Enum to hold errors for chess piece kinds
 #[derive(Debug, Display)]
 pub enum ChessPieceKindParseError {
@@ -75,15 +112,34 @@ impl ChessPiece {
         v
     }
 
-    ///Converts a [`ChessPiece`] to a file name
+    ///Gets a human-readable description of the piece, eg. `Black knight`
     #[must_use]
-    pub fn to_file_name(self) -> String {
+    pub fn description(self) -> String {
         format!(
-            "{}_{}.png",
-            if self.is_white { "white" } else { "black" },
+            "{} {}",
+            if self.is_white { "White" } else { "Black" },
             self.kind.to_string().to_lowercase()
         )
     }
+
+    ///Converts a [`ChessPiece`] to a file name, eg. `white_pawn.png`. The names are fixed, so nothing is allocated when looking up sprites every frame.
+    #[must_use]
+    pub const fn to_file_name(self) -> &'static str {
+        match (self.is_white, self.kind) {
+            (true, ChessPieceKind::Pawn) => "white_pawn.png",
+            (true, ChessPieceKind::Knight) => "white_knight.png",
+            (true, ChessPieceKind::Bishop) => "white_bishop.png",
+            (true, ChessPieceKind::Rook) => "white_rook.png",
+            (true, ChessPieceKind::Queen) => "white_queen.png",
+            (true, ChessPieceKind::King) => "white_king.png",
+            (false, ChessPieceKind::Pawn) => "black_pawn.png",
+            (false, ChessPieceKind::Knight) => "black_knight.png",
+            (false, ChessPieceKind::Bishop) => "black_bishop.png",
+            (false, ChessPieceKind::Rook) => "black_rook.png",
+            (false, ChessPieceKind::Queen) => "black_queen.png",
+            (false, ChessPieceKind::King) => "black_king.png",
+        }
+    }
 }
 
 impl Debug for ChessPiece {
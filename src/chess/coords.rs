@@ -1,3 +1,4 @@
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::Debug;
 
 ///Utility type to hold a set of [`u8`] coordinates in an `(x, y)` format. Can also represent a piece which was taken.
@@ -25,6 +26,23 @@ impl Debug for Coords {
     }
 }
 
+///Coordinates are stored as the algebraic name of the square, eg. `"e4"`, or `null` when off the board
+impl Serialize for Coords {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_algebraic().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Coords {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            None => Ok(Self::OffBoard),
+            Some(s) => Self::from_algebraic(&s)
+                .ok_or_else(|| D::Error::custom(format!("invalid square {s:?}"))),
+        }
+    }
+}
+
 impl TryFrom<(i32, i32)> for Coords {
     type Error = anyhow::Error;
 
@@ -117,4 +135,26 @@ impl Coords {
     pub fn is_on_board(&self) -> bool {
         matches!(self, Coords::OnBoard(_, _))
     }
+
+    ///Gets the algebraic name of the square, eg. `e4`. White starts at the bottom, so `y = 0` is rank 8.
+    ///
+    /// Returns `None` if it is off the board
+    #[must_use]
+    pub fn to_algebraic(&self) -> Option<String> {
+        self.to_option()
+            .map(|(x, y)| format!("{}{}", char::from(b'a' + x), 8 - y))
+    }
+
+    ///Parses the algebraic name of a square, eg. `e4` - the opposite of [`Coords::to_algebraic`].
+    ///
+    /// Returns `None` if it isn't a square on the board
+    #[must_use]
+    pub fn from_algebraic(s: &str) -> Option<Self> {
+        let &[file, rank] = s.as_bytes() else {
+            return None;
+        };
+        let x = file.checked_sub(b'a').filter(|x| *x < 8)?;
+        let rank = rank.checked_sub(b'1').filter(|r| *r < 8)?;
+        Some(Coords::OnBoard(x, 7 - rank))
+    }
 }
@@ -0,0 +1,237 @@
+use crate::{
+    chess::{
+        analysis::suggest_move,
+        boards::board::{Board, BoardMoveState},
+    },
+    prelude::{Coords, Result},
+    util::lru_cache::{CacheGauges, LruCache},
+};
+use anyhow::Context;
+use epac_utils::error_ext::{MutexExt, ToAnyhowNotErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Lines, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+///Number of results held in the [`eval_cache`]
+pub const EVAL_CACHE_ENTRIES: usize = 1 << 16;
+///Gauges the [`eval_cache`] publishes to
+pub const EVAL_CACHE_GAUGES: CacheGauges = CacheGauges {
+    hits: "cache.eval_hits",
+    misses: "cache.eval_misses",
+    entries: "memory.eval_cache_entries",
+};
+
+///A result stored in an [`EvalCache`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedEval {
+    ///The [`crate::chess::zobrist::zobrist_hash`] of the position
+    pub hash: u64,
+    ///How many moves ahead were looked at - `0` for an evaluation of the position as it is
+    pub depth: u8,
+    ///The score, in centipawns from white's point of view
+    pub score: i32,
+    ///The best move found, as `(from, to)`, if moves were looked at
+    pub best: Option<(Coords, Coords)>,
+}
+
+///Cache of evaluations and search results keyed by Zobrist hash, so that positions which come up again - through a different move order, or in a different feature - aren't evaluated again. Shared by the whole program - see [`eval_cache`].
+///
+/// Results are kept for each depth separately, so a deeper search doesn't throw away the evaluations it was built from. When the cache is full, the least recently used result is replaced.
+#[derive(Debug)]
+pub struct EvalCache {
+    ///The results, keyed by hash and depth
+    entries: Mutex<LruCache<(u64, u8), CachedEval>>,
+}
+
+///Gets the [`EvalCache`] shared by the whole program
+pub fn eval_cache() -> &'static EvalCache {
+    ///The cache, made on first use
+    static CACHE: OnceLock<EvalCache> = OnceLock::new();
+    CACHE.get_or_init(|| EvalCache::new(EVAL_CACHE_ENTRIES))
+}
+
+impl EvalCache {
+    ///Creates a new, empty `EvalCache` holding at most a number of results - at least one
+    #[must_use]
+    pub fn new(entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(entries, EVAL_CACHE_GAUGES)),
+        }
+    }
+
+    ///Gets the result for a position, if one was stored which looked exactly `depth` moves ahead
+    #[must_use]
+    pub fn get(&self, hash: u64, depth: u8) -> Option<CachedEval> {
+        self.entries
+            .lock_panic("eval cache")
+            .get(&(hash, depth))
+            .copied()
+    }
+
+    ///Stores a result, replacing any from the same position and depth
+    pub fn insert(&self, eval: CachedEval) {
+        self.entries
+            .lock_panic("eval cache")
+            .insert((eval.hash, eval.depth), eval);
+    }
+
+    ///Gets the number of results held
+    #[must_use]
+    pub fn filled(&self) -> usize {
+        self.entries.lock_panic("eval cache").len()
+    }
+
+    ///Publishes the hits, misses and number of results held to the [`crate::metrics::metrics`] registry - see [`EVAL_CACHE_GAUGES`]
+    pub fn publish_metrics(&self) {
+        self.entries.lock_panic("eval cache").publish_metrics();
+    }
+}
+
+///The engine which suggests moves for hints
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HintEngine {
+    ///The built-in [`suggest_move`], which only looks one reply ahead
+    #[default]
+    BuiltIn,
+    ///An external engine which speaks the Universal Chess Interface, like Stockfish
+    Uci {
+        ///The path of the engine executable
+        path: PathBuf,
+        ///How long the engine gets to think, in milliseconds
+        movetime_ms: u64,
+    },
+}
+
+impl HintEngine {
+    ///Asks the engine for a move for one colour, as `(from, to)`. Returns `None` if the engine doesn't have one.
+    ///
+    /// A UCI engine is started for each request, so this blocks for at least the thinking time - call it off the main thread.
+    ///
+    /// # Errors
+    /// - The UCI engine can't be started, or stops before suggesting a move
+    /// - The UCI engine suggests a move which can't be parsed
+    #[tracing::instrument(skip(board))]
+    pub fn suggest<S: BoardMoveState>(
+        &self,
+        board: &Board<S>,
+        white: bool,
+    ) -> Result<Option<(Coords, Coords)>> {
+        match self {
+            Self::BuiltIn => Ok(suggest_move(board, white)),
+            Self::Uci { path, movetime_ms } => uci_best_move(
+                path,
+                &board.to_fen_with_turn(white),
+                Duration::from_millis(*movetime_ms),
+            ),
+        }
+    }
+}
+
+///Starts a UCI engine, and asks it for the best move in a position.
+///
+/// The engine is killed afterwards, whether or not it answered.
+///
+/// # Errors
+/// - The engine can't be started
+/// - The engine stops before answering
+/// - The answer can't be parsed
+pub fn uci_best_move(
+    path: &Path,
+    fen: &str,
+    movetime: Duration,
+) -> Result<Option<(Coords, Coords)>> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("starting engine {path:?}"))?;
+
+    let best = ask_best_move(&mut child, fen, movetime);
+    //it may have already quit, which is fine
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let best = best?;
+    debug!(%best, "Engine answered");
+    parse_best_move(&best)
+}
+
+///Talks to a started UCI engine - waits for it to be ready, gives it the position, and returns the `bestmove` line it answers with
+///
+/// # Errors
+/// - Fail to write to or read from the engine
+/// - The engine stops before answering
+fn ask_best_move(child: &mut Child, fen: &str, movetime: Duration) -> Result<String> {
+    let mut stdin = child.stdin.take().ae().context("getting engine stdin")?;
+    let mut lines =
+        BufReader::new(child.stdout.take().ae().context("getting engine stdout")?).lines();
+
+    writeln!(stdin, "uci").context("sending uci")?;
+    wait_for(&mut lines, |l| l == "uciok").context("waiting for uciok")?;
+
+    writeln!(stdin, "position fen {fen}").context("sending position")?;
+    writeln!(stdin, "go movetime {}", movetime.as_millis()).context("sending go")?;
+    let best =
+        wait_for(&mut lines, |l| l.starts_with("bestmove")).context("waiting for bestmove")?;
+
+    let _ = writeln!(stdin, "quit");
+    Ok(best)
+}
+
+///Reads lines from the engine until one matches, and returns that line
+///
+/// # Errors
+/// - Fail to read a line
+/// - The engine stops before a line matches
+fn wait_for(
+    lines: &mut Lines<BufReader<ChildStdout>>,
+    matches: impl Fn(&str) -> bool,
+) -> Result<String> {
+    for line in lines {
+        let line = line.context("reading from engine")?;
+        if matches(line.trim()) {
+            return Ok(line.trim().to_string());
+        }
+    }
+    bail!("engine stopped")
+}
+
+///Parses a `bestmove` line, eg. `bestmove e2e4 ponder e7e5`. Promotions are ignored, as pawns always become queens.
+///
+/// Returns `None` if the engine has no move, which it says with `(none)` or `0000`
+///
+/// # Errors
+/// - There is no move in the line
+/// - The move isn't made of two squares
+fn parse_best_move(line: &str) -> Result<Option<(Coords, Coords)>> {
+    let m = line
+        .split_whitespace()
+        .nth(1)
+        .ae()
+        .with_context(|| format!("no move in {line:?}"))?;
+    if m == "(none)" || m == "0000" {
+        return Ok(None);
+    }
+    parse_uci_move(m).map(Some)
+}
+
+///Parses a move in the UCI long algebraic notation, eg. `e2e4` or `e7e8q`, as `(from, to)`. Promotions are ignored, as pawns always become queens.
+///
+/// # Errors
+/// - The move isn't made of two squares
+pub fn parse_uci_move(m: &str) -> Result<(Coords, Coords)> {
+    let square = |s: Option<&str>| s.and_then(Coords::from_algebraic);
+    let from = square(m.get(0..2))
+        .ae()
+        .with_context(|| format!("invalid move {m:?}"))?;
+    let to = square(m.get(2..4))
+        .ae()
+        .with_context(|| format!("invalid move {m:?}"))?;
+    Ok((from, to))
+}
@@ -0,0 +1,832 @@
+use crate::{
+    chess::{
+        attacks::pseudo_legal_moves,
+        boards::board::{Board, BoardMoveState, CanMovePiece},
+        san::{parse_san, to_san},
+    },
+    prelude::{Coords, Result},
+};
+use anyhow::Context;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use strum::{EnumIter, IntoEnumIterator};
+
+///The standard starting position, in Forsyth-Edwards Notation
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1";
+
+///A symbol annotating how good a move was, as used in PGN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum MoveGlyph {
+    ///`!!`
+    Brilliant,
+    ///`!`
+    Good,
+    ///`!?`
+    Interesting,
+    ///`?!`
+    Dubious,
+    ///`?`
+    Mistake,
+    ///`??`
+    Blunder,
+}
+
+impl MoveGlyph {
+    ///Gets the symbol written after the move, eg. `!?`
+    #[must_use]
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::Brilliant => "!!",
+            Self::Good => "!",
+            Self::Interesting => "!?",
+            Self::Dubious => "?!",
+            Self::Mistake => "?",
+            Self::Blunder => "??",
+        }
+    }
+
+    ///Gets the Numeric Annotation Glyph which PGN uses for this symbol, eg. `$5` for `!?`
+    #[must_use]
+    pub const fn nag(self) -> u8 {
+        match self {
+            Self::Good => 1,
+            Self::Mistake => 2,
+            Self::Brilliant => 3,
+            Self::Blunder => 4,
+            Self::Interesting => 5,
+            Self::Dubious => 6,
+        }
+    }
+
+    ///Gets the glyph for a symbol, either written out like `!?` or as a Numeric Annotation Glyph like `$5`
+    #[must_use]
+    pub fn from_symbol(s: &str) -> Option<Self> {
+        match s.strip_prefix('$') {
+            Some(nag) => {
+                let nag = nag.parse::<u8>().ok()?;
+                Self::iter().find(|g| g.nag() == nag)
+            }
+            None => Self::iter().find(|g| g.symbol() == s),
+        }
+    }
+}
+
+///One move in a [`MoveHistory`], with anything the player has written about it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryMove {
+    ///The square the piece moved from
+    pub from: Coords,
+    ///The square the piece moved to
+    pub to: Coords,
+    ///How good the move was, if it has been annotated
+    #[serde(default)]
+    pub glyph: Option<MoveGlyph>,
+    ///A comment about the move, if one has been written
+    #[serde(default)]
+    pub comment: Option<String>,
+    ///Other moves which could have been made instead of this one, each followed by the rest of its line
+    #[serde(default)]
+    pub variations: Vec<Vec<HistoryMove>>,
+}
+
+impl HistoryMove {
+    ///Creates a move without any annotations or variations
+    #[must_use]
+    pub const fn new(from: Coords, to: Coords) -> Self {
+        Self {
+            from,
+            to,
+            glyph: None,
+            comment: None,
+            variations: vec![],
+        }
+    }
+}
+
+///Where a move is in a [`MoveHistory`] - the index of a move in the main line, followed by pairs of which variation of that move and which move in that variation.
+///
+/// Written counting from `1`, separated by dots - eg. `12.1.3` is the third move of the first variation of the twelfth move.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MovePath(pub Vec<usize>);
+
+impl fmt::Display for MovePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(|i| (i + 1).to_string()).collect();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+impl FromStr for MovePath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let indices = s
+            .split('.')
+            .map(|part| {
+                part.parse::<usize>()
+                    .ok()
+                    .and_then(|i| i.checked_sub(1))
+                    .ae()
+                    .with_context(|| format!("invalid move number {part:?}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if indices.len() % 2 == 0 {
+            bail!("expected a move, not a variation, in {s:?}");
+        }
+        Ok(Self(indices))
+    }
+}
+
+///A move in a [`MoveHistory`], written out for showing to the player - from [`MoveHistory::described_moves`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescribedMove {
+    ///Where the move is
+    pub path: MovePath,
+    ///How many variations deep the move is - `0` for the main line
+    pub depth: usize,
+    ///The move number, eg. `12.` for white or `12...` for black
+    pub number: String,
+    ///Whether white made the move
+    pub white: bool,
+    ///The move in Standard Algebraic Notation
+    pub san: String,
+    ///How good the move was, if it has been annotated
+    pub glyph: Option<MoveGlyph>,
+    ///A comment about the move, if one has been written
+    pub comment: Option<String>,
+}
+
+///The moves made in a game, from a starting position, along with their annotations and any variations.
+///
+/// Castling and en passant can't be made on the board, so they never appear in the history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveHistory {
+    ///The starting position, in Forsyth-Edwards Notation
+    start: String,
+    ///Whether white made the first move
+    white_first: bool,
+    ///The moves made, in order - the main line
+    moves: Vec<HistoryMove>,
+}
+
+impl MoveHistory {
+    ///Creates an empty history from a starting position
+    #[must_use]
+    pub fn new<S: BoardMoveState>(start: &Board<S>, white_first: bool) -> Self {
+        Self::from_moves(start, white_first, vec![])
+    }
+
+    ///Creates a history from a starting position and the main line, which can have variations
+    #[must_use]
+    pub fn from_moves<S: BoardMoveState>(
+        start: &Board<S>,
+        white_first: bool,
+        moves: Vec<HistoryMove>,
+    ) -> Self {
+        Self {
+            start: start.to_fen_with_turn(white_first),
+            white_first,
+            moves,
+        }
+    }
+
+    ///Gets the moves in the main line, in order
+    #[must_use]
+    pub fn moves(&self) -> &[HistoryMove] {
+        &self.moves
+    }
+
+    ///Gets whether white made the first move
+    #[must_use]
+    pub const fn white_first(&self) -> bool {
+        self.white_first
+    }
+
+    ///Gets whether white makes the next move in the main line
+    #[must_use]
+    pub fn white_to_move(&self) -> bool {
+        self.white_first == (self.moves.len() % 2 == 0)
+    }
+
+    ///Gets the starting position
+    ///
+    /// # Errors
+    /// - The starting position isn't valid - see [`Board::from_fen`]
+    pub fn start_board(&self) -> Result<Board<CanMovePiece>> {
+        Board::from_fen(&self.start)
+    }
+
+    ///Gets the position at the end of the main line
+    ///
+    /// # Errors
+    /// - The starting position isn't valid - see [`Board::from_fen`]
+    pub fn end_board(&self) -> Result<Board<CanMovePiece>> {
+        Ok(self.moves.iter().fold(self.start_board()?, |board, m| {
+            board.with_move(m.from, m.to)
+        }))
+    }
+
+    ///Adds a move to the end of the main line
+    pub fn push(&mut self, from: Coords, to: Coords) {
+        self.moves.push(HistoryMove::new(from, to));
+    }
+
+    ///Gets a move anywhere in the history
+    #[must_use]
+    pub fn get(&self, path: &MovePath) -> Option<&HistoryMove> {
+        let mut indices = path.0.iter();
+        let mut m = self.moves.get(*indices.next()?)?;
+        while let Some(variation) = indices.next() {
+            m = m.variations.get(*variation)?.get(*indices.next()?)?;
+        }
+        Some(m)
+    }
+
+    ///Gets a move anywhere in the history, to change it
+    #[must_use]
+    pub fn get_mut(&mut self, path: &MovePath) -> Option<&mut HistoryMove> {
+        let mut indices = path.0.iter();
+        let mut m = self.moves.get_mut(*indices.next()?)?;
+        while let Some(variation) = indices.next() {
+            m = m
+                .variations
+                .get_mut(*variation)?
+                .get_mut(*indices.next()?)?;
+        }
+        Some(m)
+    }
+
+    ///Sets the annotations on a move anywhere in the history. An empty comment removes the comment.
+    ///
+    /// # Errors
+    /// - There isn't a move at that path
+    pub fn annotate(
+        &mut self,
+        path: &MovePath,
+        glyph: Option<MoveGlyph>,
+        comment: Option<String>,
+    ) -> Result<()> {
+        let m = self
+            .get_mut(path)
+            .ae()
+            .with_context(|| format!("no move {path} in the history"))?;
+        m.glyph = glyph;
+        m.comment = comment.filter(|c| !c.trim().is_empty());
+        Ok(())
+    }
+
+    ///Gets a line of moves to change - the main line for an empty path, or otherwise the variation at the end of a path without its last move, eg. `[12, 0]` for the first variation of the thirteenth move
+    fn line_mut(&mut self, path: &[usize]) -> Option<&mut Vec<HistoryMove>> {
+        let mut line = &mut self.moves;
+        for pair in path.chunks(2) {
+            let &[m, variation] = pair else {
+                return None;
+            };
+            line = line.get_mut(m)?.variations.get_mut(variation)?;
+        }
+        Some(line)
+    }
+
+    ///Adds a line of moves as a variation of the move at `path` - played instead of it. Returns where the first move of the new variation is.
+    ///
+    /// # Errors
+    /// - There isn't a move at that path
+    /// - The line is empty
+    pub fn add_variation(&mut self, path: &MovePath, line: Vec<HistoryMove>) -> Result<MovePath> {
+        if line.is_empty() {
+            bail!("no moves in the variation");
+        }
+        let m = self
+            .get_mut(path)
+            .ae()
+            .with_context(|| format!("no move {path} in the history"))?;
+        m.variations.push(line);
+
+        let mut added = path.0.clone();
+        added.extend([m.variations.len() - 1, 0]);
+        Ok(MovePath(added))
+    }
+
+    ///Splits the path of a move in a variation into the path of the line the variation branches off from, the index of the move in that line which it replaces, and which variation it is
+    ///
+    /// # Errors
+    /// - The move isn't in a variation, or there isn't a move at that path
+    fn variation_of<'a>(&self, path: &'a MovePath) -> Result<(&'a [usize], usize, usize)> {
+        if self.get(path).is_none() {
+            bail!("no move {path} in the history");
+        }
+        match path.0.as_slice() {
+            [line @ .., m, variation, _] => Ok((line, *m, *variation)),
+            _ => bail!("move {path} isn't in a variation"),
+        }
+    }
+
+    ///Swaps the variation with the move at `path` in it and the line it branches off from - the variation's moves take the place of the ones they replaced, which become its first variation.
+    ///
+    /// # Errors
+    /// - The move isn't in a variation, or there isn't a move at that path
+    pub fn promote_variation(&mut self, path: &MovePath) -> Result<()> {
+        let (line, m, variation) = self.variation_of(path)?;
+        let line = self
+            .line_mut(line)
+            .ae()
+            .context("getting line to promote in")?;
+
+        let mut replaced = line.split_off(m);
+        let mut others = std::mem::take(&mut replaced[0].variations);
+        let mut promoted = others.remove(variation);
+        //still alternatives at the same point, so they stay next to the replaced line
+        let own = std::mem::take(&mut promoted[0].variations);
+        promoted[0].variations = std::iter::once(replaced).chain(others).chain(own).collect();
+        line.extend(promoted);
+        Ok(())
+    }
+
+    ///Removes the variation with the move at `path` in it, along with any variations inside it
+    ///
+    /// # Errors
+    /// - The move isn't in a variation, or there isn't a move at that path
+    pub fn delete_variation(&mut self, path: &MovePath) -> Result<()> {
+        let (line, m, variation) = self.variation_of(path)?;
+        let parent = self
+            .line_mut(line)
+            .and_then(|l| l.get_mut(m))
+            .ae()
+            .context("getting move the variation branches off")?;
+        parent.variations.remove(variation);
+        Ok(())
+    }
+
+    ///Copies the annotations and variations of the main line from another history of the same game, eg. one explored on an analysis board, for as long as the two main lines have the same moves
+    pub fn keep_variations(&mut self, other: &Self) {
+        for (m, o) in self.moves.iter_mut().zip(&other.moves) {
+            if (m.from, m.to) != (o.from, o.to) {
+                break;
+            }
+            m.glyph = o.glyph;
+            m.comment = o.comment.clone();
+            m.variations = o.variations.clone();
+        }
+    }
+
+    ///Writes out every move, with each move's variations straight after it as in PGN
+    ///
+    /// # Errors
+    /// - The starting position isn't valid
+    /// - A move starts from an empty square
+    pub fn described_moves(&self) -> Result<Vec<DescribedMove>> {
+        let mut described = vec![];
+        self.describe_line(&self.moves, self.start_board()?, 0, &[], &mut described)?;
+        Ok(described)
+    }
+
+    ///Writes out the moves in a line, which starts `ply` moves into the game, and then recursively their variations
+    ///
+    /// # Errors
+    /// - A move starts from an empty square
+    fn describe_line(
+        &self,
+        line: &[HistoryMove],
+        mut board: Board<CanMovePiece>,
+        ply: usize,
+        prefix: &[usize],
+        described: &mut Vec<DescribedMove>,
+    ) -> Result<()> {
+        for (i, m) in line.iter().enumerate() {
+            let mut path = prefix.to_vec();
+            path.push(i);
+            let white = self.white_first == ((ply + i) % 2 == 0);
+            let number = (ply + i + usize::from(!self.white_first)) / 2 + 1;
+
+            described.push(DescribedMove {
+                path: MovePath(path.clone()),
+                depth: prefix.len() / 2,
+                number: format!("{number}{}", if white { "." } else { "..." }),
+                white,
+                san: to_san(&board, (m.from, m.to))
+                    .with_context(|| format!("writing move {}", MovePath(path.clone())))?,
+                glyph: m.glyph,
+                comment: m.comment.clone(),
+            });
+
+            for (v, variation) in m.variations.iter().enumerate() {
+                let mut prefix = path.clone();
+                prefix.push(v);
+                self.describe_line(variation, board.clone(), ply + i, &prefix, described)?;
+            }
+
+            board = board.with_move(m.from, m.to);
+        }
+        Ok(())
+    }
+
+    ///Exports the history as a PGN game with the given tag pairs, eg. `("Event", "...")`, with variations in brackets. The `SetUp` and `FEN` tags are added if the game didn't start from the standard position.
+    ///
+    /// # Errors
+    /// - Fail to write the moves - see [`MoveHistory::described_moves`]
+    pub fn to_pgn(&self, tags: &[(&str, String)]) -> Result<String> {
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            pgn += &format!("[{name} \"{}\"]\n", escape_tag(value));
+        }
+        if self.start != STARTING_FEN {
+            pgn += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", self.start);
+        }
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        let mut needs_number = true;
+        let mut open = 0;
+        for m in self.described_moves()? {
+            //each variation starts with its first move, so close any which have finished
+            let starts_variation = m.depth > 0 && m.path.0.last() == Some(&0);
+            let close_to = if starts_variation {
+                m.depth - 1
+            } else {
+                m.depth
+            };
+            while open > close_to {
+                tokens.push(")".to_string());
+                open -= 1;
+                needs_number = true;
+            }
+            if starts_variation {
+                tokens.push("(".to_string());
+                open += 1;
+                needs_number = true;
+            }
+
+            if m.white || needs_number {
+                tokens.push(m.number);
+            }
+            tokens.push(m.san);
+            needs_number = false;
+
+            if let Some(glyph) = m.glyph {
+                tokens.push(format!("${}", glyph.nag()));
+            }
+            if let Some(comment) = &m.comment {
+                //braces end the comment, so they can't be inside it
+                tokens.push(format!("{{{}}}", comment.replace(['{', '}'], "")));
+                needs_number = true;
+            }
+        }
+        tokens.extend(std::iter::repeat(")".to_string()).take(open));
+        tokens.push("*".into());
+
+        for (i, token) in tokens.iter().enumerate() {
+            //variations are written like `(1... e5)`
+            if i > 0 && !pgn.ends_with('(') && token != ")" {
+                pgn.push(' ');
+            }
+            pgn += token;
+        }
+        pgn.push('\n');
+        Ok(pgn)
+    }
+
+    ///Imports the first game in a PGN file, including any variations
+    ///
+    /// # Errors
+    /// - The `FEN` tag isn't a valid position
+    /// - A move can't be made - see [`parse_san`]
+    /// - A variation doesn't follow a move, or the brackets don't match
+    pub fn from_pgn(pgn: &str) -> Result<Self> {
+        let mut start = STARTING_FEN.to_string();
+        let mut movetext = String::new();
+        for line in pgn.lines() {
+            let line = line.trim();
+            if let Some(tag) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if !movetext.trim().is_empty() {
+                    //the tags of the next game
+                    break;
+                }
+                if let Some(fen) = tag.strip_prefix("FEN ") {
+                    start = fen.trim().trim_matches('"').to_string();
+                }
+            } else if !line.starts_with('%') {
+                movetext += line;
+                movetext.push('\n');
+            }
+        }
+
+        let white_first = start.split_whitespace().nth(1) != Some("b");
+        let board = Board::from_fen(&start).context("reading starting position")?;
+        let start = board.to_fen_with_turn(white_first);
+        let mut lines = vec![PgnLine {
+            moves: vec![],
+            boards: vec![board],
+            ply: 0,
+        }];
+
+        for token in pgn_tokens(&movetext) {
+            let line = lines.last_mut().ae().context("no line to read into")?;
+            match token {
+                PgnToken::Comment(comment) => {
+                    if let Some(m) = line.moves.last_mut() {
+                        m.comment = Some(comment).filter(|c| !c.is_empty());
+                    }
+                }
+                PgnToken::Glyph(glyph) => {
+                    if let Some(m) = line.moves.last_mut() {
+                        m.glyph = Some(glyph);
+                    }
+                }
+                PgnToken::Move(san) => {
+                    let board = line.boards.last().ae().context("no board to move on")?;
+                    let white = white_first == ((line.ply + line.moves.len()) % 2 == 0);
+                    let (from, to) = parse_san(board, &san, white)
+                        .with_context(|| format!("reading move {san:?}"))?;
+                    let after = board.with_move(from, to);
+                    line.boards.push(after);
+                    line.moves.push(HistoryMove::new(from, to));
+                }
+                PgnToken::VariationStart => {
+                    //a variation replaces the last move, so starts from the position before it
+                    let Some(last) = line.moves.len().checked_sub(1) else {
+                        bail!("variation before any moves");
+                    };
+                    let variation = PgnLine {
+                        moves: vec![],
+                        boards: vec![line.boards[last].clone()],
+                        ply: line.ply + last,
+                    };
+                    lines.push(variation);
+                }
+                PgnToken::VariationEnd => {
+                    if lines.len() < 2 {
+                        bail!("variation closed without being opened");
+                    }
+                    close_variation(&mut lines)?;
+                }
+            }
+        }
+        //be lenient with variations left open at the end
+        while lines.len() > 1 {
+            close_variation(&mut lines)?;
+        }
+
+        let main = lines.pop().ae().context("no main line")?;
+        Ok(Self {
+            start,
+            white_first,
+            moves: main.moves,
+        })
+    }
+}
+
+///A line being read from PGN - the main line, or a variation
+struct PgnLine {
+    ///The moves read so far
+    moves: Vec<HistoryMove>,
+    ///The position before each of the moves, followed by the position after the last one
+    boards: Vec<Board<CanMovePiece>>,
+    ///Number of moves into the game the line starts
+    ply: usize,
+}
+
+///Takes the last line being read, and adds it to the variations of the last move in the line before it
+///
+/// # Errors
+/// - There isn't a line before it, or that line doesn't have any moves
+fn close_variation(lines: &mut Vec<PgnLine>) -> Result<()> {
+    let variation = lines.pop().ae().context("no variation to close")?;
+    let parent = lines
+        .last_mut()
+        .and_then(|l| l.moves.last_mut())
+        .ae()
+        .context("no move for the variation")?;
+    if !variation.moves.is_empty() {
+        parent.variations.push(variation.moves);
+    }
+    Ok(())
+}
+
+///Part of the moves in a PGN game
+enum PgnToken {
+    ///A move in Standard Algebraic Notation, without any check or annotation symbols
+    Move(String),
+    ///An annotation on the last move
+    Glyph(MoveGlyph),
+    ///A comment on the last move
+    Comment(String),
+    ///The start of a variation, replacing the last move
+    VariationStart,
+    ///The end of a variation
+    VariationEnd,
+}
+
+///Splits PGN movetext into moves, glyphs, comments and variations - skipping move numbers and results
+fn pgn_tokens(movetext: &str) -> Vec<PgnToken> {
+    let mut tokens = vec![];
+    let mut chars = movetext.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let comment = strip_commands(&comment)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                tokens.push(PgnToken::Comment(comment));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                tokens.push(PgnToken::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(PgnToken::VariationStart),
+            ')' => tokens.push(PgnToken::VariationEnd),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '{' | '}' | '(' | ')' | ';') {
+                        break;
+                    }
+                    word.push(*next);
+                    chars.next();
+                }
+                tokens.extend(word_tokens(&word));
+            }
+        }
+    }
+
+    tokens
+}
+
+///Removes commands embedded in a comment for other programs, like the clock times `[%clk 0:09:57]` which sites add after every move
+fn strip_commands(comment: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        stripped += &rest[..start];
+        rest = rest[start..]
+            .find(']')
+            .map_or("", |end| &rest[start + end + 1..]);
+    }
+    stripped + rest
+}
+
+///Turns one whitespace-separated word of movetext into tokens, eg. `12.Nf3!?` into a move and a glyph
+fn word_tokens(word: &str) -> Vec<PgnToken> {
+    if word.starts_with('$') {
+        return MoveGlyph::from_symbol(word)
+            .map(PgnToken::Glyph)
+            .into_iter()
+            .collect();
+    }
+    if matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*") {
+        return vec![];
+    }
+
+    //move numbers can be written right before the move, eg. `1.e4`
+    let word = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let san = word.trim_end_matches(['!', '?', '+', '#']);
+    if san.is_empty() {
+        return vec![];
+    }
+
+    let mut tokens = vec![PgnToken::Move(san.to_string())];
+    let symbol = word[san.len()..].trim_matches(['+', '#']);
+    if let Some(glyph) = MoveGlyph::from_symbol(symbol) {
+        tokens.push(PgnToken::Glyph(glyph));
+    }
+    tokens
+}
+
+///Escapes a PGN tag value, which is written inside quotes
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+///Works out which moves turned one board into another - either one move, or one move from each colour. Returns whether white moved first with the moves, or `None` if no moves explain the change, eg. when the game was restarted.
+///
+/// If the colour to move first is known, only moves from that colour are tried first.
+#[must_use]
+pub fn infer_moves<S: BoardMoveState, T: BoardMoveState>(
+    before: &Board<S>,
+    after: &Board<T>,
+    white_first: Option<bool>,
+) -> Option<(bool, Vec<(Coords, Coords)>)> {
+    let colours = match white_first {
+        Some(white) => vec![white],
+        None => vec![true, false],
+    };
+
+    for white in colours.iter().copied() {
+        for (from, to) in pseudo_legal_moves(before, white) {
+            if before.with_move(from, to).same_pieces(after) {
+                return Some((white, vec![(from, to)]));
+            }
+        }
+    }
+
+    for white in colours {
+        for first in pseudo_legal_moves(before, white) {
+            let middle = before.with_move(first.0, first.1);
+            for second in pseudo_legal_moves(&middle, !white) {
+                if middle.with_move(second.0, second.1).same_pieces(after) {
+                    return Some((white, vec![first, second]));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Gets the history from a PGN game, panicking if it isn't valid
+    fn history(pgn: &str) -> MoveHistory {
+        MoveHistory::from_pgn(pgn).unwrap()
+    }
+
+    ///Gets the movetext of a history, without any tags
+    fn movetext(history: &MoveHistory) -> String {
+        history.to_pgn(&[]).unwrap().trim().to_string()
+    }
+
+    ///Gets a move from the names of its squares, eg. `("e2", "e4")`
+    fn mv(from: &str, to: &str) -> HistoryMove {
+        HistoryMove::new(
+            Coords::from_algebraic(from).unwrap(),
+            Coords::from_algebraic(to).unwrap(),
+        )
+    }
+
+    #[test]
+    fn added_variation_replaces_the_move() {
+        let mut h = history("1. e4 e5 2. Nf3 *");
+        let added = h
+            .add_variation(&"2".parse().unwrap(), vec![mv("c7", "c5")])
+            .unwrap();
+
+        assert_eq!(added.to_string(), "2.1.1");
+        assert_eq!(movetext(&h), "1. e4 e5 (1... c5) 2. Nf3 *");
+    }
+
+    #[test]
+    fn empty_variation_isnt_added() {
+        let mut h = history("1. e4 e5 *");
+        assert!(h.add_variation(&"2".parse().unwrap(), vec![]).is_err());
+        assert!(h
+            .add_variation(&"3".parse().unwrap(), vec![mv("c7", "c5")])
+            .is_err());
+    }
+
+    #[test]
+    fn promoted_variation_swaps_with_the_main_line() {
+        let mut h = history("1. e4 e5 (1... c5 2. Nf3) 2. Nf3 *");
+        h.promote_variation(&"2.1.2".parse().unwrap()).unwrap();
+
+        assert_eq!(movetext(&h), "1. e4 c5 (1... e5 2. Nf3) 2. Nf3 *");
+    }
+
+    #[test]
+    fn promoted_variation_keeps_the_other_variations() {
+        let mut h = history("1. e4 e5 (1... c5) (1... e6) 2. Nf3 *");
+        h.promote_variation(&"2.2.1".parse().unwrap()).unwrap();
+
+        assert_eq!(movetext(&h), "1. e4 e6 (1... e5 2. Nf3) (1... c5) *");
+    }
+
+    #[test]
+    fn main_line_cant_be_promoted() {
+        let mut h = history("1. e4 e5 *");
+        assert!(h.promote_variation(&"2".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn deleted_variation_is_gone() {
+        let mut h = history("1. e4 e5 (1... c5 2. Nf3 (2. c3)) (1... e6) 2. Nf3 *");
+        h.delete_variation(&"2.1.2".parse().unwrap()).unwrap();
+
+        assert_eq!(movetext(&h), "1. e4 e5 (1... e6) 2. Nf3 *");
+        assert!(h.delete_variation(&"2.2.1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn pgn_round_trips() {
+        let pgn = "1. e4 $1 {best by test} 1... e5 (1... c5 2. Nf3 (2. c3) 2... d6) 2. Nf3 *";
+        let h = history(pgn);
+
+        assert_eq!(movetext(&h), pgn);
+        assert_eq!(history(&h.to_pgn(&[]).unwrap()), h);
+    }
+
+    #[test]
+    fn pgn_from_a_position_round_trips() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1";
+        let h = history(&format!("[FEN \"{fen}\"]\n\n1... Kd7 2. e4 *"));
+
+        assert!(!h.white_first());
+        let pgn = h.to_pgn(&[("Event", "Test".into())]).unwrap();
+        assert!(pgn.contains(&format!("[FEN \"{fen}\"]")), "{pgn}");
+        assert_eq!(history(&pgn), h);
+    }
+}
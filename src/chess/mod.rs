@@ -1,7 +1,25 @@
+///Module to evaluate positions locally
+pub mod analysis;
+///Module to work out which squares pieces attack
+pub mod attacks;
+///Module to track which castling moves are still allowed, and move the rook along with the king
+pub mod castling;
 ///Module to hold chess-related utils
 pub mod chess_piece;
 ///Module to hold coordinates
 pub mod coords;
+///Module to record the moves made in a game, with annotations, and read and write them as PGN
+pub mod history;
+///Module to ask an engine, built-in or UCI, for a suggested move, and to cache evaluations
+pub mod engine;
+///Module to read opening books in the Polyglot format
+pub mod opening_book;
+///Module to write and read moves in Standard Algebraic Notation, eg. `Nxf3+`
+pub mod san;
+///Module to probe Syzygy endgame tablebases, when built with the `syzygy` feature
+pub mod tablebase;
+///Module to hash positions, for the [`engine::EvalCache`]
+pub mod zobrist;
 
 ///Module to hold board-related modules
 pub mod boards;
@@ -0,0 +1,230 @@
+use crate::{
+    chess::boards::board::{Board, BoardMoveState},
+    prelude::{ChessPiece, ChessPieceKind, Coords, Result},
+};
+use anyhow::Context;
+use std::path::Path;
+
+///Number of keys in the Polyglot `Random64` array - 768 for pieces on squares, 4 for castling, 8 for en passant files and 1 for the turn
+const POLYGLOT_KEYS: usize = 781;
+///Index of the first castling key - white kingside, then white queenside, black kingside and black queenside
+const CASTLING_KEY: usize = 768;
+///Index of the key used when white is to move
+const TURN_KEY: usize = 780;
+///Size in bytes of one entry in a Polyglot book
+const ENTRY_SIZE: usize = 16;
+
+///The `Random64` array from the Polyglot book format specification, which Polyglot hashes are made from.
+///
+/// It isn't bundled, so it is read from a file of 781 hexadecimal numbers, in order, separated by whitespace or commas - eg. copied from the specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolyglotKeys(Vec<u64>);
+
+impl PolyglotKeys {
+    ///Reads the keys in from a file
+    ///
+    /// # Errors
+    /// - Fail to read in the file
+    /// - Any of the numbers aren't valid hexadecimal
+    /// - There aren't exactly [`POLYGLOT_KEYS`] numbers
+    pub fn load(path: &Path) -> Result<Self> {
+        let cntnts =
+            std::fs::read_to_string(path).with_context(|| format!("reading path {path:?}"))?;
+        let keys = cntnts
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let hex = s.trim_start_matches("0x").trim_end_matches("ULL");
+                u64::from_str_radix(hex, 16).with_context(|| format!("parsing key {s:?}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if keys.len() != POLYGLOT_KEYS {
+            bail!("expected {POLYGLOT_KEYS} keys, found {}", keys.len());
+        }
+        Ok(Self(keys))
+    }
+
+    ///Gets the Polyglot hash of a position, which is how book entries are looked up.
+    ///
+    /// The board doesn't track castling rights or en passant. Castling is counted as possible while the king and rook are on their starting squares, and en passant is always left out.
+    #[must_use]
+    pub fn hash<S: BoardMoveState>(&self, board: &Board<S>, white_to_move: bool) -> u64 {
+        let mut hash = if white_to_move { self.0[TURN_KEY] } else { 0 };
+
+        let is_piece = |x: u8, y: u8, kind: ChessPieceKind, is_white: bool| {
+            board[Coords::OnBoard(x, y)] == Some(ChessPiece { kind, is_white })
+        };
+        for (i, (white, rook_x)) in [(true, 7), (true, 0), (false, 7), (false, 0)]
+            .into_iter()
+            .enumerate()
+        {
+            let y = if white { 7 } else { 0 };
+            if is_piece(4, y, ChessPieceKind::King, white)
+                && is_piece(rook_x, y, ChessPieceKind::Rook, white)
+            {
+                hash ^= self.0[CASTLING_KEY + i];
+            }
+        }
+
+        for x in 0..8_u8 {
+            for y in 0..8_u8 {
+                if let Some(piece) = board[Coords::OnBoard(x, y)] {
+                    //polyglot rows start from rank 1, which is the bottom of the board
+                    let row = usize::from(7 - y);
+                    hash ^= self.0[64 * polyglot_piece(piece) + 8 * row + usize::from(x)];
+                }
+            }
+        }
+        hash
+    }
+}
+
+///Gets the index Polyglot uses for a piece - black pawn is 0, white pawn 1, black knight 2 and so on up to white king 11
+fn polyglot_piece(piece: ChessPiece) -> usize {
+    let kind = match piece.kind {
+        ChessPieceKind::Pawn => 0,
+        ChessPieceKind::Knight => 1,
+        ChessPieceKind::Bishop => 2,
+        ChessPieceKind::Rook => 3,
+        ChessPieceKind::Queen => 4,
+        ChessPieceKind::King => 5,
+    };
+    kind * 2 + usize::from(piece.is_white)
+}
+
+///One move in an [`OpeningBook`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    ///The Polyglot hash of the position the move is played from
+    pub key: u64,
+    ///The move, as `(from, to)`. Castling is stored like Polyglot stores it, as the king taking its own rook.
+    pub m: (Coords, Coords),
+    ///How good the move is - usually based on how often it was played and won
+    pub weight: u16,
+}
+
+///Statistics for a book move in one position, from [`OpeningBook::moves`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookMove {
+    ///The move, as `(from, to)`. Castling is the king moving two squares.
+    pub m: (Coords, Coords),
+    ///The weight from the book
+    pub weight: u16,
+    ///The share of the total weight of the moves in the position, from `0.0` to `1.0`
+    pub share: f64,
+}
+
+///An opening book in the Polyglot format - a sorted list of moves for positions, keyed by Polyglot hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpeningBook {
+    ///The keys used to hash positions
+    keys: PolyglotKeys,
+    ///The entries, sorted by key
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    ///Loads a Polyglot book, and the [`PolyglotKeys`] to look positions up with
+    ///
+    /// # Errors
+    /// - Fail to load the keys
+    /// - Fail to read in the book
+    /// - The book isn't a whole number of entries
+    #[tracing::instrument]
+    pub fn load(book: &Path, keys: &Path) -> Result<Self> {
+        let keys = PolyglotKeys::load(keys).context("loading polyglot keys")?;
+        let bytes = std::fs::read(book).with_context(|| format!("reading book {book:?}"))?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            bail!(
+                "book is {} bytes, which isn't a whole number of entries",
+                bytes.len()
+            );
+        }
+
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .filter_map(parse_entry)
+            .collect();
+        //they should be sorted already, but looking up relies on it
+        entries.sort_by_key(|e| e.key);
+        info!(entries=%entries.len(), "Loaded opening book");
+
+        Ok(Self { keys, entries })
+    }
+
+    ///Gets the book moves for a position, with the most heavily weighted first. Empty if the position isn't in the book.
+    #[must_use]
+    pub fn moves<S: BoardMoveState>(&self, board: &Board<S>, white_to_move: bool) -> Vec<BookMove> {
+        let key = self.keys.hash(board, white_to_move);
+        let start = self.entries.partition_point(|e| e.key < key);
+        let found: Vec<BookEntry> = self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .filter(|e| e.weight > 0)
+            .copied()
+            .collect();
+
+        let total: f64 = found.iter().map(|e| f64::from(e.weight)).sum();
+        let mut moves: Vec<BookMove> = found
+            .into_iter()
+            .map(|e| BookMove {
+                m: castling_as_king_move(board, e.m),
+                weight: e.weight,
+                share: f64::from(e.weight) / total,
+            })
+            .collect();
+        moves.sort_by(|a, b| b.weight.cmp(&a.weight));
+        moves
+    }
+
+    ///Gets the most heavily weighted book move for a position, if it is in the book
+    #[must_use]
+    pub fn best_move<S: BoardMoveState>(
+        &self,
+        board: &Board<S>,
+        white_to_move: bool,
+    ) -> Option<(Coords, Coords)> {
+        self.moves(board, white_to_move).first().map(|b| b.m)
+    }
+}
+
+///Parses one 16 byte entry - a big-endian 64 bit key, 16 bit move, 16 bit weight and 32 bit learn value, which isn't used. Returns `None` if there are too few bytes.
+fn parse_entry(bytes: &[u8]) -> Option<BookEntry> {
+    let key = u64::from_be_bytes(bytes.get(0..8)?.try_into().ok()?);
+    let m = u16::from_be_bytes(bytes.get(8..10)?.try_into().ok()?);
+    let weight = u16::from_be_bytes(bytes.get(10..12)?.try_into().ok()?);
+
+    //3 bits each, from the bottom - to file, to row, from file, from row, then promotion which is ignored as pawns always become queens
+    let part = |shift: u16| u8::try_from((m >> shift) & 0b111).ok();
+    //polyglot rows start from rank 1, which is the bottom of the board
+    let square = |file: u8, row: u8| Coords::OnBoard(file, 7 - row);
+
+    Some(BookEntry {
+        key,
+        m: (square(part(6)?, part(9)?), square(part(0)?, part(3)?)),
+        weight,
+    })
+}
+
+///Converts a move of a king onto its own rook, which is how Polyglot stores castling, into the king moving two squares
+fn castling_as_king_move<S: BoardMoveState>(
+    board: &Board<S>,
+    (from, to): (Coords, Coords),
+) -> (Coords, Coords) {
+    let (Some(king), Some(rook)) = (board[from], board[to]) else {
+        return (from, to);
+    };
+    if king.kind != ChessPieceKind::King
+        || rook.kind != ChessPieceKind::Rook
+        || king.is_white != rook.is_white
+    {
+        return (from, to);
+    }
+
+    match (to.x(), to.y()) {
+        (Some(7), Some(y)) => (from, Coords::OnBoard(6, y)),
+        (Some(0), Some(y)) => (from, Coords::OnBoard(2, y)),
+        _ => (from, to),
+    }
+}
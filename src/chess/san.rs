@@ -0,0 +1,225 @@
+use crate::{
+    chess::{
+        attacks::pseudo_legal_moves,
+        boards::board::{Board, BoardMoveState},
+        castling::{castle_side, king_move},
+    },
+    prelude::{ChessPiece, ChessPieceKind, Coords, Result},
+};
+use anyhow::Context;
+use epac_utils::error_ext::ToAnyhowNotErr;
+
+///The letters of the files, from the a-file at `x = 0`
+const FILES: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
+
+///Writes a move, as `(from, to)`, in Standard Algebraic Notation, eg. `Nbd7`, `exd5`, `e8=Q+` or `Qh4#`.
+///
+/// Pawns reaching the far side are written as becoming queens, like on the board - see [`to_san_promoting`] for other pieces.
+///
+/// # Errors
+/// - There isn't a piece on `from`
+/// - Either square isn't on the board
+pub fn to_san<S: BoardMoveState>(board: &Board<S>, mv: (Coords, Coords)) -> Result<String> {
+    to_san_promoting(board, mv, ChessPieceKind::Queen)
+}
+
+///Writes a move, as `(from, to)`, in Standard Algebraic Notation, with a pawn reaching the far side becoming `promotion`.
+///
+/// Other pieces which could make the same move are only used to disambiguate it if they could legally make it - a pinned piece doesn't count.
+///
+/// # Errors
+/// - There isn't a piece on `from`
+/// - Either square isn't on the board
+pub fn to_san_promoting<S: BoardMoveState>(
+    board: &Board<S>,
+    (from, to): (Coords, Coords),
+    promotion: ChessPieceKind,
+) -> Result<String> {
+    let piece = board[from].ae().context("no piece to move")?;
+    let (from_x, from_y) = from.to_option().ae().context("move from off the board")?;
+    let square = to.to_algebraic().ae().context("move to off the board")?;
+    let capture = if board[to].is_some() { "x" } else { "" };
+    let promotes = board.is_promotion(from, to);
+
+    let mut san = if let Some(king_side) = castle_side(piece, from, to) {
+        String::from(if king_side { "O-O" } else { "O-O-O" })
+    } else if piece.kind == ChessPieceKind::Pawn {
+        let promotion = if promotes {
+            format!("={}", promotion.letter())
+        } else {
+            String::new()
+        };
+        if capture.is_empty() {
+            format!("{square}{promotion}")
+        } else {
+            format!("{}x{square}{promotion}", FILES[usize::from(from_x)])
+        }
+    } else {
+        //other pieces of the same kind which could also move there
+        let others: Vec<(u8, u8)> = pseudo_legal_moves(board, piece.is_white)
+            .into_iter()
+            .filter(|(f, t)| *t == to && *f != from && board[*f] == Some(piece))
+            .filter(|(f, t)| !board.with_move(*f, *t).is_in_check(piece.is_white))
+            .filter_map(|(f, _)| f.to_option())
+            .collect();
+        let rank = (8 - from_y).to_string();
+        let disambiguation = if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|(x, _)| *x != from_x) {
+            FILES[usize::from(from_x)].to_string()
+        } else if others.iter().all(|(_, y)| *y != from_y) {
+            rank
+        } else {
+            format!("{}{rank}", FILES[usize::from(from_x)])
+        };
+        format!("{}{disambiguation}{capture}{square}", piece.kind.letter())
+    };
+
+    let mut after = board.with_move(from, to);
+    if promotes {
+        if let Some(p) = after[to] {
+            after.set_piece(
+                to,
+                Some(ChessPiece {
+                    kind: promotion,
+                    ..p
+                }),
+            );
+        }
+    }
+    if after.is_checkmate(!piece.is_white) {
+        san.push('#');
+    } else if after.is_in_check(!piece.is_white) {
+        san.push('+');
+    }
+    Ok(san)
+}
+
+///Reads a move in Standard Algebraic Notation, eg. `Nbd7`, for one colour. Check and annotation symbols should already have been removed.
+///
+/// Pawns always promote to queens on the board, so any promotion is read as a queen.
+///
+/// # Errors
+/// - The move castles, but castling that way isn't allowed
+/// - The destination square isn't valid
+/// - No piece can make the move, or more than one can
+pub fn parse_san<S: BoardMoveState>(
+    board: &Board<S>,
+    san: &str,
+    white: bool,
+) -> Result<(Coords, Coords)> {
+    if san.starts_with("O-O") || san.starts_with("0-0") {
+        let queen_side = san.starts_with("O-O-O") || san.starts_with("0-0-0");
+        let castle = king_move(white, !queen_side);
+        if !pseudo_legal_moves(board, white).contains(&castle) {
+            bail!("can't castle that way");
+        }
+        return Ok(castle);
+    }
+
+    //promotions are written `e8=Q` or `e8Q`
+    let without_promotion = match san.find('=') {
+        Some(i) => &san[..i],
+        None => san.trim_end_matches(['Q', 'R', 'B', 'N']),
+    };
+    let (kind, rest) = match without_promotion.chars().next() {
+        Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => (
+            ChessPieceKind::from_letter(c)
+                .ae()
+                .context("piece letter")?,
+            &without_promotion[1..],
+        ),
+        _ => (ChessPieceKind::Pawn, without_promotion),
+    };
+    let rest = rest.replace('x', "");
+    if rest.len() < 2 {
+        bail!("missing destination square");
+    }
+    let (disambiguation, square) = rest.split_at(rest.len() - 2);
+    let to = Coords::from_algebraic(square)
+        .ae()
+        .with_context(|| format!("invalid square {square:?}"))?;
+
+    let mut candidates: Vec<(Coords, Coords)> = pseudo_legal_moves(board, white)
+        .into_iter()
+        .filter(|(f, t)| {
+            *t == to
+                && matches!(board[*f], Some(p) if p.kind == kind)
+                && f.to_algebraic()
+                    .is_some_and(|name| disambiguation.chars().all(|c| name.contains(c)))
+        })
+        .collect();
+    //pins aren't worked out when finding moves, so a move which would leave the king attacked can look ambiguous
+    if candidates.len() > 1 {
+        candidates.retain(|(f, t)| !board.with_move(*f, *t).is_in_check(white));
+    }
+
+    match candidates.as_slice() {
+        [m] => Ok(*m),
+        [] => bail!("no piece can move to {square}"),
+        _ => bail!("more than one piece can move to {square}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::boards::board::CanMovePiece;
+
+    ///Gets the board from `fen`, panicking if it isn't valid
+    fn board(fen: &str) -> Board<CanMovePiece> {
+        Board::from_fen(fen).unwrap()
+    }
+
+    ///Gets a move from the names of its squares, eg. `("e2", "e4")`
+    fn mv(from: &str, to: &str) -> (Coords, Coords) {
+        (
+            Coords::from_algebraic(from).unwrap(),
+            Coords::from_algebraic(to).unwrap(),
+        )
+    }
+
+    #[test]
+    fn moves_are_written_in_san() {
+        let cases = [
+            //pawns
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                ("e2", "e4"),
+                "e4",
+            ),
+            ("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", ("e4", "d5"), "exd5"),
+            //disambiguation by file, rank, and both
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", ("b1", "d2"), "Nbd2"),
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", ("a1", "a3"), "R1a3"),
+            ("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", ("a1", "b2"), "Qa1b2"),
+            //a pinned piece can't make the move, so doesn't need telling apart
+            ("4k3/4r3/8/8/8/8/4N3/1N2K3 w - - 0 1", ("b1", "c3"), "Nc3"),
+            //castling
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", ("e1", "g1"), "O-O"),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", ("e1", "c1"), "O-O-O"),
+            //promotion, with check
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", ("a7", "a8"), "a8=Q+"),
+            //check and mate
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", ("a1", "a8"), "Ra8+"),
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", ("a1", "a8"), "Ra8#"),
+        ];
+
+        for (fen, (from, to), expected) in cases {
+            assert_eq!(
+                to_san(&board(fen), mv(from, to)).unwrap(),
+                expected,
+                "{from}{to} in {fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn promotion_is_written_as_the_picked_piece() {
+        let b = board("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            to_san_promoting(&b, mv("a7", "a8"), ChessPieceKind::Knight).unwrap(),
+            "a8=N"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    chess::boards::board::{Board, BoardMoveState},
+    prelude::{Coords, Result},
+};
+use std::{fmt, path::Path};
+
+#[cfg(feature = "syzygy")]
+use crate::chess::engine::parse_uci_move;
+#[cfg(feature = "syzygy")]
+use anyhow::Context;
+#[cfg(feature = "syzygy")]
+use shakmaty::{fen::Fen, CastlingMode, Chess};
+#[cfg(feature = "syzygy")]
+use shakmaty_syzygy::Wdl;
+
+///The tables, when built with the `syzygy` feature
+#[cfg(feature = "syzygy")]
+type Tables = shakmaty_syzygy::Tablebase<Chess>;
+///Without the `syzygy` feature there are no tables, and [`Tablebase::open`] always fails
+#[cfg(not(feature = "syzygy"))]
+type Tables = ();
+
+///The result of a position with perfect play, from a [`Tablebase`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TablebaseOutcome {
+    ///White wins
+    WhiteWins,
+    ///Neither side can win - including wins which the fifty-move rule turns into draws
+    Draw,
+    ///Black wins
+    BlackWins,
+}
+
+impl TablebaseOutcome {
+    ///Gets the share of the evaluation bar which should be white
+    #[must_use]
+    pub const fn white_share(self) -> f64 {
+        match self {
+            Self::WhiteWins => 1.0,
+            Self::Draw => 0.5,
+            Self::BlackWins => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for TablebaseOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WhiteWins => write!(f, "white wins"),
+            Self::Draw => write!(f, "draw"),
+            Self::BlackWins => write!(f, "black wins"),
+        }
+    }
+}
+
+///What a [`Tablebase`] knows about a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TablebaseProbe {
+    ///The result with perfect play
+    pub outcome: TablebaseOutcome,
+    ///The move which keeps that result, as `(from, to)` - `None` if there are no moves
+    pub best: Option<(Coords, Coords)>,
+}
+
+///Syzygy endgame tablebases, which know the exact result of positions with few pieces.
+///
+/// Probing needs the client to be built with the `syzygy` feature, and the table files downloaded separately.
+pub struct Tablebase {
+    ///The tables which have been found
+    tables: Tables,
+}
+
+impl fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tablebase").finish_non_exhaustive()
+    }
+}
+
+impl Tablebase {
+    ///Opens the tables in a directory
+    ///
+    /// # Errors
+    /// - The client wasn't built with the `syzygy` feature
+    /// - Fail to read the directory
+    /// - There aren't any tables in the directory
+    #[tracing::instrument]
+    pub fn open(dir: &Path) -> Result<Self> {
+        #[cfg(feature = "syzygy")]
+        {
+            let mut tables = Tables::new();
+            let found = tables
+                .add_directory(dir)
+                .with_context(|| format!("reading tablebase directory {dir:?}"))?;
+            if found == 0 {
+                bail!("no tablebase files in {dir:?}");
+            }
+            info!(%found, max_pieces=%tables.max_pieces(), "Opened tablebase");
+            Ok(Self { tables })
+        }
+        #[cfg(not(feature = "syzygy"))]
+        {
+            bail!("unable to open {dir:?} - built without the syzygy feature")
+        }
+    }
+
+    ///Looks up a position, with one colour to move. Returns `None` if there are too many pieces for the tables.
+    ///
+    /// The board doesn't track castling rights or en passant, so neither is counted - with so few pieces, castling is rarely still possible.
+    ///
+    /// # Errors
+    /// - The position isn't valid, eg. a king is missing
+    /// - The table for the position is missing or can't be read
+    #[tracing::instrument(skip(self, board))]
+    pub fn probe<S: BoardMoveState>(
+        &self,
+        board: &Board<S>,
+        white_to_move: bool,
+    ) -> Result<Option<TablebaseProbe>> {
+        #[cfg(feature = "syzygy")]
+        {
+            let pieces = (0..8_u8)
+                .flat_map(|x| (0..8_u8).map(move |y| Coords::OnBoard(x, y)))
+                .filter(|c| board[*c].is_some())
+                .count();
+            if pieces > self.tables.max_pieces() {
+                return Ok(None);
+            }
+
+            let fen: Fen = board
+                .to_fen_with_turn(white_to_move)
+                .parse()
+                .context("parsing fen")?;
+            let pos: Chess = fen
+                .into_position(CastlingMode::Standard)
+                .map_err(|e| anyhow!("invalid position: {e}"))?;
+
+            let wdl = self.tables.probe_wdl(&pos).context("probing wdl")?;
+            //from the point of view of the side to move
+            let outcome = match (wdl, white_to_move) {
+                (Wdl::Win, true) | (Wdl::Loss, false) => TablebaseOutcome::WhiteWins,
+                (Wdl::Win, false) | (Wdl::Loss, true) => TablebaseOutcome::BlackWins,
+                _ => TablebaseOutcome::Draw,
+            };
+            let best = match self.tables.best_move(&pos).context("probing best move")? {
+                Some((m, _dtz)) => Some(parse_uci_move(
+                    &m.to_uci(CastlingMode::Standard).to_string(),
+                )?),
+                None => None,
+            };
+
+            Ok(Some(TablebaseProbe { outcome, best }))
+        }
+        #[cfg(not(feature = "syzygy"))]
+        {
+            let _ = (&self.tables, board, white_to_move);
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    chess::boards::board::{Board, BoardMoveState},
+    prelude::ChessPiece,
+};
+
+///Number of different pieces - 6 kinds, in 2 colours
+const PIECES: usize = 12;
+
+///Steps a splitmix64 generator, returning the new state and the next number. Used to make the [`KEYS`] at compile time, so they are the same every run.
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+///Makes one random key for each piece on each square, then one for black to move
+const fn make_keys() -> [u64; PIECES * 64 + 1] {
+    let mut keys = [0; PIECES * 64 + 1];
+    let mut state = 0x00A5_C0FF_EE00_C4E5;
+    let mut i = 0;
+    while i < keys.len() {
+        let (next_state, key) = splitmix64(state);
+        state = next_state;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+}
+
+///The random keys which are combined to make a hash - see [`make_keys`]
+const KEYS: [u64; PIECES * 64 + 1] = make_keys();
+
+///Gets the key for a piece on a square, from its index like [`crate::prelude::Coords::to_usize`]
+pub(crate) fn piece_key(piece: ChessPiece, square: usize) -> u64 {
+    let piece = piece.kind as usize * 2 + usize::from(!piece.is_white);
+    KEYS[piece * 64 + square]
+}
+
+///Hashes where the pieces are, with white to move, by combining the [`piece_key`] of each piece - indexed like [`crate::prelude::Coords::to_usize`]
+///
+/// Only needed when a whole board is made at once - after that, [`Board`] keeps its hash up to date as pieces are set.
+#[must_use]
+pub(crate) fn placement_hash(pieces: &[Option<ChessPiece>; 64]) -> u64 {
+    pieces
+        .iter()
+        .enumerate()
+        .filter_map(|(square, piece)| piece.map(|p| piece_key(p, square)))
+        .fold(0, |hash, key| hash ^ key)
+}
+
+///Hashes a position, with the side to move, by combining a random key for each piece on each square.
+///
+/// Positions reached by different move orders get the same hash, so results for them can be shared. Taken pieces aren't included, as they don't change the position.
+///
+/// This doesn't look at the squares, as the board keeps the hash of its pieces up to date as they move - see [`Board::hash`].
+#[must_use]
+pub fn zobrist_hash<S: BoardMoveState>(board: &Board<S>, white_to_move: bool) -> u64 {
+    if white_to_move {
+        board.hash()
+    } else {
+        board.hash() ^ KEYS[PIECES * 64]
+    }
+}
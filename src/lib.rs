@@ -23,8 +23,12 @@ extern crate epac_utils;
 
 ///Module to hold all chess-related modules
 pub mod chess;
+///Module to hold the [`metrics::MetricsRegistry`], which collects values from around the client for the debug overlay
+pub mod metrics;
 ///Module to hold all networking modules
 pub mod net;
+///Module to hold general-purpose utilities, like the [`util::lru_cache::LruCache`]
+pub mod util;
 
 ///Module to hold commonly used structs, enums and functions that should be in a prelude
 pub mod prelude {
@@ -0,0 +1,122 @@
+use epac_utils::error_ext::MutexExt;
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+};
+
+///Registry of named values published from around the client, so they can all be shown in one place, like the debug overlay.
+///
+/// Names are dotted, with the part before the first dot grouping related values, eg. `memory.texture_bytes`.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    ///The current value of each gauge
+    gauges: Mutex<BTreeMap<&'static str, i64>>,
+}
+
+///Gets the [`MetricsRegistry`] shared by the whole program
+pub fn metrics() -> &'static MetricsRegistry {
+    ///The registry, made on first use
+    static REGISTRY: OnceLock<MetricsRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(MetricsRegistry::default)
+}
+
+impl MetricsRegistry {
+    ///Sets a gauge to a value
+    pub fn set_gauge(&self, name: &'static str, value: i64) {
+        self.gauges.lock_panic("metrics gauges").insert(name, value);
+    }
+
+    ///Adds to a gauge, starting from 0 if it hasn't been set. `delta` can be negative.
+    pub fn add_to_gauge(&self, name: &'static str, delta: i64) {
+        *self
+            .gauges
+            .lock_panic("metrics gauges")
+            .entry(name)
+            .or_default() += delta;
+    }
+
+    ///Gets the current value of a gauge
+    #[must_use]
+    pub fn gauge(&self, name: &str) -> Option<i64> {
+        self.gauges.lock_panic("metrics gauges").get(name).copied()
+    }
+
+    ///Gets a copy of every gauge, sorted by name
+    #[must_use]
+    pub fn gauges(&self) -> Vec<(&'static str, i64)> {
+        self.gauges
+            .lock_panic("metrics gauges")
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect()
+    }
+}
+
+///Upper bounds of the [`Histogram`] buckets, in milliseconds - anything bigger goes into an overflow bucket
+pub const LATENCY_BUCKETS_MS: [u64; 12] = [
+    5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10_000, 30_000,
+];
+
+///Histogram of latencies, in fixed buckets so it stays the same size however many values are recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    ///Number of values in each bucket of [`LATENCY_BUCKETS_MS`], then the overflow bucket
+    counts: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    ///Number of values recorded
+    total: u64,
+    ///Biggest value recorded
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; LATENCY_BUCKETS_MS.len() + 1],
+            total: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    ///Records a value, in milliseconds
+    pub fn record(&mut self, ms: u64) {
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.total += 1;
+        self.max = self.max.max(ms);
+    }
+
+    ///Gets the number of values recorded
+    #[must_use]
+    pub const fn count(&self) -> u64 {
+        self.total
+    }
+
+    ///Estimates a percentile, eg. `0.9` for p90, as the upper bound of the bucket it falls in - so it is never an underestimate. `None` if nothing has been recorded.
+    #[must_use]
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        if self.total == 0 {
+            return None;
+        }
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = ((p.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = LATENCY_BUCKETS_MS.get(bucket).copied().unwrap_or(u64::MAX);
+                return Some(bound.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
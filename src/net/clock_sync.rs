@@ -0,0 +1,171 @@
+use crate::metrics::metrics;
+use epac_utils::error_ext::MutexExt;
+use reqwest::header::{HeaderMap, DATE};
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+///Header a server can send with its time in milliseconds since the unix epoch, which is more precise than the `Date` header
+pub const SERVER_TIME_HEADER: &str = "X-Server-Time";
+///Number of samples kept to estimate the skew from
+pub const SKEW_SAMPLES: usize = 8;
+///Number of requests sent by [`crate::net::server_client::ServerClient::sync_clock`] when a connection starts
+pub const HANDSHAKE_SAMPLES: usize = 3;
+
+///How far the server's clock is ahead of the local one, in milliseconds - negative if it is behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockSkew(pub i64);
+
+impl ClockSkew {
+    ///Gets what the time is on the server right now
+    #[must_use]
+    pub fn server_now(self) -> SystemTime {
+        self.to_server(SystemTime::now())
+    }
+
+    ///Turns a local time into the server's time
+    #[must_use]
+    pub fn to_server(self, local: SystemTime) -> SystemTime {
+        shift(local, self.0)
+    }
+
+    ///Turns a time from the server into the local time
+    #[must_use]
+    pub fn to_local(self, server: SystemTime) -> SystemTime {
+        shift(server, -self.0)
+    }
+
+    ///Gets how long ago a time from the server in milliseconds since the unix epoch was, according to the server's clock. Times in the future count as now.
+    #[must_use]
+    pub fn since_server_millis(self, server_ms: u64) -> Duration {
+        self.server_now()
+            .duration_since(UNIX_EPOCH + Duration::from_millis(server_ms))
+            .unwrap_or_default()
+    }
+}
+
+impl Display for ClockSkew {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:+.1}s", self.0 as f64 / 1000.0)
+    }
+}
+
+///Moves a time by a signed number of milliseconds
+fn shift(time: SystemTime, ms: i64) -> SystemTime {
+    let by = Duration::from_millis(ms.unsigned_abs());
+    if ms >= 0 {
+        time + by
+    } else {
+        time - by
+    }
+}
+
+///Estimates the [`ClockSkew`] to the server from the times it sends with its responses.
+///
+/// Each sample assumes the server stamped its response halfway through the round trip, and the estimate is the median of the last [`SKEW_SAMPLES`] samples so one slow request doesn't throw it off.
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    ///The most recent offsets in milliseconds, oldest first
+    samples: Mutex<VecDeque<i64>>,
+}
+
+impl ClockSync {
+    ///Records a sample from the server's time in milliseconds since the unix epoch, from a response which took `round_trip` and has just been received
+    pub fn record(&self, server_ms: i64, round_trip: Duration) {
+        let midpoint = SystemTime::now() - round_trip / 2;
+        let local_ms = midpoint
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX));
+
+        let mut samples = self.samples.lock_panic("clock samples");
+        if samples.len() == SKEW_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(server_ms - local_ms);
+        drop(samples);
+
+        if let Some(skew) = self.skew() {
+            metrics().set_gauge("net.clock_skew_ms", skew.0);
+        }
+    }
+
+    ///Records a sample from the headers of a response, preferring the [`SERVER_TIME_HEADER`] to the `Date` header.
+    ///
+    /// Returns whether or not there was a time to record.
+    pub fn record_headers(&self, headers: &HeaderMap, round_trip: Duration) -> bool {
+        let precise = headers
+            .get(SERVER_TIME_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+        let server_ms = precise.or_else(|| {
+            headers
+                .get(DATE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_http_date)
+        });
+
+        if let Some(server_ms) = server_ms {
+            self.record(server_ms, round_trip);
+        }
+        server_ms.is_some()
+    }
+
+    ///Gets the estimated skew, or `None` if there haven't been any samples
+    #[must_use]
+    pub fn skew(&self) -> Option<ClockSkew> {
+        let mut samples: Vec<i64> = self
+            .samples
+            .lock_panic("clock samples")
+            .iter()
+            .copied()
+            .collect();
+        if samples.is_empty() {
+            return None;
+        }
+
+        samples.sort_unstable();
+        Some(ClockSkew(samples[samples.len() / 2]))
+    }
+
+    ///Gets the number of samples the estimate is made from
+    #[must_use]
+    pub fn samples(&self) -> usize {
+        self.samples.lock_panic("clock samples").len()
+    }
+}
+
+///Parses an HTTP date like `Sun, 06 Nov 1994 08:49:37 GMT` into milliseconds since the unix epoch
+fn parse_http_date(date: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = date.split_whitespace().skip(1);
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = MONTHS.iter().position(|m| Some(*m) == parts.next())?;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(str::parse::<i64>);
+    let (h, m, s) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if parts.next() != Some("GMT") {
+        return None;
+    }
+
+    //days since the epoch from the civil date, counting years from March so the leap day is last
+    let month = i64::try_from(month).ok()? + 1;
+    let (y, m_from_march) = if month > 2 {
+        (year, month - 3)
+    } else {
+        (year - 1, month + 9)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(((days * 24 + h) * 60 + m) * 60_000 + s * 1000)
+}
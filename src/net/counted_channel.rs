@@ -0,0 +1,442 @@
+use crate::metrics::metrics;
+use epac_utils::error_ext::MutexExt;
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{channel, Receiver, RecvError, SendError, Sender, TryRecvError, TrySendError},
+        Arc, Condvar, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+///Creates a channel which keeps the gauge `name` in the [`crate::metrics::MetricsRegistry`] up to date with the number of messages waiting in it.
+///
+/// Every channel with the same name adds to the same gauge, so it shows the total across them.
+#[must_use]
+pub fn counted_channel<T>(name: &'static str) -> (CountedSender<T>, CountedReceiver<T>) {
+    let (tx, rx) = channel();
+    (CountedSender { tx, name }, CountedReceiver { rx, name })
+}
+
+///The sending half of a [`counted_channel`]
+#[derive(Debug)]
+pub struct CountedSender<T> {
+    ///The inner sender
+    tx: Sender<T>,
+    ///The name of the gauge
+    name: &'static str,
+}
+
+//derive would require `T: Clone`
+impl<T> Clone for CountedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            name: self.name,
+        }
+    }
+}
+
+impl<T> CountedSender<T> {
+    ///Sends a message, like [`Sender::send`]
+    ///
+    /// # Errors
+    /// - The receiver has been dropped
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        //counted first, so the receiver can't take it off before it is added
+        metrics().add_to_gauge(self.name, 1);
+        self.tx.send(t).map_err(|e| {
+            metrics().add_to_gauge(self.name, -1);
+            e
+        })
+    }
+}
+
+///The receiving half of a [`counted_channel`]
+#[derive(Debug)]
+pub struct CountedReceiver<T> {
+    ///The inner receiver
+    rx: Receiver<T>,
+    ///The name of the gauge
+    name: &'static str,
+}
+
+impl<T> CountedReceiver<T> {
+    ///Blocks until there is a message, like [`Receiver::recv`]
+    ///
+    /// # Errors
+    /// - All of the senders have been dropped
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let t = self.rx.recv()?;
+        metrics().add_to_gauge(self.name, -1);
+        Ok(t)
+    }
+
+    ///Receives a message if there is one, like [`Receiver::try_recv`]
+    ///
+    /// # Errors
+    /// - There is no message
+    /// - All of the senders have been dropped
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let t = self.rx.try_recv()?;
+        metrics().add_to_gauge(self.name, -1);
+        Ok(t)
+    }
+}
+
+impl<T> Drop for CountedReceiver<T> {
+    fn drop(&mut self) {
+        //anything left in the channel is dropped with it
+        let left = self.rx.try_iter().count();
+        metrics().add_to_gauge(self.name, -i64::try_from(left).unwrap_or(i64::MAX));
+    }
+}
+
+///What a [`BoundedSender`] does with a message when the channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    ///Throw away the oldest waiting message which was also sent with `DropOldest` - which is the new message if none are waiting. For messages which newer ones make pointless, like refresh requests.
+    DropOldest,
+    ///Wait up to this long for room, then fail
+    Block(Duration),
+    ///Fail straight away
+    Fail,
+}
+
+///A message which knows what should happen to it when a [`bounded_channel`] is full
+pub trait HasBackpressure {
+    ///Gets what to do with this message if the channel is full
+    fn backpressure(&self) -> Backpressure;
+}
+
+///The parts of a [`bounded_channel`] shared between the senders and the receiver
+#[derive(Debug)]
+struct BoundedShared<T> {
+    ///The waiting messages, and the senders and receiver still around
+    state: Mutex<BoundedState<T>>,
+    ///Notified whenever a message is sent or received, or either side is dropped
+    changed: Condvar,
+    ///The most messages which can wait at once
+    capacity: usize,
+    ///The name of the gauge for the number of messages waiting
+    name: &'static str,
+    ///The name of the gauge for the number of messages thrown away
+    dropped: &'static str,
+}
+
+///The state of a [`bounded_channel`]
+#[derive(Debug)]
+struct BoundedState<T> {
+    ///The waiting messages, oldest first, with whether each can be thrown away by [`Backpressure::DropOldest`]
+    queue: VecDeque<(T, bool)>,
+    ///The number of senders still around
+    senders: usize,
+    ///Whether the receiver is still around
+    receiver: bool,
+}
+
+///Creates a channel which holds at most `capacity` messages, with a [`Backpressure`] policy for each message sent when it is full.
+///
+/// Like [`counted_channel`], the gauge `name` in the [`crate::metrics::MetricsRegistry`] is kept up to date with the number of messages waiting in it. The gauge `dropped` counts the messages thrown away.
+#[must_use]
+pub fn bounded_channel<T>(
+    name: &'static str,
+    dropped: &'static str,
+    capacity: usize,
+) -> (BoundedSender<T>, BoundedReceiver<T>) {
+    let shared = Arc::new(BoundedShared {
+        state: Mutex::new(BoundedState {
+            queue: VecDeque::new(),
+            senders: 1,
+            receiver: true,
+        }),
+        changed: Condvar::new(),
+        capacity: capacity.max(1),
+        name,
+        dropped,
+    });
+    (
+        BoundedSender {
+            shared: shared.clone(),
+        },
+        BoundedReceiver { shared },
+    )
+}
+
+///The sending half of a [`bounded_channel`]
+#[derive(Debug)]
+pub struct BoundedSender<T> {
+    ///The channel
+    shared: Arc<BoundedShared<T>>,
+}
+
+//derive would require `T: Clone`, and the sender needs counting
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock_panic("bounded channel").senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for BoundedSender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock_panic("bounded channel").senders -= 1;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl<T> BoundedSender<T> {
+    ///Sends a message, doing what `policy` says if the channel is full
+    ///
+    /// # Errors
+    /// - The receiver has been dropped
+    /// - The channel is full, and the policy is [`Backpressure::Block`] or [`Backpressure::Fail`]
+    pub fn send_with(&self, t: T, policy: Backpressure) -> Result<(), TrySendError<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock_panic("bounded channel");
+        if let Backpressure::Block(timeout) = policy {
+            state = shared
+                .changed
+                .wait_timeout_while(state, timeout, |s| {
+                    s.receiver && s.queue.len() >= shared.capacity
+                })
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        if !state.receiver {
+            return Err(TrySendError::Disconnected(t));
+        }
+
+        let droppable = policy == Backpressure::DropOldest;
+        if state.queue.len() >= shared.capacity {
+            if !droppable {
+                return Err(TrySendError::Full(t));
+            }
+            metrics().add_to_gauge(shared.dropped, 1);
+            match state.queue.iter().position(|(_, d)| *d) {
+                Some(oldest) => {
+                    state.queue.remove(oldest);
+                    metrics().add_to_gauge(shared.name, -1);
+                }
+                //the new message is the oldest which can be thrown away
+                None => return Ok(()),
+            }
+        }
+
+        state.queue.push_back((t, droppable));
+        metrics().add_to_gauge(shared.name, 1);
+        shared.changed.notify_all();
+        Ok(())
+    }
+
+    ///Gets the number of messages waiting to be received
+    #[must_use]
+    pub fn waiting(&self) -> usize {
+        self.shared.state.lock_panic("bounded channel").queue.len()
+    }
+
+    ///Takes every waiting message back out of the channel, oldest first - eg. to hand them to a new receiver when this one has stopped receiving
+    pub fn take_waiting(&self) -> Vec<T> {
+        let mut state = self.shared.state.lock_panic("bounded channel");
+        let taken: Vec<T> = std::mem::take(&mut state.queue)
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+        metrics().add_to_gauge(
+            self.shared.name,
+            -i64::try_from(taken.len()).unwrap_or(i64::MAX),
+        );
+        drop(state);
+        self.shared.changed.notify_all();
+        taken
+    }
+}
+
+impl<T: HasBackpressure> BoundedSender<T> {
+    ///Sends a message, doing what [`HasBackpressure::backpressure`] says if the channel is full
+    ///
+    /// # Errors
+    /// - See [`BoundedSender::send_with`]
+    pub fn send(&self, t: T) -> Result<(), TrySendError<T>> {
+        let policy = t.backpressure();
+        self.send_with(t, policy)
+    }
+}
+
+///The receiving half of a [`bounded_channel`]
+#[derive(Debug)]
+pub struct BoundedReceiver<T> {
+    ///The channel
+    shared: Arc<BoundedShared<T>>,
+}
+
+impl<T> BoundedReceiver<T> {
+    ///Takes the oldest message, and makes room for a blocked sender
+    fn take(&self, state: &mut BoundedState<T>) -> Option<T> {
+        let (t, _) = state.queue.pop_front()?;
+        metrics().add_to_gauge(self.shared.name, -1);
+        self.shared.changed.notify_all();
+        Some(t)
+    }
+
+    ///Blocks until there is a message, like [`Receiver::recv`]
+    ///
+    /// # Errors
+    /// - All of the senders have been dropped, and there are no messages left
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let state = self.shared.state.lock_panic("bounded channel");
+        let mut state = self
+            .shared
+            .changed
+            .wait_while(state, |s| s.queue.is_empty() && s.senders > 0)
+            .unwrap_or_else(PoisonError::into_inner);
+        self.take(&mut state).ok_or(RecvError)
+    }
+
+    ///Receives a message if there is one, like [`Receiver::try_recv`]
+    ///
+    /// # Errors
+    /// - There is no message
+    /// - All of the senders have been dropped, and there are no messages left
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.state.lock_panic("bounded channel");
+        match self.take(&mut state) {
+            Some(t) => Ok(t),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+impl<T> Drop for BoundedReceiver<T> {
+    fn drop(&mut self) {
+        //anything left in the channel is dropped with it
+        let mut state = self.shared.state.lock_panic("bounded channel");
+        state.receiver = false;
+        let left = std::mem::take(&mut state.queue).len();
+        metrics().add_to_gauge(self.shared.name, -i64::try_from(left).unwrap_or(i64::MAX));
+        drop(state);
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    ///Gets the value of a gauge, which is 0 before anything has been added to it
+    fn gauge(name: &str) -> i64 {
+        metrics().gauge(name).unwrap_or(0)
+    }
+
+    #[test]
+    fn drop_oldest_only_evicts_droppable_messages() {
+        let (tx, rx) = bounded_channel("test.drop_oldest", "test.drop_oldest_dropped", 2);
+        tx.send_with(1, Backpressure::Fail).unwrap();
+        tx.send_with(2, Backpressure::DropOldest).unwrap();
+        tx.send_with(3, Backpressure::DropOldest).unwrap();
+        assert_eq!(gauge("test.drop_oldest_dropped"), 1);
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn drop_oldest_throws_away_new_message_if_nothing_else_can_go() {
+        let (tx, rx) = bounded_channel("test.drop_new", "test.drop_new_dropped", 1);
+        tx.send_with(1, Backpressure::Fail).unwrap();
+        tx.send_with(2, Backpressure::DropOldest).unwrap();
+        assert_eq!(gauge("test.drop_new_dropped"), 1);
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn block_times_out_when_full() {
+        let (tx, _rx) = bounded_channel("test.block", "test.block_dropped", 1);
+        tx.send_with(1, Backpressure::Fail).unwrap();
+
+        let timeout = Duration::from_millis(30);
+        let start = Instant::now();
+        let sent = tx.send_with(2, Backpressure::Block(timeout));
+        assert_eq!(sent, Err(TrySendError::Full(2)));
+        assert!(start.elapsed() >= timeout);
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (tx, rx) = bounded_channel("test.block_room", "test.block_room_dropped", 1);
+        tx.send_with(1, Backpressure::Fail).unwrap();
+
+        let receiver = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            let first = rx.recv();
+            (first, rx.recv())
+        });
+        tx.send_with(2, Backpressure::Block(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(receiver.join().unwrap(), (Ok(1), Ok(2)));
+    }
+
+    #[test]
+    fn fail_fails_straight_away_when_full() {
+        let (tx, rx) = bounded_channel("test.fail", "test.fail_dropped", 1);
+        tx.send_with(1, Backpressure::Fail).unwrap();
+        assert_eq!(
+            tx.send_with(2, Backpressure::Fail),
+            Err(TrySendError::Full(2))
+        );
+        assert_eq!(gauge("test.fail_dropped"), 0);
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+
+    #[test]
+    fn sending_fails_once_receiver_dropped() {
+        let (tx, rx) = bounded_channel("test.no_receiver", "test.no_receiver_dropped", 1);
+        tx.send_with(1, Backpressure::Fail).unwrap();
+        drop(rx);
+        assert_eq!(gauge("test.no_receiver"), 0);
+        assert_eq!(
+            tx.send_with(2, Backpressure::Block(Duration::from_secs(5))),
+            Err(TrySendError::Disconnected(2))
+        );
+    }
+
+    #[test]
+    fn receiving_fails_once_senders_dropped_and_drained() {
+        let (tx, rx) = bounded_channel("test.no_sender", "test.no_sender_dropped", 2);
+        let other = tx.clone();
+        tx.send_with(1, Backpressure::Fail).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        drop(other);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn take_waiting_empties_channel_and_gauge() {
+        let (tx, rx) = bounded_channel("test.take_waiting", "test.take_waiting_dropped", 4);
+        for i in 1..=3 {
+            tx.send_with(i, Backpressure::Fail).unwrap();
+        }
+        assert_eq!(tx.waiting(), 3);
+        assert_eq!(gauge("test.take_waiting"), 3);
+
+        assert_eq!(tx.take_waiting(), vec![1, 2, 3]);
+        assert_eq!(tx.waiting(), 0);
+        assert_eq!(gauge("test.take_waiting"), 0);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        tx.send_with(4, Backpressure::Fail).unwrap();
+        assert_eq!(rx.recv(), Ok(4));
+        assert_eq!(gauge("test.take_waiting"), 0);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::util::event_bus::{BusEvent, EventBus};
+use std::sync::OnceLock;
+
+///Something which happened to a game, published on the [`game_events`] bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameEvent {
+    ///A new board was received, and it differs from the last one
+    BoardChanged {
+        ///The game id
+        id: u32,
+    },
+    ///Requests for a game are now going to a different server
+    ConnectionChanged {
+        ///The game id
+        id: u32,
+        ///The base URL of the server now being used
+        server: String,
+    },
+    ///The server answered a move
+    MoveResult {
+        ///The game id
+        id: u32,
+        ///Whether the move was made - if not, it was rejected or couldn't be sent
+        worked: bool,
+    },
+}
+
+///The kinds of [`GameEvent`], to subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEventKind {
+    ///[`GameEvent::BoardChanged`]
+    BoardChanged,
+    ///[`GameEvent::ConnectionChanged`]
+    ConnectionChanged,
+    ///[`GameEvent::MoveResult`]
+    MoveResult,
+}
+
+impl BusEvent for GameEvent {
+    type Kind = GameEventKind;
+
+    fn kind(&self) -> Self::Kind {
+        match self {
+            Self::BoardChanged { .. } => GameEventKind::BoardChanged,
+            Self::ConnectionChanged { .. } => GameEventKind::ConnectionChanged,
+            Self::MoveResult { .. } => GameEventKind::MoveResult,
+        }
+    }
+}
+
+///Gets the [`EventBus`] for [`GameEvent`]s shared by the whole program
+pub fn game_events() -> &'static EventBus<GameEvent> {
+    ///The bus, made on first use
+    static BUS: OnceLock<EventBus<GameEvent>> = OnceLock::new();
+    BUS.get_or_init(EventBus::default)
+}
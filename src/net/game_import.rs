@@ -0,0 +1,227 @@
+use super::{
+    server_client::{ClientConfig, ServerClient},
+    server_interface::{parse_response, ServerError},
+};
+use crate::{chess::history::MoveHistory, prelude::Result};
+use anyhow::Context;
+use epac_utils::error_ext::ToAnyhowNotErr;
+use reqwest::header::ACCEPT;
+use serde::Deserialize;
+
+///Base URL of the Lichess API
+pub const LICHESS_API: &str = "https://lichess.org";
+///Base URL of the Chess.com public API
+pub const CHESS_COM_API: &str = "https://api.chess.com";
+
+///A game on another site, to import with a [`GameImporter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalGame {
+    ///A Lichess game
+    Lichess {
+        ///The 8 character id of the game
+        id: String,
+    },
+    ///A Chess.com game. The public API only lists games by player and month, so those are needed to find it.
+    ChessCom {
+        ///The username of either player
+        player: String,
+        ///The year the game was played
+        year: u32,
+        ///The month the game was played, from `1`
+        month: u32,
+        ///The number at the end of the game's URL
+        id: String,
+    },
+}
+
+impl ExternalGame {
+    ///Reads a Lichess game from its URL, eg. `https://lichess.org/abcdEFGH/black`, or just its id
+    ///
+    /// # Errors
+    /// - There isn't a game id in it
+    pub fn lichess(url_or_id: &str) -> Result<Self> {
+        let id = url_segments(url_or_id)
+            .find(|s| s.len() >= 8 && s.chars().all(|c| c.is_ascii_alphanumeric()))
+            .ae()
+            .with_context(|| format!("no lichess game id in {url_or_id:?}"))?;
+        //links for one player add 4 more characters
+        Ok(Self::Lichess {
+            id: id[..8].to_string(),
+        })
+    }
+
+    ///Reads a Chess.com game from the player, the month it was played as `YYYY/MM`, and its URL, eg. `https://www.chess.com/game/live/123456789`, or just its id
+    ///
+    /// # Errors
+    /// - The month isn't `YYYY/MM`
+    /// - There isn't a game id in the URL
+    pub fn chess_com(player: &str, month: &str, url_or_id: &str) -> Result<Self> {
+        let (year, month) = month
+            .split_once(['/', '-'])
+            .and_then(|(y, m)| Some((y.parse::<u32>().ok()?, m.parse::<u32>().ok()?)))
+            .filter(|(_, m)| (1..=12).contains(m))
+            .ae()
+            .with_context(|| format!("expected a month like 2024/05, found {month:?}"))?;
+        let id = url_segments(url_or_id)
+            .find(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+            .ae()
+            .with_context(|| format!("no chess.com game id in {url_or_id:?}"))?;
+
+        Ok(Self::ChessCom {
+            player: player.to_lowercase(),
+            year,
+            month,
+            id: id.to_string(),
+        })
+    }
+}
+
+///Gets the parts of a URL between slashes, last first, without any query or fragment
+fn url_segments(url: &str) -> impl Iterator<Item = &str> {
+    let url = url.trim();
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    url[..end].trim_end_matches('/').rsplit('/')
+}
+
+///A game from the Lichess game export API, as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LichessGame {
+    ///The id of the game
+    pub id: String,
+    ///The moves in Standard Algebraic Notation, separated by spaces
+    pub moves: String,
+    ///The starting position, if the game didn't start from the standard one
+    #[serde(default)]
+    pub initial_fen: Option<String>,
+    ///The rules the game was played with, eg. `standard` or `chess960`
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+///A month of a player's games from the Chess.com public API
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChessComArchive {
+    ///Every game the player finished that month
+    pub games: Vec<ChessComGame>,
+}
+
+///A game from the Chess.com public API
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChessComGame {
+    ///The URL of the game, ending in its id
+    pub url: String,
+    ///The game as PGN
+    #[serde(default)]
+    pub pgn: Option<String>,
+    ///The rules the game was played with, eg. `chess` or `chess960`
+    #[serde(default)]
+    pub rules: Option<String>,
+}
+
+///Imports games from Lichess and Chess.com into [`MoveHistory`]s.
+///
+/// Uses a [`ServerClient`] for each site, with the user agent and client version from the [`ClientConfig`] - but never the API key, which is only for the chess server.
+#[derive(Debug, Clone)]
+pub struct GameImporter {
+    ///Client for the [`LICHESS_API`]
+    lichess: ServerClient,
+    ///Client for the [`CHESS_COM_API`]
+    chess_com: ServerClient,
+}
+
+impl GameImporter {
+    ///Creates a new `GameImporter`, identifying itself like the [`ClientConfig`]
+    ///
+    /// # Errors
+    /// - Fail to create either [`ServerClient`]
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let for_site = |server: &str| {
+            ServerClient::new(&ClientConfig {
+                server: server.into(),
+                backup_servers: vec![],
+                api_key: None,
+                record_session: false,
+                playback: None,
+                ..config.clone()
+            })
+        };
+
+        Ok(Self {
+            lichess: for_site(LICHESS_API).context("making lichess client")?,
+            chess_com: for_site(CHESS_COM_API).context("making chess.com client")?,
+        })
+    }
+
+    ///Fetches a game, and reads its moves into a [`MoveHistory`]
+    ///
+    /// # Errors
+    /// - The site can't be reached, or responds with an error
+    /// - The response isn't what was expected
+    /// - The game can't be found, or isn't standard chess
+    /// - The moves can't be read - see [`MoveHistory::from_pgn`]
+    #[tracing::instrument(skip(self))]
+    pub fn import(&self, game: &ExternalGame) -> Result<MoveHistory> {
+        match game {
+            ExternalGame::Lichess { id } => {
+                let rsp = self
+                    .lichess
+                    .send(
+                        self.lichess
+                            .get(&format!("game/export/{id}"))
+                            .header(ACCEPT, "application/json"),
+                    )
+                    .context("fetching lichess game")?;
+                if rsp.is_error() {
+                    return Err(ServerError::from_response(rsp.status.as_u16(), &rsp.body))
+                        .context("fetching lichess game");
+                }
+
+                let game: LichessGame = parse_response("lichess game", &rsp.body)?;
+                if game.variant.as_deref().is_some_and(|v| v != "standard") {
+                    bail!("only standard chess can be imported");
+                }
+                let pgn = match &game.initial_fen {
+                    Some(fen) => format!("[FEN \"{fen}\"]\n\n{}", game.moves),
+                    None => game.moves.clone(),
+                };
+                info!(id=%game.id, "Imported lichess game");
+                MoveHistory::from_pgn(&pgn).context("reading lichess moves")
+            }
+            ExternalGame::ChessCom {
+                player,
+                year,
+                month,
+                id,
+            } => {
+                let rsp = self
+                    .chess_com
+                    .send(
+                        self.chess_com
+                            .get(&format!("pub/player/{player}/games/{year}/{month:02}")),
+                    )
+                    .context("fetching chess.com games")?;
+                if rsp.is_error() {
+                    return Err(ServerError::from_response(rsp.status.as_u16(), &rsp.body))
+                        .context("fetching chess.com games");
+                }
+
+                //a month of games is often more than the server's responses are limited to
+                let archive: ChessComArchive =
+                    serde_json::from_str(&rsp.body).context("reading chess.com games")?;
+                let game = archive
+                    .games
+                    .into_iter()
+                    .find(|g| g.url.trim_end_matches('/').ends_with(&format!("/{id}")))
+                    .ae()
+                    .with_context(|| format!("{player} has no game {id} in {year}/{month:02}"))?;
+                if game.rules.as_deref().is_some_and(|r| r != "chess") {
+                    bail!("only standard chess can be imported");
+                }
+                let pgn = game.pgn.ae().context("chess.com game has no pgn")?;
+                info!(%id, "Imported chess.com game");
+                MoveHistory::from_pgn(&pgn).context("reading chess.com moves")
+            }
+        }
+    }
+}
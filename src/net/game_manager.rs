@@ -0,0 +1,382 @@
+use crate::util::task_handle::{CancelToken, TaskHandle};
+use anyhow::{Context as _, Result};
+use epac_utils::{error_ext::ErrorExt, time_based_structs::do_on_interval::DoOnInterval};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::{
+    list_refresher::{BoardMessage, ListRefresher, MessageToGame, MessageToWorker},
+    rate_limiter::RateLimit,
+    server_client::{ClientConfig, ServerClient},
+    server_interface::{fetch_boards, JSONBoardState},
+};
+
+///A [`MessageToGame`] tagged with the id of the game it came from
+#[derive(Debug)]
+pub struct GameMessage {
+    ///The id of the game the message is about
+    pub id: u32,
+    ///The message from that game's [`ListRefresher`]
+    pub msg: MessageToGame,
+}
+
+///Struct to follow several games at once.
+///
+/// Owns one [`ListRefresher`] per game, and multiplexes all of their messages into one channel of [`GameMessage`]s. Rather than each refresher polling on its own timer, the manager fetches every game in one request per interval using [`fetch_boards`], so following more games doesn't mean more requests. If the server doesn't have the bulk endpoint, it polls one game per interval in a round-robin instead.
+pub struct GameManager {
+    ///The refreshers, keyed by game id. A [`BTreeMap`] so that the round-robin order is stable.
+    refreshers: BTreeMap<u32, ListRefresher>,
+    ///Identity of the player, passed to every new [`ListRefresher`]
+    player: String,
+    ///The rate limit, passed to every new [`ListRefresher`]
+    rate_limit: RateLimit,
+    ///The client configuration, passed to every new [`ListRefresher`] and used for the bulk fetches
+    client_config: ClientConfig,
+    ///Timer for when to poll the next game
+    poll_timer: DoOnInterval,
+    ///Index into `refreshers` of the next game to poll
+    next_poll: usize,
+    ///Client used for the bulk fetches - `None` if it couldn't be built, in which case each game is polled on its own
+    client: Option<ServerClient>,
+    ///The bulk fetch in flight, if any - a new one isn't started until it finishes. Gives back the ids of the games the server left out.
+    bulk: Option<TaskHandle<Vec<u32>>>,
+    ///Whether or not the server has the bulk endpoint - cleared the first time it says it doesn't
+    bulk_supported: Arc<AtomicBool>,
+    ///Sender for the multiplexed messages
+    tx: Sender<GameMessage>,
+    ///Receiver for the multiplexed messages
+    rx: Receiver<GameMessage>,
+}
+
+impl GameManager {
+    ///Creates a new `GameManager` with no games.
+    ///
+    /// `poll_interval` is the time between bulk fetches. Without the bulk endpoint, or a client to make bulk fetches with, it is the time between polls of *any* game, so each game is polled every `poll_interval * number of games`.
+    ///
+    /// Every game is followed using `rate_limit` and `client_config` - see [`ListRefresher::with_config`].
+    #[must_use]
+    pub fn new(
+        player: String,
+        poll_interval: Duration,
+        rate_limit: RateLimit,
+        client_config: ClientConfig,
+    ) -> Self {
+        let (tx, rx) = channel();
+        let client = match ServerClient::new(&client_config) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                warn!(%e, "Unable to build bulk client, polling games one at a time");
+                None
+            }
+        };
+        let bulk_supported = client.is_some();
+        Self {
+            refreshers: BTreeMap::new(),
+            player,
+            rate_limit,
+            client_config,
+            poll_timer: DoOnInterval::new(poll_interval),
+            next_poll: 0,
+            client,
+            bulk: None,
+            bulk_supported: Arc::new(AtomicBool::new(bulk_supported)),
+            tx,
+            rx,
+        }
+    }
+
+    ///Starts following a game. Does nothing if the game is already being followed.
+    pub fn add_game(&mut self, id: u32) {
+        if !self.refreshers.contains_key(&id) {
+            info!(%id, "Following game");
+            let refresher = ListRefresher::with_config(
+                id,
+                self.player.clone(),
+                self.rate_limit,
+                self.client_config.clone(),
+            );
+            self.refreshers.insert(id, refresher);
+        }
+    }
+
+    ///Stops following a game, telling the server we're done with it.
+    ///
+    /// # Errors
+    /// Can fail if the refresher can't be told to stop
+    pub fn remove_game(&mut self, id: u32) -> Result<()> {
+        if let Some(refresher) = self.refreshers.remove(&id) {
+            info!(%id, "No longer following game");
+            refresher
+                .send_msg(MessageToWorker::InvalidateKill)
+                .with_context(|| format!("sending invalidatekill to {id}"))?;
+        }
+        Ok(())
+    }
+
+    ///Gets the ids of all of the games being followed, in ascending order
+    #[must_use]
+    pub fn game_ids(&self) -> Vec<u32> {
+        self.refreshers.keys().copied().collect()
+    }
+
+    ///Sends a message to the refresher for a given game
+    ///
+    /// # Errors
+    /// - The game isn't being followed
+    /// - There is an error sending the message
+    pub fn send_msg(&self, id: u32, m: MessageToWorker) -> Result<()> {
+        self.refreshers
+            .get(&id)
+            .ok_or_else(|| anyhow!("not following game {id}"))?
+            .send_msg(m)
+            .with_context(|| format!("sending message to {id}"))
+    }
+
+    ///Forwards all waiting messages from the refreshers into the multiplexed channel, and polls the games if the timer allows.
+    ///
+    /// Should be called often - eg. on every update tick.
+    pub fn update(&mut self) {
+        for refresher in self.refreshers.values_mut() {
+            refresher.restart_if_wedged();
+        }
+
+        for (id, refresher) in &self.refreshers {
+            loop {
+                match refresher.try_recv() {
+                    Ok(msg) => {
+                        self.tx
+                            .send(GameMessage { id: *id, msg })
+                            .context("forwarding game message")
+                            .warn();
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        warn!(%id, "Refresher disconnected");
+                        break;
+                    }
+                }
+            }
+        }
+
+        if self.refreshers.is_empty() {
+            return;
+        }
+
+        if self.bulk.as_ref().is_some_and(TaskHandle::is_finished) {
+            match self.bulk.take().map(TaskHandle::join) {
+                Some(Ok(left_out)) => poll_left_out(left_out, |id, m| self.send_msg(id, m)),
+                Some(Err(e)) => warn!(?e, "Error joining bulk fetch"),
+                None => {}
+            }
+        }
+
+        if let Some(_doiu) = self.poll_timer.get_updater() {
+            let bulk_client = self
+                .client
+                .as_ref()
+                .filter(|_| self.bulk_supported.load(Ordering::SeqCst));
+            if let Some(client) = bulk_client {
+                if self.bulk.is_none() {
+                    self.bulk = Some(self.spawn_bulk_fetch(client.clone()));
+                }
+                return;
+            }
+
+            let next = round_robin(&mut self.next_poll, self.refreshers.len());
+            if let Some((id, refresher)) = next.and_then(|i| self.refreshers.iter().nth(i)) {
+                refresher
+                    .send_msg(MessageToWorker::UpdateNOW)
+                    .with_context(|| format!("polling game {id}"))
+                    .warn();
+            }
+        }
+    }
+
+    ///Starts fetching the boards of every game in one request, sending each one on as a [`BoardMessage::NewList`]. Games the server left out are polled on their own.
+    fn spawn_bulk_fetch(&self, client: ServerClient) -> TaskHandle<Vec<u32>> {
+        let ids = self.game_ids();
+        let (tx, supported) = (self.tx.clone(), self.bulk_supported.clone());
+
+        TaskHandle::spawn(move |cancel| {
+            forward_bulk(fetch_boards(&client, &ids), ids, &tx, &supported, cancel)
+        })
+    }
+
+    ///Tries to receive a message from any of the games in a non-blocking fashion
+    ///
+    /// # Errors
+    /// - There is no message
+    pub fn try_recv(&self) -> Result<GameMessage, TryRecvError> {
+        self.rx.try_recv()
+    }
+}
+
+///Gets the index of the next game to poll out of `games`, and moves `next_poll` on past it. Wraps round to the first game if `next_poll` has gone past the end - including when games have been removed since the last poll.
+fn round_robin(next_poll: &mut usize, games: usize) -> Option<usize> {
+    if games == 0 {
+        return None;
+    }
+    let i = *next_poll % games;
+    *next_poll = i + 1;
+    Some(i)
+}
+
+///Sends on each board from a bulk fetch of `ids` as a [`BoardMessage::NewList`], and gets the ids of the games the server left out. Clears `supported` if the server doesn't have the bulk endpoint.
+fn forward_bulk(
+    fetched: Result<Option<BTreeMap<u32, JSONBoardState>>>,
+    ids: Vec<u32>,
+    tx: &Sender<GameMessage>,
+    supported: &AtomicBool,
+    cancel: &CancelToken,
+) -> Vec<u32> {
+    match fetched {
+        Ok(Some(mut boards)) => {
+            let mut left_out = vec![];
+            for id in ids {
+                if cancel.is_cancelled() {
+                    break;
+                }
+
+                if let Some(state) = boards.remove(&id) {
+                    let msg = MessageToGame::UpdateBoard(BoardMessage::NewList(state));
+                    tx.send(GameMessage { id, msg })
+                        .context("sending bulk board")
+                        .warn();
+                } else {
+                    left_out.push(id);
+                }
+            }
+            left_out
+        }
+        Ok(None) => {
+            info!("Server doesn't have the bulk endpoint, polling each game instead");
+            supported.store(false, Ordering::SeqCst);
+            vec![]
+        }
+        Err(e) => {
+            warn!(?e, "Error fetching boards in bulk");
+            vec![]
+        }
+    }
+}
+
+///Polls each game left out of a bulk fetch on its own, using `send` to tell its refresher
+fn poll_left_out(left_out: Vec<u32>, mut send: impl FnMut(u32, MessageToWorker) -> Result<()>) {
+    for id in left_out {
+        send(id, MessageToWorker::UpdateNOW)
+            .context("polling game left out of bulk fetch")
+            .warn();
+    }
+}
+
+impl Drop for GameManager {
+    fn drop(&mut self) {
+        if let Some(bulk) = &self.bulk {
+            bulk.cancel();
+        }
+        //the refreshers join their threads on drop, which only finish once told to stop
+        for id in self.game_ids() {
+            self.remove_game(id).context("stopping game on drop").warn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::server_interface::JSONPieceList;
+
+    ///Gets an empty board, as the server would send it
+    fn empty_board() -> JSONBoardState {
+        JSONBoardState::Flat(JSONPieceList::default())
+    }
+
+    #[test]
+    fn round_robin_wraps_after_a_game_is_removed() {
+        let mut next_poll = 0;
+        let polled: Vec<_> = (0..4).map(|_| round_robin(&mut next_poll, 3)).collect();
+        assert_eq!(polled, [Some(0), Some(1), Some(2), Some(0)]);
+
+        round_robin(&mut next_poll, 3);
+        //the last of the three games is removed, so the one it was going to poll next is gone
+        assert_eq!(round_robin(&mut next_poll, 2), Some(0));
+        assert_eq!(round_robin(&mut next_poll, 2), Some(1));
+
+        assert_eq!(round_robin(&mut next_poll, 0), None);
+    }
+
+    #[test]
+    fn missing_bulk_endpoint_stops_bulk_fetches() {
+        let (tx, rx) = channel();
+        let supported = AtomicBool::new(true);
+
+        let left_out = forward_bulk(
+            Ok(None),
+            vec![1, 2],
+            &tx,
+            &supported,
+            &CancelToken::default(),
+        );
+        assert!(left_out.is_empty());
+        assert!(!supported.load(Ordering::SeqCst));
+        assert!(rx.try_recv().is_err());
+
+        //other errors might not last, so bulk fetches are tried again
+        let supported = AtomicBool::new(true);
+        forward_bulk(
+            Err(anyhow!("timed out")),
+            vec![1, 2],
+            &tx,
+            &supported,
+            &CancelToken::default(),
+        );
+        assert!(supported.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn games_left_out_of_a_bulk_fetch_are_polled() {
+        let (tx, rx) = channel();
+        let supported = AtomicBool::new(true);
+        let boards = BTreeMap::from([(1, empty_board()), (3, empty_board())]);
+
+        let left_out = forward_bulk(
+            Ok(Some(boards)),
+            vec![1, 2, 3, 4],
+            &tx,
+            &supported,
+            &CancelToken::default(),
+        );
+        assert_eq!(left_out, [2, 4]);
+        let forwarded: Vec<_> = rx
+            .try_iter()
+            .map(|GameMessage { id, msg }| {
+                assert!(matches!(
+                    msg,
+                    MessageToGame::UpdateBoard(BoardMessage::NewList(_))
+                ));
+                id
+            })
+            .collect();
+        assert_eq!(forwarded, [1, 3]);
+
+        let mut polled = vec![];
+        poll_left_out(left_out, |id, m| {
+            polled.push((id, m));
+            Ok(())
+        });
+        assert_eq!(
+            polled,
+            [
+                (2, MessageToWorker::UpdateNOW),
+                (4, MessageToWorker::UpdateNOW)
+            ]
+        );
+    }
+}
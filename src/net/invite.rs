@@ -0,0 +1,198 @@
+use crate::net::server_client::{ClientConfig, ServerClient};
+use anyhow::{Context as _, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+///Scheme and path at the start of every invite link
+pub const INVITE_PREFIX: &str = "asyncchess://join";
+
+///What the invited player is asked to join as
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InviteRole {
+    ///Play the white pieces
+    White,
+    ///Play the black pieces
+    Black,
+    ///Watch without moving
+    Spectator,
+}
+
+impl InviteRole {
+    ///Gets the name used in invite links
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::White => "white",
+            Self::Black => "black",
+            Self::Spectator => "spectator",
+        }
+    }
+
+    ///Gets the colour this role plays as - `None` for spectators
+    #[must_use]
+    pub const fn is_white(self) -> Option<bool> {
+        match self {
+            Self::White => Some(true),
+            Self::Black => Some(false),
+            Self::Spectator => None,
+        }
+    }
+}
+
+///An invitation to a game - everything needed to join it.
+///
+/// Shared as a link like `asyncchess://join?server=http%3A%2F%2Fexample.com&id=7&role=white`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    ///The base URL of the server the game is on
+    pub server: String,
+    ///The game id
+    pub id: u32,
+    ///What the invited player should join as - `None` if it is up to them
+    pub role: Option<InviteRole>,
+}
+
+impl Invite {
+    ///Creates the invite link
+    ///
+    /// # Errors
+    /// - Fail to build the URL
+    pub fn to_link(&self) -> Result<String> {
+        let id = self.id.to_string();
+        let mut params = vec![("server", self.server.as_str()), ("id", id.as_str())];
+        if let Some(role) = self.role {
+            params.push(("role", role.as_str()));
+        }
+
+        Url::parse_with_params(INVITE_PREFIX, &params)
+            .context("building invite link")
+            .map(String::from)
+    }
+
+    ///Parses an invite link. The game id can also be given as `game`, eg. `asyncchess://join?server=http%3A%2F%2Fexample.com&game=7`.
+    ///
+    /// # Errors
+    /// - It isn't an invite link
+    /// - The server or id are missing
+    /// - The id or role aren't valid
+    pub fn parse(link: &str) -> Result<Self> {
+        let link = link.trim();
+        if !link.starts_with(INVITE_PREFIX) {
+            bail!("invite links start with {INVITE_PREFIX}");
+        }
+        let url = Url::parse(link).context("parsing invite link")?;
+
+        let (mut server, mut id, mut role) = (None, None, None);
+        for (k, v) in url.query_pairs() {
+            match k.as_ref() {
+                "server" => server = Some(v.trim_end_matches('/').to_string()),
+                "id" | "game" => {
+                    let parsed = v
+                        .parse()
+                        .with_context(|| format!("parsing game id {v:?}"))?;
+                    id = Some(parsed);
+                }
+                "role" => {
+                    role = Some(match v.as_ref() {
+                        "white" => InviteRole::White,
+                        "black" => InviteRole::Black,
+                        "spectator" => InviteRole::Spectator,
+                        _ => bail!("unknown role {v:?}"),
+                    });
+                }
+                _ => warn!(%k, "Ignoring unknown invite parameter"),
+            }
+        }
+
+        Ok(Self {
+            server: server.context("invite is missing the server")?,
+            id: id.context("invite is missing the game id")?,
+            role,
+        })
+    }
+
+    ///Checks that the game exists on the invite's server, identifying to it using the rest of the given [`ClientConfig`]
+    ///
+    /// # Errors
+    /// - Fail to build the [`ServerClient`]
+    /// - Fail to reach the server
+    /// - The server doesn't have the game
+    #[tracing::instrument(skip(config))]
+    pub fn verify(&self, config: &ClientConfig) -> Result<()> {
+        let client = ServerClient::new(&ClientConfig {
+            server: self.server.clone(),
+            backup_servers: vec![],
+            ..config.clone()
+        })?;
+
+        let rsp = client
+            .send(client.get(&format!("games/{}", self.id)))
+            .context("reaching server")?;
+        if rsp.is_error() {
+            bail!("finding game {} - server responded {}", self.id, rsp.status);
+        }
+        Ok(())
+    }
+}
+
+impl Display for Invite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "game {} on {}", self.id, self.server)?;
+        if let Some(role) = self.role {
+            write!(f, " as {}", role.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Gets an invite to game 7 on a server with a path, so it needs escaping in the link
+    fn invite(role: Option<InviteRole>) -> Invite {
+        Invite {
+            server: "http://example.com:8080/chess".into(),
+            id: 7,
+            role,
+        }
+    }
+
+    #[test]
+    fn links_round_trip() {
+        for role in [
+            None,
+            Some(InviteRole::White),
+            Some(InviteRole::Black),
+            Some(InviteRole::Spectator),
+        ] {
+            let link = invite(role).to_link().unwrap();
+            assert!(link.starts_with(INVITE_PREFIX), "{link}");
+            assert_eq!(Invite::parse(&link).unwrap(), invite(role), "{link}");
+        }
+    }
+
+    #[test]
+    fn parses_hand_written_links() {
+        let parsed = Invite::parse(
+            "  asyncchess://join?server=http%3A%2F%2Fexample.com%3A8080%2Fchess%2F&game=7&colour=red\n",
+        )
+        .unwrap();
+        assert_eq!(parsed, invite(None));
+    }
+
+    #[test]
+    fn rejects_bad_links() {
+        for link in [
+            "https://example.com/join?server=http%3A%2F%2Fexample.com&id=7",
+            "asyncchess://join?id=7",
+            "asyncchess://join?server=http%3A%2F%2Fexample.com",
+            "asyncchess://join?server=http%3A%2F%2Fexample.com&id=seven",
+            "asyncchess://join?server=http%3A%2F%2Fexample.com&id=-1",
+            "asyncchess://join?server=http%3A%2F%2Fexample.com&id=7&role=referee",
+        ] {
+            assert!(Invite::parse(link).is_err(), "{link}");
+        }
+    }
+}
@@ -1,34 +1,47 @@
 use anyhow::{Context as _, Result};
-use reqwest::{
-    blocking::{Client, ClientBuilder},
-    StatusCode,
-};
+use reqwest::{blocking::RequestBuilder, header::CACHE_CONTROL, StatusCode};
 use std::{
+    collections::VecDeque,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::{channel, Receiver, SendError, Sender, TryRecvError},
-        Arc, Mutex,
+        mpsc::{TryRecvError, TrySendError},
+        Arc, Mutex, OnceLock,
     },
-    thread::JoinHandle,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use epac_utils::either::Either;
-use epac_utils::error_ext::{ErrorExt, MutexExt, ToAnyhowThreadErr};
+use epac_utils::error_ext::{ErrorExt, MutexExt};
 use epac_utils::time_based_structs::do_on_interval::DoOnInterval;
-use epac_utils::time_based_structs::memcache::MemoryTimedCacher;
-use epac_utils::time_based_structs::scoped_timers::ThreadSafeScopedToListTimer;
 
 use crate::{
+    chess::{
+        boards::{board::DrawReason, pattern::OfflineBoard},
+        history::MoveHistory,
+    },
+    metrics::metrics,
     prelude::{DoOnInterval, Either, ErrorExt},
     util::{
-        error_ext::{MutexExt, ToAnyhowThreadErr},
-        time_based_structs::{
-            memcache::MemoryTimedCacher, scoped_timers::ThreadSafeScopedToListTimer,
-        },
+        error_ext::MutexExt,
+        task_handle::{CancelToken, TaskHandle},
     },
 };
 
-use super::server_interface::{JSONMove, JSONPieceList};
+use super::{
+    clock_sync::{ClockSkew, HANDSHAKE_SAMPLES},
+    counted_channel::{
+        bounded_channel, Backpressure, BoundedReceiver, BoundedSender, HasBackpressure,
+    },
+    events::{game_events, GameEvent},
+    rate_limiter::{RateLimit, TokenBucket},
+    recorder::NetworkRecorder,
+    request_stats::RequestSample,
+    server_client::{ClientConfig, ServerClient, ServerResponse},
+    server_interface::{
+        parse_board_update, parse_response, JSONBoardState, JSONBoardUpdate, JSONCapabilities,
+        JSONDrawClaim, JSONDrawReason, JSONHeartbeat, JSONHeartbeatResponse, JSONHistory,
+        JSONMove, PresenceState, ResponseDiagnostic, ServerError,
+    },
+};
 
 ///Enum for sending a message to the worker
 #[derive(Debug, PartialEq, Eq)]
@@ -43,6 +56,53 @@ pub enum MessageToWorker {
     InvalidateKill,
     ///Ask the server to make a move
     MakeMove(JSONMove),
+    ///Claim a draw from the server, for this reason - only for servers with [`JSONCapabilities::draw_claims`]
+    ClaimDraw(DrawReason),
+    ///Fetch the whole board from the server, without a conditional request, because it doesn't match the server's checksum
+    Resync,
+    ///Ask the server for the whole history of the game, for when only the current position is known
+    FetchHistory,
+    ///Act as though the server can't be reached (or stop doing so), for testing what happens when offline
+    SimulateOffline(bool),
+    ///Refresh every [`BURST_REFRESH_INTERVAL`] rather than waiting for the [`DoOnInterval`], for this long - eg. after the player comes back to the window, when a new move is most likely to be looked for
+    PollBurst(Duration),
+    ///Do several of these in order, as one unit - nothing sent in the meantime is done between them, and each starts once the requests before it have finished
+    Batch(Vec<MessageToWorker>),
+}
+
+impl MessageToWorker {
+    ///Gets the messages to do in order - the messages in a [`MessageToWorker::Batch`], with any batches inside it flattened, or just this message
+    #[must_use]
+    pub fn into_steps(self) -> Vec<MessageToWorker> {
+        match self {
+            Self::Batch(msgs) => msgs.into_iter().flat_map(Self::into_steps).collect(),
+            m => vec![m],
+        }
+    }
+}
+
+///Most messages which can wait for the worker - refresh requests are sent every frame, so a stalled worker would otherwise build up a backlog
+pub const TO_WORKER_CAPACITY: usize = 16;
+///Most messages which can wait for the game
+pub const TO_GAME_CAPACITY: usize = 64;
+///How long a sender waits for room for a message which can't be thrown away
+pub const SEND_TIMEOUT: Duration = Duration::from_millis(250);
+
+impl HasBackpressure for MessageToWorker {
+    ///Refresh requests are replaced by newer ones, moves fail if the worker can't take them soon, and anything which changes what the worker does waits for room
+    fn backpressure(&self) -> Backpressure {
+        match self {
+            Self::UpdateList | Self::UpdateNOW | Self::Resync | Self::FetchHistory => {
+                Backpressure::DropOldest
+            }
+            Self::MakeMove(_) | Self::ClaimDraw(_) => Backpressure::Block(SEND_TIMEOUT),
+            Self::RestartBoard => Backpressure::Fail,
+            Self::InvalidateKill
+            | Self::SimulateOffline(_)
+            | Self::PollBurst(_)
+            | Self::Batch(_) => Backpressure::Block(SEND_TIMEOUT),
+        }
+    }
 }
 
 ///Enum for sending a message back to the game
@@ -50,6 +110,40 @@ pub enum MessageToWorker {
 pub enum MessageToGame {
     ///Update the board
     UpdateBoard(BoardMessage),
+    ///The server has told us whether or not the opponent is around
+    OpponentPresence(PresenceState),
+    ///The server sent a response which failed validation
+    InvalidResponse(ResponseDiagnostic),
+    ///A request to the server has finished
+    RequestFinished(RequestSample),
+    ///The server sent the whole history of the game
+    History(MoveHistory),
+    ///The server sent the checksum of its board along with the last board update - see [`crate::net::server_client::CHECKSUM_HEADER`]
+    Checksum(u64),
+    ///Whether or not the worker has a [`ServerClient`] to make requests with has changed
+    Connection(ConnectionState),
+    ///Response from the server on a draw claim
+    DrawClaim(DrawClaimOutcome),
+}
+
+///The outcome of a draw claim from the server
+#[derive(Debug)]
+pub enum DrawClaimOutcome {
+    ///The server agreed, and the game is drawn for this reason
+    Accepted(DrawReason),
+    ///The server said the draw can't be claimed
+    Rejected(ServerError),
+    ///The request from `reqwest` failed
+    CouldntProcessClaim,
+}
+
+///Whether or not the worker can make requests at all - separate from whether the server can be reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    ///The [`ServerClient`] has been built
+    Available,
+    ///The [`ServerClient`] couldn't be built, eg. if no TLS backend could be loaded, for this reason. Building it is tried again every [`CLIENT_RETRY_INTERVAL`].
+    Unavailable(String),
 }
 
 ///Enum for messages to the game, relating to the board
@@ -61,10 +155,29 @@ pub enum BoardMessage {
     Move(MoveOutcome),
     ///The board hasn't changed since the last update
     UseExisting,
-    ///No connection - use the [`crate::server_interface::no_connection_list`]
-    NoConnectionList,
+    ///The server's board can't be shown - use the [`OfflineBoard::board`] for why
+    Offline(OfflineBoard),
     ///The board has changed, use all of these pieces
-    NewList(JSONPieceList),
+    NewList(JSONBoardState),
+    ///The board has changed by these moves, in order - apply them to the current board
+    ApplyMoves(Vec<JSONMove>),
+    ///These moves were never sent, oldest first, as a move before them was rejected or couldn't be sent - they were made on a board with that move
+    Dropped(Vec<JSONMove>),
+}
+
+impl HasBackpressure for MessageToGame {
+    ///Moves have to arrive, in order, and one-off replies like draw claims and the history aren't sent again, so wait for room - anything else is replaced by newer messages
+    fn backpressure(&self) -> Backpressure {
+        match self {
+            Self::UpdateBoard(
+                BoardMessage::TmpMove(_) | BoardMessage::Move(_) | BoardMessage::Dropped(_),
+            )
+            | Self::Connection(_)
+            | Self::DrawClaim(_)
+            | Self::History(_) => Backpressure::Block(SEND_TIMEOUT),
+            _ => Backpressure::DropOldest,
+        }
+    }
 }
 
 ///The outcome of a move from the server
@@ -72,189 +185,747 @@ pub enum BoardMessage {
 pub enum MoveOutcome {
     ///The move worked and was successful. Bool signifies whether or not a piece was taken
     Worked(bool),
-    ///The move is invalid, and should be undone
-    Invalid,
+    ///The server rejected the move, and it should be undone
+    Invalid(ServerError),
     ///The request from `reqwest` failed
     CouldntProcessMove,
 }
 
+///How long the main thread waits for requests still in flight when it ends, before abandoning them
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+///Time between tries at building the [`ServerClient`], if it couldn't be built
+pub const CLIENT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+///Shortest time between board refreshes
+pub const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+///Shortest time between board refreshes with [`ClientConfig::low_bandwidth`] on
+pub const LOW_BANDWIDTH_REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+///Shortest time between board refreshes during a [`MessageToWorker::PollBurst`]
+pub const BURST_REFRESH_INTERVAL: Duration = Duration::from_millis(150);
+///How long a board refresh can take before it is given up on
+pub const REFRESH_TIMEOUT: Duration = Duration::from_secs(10);
+///The longest each step of a [`MessageToWorker::Batch`] waits for the requests before it to finish
+pub const BATCH_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+///How long the main thread can go without dealing with a message while others wait, before it counts as stuck and is restarted by [`ListRefresher::restart_if_wedged`]
+pub const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(30);
+
+///Shared between a [`ListRefresher`] and its main thread, so the thread can be seen to be making progress - and told to give up if it has been replaced
+#[derive(Debug)]
+struct Liveness {
+    ///When the main thread last dealt with a message
+    last_beat: Mutex<Instant>,
+    ///Whether the main thread has been replaced, so it should end without doing anything else
+    abandoned: AtomicBool,
+}
+
+impl Liveness {
+    ///Makes a new `Liveness`, which last beat now
+    fn new() -> Self {
+        Self {
+            last_beat: Mutex::new(Instant::now()),
+            abandoned: AtomicBool::new(false),
+        }
+    }
+
+    ///Records that the main thread is making progress
+    fn beat(&self) {
+        *self.last_beat.lock_panic("worker liveness") = Instant::now();
+    }
+
+    ///Gets how long it has been since the main thread last made progress
+    fn since_beat(&self) -> Duration {
+        self.last_beat.lock_panic("worker liveness").elapsed()
+    }
+
+    ///Tells the main thread it has been replaced
+    fn abandon(&self) {
+        self.abandoned.store(true, Ordering::SeqCst);
+    }
+
+    ///Gets whether the main thread has been replaced
+    fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::SeqCst)
+    }
+}
+
 ///Struct to refresh the board and deal with requests to the server, using multi-threading and channels
 pub struct ListRefresher {
     ///Handle to hold the main thread.
     ///
-    ///It is an `Option` because that makes it ownable for [`Drop::drop`] using [`std::mem::take`] as you need to own a [`JoinHandle`] to [`JoinHandle::join`] it to receive any errors.
-    handle: Option<JoinHandle<()>>,
+    ///It is an `Option` because that makes it ownable for [`Drop::drop`] using [`std::mem::take`] as you need to own a [`TaskHandle`] to [`TaskHandle::join`] it to receive any errors.
+    handle: Option<TaskHandle<()>>,
     ///Sender to send messages to the main thread
-    tx: Sender<MessageToWorker>,
+    tx: BoundedSender<MessageToWorker>,
     ///Receiver for messages sent from the main thread to send them to the game.
-    rx: Receiver<MessageToGame>,
+    rx: BoundedReceiver<MessageToGame>,
+    ///A clone of the main thread's [`ServerClient`], to read what it has found out about the server - unset until it has been built
+    client: Arc<OnceLock<ServerClient>>,
+    ///Whether the main thread is still making progress
+    liveness: Arc<Liveness>,
+    ///The game id, kept to restart the main thread
+    id: u32,
+    ///The player, kept to restart the main thread
+    player: String,
+    ///The rate limit, kept to restart the main thread
+    rate_limit: RateLimit,
+    ///The client configuration, kept to restart the main thread
+    client_config: ClientConfig,
+}
+
+///Spawns a thread as a [`TaskHandle`], keeping the `threads.workers` gauge in the [`metrics`] registry up to date with how many are running
+fn spawn_worker<T: Send + 'static>(
+    f: impl FnOnce(&CancelToken) -> T + Send + 'static,
+) -> TaskHandle<T> {
+    metrics().add_to_gauge("threads.workers", 1);
+    TaskHandle::spawn(move |cancel| {
+        let _guard = WorkerGuard;
+        f(cancel)
+    })
 }
 
-///Run the loop - this should be called from a new thread as it blocks heavily until the [`Receiver`] is closed
+///Moves waiting to be sent to the server, oldest first. They are sent one at a time, so the game hears about each one in the order they were made.
+#[derive(Debug, Default)]
+struct MoveQueue {
+    ///The moves which haven't been sent yet
+    moves: VecDeque<JSONMove>,
+    ///Whether a task is sending the moves - if not, one needs to be started when a move is added
+    sending: bool,
+}
+
+impl MoveQueue {
+    ///Adds a move to the back of the queue, returning whether a task needs to be started to send it
+    fn push(&mut self, m: JSONMove) -> bool {
+        self.moves.push_back(m);
+        !std::mem::replace(&mut self.sending, true)
+    }
+
+    ///Takes the next move to send. If there isn't one, or the sender has been `cancelled`, nothing is sending any more - and the moves are left for the next sender.
+    fn next(&mut self, cancelled: bool) -> Option<JSONMove> {
+        let next = if cancelled {
+            None
+        } else {
+            self.moves.pop_front()
+        };
+        if next.is_none() {
+            self.sending = false;
+        }
+        next
+    }
+
+    ///Gets whether any moves are waiting to be sent, or being sent
+    fn is_busy(&self) -> bool {
+        self.sending || !self.moves.is_empty()
+    }
+
+    ///Takes every move which hasn't been sent yet, oldest first - nothing is sending afterwards
+    fn clear(&mut self) -> Vec<JSONMove> {
+        self.sending = false;
+        self.moves.drain(..).collect()
+    }
+}
+
+///Sends the moves in `queue` one at a time using `send`, which returns whether the move worked, until there are none left or `cancel` is set.
+///
+/// If a move is rejected or can't be sent, the moves queued behind it were made on a board which had it, so they are dropped rather than sent, and passed to `dropped`.
+fn send_queued_moves(
+    queue: &Mutex<MoveQueue>,
+    cancel: &CancelToken,
+    mut send: impl FnMut(JSONMove) -> bool,
+    dropped: impl FnOnce(Vec<JSONMove>),
+) {
+    loop {
+        //checked and cleared under the same lock as moves are added, so none are left behind
+        let next = queue.lock_panic("move queue").next(cancel.is_cancelled());
+        let Some(m) = next else {
+            return;
+        };
+        if !send(m) {
+            let rest = queue.lock_panic("move queue").clear();
+            if !rest.is_empty() {
+                warn!(?m, ?rest, "Dropping moves queued behind a failed one");
+                dropped(rest);
+            }
+            return;
+        }
+    }
+}
+
+///Takes a thread off the `threads.workers` gauge when dropped - including when the thread panics
+struct WorkerGuard;
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        metrics().add_to_gauge("threads.workers", -1);
+    }
+}
+
+///Run the loop - this should be called from a new thread as it blocks heavily until the [`BoundedReceiver`] is closed
+///
+/// All requests apart from the final invalidate go through a [`TokenBucket`] using `rate_limit`. Refreshes, history fetches and heartbeats over the limit are skipped, as another will come along. Restarts, moves and draw claims were asked for by the player, so they are never dropped - they are made anyway using [`TokenBucket::take_anyway`], and the skippable requests are held back until the bucket has refilled.
+///
+/// Only one board refresh is in flight at once. A newer [`MessageToWorker::UpdateNOW`] or a [`MessageToWorker::MakeMove`] cancels it, so a slow refresh can't land after a newer one (or a move) and put an old board back. No refresh is started while moves are queued or being sent, for the same reason.
+///
+/// When it ends, every request still in flight is cancelled, and given up to [`SHUTDOWN_TIMEOUT`] to finish before being abandoned.
+///
+/// It beats `liveness` for each message it deals with, and ends as soon as it notices it has been abandoned for a new thread.
 ///
 /// # Errors
-/// Can return an error if the board is upating and the response cannot be marshalled into [`JSONPieceList`] or if there are errors joining threads.
+/// Can return an error if the board is upating and the response cannot be marshalled into [`JSONBoardState`] or if a request thread panicked.
 ///
-/// NB: Threads can still be running when this function ends so be careful about the receiver
+/// NB: Abandoned threads can still be running when this function ends so be careful about the receiver
 fn run_loop(
-    mtw_rx: Receiver<MessageToWorker>,
-    mtg_tx: Sender<MessageToGame>,
+    mtw_rx: BoundedReceiver<MessageToWorker>,
+    mtg_tx: BoundedSender<MessageToGame>,
     id: u32,
+    player: String,
+    rate_limit: RateLimit,
+    client: ServerClient,
+    liveness: &Liveness,
 ) -> Result<()> {
-    let update_req_inflight = Arc::new(AtomicBool::new(false));
-    let move_req_inflight = Arc::new(AtomicBool::new(false));
+    let move_queue = Arc::new(Mutex::new(MoveQueue::default()));
 
-    let client = ClientBuilder::default()
-        .user_agent("JackyBoi/AsyncChess")
-        .build()
-        .context("building client")
-        .unwrap_log_error();
-    let mut handles: Vec<JoinHandle<Result<()>>> = vec![]; //technically could be an option but easier for it to be a vec
+    let mut tasks: Vec<TaskHandle<()>> = vec![];
+    //kept apart from the other tasks so it can be superseded
+    let mut refresh: Option<TaskHandle<()>> = None;
 
-    let refresh_timer = Arc::new(Mutex::new(DoOnInterval::new(Duration::from_millis(500)))); //timer for updating board
+    let refresh_interval = if client.low_bandwidth() {
+        LOW_BANDWIDTH_REFRESH_INTERVAL
+    } else {
+        REFRESH_INTERVAL
+    };
+    let refresh_timer = Arc::new(Mutex::new(DoOnInterval::new(refresh_interval))); //timer for updating board
     let reqwest_error_at_last_refresh = Arc::new(AtomicBool::new(false));
 
-    let request_timer = Arc::new(Mutex::new(MemoryTimedCacher::<_, 150>::new(None))); //cacher for printing av requests ttr
-    let mut request_print_timer = DoOnInterval::new(Duration::from_millis(2500)); //timer for when to print av request ttr
-
-    while let Ok(msg) = mtw_rx.recv() {
-        {
-            let rt = request_timer.clone();
-            let lock = rt.lock_panic("unlocking mtc mutex");
+    let mut heartbeat_timer = DoOnInterval::new(Duration::from_secs(10)); //timer for sending heartbeats
+    let mut rate_limiter = TokenBucket::new(rate_limit);
+    let mut simulate_offline = false;
+    //when the current poll burst ends, and when it last refreshed
+    let mut burst_until: Option<Instant> = None;
+    let mut last_burst_refresh = Instant::now();
 
-            if let Some(_doiu) = request_print_timer.get_updater() {
-                let avg_ttr = lock.average_u32();
-                info!(?avg_ttr, "Average time for response");
+    {
+        let client = client.clone();
+        tasks.push(spawn_worker(move |cancel| {
+            if !cancel.is_cancelled() {
+                discover_capabilities(&client);
             }
-        }
-
-        {
-            let mut finished_indicies = vec![];
-            for (index, handle) in handles.iter().enumerate() {
-                if handle.is_finished() {
-                    finished_indicies.push(index - finished_indicies.len()); //to account for removing indicies and making the vec smaller
+            if !cancel.is_cancelled() {
+                match client.sync_clock(HANDSHAKE_SAMPLES, REFRESH_TIMEOUT) {
+                    Ok(Some(skew)) => info!(%skew, "Synced clock with server"),
+                    Ok(None) => warn!("No time from the server - assuming the clocks match"),
+                    Err(e) => warn!(%e, "Couldn't sync clock with server"),
                 }
             }
+        }));
+    }
 
-            for index in finished_indicies {
-                let handle = handles.remove(index);
-                handle
-                    .join()
-                    .ae()
-                    .context("error joining handle")?
-                    .context("error from handle")?;
+    'messages: while let Ok(batch) = mtw_rx.recv() {
+        if liveness.is_abandoned() {
+            warn!("Abandoned worker woke up, ending it");
+            break 'messages;
+        }
+        liveness.beat();
+
+        if let Some(_doiu) = heartbeat_timer.get_updater().filter(|_| !simulate_offline) {
+            if rate_limiter.try_take() {
+                let (mtg_tx, client, player) = (mtg_tx.clone(), client.clone(), player.clone());
+                tasks.push(spawn_worker(move |cancel| {
+                    if !cancel.is_cancelled() {
+                        do_heartbeat(id, player, mtg_tx, client);
+                    }
+                }));
             }
         }
 
-        match msg {
-            MessageToWorker::UpdateList | MessageToWorker::UpdateNOW => {
-                let can = if msg == MessageToWorker::UpdateNOW {
-                    true
-                } else {
-                    refresh_timer.lock_panic("refresh timer").can_do()
-                };
-                if !can {
-                    continue;
+        let (finished, running): (Vec<_>, Vec<_>) = std::mem::take(&mut tasks)
+            .into_iter()
+            .partition(TaskHandle::is_finished);
+        tasks = running;
+        for task in finished {
+            task.join().context("error joining request thread")?;
+        }
+
+        for (step, msg) in batch.into_steps().into_iter().enumerate() {
+            //each step of a batch starts once everything before it has finished
+            if step > 0 {
+                settle(&tasks, refresh.as_ref(), BATCH_STEP_TIMEOUT);
+                if liveness.is_abandoned() {
+                    break 'messages;
                 }
+                liveness.beat();
+            }
 
-                let (
-                    update_req_inflight,
-                    reqwest_error_at_last_refresh,
-                    mtg_tx,
-                    client,
-                    request_timer,
-                    refresh_timer,
-                ) = (
-                    update_req_inflight.clone(),
-                    reqwest_error_at_last_refresh.clone(),
-                    mtg_tx.clone(),
-                    client.clone(),
-                    request_timer.clone(),
-                    refresh_timer.clone(),
-                );
-
-                std::thread::spawn(move || {
-                    if !update_req_inflight.load(Ordering::SeqCst) {
-                        update_req_inflight.store(true, Ordering::SeqCst);
-                        let _st = ThreadSafeScopedToListTimer::new(request_timer);
-
-                        do_update_list(id, reqwest_error_at_last_refresh, mtg_tx, client);
-
-                        update_req_inflight.store(false, Ordering::SeqCst);
-                        refresh_timer.lock_panic("refresh timer").update_timer();
+            match msg {
+                MessageToWorker::UpdateList
+                | MessageToWorker::UpdateNOW
+                | MessageToWorker::Resync => {
+                    let bursting = burst_until.is_some_and(|until| Instant::now() < until);
+                    let can = if msg == MessageToWorker::UpdateList {
+                        (bursting && last_burst_refresh.elapsed() >= BURST_REFRESH_INTERVAL)
+                            || refresh_timer.lock_panic("refresh timer").can_do()
+                    } else {
+                        true
+                    };
+                    if !can {
+                        continue;
                     }
-                });
-            }
-            MessageToWorker::RestartBoard => {
-                let (client, rt) = (client.clone(), request_timer.clone());
-                //not added to the handles list because I don't care about the results
-                std::thread::spawn(move || {
-                    let _st = ThreadSafeScopedToListTimer::new(rt);
-                    do_restart_board(id, client);
-                });
-            }
-            MessageToWorker::MakeMove(m) => {
-                let (mtg_tx, client, rt, mr_inflight) = (
-                    mtg_tx.clone(),
-                    client.clone(),
-                    request_timer.clone(),
-                    move_req_inflight.clone(),
-                );
-                std::thread::spawn(move || {
-                    if mr_inflight.load(Ordering::SeqCst) {
+                    //a board fetched now could be from before the moves, and undo them - the next refresh after they are answered brings the new board
+                    if move_queue.lock_panic("move queue").is_busy() {
+                        debug!("Moves waiting on the server, skipping refresh");
+                        continue;
+                    }
+                    if simulate_offline {
+                        refresh_timer.lock_panic("refresh timer").update_timer();
                         mtg_tx
-                            .send(MessageToGame::UpdateBoard(BoardMessage::Move(
-                                MoveOutcome::CouldntProcessMove,
+                            .send(MessageToGame::UpdateBoard(BoardMessage::Offline(
+                                OfflineBoard::NoConnection,
                             )))
-                            .context("piece move result")
+                            .context("simulated no connection list")
                             .warn();
+                        continue;
+                    }
+                    let in_flight = refresh.as_ref().is_some_and(|r| !r.is_finished());
+                    if in_flight && msg == MessageToWorker::UpdateList {
+                        continue;
+                    }
+                    if !rate_limiter.try_take() {
+                        debug!(
+                            per_minute = rate_limiter.requests_per_minute(),
+                            "Rate limited refreshing list"
+                        );
+                        continue;
+                    }
+
+                    if in_flight {
+                        debug!("Superseding in-flight refresh");
+                    }
+                    supersede(&mut refresh, &mut tasks);
+                    last_burst_refresh = Instant::now();
+
+                    let (reqwest_error_at_last_refresh, mtg_tx, client, refresh_timer) = (
+                        reqwest_error_at_last_refresh.clone(),
+                        mtg_tx.clone(),
+                        client.clone(),
+                        refresh_timer.clone(),
+                    );
+
+                    let full = msg == MessageToWorker::Resync;
+                    refresh = Some(spawn_worker(move |cancel| {
+                        do_update_list(
+                            id,
+                            cancel,
+                            full,
+                            reqwest_error_at_last_refresh,
+                            mtg_tx,
+                            client,
+                        );
+                        refresh_timer.lock_panic("refresh timer").update_timer();
+                    }));
+                }
+                MessageToWorker::RestartBoard => {
+                    //asked for by the user, so made even when over the limit
+                    if !rate_limiter.take_anyway() {
+                        warn!(
+                            per_minute = rate_limiter.requests_per_minute(),
+                            "Over the rate limit, restarting board anyway"
+                        );
+                    }
+
+                    let (mtg_tx, client) = (mtg_tx.clone(), client.clone());
+                    tasks.push(spawn_worker(move |cancel| {
+                        if !cancel.is_cancelled() {
+                            do_restart_board(id, mtg_tx, client);
+                        }
+                    }));
+                }
+                MessageToWorker::FetchHistory => {
+                    if simulate_offline || client.capabilities().is_some_and(|c| !c.history) {
+                        continue;
+                    }
+                    if !rate_limiter.try_take() {
+                        warn!(
+                            per_minute = rate_limiter.requests_per_minute(),
+                            "Rate limited fetching history"
+                        );
+                        continue;
+                    }
+
+                    let (mtg_tx, client) = (mtg_tx.clone(), client.clone());
+                    tasks.push(spawn_worker(move |cancel| {
+                        if !cancel.is_cancelled() {
+                            do_fetch_history(id, mtg_tx, client);
+                        }
+                    }));
+                }
+                MessageToWorker::MakeMove(m) => {
+                    if simulate_offline {
+                        info!(?m, "Simulating offline, so not making move");
+                        for msg in [
+                            BoardMessage::TmpMove(m),
+                            BoardMessage::Move(MoveOutcome::CouldntProcessMove),
+                        ] {
+                            mtg_tx
+                                .send(MessageToGame::UpdateBoard(msg))
+                                .context("simulated piece move result")
+                                .warn();
+                        }
+                        continue;
+                    }
+                    //dropping the move would leave the game waiting on it forever, so it is made even when over the limit
+                    if !rate_limiter.take_anyway() {
+                        warn!(
+                            ?m,
+                            per_minute = rate_limiter.requests_per_minute(),
+                            "Over the rate limit, making move anyway"
+                        );
+                    }
+                    //a board fetched before the move would undo it
+                    supersede(&mut refresh, &mut tasks);
+
+                    //a server which doesn't take promotions always queens, so the board has to as well
+                    let m = if client.capabilities().is_some_and(|c| !c.promotion) {
+                        JSONMove {
+                            promotion: None,
+                            ..m
+                        }
                     } else {
-                        mr_inflight.store(true, Ordering::SeqCst);
+                        m
+                    };
+                    //shown straight away, on top of any moves still queued, so they can all be undone if one fails
+                    mtg_tx
+                        .send(MessageToGame::UpdateBoard(BoardMessage::TmpMove(m)))
+                        .context("sending msg to game re moving piece temp")
+                        .warn();
 
-                        let _st = ThreadSafeScopedToListTimer::new(rt);
-                        do_make_move(m, mtg_tx, client);
+                    {
+                        let mut queue = move_queue.lock_panic("move queue");
+                        if !queue.push(m) {
+                            debug!(
+                                ?m,
+                                queued = queue.moves.len(),
+                                "Queued move behind the one being sent"
+                            );
+                            continue;
+                        }
+                    }
 
-                        mr_inflight.store(false, Ordering::SeqCst);
+                    let (mtg_tx, client, move_queue) =
+                        (mtg_tx.clone(), client.clone(), move_queue.clone());
+                    tasks.push(spawn_worker(move |cancel| {
+                        send_queued_moves(
+                            &move_queue,
+                            cancel,
+                            |m| do_make_move(m, mtg_tx.clone(), client.clone()),
+                            |dropped| {
+                                mtg_tx
+                                    .send(MessageToGame::UpdateBoard(BoardMessage::Dropped(
+                                        dropped,
+                                    )))
+                                    .context("sending dropped moves")
+                                    .warn();
+                            },
+                        );
+                    }));
+                }
+                MessageToWorker::ClaimDraw(reason) => {
+                    if simulate_offline {
+                        info!(%reason, "Simulating offline, so not claiming draw");
+                        mtg_tx
+                            .send(MessageToGame::DrawClaim(
+                                DrawClaimOutcome::CouldntProcessClaim,
+                            ))
+                            .context("simulated draw claim result")
+                            .warn();
+                        continue;
                     }
-                });
-            }
-            MessageToWorker::InvalidateKill => {
-                do_invalidate_exit(id, client);
-                break;
+                    //asked for by the user, so made even when over the limit
+                    if !rate_limiter.take_anyway() {
+                        warn!(
+                            per_minute = rate_limiter.requests_per_minute(),
+                            "Over the rate limit, claiming draw anyway"
+                        );
+                    }
+
+                    let (mtg_tx, client, player) = (mtg_tx.clone(), client.clone(), player.clone());
+                    tasks.push(spawn_worker(move |cancel| {
+                        if !cancel.is_cancelled() {
+                            do_claim_draw(id, player, reason, mtg_tx, client);
+                        }
+                    }));
+                }
+                MessageToWorker::SimulateOffline(offline) => {
+                    info!(%offline, "Changing simulated offline");
+                    simulate_offline = offline;
+                }
+                MessageToWorker::PollBurst(length) => {
+                    debug!(?length, "Starting poll burst");
+                    burst_until = Some(Instant::now() + length);
+                }
+                MessageToWorker::InvalidateKill => {
+                    tasks.extend(refresh.take());
+                    tasks.iter().for_each(TaskHandle::cancel);
+                    do_invalidate_exit(id, client);
+                    break 'messages;
+                }
+                //flattened by `into_steps`
+                MessageToWorker::Batch(_) => {}
             }
+
+            //NB: Can have no logic here as there are continue statements
         }
+    }
 
-        //NB: Can have no logic here as there are continue statements
+    tasks.extend(refresh.take());
+    tasks.iter().for_each(TaskHandle::cancel);
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    for task in tasks {
+        match task.join_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Some(())) => {}
+            Ok(None) => warn!("Abandoning slow request on shutdown"),
+            Err(e) => warn!(%e, "Request thread failed on shutdown"),
+        }
     }
 
     Ok(())
 }
 
+///Keeps trying to build the [`ServerClient`] every [`CLIENT_RETRY_INTERVAL`], for when it couldn't be built to start with. Meanwhile, the game is told the connection is [`ConnectionState::Unavailable`] and shown the [`OfflineBoard::NoConnection`] board, and moves are turned down.
+///
+/// Returns `None` if the game ends, or the thread is abandoned, before the client can be built.
+fn wait_for_client(
+    config: &ClientConfig,
+    mut error: anyhow::Error,
+    mtw_rx: &BoundedReceiver<MessageToWorker>,
+    mtg_tx: &BoundedSender<MessageToGame>,
+    liveness: &Liveness,
+) -> Option<ServerClient> {
+    loop {
+        warn!(%error, "Unable to build client, retrying in {CLIENT_RETRY_INTERVAL:?}");
+        for msg in [
+            MessageToGame::Connection(ConnectionState::Unavailable(format!("{error:#}"))),
+            MessageToGame::UpdateBoard(BoardMessage::Offline(OfflineBoard::NoConnection)),
+        ] {
+            mtg_tx
+                .send(msg)
+                .context("sending client unavailable")
+                .warn();
+        }
+
+        let retry_at = Instant::now() + CLIENT_RETRY_INTERVAL;
+        //refreshes are asked for every frame, so there is always a message to wake up on
+        while Instant::now() < retry_at {
+            let batch = mtw_rx.recv().ok()?;
+            if liveness.is_abandoned() {
+                return None;
+            }
+            liveness.beat();
+            for msg in batch.into_steps() {
+                match msg {
+                    MessageToWorker::InvalidateKill => return None,
+                    MessageToWorker::MakeMove(m) => {
+                        info!(?m, "No client, so not making move");
+                        for msg in [
+                            BoardMessage::TmpMove(m),
+                            BoardMessage::Move(MoveOutcome::CouldntProcessMove),
+                        ] {
+                            mtg_tx
+                                .send(MessageToGame::UpdateBoard(msg))
+                                .context("no client piece move result")
+                                .warn();
+                        }
+                    }
+                    MessageToWorker::ClaimDraw(reason) => {
+                        info!(%reason, "No client, so not claiming draw");
+                        mtg_tx
+                            .send(MessageToGame::DrawClaim(
+                                DrawClaimOutcome::CouldntProcessClaim,
+                            ))
+                            .context("no client draw claim result")
+                            .warn();
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        match ServerClient::new(config).context("building client") {
+            Ok(client) => {
+                info!("Built client");
+                mtg_tx
+                    .send(MessageToGame::Connection(ConnectionState::Available))
+                    .context("sending client available")
+                    .warn();
+                return Some(client);
+            }
+            Err(e) => error = e,
+        }
+    }
+}
+
+///Waits at most `timeout` for every request in flight to finish
+fn settle(tasks: &[TaskHandle<()>], refresh: Option<&TaskHandle<()>>, timeout: Duration) {
+    let start = Instant::now();
+    while tasks.iter().chain(refresh).any(|t| !t.is_finished()) {
+        if start.elapsed() >= timeout {
+            warn!("Requests still in flight, starting the next step of the batch anyway");
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+///Cancels the board refresh if there is one, and moves it to the other tasks to be joined once it finishes
+fn supersede(refresh: &mut Option<TaskHandle<()>>, tasks: &mut Vec<TaskHandle<()>>) {
+    if let Some(old) = refresh.take() {
+        old.cancel();
+        tasks.push(old);
+    }
+}
+
 impl ListRefresher {
-    ///Create a new `ListRefresher`, and start up the main thread
+    ///Create a new `ListRefresher` with the default [`RateLimit`] and [`ClientConfig`], and start up the main thread
+    ///
+    /// `player` is used to identify this client in heartbeats
     #[must_use]
-    pub fn new(id: u32) -> Self {
-        let (mtw_tx, mtw_rx) = channel();
-        let (mtg_tx, mtg_rx) = channel();
-
-        let thread = std::thread::spawn(move || {
-            run_loop(mtw_rx, mtg_tx, id)
-                .context("error running refresh loop")
-                .error();
+    pub fn new(id: u32, player: String) -> Self {
+        Self::with_config(id, player, RateLimit::default(), ClientConfig::default())
+    }
+
+    ///Create a new `ListRefresher` which limits outgoing requests using `rate_limit`, and talks to the server using `client_config`, and start up the main thread
+    ///
+    /// `player` is used to identify this client in heartbeats
+    #[must_use]
+    pub fn with_config(
+        id: u32,
+        player: String,
+        rate_limit: RateLimit,
+        client_config: ClientConfig,
+    ) -> Self {
+        let (mtw_tx, mtw_rx) = bounded_channel(
+            "queue.to_worker",
+            "queue.to_worker_dropped",
+            TO_WORKER_CAPACITY,
+        );
+        let (mtg_tx, mtg_rx) =
+            bounded_channel("queue.to_game", "queue.to_game_dropped", TO_GAME_CAPACITY);
+
+        //built here if it can be, so the recorder and clock are there straight away
+        let first_try = ServerClient::new(&client_config).context("building client");
+        let shared_client = Arc::new(OnceLock::new());
+        if let Ok(client) = &first_try {
+            let _ = shared_client.set(client.clone());
+        }
+
+        let liveness = Arc::new(Liveness::new());
+        let (worker_client, worker_liveness, worker_config, worker_player) = (
+            shared_client.clone(),
+            liveness.clone(),
+            client_config.clone(),
+            player.clone(),
+        );
+        let thread = spawn_worker(move |_| {
+            let client = match first_try {
+                Ok(client) => client,
+                Err(e) => {
+                    let Some(client) =
+                        wait_for_client(&worker_config, e, &mtw_rx, &mtg_tx, &worker_liveness)
+                    else {
+                        return;
+                    };
+                    //nothing else sets it, as the first try failed
+                    let _ = worker_client.set(client.clone());
+                    client
+                }
+            };
+            run_loop(
+                mtw_rx,
+                mtg_tx,
+                id,
+                worker_player,
+                rate_limit,
+                client,
+                &worker_liveness,
+            )
+            .context("error running refresh loop")
+            .error();
         });
 
         Self {
             handle: Some(thread),
             tx: mtw_tx,
             rx: mtg_rx,
+            client: shared_client,
+            liveness,
+            id,
+            player,
+            rate_limit,
+            client_config,
+        }
+    }
+
+    ///Restarts the main thread with a new [`ServerClient`] if it has got stuck - messages are waiting for it, but it hasn't dealt with one for [`WATCHDOG_TIMEOUT`]. Returns whether it was restarted.
+    ///
+    /// Should be called often - eg. on every update tick.
+    ///
+    /// The old thread is abandoned rather than joined, as it may never finish - if it does wake up, it ends without doing anything else. The messages it hadn't got to are handed to the new thread, after a [`MessageToWorker::Resync`] as anything it had in flight is lost.
+    pub fn restart_if_wedged(&mut self) -> bool {
+        let waiting = self.tx.waiting();
+        let stalled = self.liveness.since_beat();
+        if waiting == 0 || stalled < WATCHDOG_TIMEOUT {
+            return false;
+        }
+
+        error!(
+            id = self.id,
+            %waiting,
+            ?stalled,
+            capabilities = ?self.capabilities(),
+            "Worker stopped dealing with messages, restarting it"
+        );
+        metrics().add_to_gauge("threads.worker_restarts", 1);
+
+        self.liveness.abandon();
+        if let Some(old) = self.handle.take() {
+            old.cancel();
+        }
+        let waiting = self.tx.take_waiting();
+        *self = Self::with_config(
+            self.id,
+            self.player.clone(),
+            self.rate_limit,
+            self.client_config.clone(),
+        );
+
+        for msg in std::iter::once(MessageToWorker::Resync).chain(waiting) {
+            self.send_msg(msg)
+                .context("handing message to restarted worker")
+                .warn();
         }
+        true
+    }
+
+    ///Gets the [`NetworkRecorder`] shared with the main thread, if [`ClientConfig::record_session`] is on
+    #[must_use]
+    pub fn recorder(&self) -> Option<&NetworkRecorder> {
+        self.client.get().and_then(ServerClient::recorder)
+    }
+
+    ///Gets the estimated skew to the server's clock, or `None` if the server hasn't sent its time yet
+    #[must_use]
+    pub fn clock_skew(&self) -> Option<ClockSkew> {
+        self.client.get().and_then(|c| c.clock().skew())
+    }
+
+    ///Gets what the server supports, or `None` if it hasn't been asked yet - features it doesn't support shouldn't be offered
+    #[must_use]
+    pub fn capabilities(&self) -> Option<JSONCapabilities> {
+        self.client.get().and_then(ServerClient::capabilities)
     }
 
     ///Sends a message to the main thread
     ///
     /// # Errors
     /// Can error if there is an error sending the message
-    pub fn send_msg(&self, m: MessageToWorker) -> Result<(), SendError<MessageToWorker>> {
+    pub fn send_msg(&self, m: MessageToWorker) -> Result<(), TrySendError<MessageToWorker>> {
         self.tx.send(m)
     }
     ///Tries to receive a message from the main thread in a non-blocking fashion
@@ -267,56 +938,92 @@ impl ListRefresher {
     }
 }
 
-///Function to be run on a separate thread to update the list and send a message to a [`Sender`]
+///Function to be run on a separate thread to update the list and send a message to a [`BoundedSender`]
+///
+/// If it is cancelled, nothing is sent - whatever superseded it will bring a newer board. If `full`, the request isn't conditional, for a [`MessageToWorker::Resync`].
 fn do_update_list(
     id: u32,
+    cancel: &CancelToken,
+    full: bool,
     reqwest_error_at_last_refresh: Arc<AtomicBool>,
-    mtg_tx: Sender<MessageToGame>,
-    client: Client,
+    mtg_tx: BoundedSender<MessageToGame>,
+    client: ServerClient,
 ) {
-    let result_rsp = client
-        .get(format!("http://109.74.205.63:12345/games/{id}"))
-        .send();
+    if cancel.is_cancelled() {
+        return;
+    }
 
-    let msg = match result_rsp {
+    let path = format!("games/{id}");
+    let endpoint = client.url(&path);
+    let mut request = client.get(&path).timeout(REFRESH_TIMEOUT);
+    if full {
+        //no `ETag`, and the server shouldn't say it has already sent the board
+        request = request.header(CACHE_CONTROL, "no-cache");
+    }
+    let result_rsp = send_timed(&client, &mtg_tx, "games", request);
+    if cancel.is_cancelled() {
+        debug!(%id, "Dropping superseded refresh");
+        return;
+    }
+    let checksum = result_rsp
+        .as_ref()
+        .ok()
+        .filter(|rsp| !rsp.is_error())
+        .and_then(|rsp| rsp.checksum);
+
+    let msg: Either<BoardMessage, (anyhow::Error, OfflineBoard)> = match result_rsp {
+        Ok(rsp) if rsp.is_error() => {
+            warn!(status=%rsp.status, "Error updating list");
+            if rsp.status.is_server_error() {
+                note_failure(id, &client);
+            } else {
+                client.record_success();
+            }
+
+            Either::Right((
+                anyhow!("error code from server: {}", rsp.status),
+                OfflineBoard::from_status(rsp.status.as_u16()),
+            ))
+        }
         Ok(rsp) => {
-            let rsp = rsp.error_for_status();
-            match rsp {
-                Ok(rsp) => {
-                    reqwest_error_at_last_refresh.store(false, Ordering::SeqCst);
+            reqwest_error_at_last_refresh.store(false, Ordering::SeqCst);
+            client.record_success();
 
-                    if rsp.status() == StatusCode::ALREADY_REPORTED {
-                        Either::Left(BoardMessage::UseExisting)
-                    } else {
-                        match rsp.json::<JSONPieceList>() {
-                            Ok(l) => Either::Left(BoardMessage::NewList(l)),
-                            Err(e) => {
-                                error!(%e, "Unable to parse JSON list from reqwest");
-                                Either::Right(e)
-                            }
-                        }
+            if rsp.status == StatusCode::ALREADY_REPORTED {
+                Either::Left(BoardMessage::UseExisting)
+            } else {
+                match parse_board_update(&endpoint, &rsp.body) {
+                    Ok(JSONBoardUpdate::Full(l)) => Either::Left(BoardMessage::NewList(l)),
+                    Ok(JSONBoardUpdate::Delta(moves)) => Either::Left(BoardMessage::ApplyMoves(
+                        moves.into_iter().map(|m| m.with_id(id)).collect(),
+                    )),
+                    Err(diag) => {
+                        error!(%diag, "Invalid JSON list from server");
+                        mtg_tx
+                            .send(MessageToGame::InvalidResponse(diag.clone()))
+                            .context("sending diagnostic")
+                            .warn();
+                        Either::Right((diag.into(), OfflineBoard::Error(None)))
                     }
                 }
-                Err(e) => {
-                    warn!(%e, "Error updating list");
-
-                    Either::Right(e)
-                }
             }
         }
-        Err(e) => Either::Right(e),
+        Err(e) => {
+            note_failure(id, &client);
+            Either::Right((e, OfflineBoard::NoConnection))
+        }
     };
 
     let msg = match msg {
         Either::Left(m) => m,
-        Either::Right(e) => {
+        Either::Right((e, offline)) => {
             if reqwest_error_at_last_refresh.load(Ordering::SeqCst) {
                 warn!(%e, "Using existing list due to errors");
                 BoardMessage::UseExisting
             } else {
                 reqwest_error_at_last_refresh.store(true, Ordering::SeqCst);
-                error!(%e, "Error refreshing list - sending NCL");
-                BoardMessage::NoConnectionList
+                error!(%e, ?offline, "Error refreshing list - sending offline board");
+                BoardMessage::Offline(offline)
             }
         }
     };
@@ -325,89 +1032,248 @@ fn do_update_list(
         .send(MessageToGame::UpdateBoard(msg))
         .context("sending update list msg")
         .error();
+    //after the board, so it is checked against the board it came with
+    if let Some(checksum) = checksum {
+        mtg_tx
+            .send(MessageToGame::Checksum(checksum))
+            .context("sending board checksum")
+            .warn();
+    }
+}
+
+///Sends a request using [`ServerClient::send`], and tells the game how it went with a [`RequestSample`] for `endpoint`
+fn send_timed(
+    client: &ServerClient,
+    mtg_tx: &BoundedSender<MessageToGame>,
+    endpoint: &'static str,
+    request: RequestBuilder,
+) -> Result<ServerResponse> {
+    let start = Instant::now();
+    let rsp = client.send(request);
+
+    let sample = RequestSample {
+        endpoint,
+        duration: start.elapsed(),
+        failed: rsp.as_ref().map_or(true, ServerResponse::is_error),
+    };
+    mtg_tx
+        .send(MessageToGame::RequestFinished(sample))
+        .context("sending request sample")
+        .warn();
+
+    rsp
+}
+
+///Records a failed request with the client, and publishes a [`GameEvent::ConnectionChanged`] if that meant failing over to another server
+fn note_failure(id: u32, client: &ServerClient) {
+    if let Some(server) = client.record_failure() {
+        game_events().publish(GameEvent::ConnectionChanged { id, server });
+        discover_capabilities(client);
+    }
+}
+
+///Asks the active server what it supports, logging the answer
+fn discover_capabilities(client: &ServerClient) {
+    match client.discover_capabilities() {
+        Ok(capabilities) => info!(?capabilities, "Found server capabilities"),
+        Err(e) => warn!(%e, "Couldn't find server capabilities"),
+    }
 }
 
 ///Utility function to be run on a separate thread to restart the board
-fn do_restart_board(id: u32, client: Client) {
-    match client
-        .post("http://109.74.205.63:12345/newgame")
-        .body(id.to_string())
-        .send()
-    {
-        Ok(rsp) => match rsp.error_for_status() {
-            Ok(rsp) => {
-                info!(update=?rsp.text(), "Update from server on restarting");
-            }
-            Err(e) => warn!(%e, "Error code from server on restarting"),
-        },
+fn do_restart_board(id: u32, mtg_tx: BoundedSender<MessageToGame>, client: ServerClient) {
+    let request = client.post("newgame").body(id.to_string());
+    match send_timed(&client, &mtg_tx, "newgame", request) {
+        Ok(rsp) if rsp.is_error() => {
+            warn!(status=%rsp.status, "Error code from server on restarting");
+        }
+        Ok(rsp) => info!(update=?rsp.body, "Update from server on restarting"),
         Err(e) => error!(%e, "Error restarting"),
     }
 }
 
-///Utility function to be run on a separate thread to make a move.
+///Utility function to be run on a separate thread to make a move, returning whether it worked. The game has already been sent the move as a [`BoardMessage::TmpMove`] when it was queued.
 ///
 /// NB: Make sure not to call this method again until it has finished
-fn do_make_move(m: JSONMove, mtg_tx: Sender<MessageToGame>, client: Client) {
-    mtg_tx
-        .send(MessageToGame::UpdateBoard(BoardMessage::TmpMove(m)))
-        .context("sending msg to game re moving piece temp")
-        .warn();
-
-    let rsp = client
-        .post("http://109.74.205.63:12345/movepiece")
-        .json(&m)
-        .send();
+fn do_make_move(m: JSONMove, mtg_tx: BoundedSender<MessageToGame>, client: ServerClient) -> bool {
+    //the promotion is only serialised when there is one, so older servers see the same body as before
+    debug!(promotion=?m.promotion, "Sending move");
+    let request = client.post("movepiece").json(&m);
+    let rsp = send_timed(&client, &mtg_tx, "movepiece", request);
 
     let outcome = match rsp {
-        Ok(rsp) => match rsp.error_for_status() {
-            Ok(rsp) => {
-                let txt = rsp.text();
-                info!(update=?txt, "Update from server on moving");
-                let taken = txt.map_or(false, |txt| !txt.contains("not"));
-                MoveOutcome::Worked(taken)
+        Ok(rsp) => {
+            let sc = rsp.status;
+            if sc.is_server_error() {
+                note_failure(m.id, &client);
+            } else {
+                client.record_success();
             }
-            Err(e) => {
-                if let Some(sc) = e.status() {
-                    if sc == StatusCode::PRECONDITION_FAILED {
-                        error!("Invalid move");
-                        MoveOutcome::Invalid
-                    } else {
-                        error!(%e, %sc, "Error in input response status code");
-                        MoveOutcome::CouldntProcessMove
-                    }
+
+            if sc.is_success() {
+                info!(update=?rsp.body, "Update from server on moving");
+                let taken = !rsp.body.contains("not");
+                MoveOutcome::Worked(taken)
+            } else {
+                let e = ServerError::from_response(sc.as_u16(), &rsp.body);
+                if sc.is_client_error() {
+                    error!(%e, %sc, "Invalid move");
+                    MoveOutcome::Invalid(e)
                 } else {
+                    error!(%e, %sc, "Error in input response status code");
                     MoveOutcome::CouldntProcessMove
                 }
             }
-        },
+        }
         Err(e) => {
             error!(%e, "Error in input response");
+            note_failure(m.id, &client);
             MoveOutcome::CouldntProcessMove
         }
     };
 
+    let worked = matches!(outcome, MoveOutcome::Worked(_));
+    game_events().publish(GameEvent::MoveResult { id: m.id, worked });
     mtg_tx
         .send(MessageToGame::UpdateBoard(BoardMessage::Move(outcome)))
         .context("piece move result")
         .warn();
+    worked
 }
 
-///Utility function to send the invalidate-kill message
-fn do_invalidate_exit(id: u32, client: Client) {
-    info!("InvalidateKill msg sending");
+///Utility function to be run on a separate thread to tell the server we're still here, and pass on anything it knows about the opponent
+fn do_heartbeat(
+    id: u32,
+    player: String,
+    mtg_tx: BoundedSender<MessageToGame>,
+    client: ServerClient,
+) {
+    let endpoint = client.url("heartbeat");
+    let request = client.post("heartbeat").json(&JSONHeartbeat { id, player });
+    let rsp = send_timed(&client, &mtg_tx, "heartbeat", request);
+
+    match rsp {
+        Ok(rsp) if rsp.is_error() => {
+            debug!(status=%rsp.status, "Error code from server on heartbeat");
+        }
+        Ok(rsp) => match parse_response::<JSONHeartbeatResponse>(&endpoint, &rsp.body) {
+            Ok(JSONHeartbeatResponse {
+                opponent: Some(presence),
+            }) => {
+                mtg_tx
+                    .send(MessageToGame::OpponentPresence(presence))
+                    .context("sending opponent presence")
+                    .warn();
+            }
+            Ok(_) => {}
+            Err(diag) => {
+                warn!(%diag, "Invalid heartbeat response");
+                mtg_tx
+                    .send(MessageToGame::InvalidResponse(diag))
+                    .context("sending diagnostic")
+                    .warn();
+            }
+        },
+        Err(e) => warn!(%e, "Error sending heartbeat"),
+    }
+}
 
-    let rsp = client
-        .post("http://109.74.205.63:12345/invalidate")
-        .body(id.to_string())
-        .send();
+///Asks the server for the whole history of the game, and sends it to the game. Servers without the endpoint answer `404 Not Found`, which is ignored.
+fn do_fetch_history(id: u32, mtg_tx: BoundedSender<MessageToGame>, client: ServerClient) {
+    let path = format!("games/{id}/history");
+    let endpoint = client.url(&path);
+    let rsp = send_timed(&client, &mtg_tx, "history", client.get(&path));
 
     match rsp {
-        Ok(rsp) => match rsp.error_for_status() {
-            Ok(rsp) => {
-                info!(update=?rsp.text(), "Update from server on invalidating");
+        Ok(rsp) if rsp.status == StatusCode::NOT_FOUND => {
+            debug!("Server doesn't have the move history");
+        }
+        Ok(rsp) if rsp.is_error() => {
+            warn!(status=%rsp.status, "Error code from server on fetching history");
+        }
+        Ok(rsp) => match parse_response::<JSONHistory>(&endpoint, &rsp.body) {
+            Ok(history) => match history.into_history() {
+                Ok(history) => {
+                    mtg_tx
+                        .send(MessageToGame::History(history))
+                        .context("sending move history")
+                        .warn();
+                }
+                Err(e) => warn!(%e, "Invalid move history from server"),
+            },
+            Err(diag) => {
+                warn!(%diag, "Invalid history response");
+                mtg_tx
+                    .send(MessageToGame::InvalidResponse(diag))
+                    .context("sending diagnostic")
+                    .warn();
             }
-            Err(e) => warn!(%e, "Error code from server on invalidating"),
         },
+        Err(e) => warn!(%e, "Error fetching history"),
+    }
+}
+
+///Utility function to be run on a separate thread to claim a draw, sending the server's answer back to the game
+fn do_claim_draw(
+    id: u32,
+    player: String,
+    reason: DrawReason,
+    mtg_tx: BoundedSender<MessageToGame>,
+    client: ServerClient,
+) {
+    let claim = match JSONDrawReason::try_from(reason) {
+        Ok(json_reason) => JSONDrawClaim {
+            id,
+            player,
+            reason: json_reason,
+        },
+        Err(e) => {
+            error!(%e, "Claiming a draw which can't be claimed");
+            mtg_tx
+                .send(MessageToGame::DrawClaim(DrawClaimOutcome::CouldntProcessClaim))
+                .context("sending draw claim result")
+                .warn();
+            return;
+        }
+    };
+    let request = client.post("claimdraw").json(&claim);
+
+    let outcome = match send_timed(&client, &mtg_tx, "claimdraw", request) {
+        Ok(rsp) if rsp.status.is_success() => {
+            info!(update=?rsp.body, "Update from server on claiming draw");
+            DrawClaimOutcome::Accepted(reason)
+        }
+        Ok(rsp) if rsp.status.is_client_error() => {
+            let e = ServerError::from_response(rsp.status.as_u16(), &rsp.body);
+            warn!(%e, status=%rsp.status, "Draw claim rejected");
+            DrawClaimOutcome::Rejected(e)
+        }
+        Ok(rsp) => {
+            warn!(status=%rsp.status, "Error code from server on claiming draw");
+            DrawClaimOutcome::CouldntProcessClaim
+        }
+        Err(e) => {
+            error!(%e, "Error claiming draw");
+            DrawClaimOutcome::CouldntProcessClaim
+        }
+    };
+    mtg_tx
+        .send(MessageToGame::DrawClaim(outcome))
+        .context("sending draw claim result")
+        .warn();
+}
+
+///Utility function to send the invalidate-kill message
+fn do_invalidate_exit(id: u32, client: ServerClient) {
+    info!("InvalidateKill msg sending");
+
+    let rsp = client.send(client.post("invalidate").body(id.to_string()));
+
+    match rsp {
+        Ok(rsp) if rsp.is_error() => {
+            warn!(status=%rsp.status, "Error code from server on invalidating");
+        }
+        Ok(rsp) => info!(update=?rsp.body, "Update from server on invalidating"),
         Err(e) => error!(%e, "Error invalidating"),
     }
 
@@ -417,10 +1283,86 @@ fn do_invalidate_exit(id: u32, client: Client) {
 impl Drop for ListRefresher {
     fn drop(&mut self) {
         if let Some(h) = std::mem::take(&mut self.handle) {
-            h.join()
-                .ae()
-                .context("ending list refresher")
-                .unwrap_log_error();
+            h.join().context("ending list refresher").unwrap_log_error();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejected_move_drops_the_moves_queued_behind_it() {
+        let a = JSONMove::new(1, 4, 6, 4, 4);
+        let b = JSONMove::new(1, 6, 7, 5, 5);
+        let queue = Mutex::new(MoveQueue::default());
+        assert!(queue.lock_panic("move queue").push(a));
+        assert!(!queue.lock_panic("move queue").push(b));
+
+        let mut sent = vec![];
+        let mut dropped = vec![];
+        send_queued_moves(
+            &queue,
+            &CancelToken::default(),
+            |m| {
+                sent.push(m);
+                false
+            },
+            |rest| dropped = rest,
+        );
+
+        assert_eq!(sent, vec![a]);
+        assert_eq!(dropped, vec![b]);
+        let mut queue = queue.lock_panic("move queue");
+        assert!(queue.moves.is_empty());
+        assert!(queue.push(b), "a new sender is needed after the queue is cleared");
+    }
+
+    #[test]
+    fn queued_moves_are_sent_in_order() {
+        let moves = [
+            JSONMove::new(1, 4, 6, 4, 4),
+            JSONMove::new(1, 4, 1, 4, 3),
+            JSONMove::new(1, 6, 7, 5, 5),
+        ];
+        let queue = Mutex::new(MoveQueue::default());
+        for m in moves {
+            queue.lock_panic("move queue").push(m);
         }
+
+        let mut sent = vec![];
+        send_queued_moves(
+            &queue,
+            &CancelToken::default(),
+            |m| {
+                sent.push(m);
+                true
+            },
+            |rest| panic!("nothing should be dropped, but {rest:?} were"),
+        );
+
+        assert_eq!(sent, moves);
+        assert!(!queue.lock_panic("move queue").sending);
+    }
+
+    #[test]
+    fn cancelled_sender_leaves_moves_for_the_next() {
+        let m = JSONMove::new(1, 4, 6, 4, 4);
+        let queue = Mutex::new(MoveQueue::default());
+        queue.lock_panic("move queue").push(m);
+
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        send_queued_moves(
+            &queue,
+            &cancel,
+            |m| panic!("{m:?} shouldn't be sent once cancelled"),
+            |_| {},
+        );
+
+        let mut queue = queue.lock_panic("move queue");
+        assert!(!queue.sending);
+        assert_eq!(queue.next(false), Some(m));
     }
 }
@@ -1,4 +1,26 @@
 ///Module to hold the [`list_refresher::ListRefresher`] struct
 pub mod list_refresher;
-///Module to deal with JSON responses from the server - [`server_interface::JSONMove`], [`server_interface::JSONPiece`], and [`server_interface::JSONPieceList`]
+///Module to deal with JSON responses from the server - [`server_interface::JSONMove`], [`server_interface::JSONPiece`], [`server_interface::JSONPieceList`] and the versioned [`server_interface::JSONBoardState`]
 pub mod server_interface;
+///Module to estimate the [`clock_sync::ClockSkew`] to the server's clock with a [`clock_sync::ClockSync`]
+pub mod clock_sync;
+///Module to hold [`counted_channel::counted_channel`] and [`counted_channel::bounded_channel`], which publish how many messages are waiting in them
+pub mod counted_channel;
+///Module to hold the [`events::GameEvent`]s published on the [`events::game_events`] bus
+pub mod events;
+///Module to hold the [`game_manager::GameManager`] struct, for following several games at once
+pub mod game_manager;
+///Module to import games from Lichess and Chess.com with a [`game_import::GameImporter`]
+pub mod game_import;
+///Module to create and parse [`invite::Invite`] links
+pub mod invite;
+///Module to hold the [`playback::SessionPlayer`], which plays back a recorded session in place of the server
+pub mod playback;
+///Module to hold the [`rate_limiter::TokenBucket`] used to limit outgoing requests
+pub mod rate_limiter;
+///Module to hold the [`recorder::NetworkRecorder`], which records exchanges with the server for bug reports
+pub mod recorder;
+///Module to hold the [`request_stats::RequestStats`] kept for each endpoint
+pub mod request_stats;
+///Module to hold the [`server_client::ServerClient`], which attaches identification headers to every request
+pub mod server_client;
@@ -0,0 +1,102 @@
+use super::{
+    recorder::{NetworkSession, RecordedExchange},
+    server_client::ServerResponse,
+};
+use anyhow::{Context as _, Result};
+use epac_utils::error_ext::MutexExt;
+use reqwest::{StatusCode, Url};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+///Gets the key used to match a request to recorded exchanges - the method and the path, so that the server doesn't matter.
+///
+/// Fixtures can use just the path as the URL, with or without the leading slash.
+fn exchange_key(method: &str, url: &str) -> String {
+    let path = Url::parse(url).map_or_else(|_| url.to_string(), |u| u.path().to_string());
+    format!("{method} /{}", path.trim_start_matches('/'))
+}
+
+///The recorded exchanges for one key
+#[derive(Debug, Default)]
+struct Responses {
+    ///The exchanges which haven't been played back yet, oldest first
+    queued: VecDeque<RecordedExchange>,
+    ///The exchange played back most recently
+    last: Option<RecordedExchange>,
+}
+
+///Plays back a [`NetworkSession`] in place of the server, so that a bug can be reproduced without one.
+///
+/// Each request gets the next recorded response for the same method and path, in the order they were recorded. Once they run out, the last one is repeated, so the game stays where the recording ended. Clones share the same position in the recording.
+#[derive(Debug, Clone)]
+pub struct SessionPlayer {
+    ///The exchanges, keyed by [`exchange_key`]
+    responses: Arc<Mutex<HashMap<String, Responses>>>,
+}
+
+impl SessionPlayer {
+    ///Creates a new `SessionPlayer` for a session, starting at the beginning
+    #[must_use]
+    pub fn new(session: NetworkSession) -> Self {
+        let mut responses: HashMap<String, Responses> = HashMap::new();
+        for exchange in session.exchanges {
+            responses
+                .entry(exchange_key(&exchange.method, &exchange.url))
+                .or_default()
+                .queued
+                .push_back(exchange);
+        }
+
+        Self {
+            responses: Arc::new(Mutex::new(responses)),
+        }
+    }
+
+    ///Loads a session from a file and creates a `SessionPlayer` for it
+    ///
+    /// # Errors
+    /// - Fail to load the [`NetworkSession`]
+    pub fn load(path: &Path) -> Result<Self> {
+        NetworkSession::load(path).map(Self::new)
+    }
+
+    ///Gets the recorded response to a request
+    ///
+    /// # Errors
+    /// - Nothing was recorded for that method and path
+    /// - The recorded request failed - the error is the recorded one
+    /// - The recorded status code isn't valid
+    pub fn respond(&self, method: &str, url: &str) -> Result<ServerResponse> {
+        let key = exchange_key(method, url);
+        let exchange = {
+            let mut responses = self.responses.lock_panic("session player");
+            let responses = responses
+                .get_mut(&key)
+                .with_context(|| format!("nothing recorded for {key}"))?;
+            if let Some(next) = responses.queued.pop_front() {
+                responses.last = Some(next);
+            }
+            responses
+                .last
+                .clone()
+                .with_context(|| format!("nothing recorded for {key}"))?
+        };
+        debug!(%key, status=?exchange.status, "Playing back response");
+
+        match (exchange.status, exchange.response_body) {
+            (Some(status), body) => Ok(ServerResponse {
+                status: StatusCode::from_u16(status)
+                    .with_context(|| format!("recorded status {status} for {key}"))?,
+                body: body.unwrap_or_default(),
+                checksum: None,
+            }),
+            (None, _) => Err(anyhow!(exchange
+                .error
+                .unwrap_or_else(|| "no response recorded".into())))
+            .with_context(|| format!("recorded failure for {key}")),
+        }
+    }
+}
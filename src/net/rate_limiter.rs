@@ -0,0 +1,158 @@
+use crate::util::rate_counter::RateCounter;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+///Configuration for a [`TokenBucket`]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    ///How many requests can be made per second, on average
+    pub requests_per_second: f64,
+    ///How many requests can be made in a burst, before the rate applies
+    pub burst: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 4.0,
+            burst: 8,
+        }
+    }
+}
+
+///Token bucket rate limiter.
+///
+/// Starts full with [`RateLimit::burst`] tokens, and refills at [`RateLimit::requests_per_second`]. Each request takes one token, and can't be made if there are none left. The requests let through are counted over the last minute, so the actual rate can be logged alongside limiting decisions.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    ///Maximum number of tokens
+    capacity: f64,
+    ///Current number of tokens - fractional so that refilling works at any rate
+    tokens: f64,
+    ///Number of tokens added per second
+    refill_per_sec: f64,
+    ///When tokens were last added
+    last_refill: Instant,
+    ///The requests let through in the last minute
+    taken: RateCounter,
+}
+
+impl TokenBucket {
+    ///Creates a new, full, `TokenBucket`
+    #[must_use]
+    pub fn new(limit: RateLimit) -> Self {
+        let capacity = f64::from(limit.burst.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: limit.requests_per_second.max(0.0),
+            last_refill: Instant::now(),
+            taken: RateCounter::new(Duration::from_secs(60)),
+        }
+    }
+
+    ///Adds the tokens earned between the last refill and `now`
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    ///Tries to take a token. Returns whether or not a request can be made.
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    ///Tries to take a token at a given time, which mustn't be before the last time one was taken - see [`TokenBucket::try_take`]
+    pub fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.taken.record_at(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    ///Takes a token even if there are none left, for requests the user asked for which can't be dropped. Returns whether there was a token to take.
+    ///
+    /// Going below zero means the requests which can wait, like refreshes, are held back until the bucket has refilled.
+    pub fn take_anyway(&mut self) -> bool {
+        self.take_anyway_at(Instant::now())
+    }
+
+    ///Takes a token at a given time even if there are none left, which mustn't be before the last time one was taken - see [`TokenBucket::take_anyway`]
+    pub fn take_anyway_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        let had_token = self.tokens >= 1.0;
+        self.tokens -= 1.0;
+        self.taken.record_at(now);
+        had_token
+    }
+
+    ///Gets the number of requests let through in the last minute
+    pub fn requests_per_minute(&mut self) -> usize {
+        self.taken.count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Makes a bucket, and gets when it was made - to take tokens at times after it
+    fn full_bucket(requests_per_second: f64, burst: u32) -> (TokenBucket, Instant) {
+        let bucket = TokenBucket::new(RateLimit {
+            requests_per_second,
+            burst,
+        });
+        let start = bucket.last_refill;
+        (bucket, start)
+    }
+
+    #[test]
+    fn refills_no_further_than_the_burst() {
+        let (mut bucket, start) = full_bucket(1.0, 3);
+        let later = start + Duration::from_secs(100);
+
+        for _ in 0..3 {
+            assert!(bucket.try_take_at(later));
+        }
+        assert!(!bucket.try_take_at(later));
+    }
+
+    #[test]
+    fn empty_bucket_turns_down_requests_until_refilled() {
+        let (mut bucket, start) = full_bucket(1.0, 1);
+
+        assert!(bucket.try_take_at(start));
+        assert!(!bucket.try_take_at(start));
+        assert!(!bucket.try_take_at(start + Duration::from_millis(500)));
+        assert!(bucket.try_take_at(start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn taking_anyway_holds_back_refreshes() {
+        let (mut bucket, start) = full_bucket(1.0, 1);
+
+        assert!(bucket.take_anyway_at(start));
+        assert!(
+            !bucket.take_anyway_at(start),
+            "the bucket was already empty"
+        );
+        //a token short, so the next one only makes it back to empty
+        assert!(!bucket.try_take_at(start + Duration::from_secs(1)));
+        assert!(bucket.try_take_at(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn counts_every_request_let_through() {
+        let (mut bucket, start) = full_bucket(1.0, 1);
+
+        bucket.try_take_at(start);
+        bucket.try_take_at(start);
+        bucket.take_anyway_at(start);
+        assert_eq!(bucket.requests_per_minute(), 2);
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context as _, Result};
+use epac_utils::error_ext::MutexExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+///Most characters kept from each request and response body
+pub const MAX_RECORDED_BODY: usize = 4096;
+///Most exchanges kept in a session - older ones are dropped first, so a client left open doesn't grow forever
+pub const MAX_RECORDED_EXCHANGES: usize = 5000;
+
+///One request to the server, and what came back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    ///The HTTP method, eg. `GET`
+    pub method: String,
+    ///The full URL
+    pub url: String,
+    ///The start of the request body, if there was one
+    pub request_body: Option<String>,
+    ///The status code - `None` if the server couldn't be reached
+    pub status: Option<u16>,
+    ///The start of the response body - `None` if the server couldn't be reached
+    pub response_body: Option<String>,
+    ///Why the request failed, if it did
+    pub error: Option<String>,
+    ///When the request was sent, in milliseconds since the session started
+    pub sent_after_ms: u64,
+    ///How long the request took, in milliseconds
+    pub duration_ms: u64,
+}
+
+///A saved recording of all of the exchanges with the server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkSession {
+    ///When the session started, in seconds since the unix epoch
+    pub started_at: u64,
+    ///The exchanges, oldest first
+    pub exchanges: Vec<RecordedExchange>,
+}
+
+impl NetworkSession {
+    ///Loads a session saved by [`NetworkRecorder::save`], or a hand-written fixture in the same format
+    ///
+    /// # Errors
+    /// - Fail to read in the file
+    /// - Fail to parse the contents into a `NetworkSession`
+    #[tracing::instrument]
+    pub fn load(path: &Path) -> Result<Self> {
+        let cntnts = read_to_string(path).with_context(|| format!("reading path {path:?}"))?;
+        serde_json::from_str(&cntnts).context("parsing network session")
+    }
+
+    ///Finds the id of the game the session was playing, from the first request for a board
+    #[must_use]
+    pub fn game_id(&self) -> Option<u32> {
+        self.exchanges.iter().find_map(|e| {
+            let (_, rest) = e.url.split_once("games/")?;
+            rest.split(['/', '?']).next()?.parse().ok()
+        })
+    }
+}
+
+///Records every exchange with the server. Clones share the same recording.
+#[derive(Debug, Clone)]
+pub struct NetworkRecorder {
+    ///When the session started
+    started: Instant,
+    ///When the session started, in seconds since the unix epoch
+    started_at: u64,
+    ///The exchanges so far, oldest first
+    exchanges: Arc<Mutex<VecDeque<RecordedExchange>>>,
+}
+
+impl Default for NetworkRecorder {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            exchanges: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+}
+
+///Truncates a body to [`MAX_RECORDED_BODY`] characters
+fn truncate(body: &str) -> String {
+    body.chars().take(MAX_RECORDED_BODY).collect()
+}
+
+impl NetworkRecorder {
+    ///Records an exchange. `result` is the status and body of the response, or why there wasn't one.
+    pub fn record(
+        &self,
+        method: String,
+        url: String,
+        request_body: Option<&str>,
+        result: Result<(u16, &str), String>,
+        sent: Instant,
+    ) {
+        #[allow(clippy::cast_possible_truncation)]
+        let ms = |d: Duration| d.as_millis() as u64;
+
+        let (status, response_body, error) = match result {
+            Ok((status, body)) => (Some(status), Some(truncate(body)), None),
+            Err(e) => (None, None, Some(e)),
+        };
+        let exchange = RecordedExchange {
+            method,
+            url,
+            request_body: request_body.map(truncate),
+            status,
+            response_body,
+            error,
+            sent_after_ms: ms(sent.saturating_duration_since(self.started)),
+            duration_ms: ms(sent.elapsed()),
+        };
+
+        let mut exchanges = self.exchanges.lock_panic("network recorder");
+        if exchanges.len() == MAX_RECORDED_EXCHANGES {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    ///Gets a copy of everything recorded so far
+    #[must_use]
+    pub fn session(&self) -> NetworkSession {
+        NetworkSession {
+            started_at: self.started_at,
+            exchanges: self
+                .exchanges
+                .lock_panic("network recorder")
+                .iter()
+                .cloned()
+                .collect(),
+        }
+    }
+
+    ///Saves everything recorded so far as JSON into a new file in `dir`, and returns the path of that file
+    ///
+    /// # Errors
+    /// - Fail to turn the session into JSON
+    /// - Fail to write to the file
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let session = self.session();
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = dir.join(format!("network-{}-{saved_at}.json", session.started_at));
+
+        let st = serde_json::to_string_pretty(&session).context("turning session to string")?;
+        std::fs::write(&path, st).with_context(|| format!("writing session to {path:?}"))?;
+        Ok(path)
+    }
+}
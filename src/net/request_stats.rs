@@ -0,0 +1,64 @@
+use crate::metrics::Histogram;
+use std::{collections::BTreeMap, time::Duration};
+
+///How one request to the server went, sent from the worker threads to the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestSample {
+    ///The endpoint, without any ids, eg. `games`
+    pub endpoint: &'static str,
+    ///How long it took to get a response, or to fail
+    pub duration: Duration,
+    ///Whether the server couldn't be reached, or responded with an error status
+    pub failed: bool,
+}
+
+///Statistics for requests to one endpoint
+#[derive(Debug, Clone, Default)]
+pub struct EndpointStats {
+    ///Number of requests made
+    pub requests: u64,
+    ///Number of those requests which failed
+    pub errors: u64,
+    ///How long the requests took
+    pub latency: Histogram,
+}
+
+impl EndpointStats {
+    ///Gets the share of requests which failed, from 0 to 1
+    #[must_use]
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let rate = self.errors as f64 / self.requests as f64;
+            rate
+        }
+    }
+}
+
+///Statistics for all requests made to the server, per endpoint, built up from [`RequestSample`]s
+#[derive(Debug, Clone, Default)]
+pub struct RequestStats {
+    ///The statistics, keyed by [`RequestSample::endpoint`]
+    endpoints: BTreeMap<&'static str, EndpointStats>,
+}
+
+impl RequestStats {
+    ///Adds a sample to the statistics for its endpoint
+    pub fn record(&mut self, sample: &RequestSample) {
+        let stats = self.endpoints.entry(sample.endpoint).or_default();
+        stats.requests += 1;
+        if sample.failed {
+            stats.errors += 1;
+        }
+        stats
+            .latency
+            .record(u64::try_from(sample.duration.as_millis()).unwrap_or(u64::MAX));
+    }
+
+    ///Gets the statistics for each endpoint, sorted by endpoint
+    pub fn endpoints(&self) -> impl Iterator<Item = (&'static str, &EndpointStats)> {
+        self.endpoints.iter().map(|(k, v)| (*k, v))
+    }
+}
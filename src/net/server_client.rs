@@ -0,0 +1,471 @@
+use super::{
+    clock_sync::{ClockSkew, ClockSync},
+    playback::SessionPlayer,
+    recorder::NetworkRecorder,
+    server_interface::{parse_response, JSONCapabilities},
+};
+use crate::util::lru_cache::{CacheGauges, LruCache};
+use anyhow::{Context as _, Result};
+use epac_utils::error_ext::MutexExt;
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{
+    blocking::{Body, Client, ClientBuilder, Request, RequestBuilder, Response},
+    header::{HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, ETAG, IF_NONE_MATCH},
+    Method, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+///Header used to send the [`ClientConfig::client_version`]
+pub const CLIENT_VERSION_HEADER: &str = "X-Client-Version";
+///Header used to send the [`ClientConfig::api_key`]
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+///Header the server can send with the [`crate::chess::zobrist::zobrist_hash`] of its board, in hex, so the client can check it has the same one
+pub const CHECKSUM_HEADER: &str = "X-Board-Checksum";
+///Number of failed requests in a row before failing over to the next server
+pub const FAILOVER_THRESHOLD: u32 = 3;
+///Smallest request body in bytes which is compressed, if the server accepts gzip - smaller bodies barely shrink
+pub const COMPRESS_THRESHOLD: usize = 512;
+///Largest response body in bytes which is read - anything larger is an error, so a buggy or malicious server can't make the client run out of memory. Much larger than [`MAX_BODY_BYTES`](super::server_interface::MAX_BODY_BYTES) to leave room for other sites, like archives of games to import.
+pub const MAX_RESPONSE_BYTES: u64 = 8 * 1024 * 1024;
+///Number of `GET` responses kept by each [`ServerClient`] to make conditional requests with
+pub const CONDITIONAL_ENTRIES: usize = 64;
+///Gauges the conditional-request cache of a [`ServerClient`] publishes to
+pub const CONDITIONAL_GAUGES: CacheGauges = CacheGauges {
+    hits: "cache.http_not_modified",
+    misses: "cache.http_modified",
+    entries: "memory.http_etags",
+};
+
+///A response kept to make a conditional request with - its `ETag`, and the body to use if the server says it hasn't changed
+type Validated = (HeaderValue, String);
+
+///Configuration for the [`ServerClient`] - where the server is, and how the client identifies itself to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    ///The base URL of the server, without a trailing slash
+    pub server: String,
+    ///Base URLs of backup servers, in priority order, to fail over to if the server stops responding
+    pub backup_servers: Vec<String>,
+    ///The user agent sent with every request
+    pub user_agent: String,
+    ///The client version sent in the [`CLIENT_VERSION_HEADER`] with every request
+    pub client_version: String,
+    ///If `Some`, sent in the [`API_KEY_HEADER`] with every request
+    pub api_key: Option<String>,
+    ///Whether or not to record every exchange with the server using a [`NetworkRecorder`], so it can be saved for bug reports
+    pub record_session: bool,
+    ///If `Some`, the recorded session to play back using a [`SessionPlayer`] rather than talking to the server. Never saved.
+    #[serde(skip)]
+    pub playback: Option<PathBuf>,
+    ///Whether or not to save bandwidth for slow links like mobile tethering - the board is refreshed less often, and the launcher doesn't fetch previews
+    pub low_bandwidth: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            server: "http://109.74.205.63:12345".into(),
+            backup_servers: vec![],
+            user_agent: "JackyBoi/AsyncChess".into(),
+            client_version: env!("CARGO_PKG_VERSION").into(),
+            api_key: None,
+            record_session: false,
+            playback: None,
+            low_bandwidth: false,
+        }
+    }
+}
+
+///A response from the server, with the body already read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerResponse {
+    ///The status code
+    pub status: StatusCode,
+    ///The body
+    pub body: String,
+    ///The checksum of the server's board from the [`CHECKSUM_HEADER`], if it sent one
+    pub checksum: Option<u64>,
+}
+
+impl ServerResponse {
+    ///Whether or not the status is a client or server error - like [`reqwest::blocking::Response::error_for_status`]
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.status.is_client_error() || self.status.is_server_error()
+    }
+}
+
+///HTTP client for talking to the server, which automatically attaches the identification headers from a [`ClientConfig`] to every request
+///
+/// If [`FAILOVER_THRESHOLD`] requests in a row fail, it moves on to the next server in the list. Clones share which server is active.
+///
+/// `GET` responses with an `ETag` are kept, and sent back in `If-None-Match` the next time, so the server can answer `304 Not Modified` without a body. Clones share the responses kept.
+///
+/// Request bodies of at least [`COMPRESS_THRESHOLD`] bytes are compressed with gzip if the server says it accepts them - see [`JSONCapabilities::gzip`].
+#[derive(Debug, Clone)]
+pub struct ServerClient {
+    ///The underlying `reqwest` client, with the default headers set
+    client: Client,
+    ///The base URLs of all of the servers in priority order, without trailing slashes
+    servers: Arc<Vec<String>>,
+    ///Index into `servers` of the server currently being used
+    active: Arc<AtomicUsize>,
+    ///Number of requests in a row which have failed
+    consecutive_failures: Arc<AtomicU32>,
+    ///Records every exchange, if [`ClientConfig::record_session`] is on
+    recorder: Option<NetworkRecorder>,
+    ///Plays back a recorded session instead of sending requests, if [`ClientConfig::playback`] is set
+    player: Option<SessionPlayer>,
+    ///The most recent `GET` responses with an `ETag`, keyed by URL
+    validated: Arc<Mutex<LruCache<String, Validated>>>,
+    ///Estimates the skew to the server's clock from the times on its responses
+    clock: Arc<ClockSync>,
+    ///What each server supports, keyed by base URL, once it has been asked
+    capabilities: Arc<Mutex<HashMap<String, JSONCapabilities>>>,
+    ///Whether or not [`ClientConfig::low_bandwidth`] is on
+    low_bandwidth: bool,
+}
+
+impl ServerClient {
+    ///Creates a new `ServerClient` using the given [`ClientConfig`]
+    ///
+    /// # Errors
+    /// - The client version or API key can't be used as a header value
+    /// - The underlying client fails to build
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CLIENT_VERSION_HEADER,
+            HeaderValue::from_str(&config.client_version).context("client version header")?,
+        );
+        if let Some(key) = &config.api_key {
+            let mut value = HeaderValue::from_str(key).context("api key header")?;
+            value.set_sensitive(true);
+            headers.insert(API_KEY_HEADER, value);
+        }
+
+        let client = ClientBuilder::default()
+            .user_agent(config.user_agent.clone())
+            .default_headers(headers)
+            .build()
+            .context("building reqwest client")?;
+
+        let servers = std::iter::once(&config.server)
+            .chain(config.backup_servers.iter())
+            .map(|s| s.trim_end_matches('/').to_string())
+            .collect();
+
+        Ok(Self {
+            client,
+            servers: Arc::new(servers),
+            active: Arc::new(AtomicUsize::new(0)),
+            consecutive_failures: Arc::new(AtomicU32::new(0)),
+            recorder: config.record_session.then(NetworkRecorder::default),
+            player: config
+                .playback
+                .as_deref()
+                .map(SessionPlayer::load)
+                .transpose()
+                .context("loading session to play back")?,
+            validated: Arc::new(Mutex::new(LruCache::new(
+                CONDITIONAL_ENTRIES,
+                CONDITIONAL_GAUGES,
+            ))),
+            clock: Arc::new(ClockSync::default()),
+            capabilities: Arc::new(Mutex::new(HashMap::new())),
+            low_bandwidth: config.low_bandwidth,
+        })
+    }
+
+    ///Whether or not [`ClientConfig::low_bandwidth`] is on
+    #[must_use]
+    pub const fn low_bandwidth(&self) -> bool {
+        self.low_bandwidth
+    }
+
+    ///Gets the base URL of the server currently being used
+    #[must_use]
+    pub fn active_server(&self) -> &str {
+        &self.servers[self.active.load(Ordering::SeqCst) % self.servers.len()]
+    }
+
+    ///Gets the full URL for a path on the active server
+    #[must_use]
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.active_server(), path.trim_start_matches('/'))
+    }
+
+    ///Records that a request reached the server
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+    }
+
+    ///Records that a request couldn't reach the server.
+    ///
+    /// Returns the new active server if this failure meant failing over to the next one.
+    pub fn record_failure(&self) -> Option<String> {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures < FAILOVER_THRESHOLD || self.servers.len() < 2 {
+            return None;
+        }
+
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        let next = (self.active.load(Ordering::SeqCst) + 1) % self.servers.len();
+        self.active.store(next, Ordering::SeqCst);
+
+        let server = self.servers[next].clone();
+        warn!(%server, %failures, "Failing over to next server");
+        Some(server)
+    }
+
+    ///Starts a `GET` request to a path on the server
+    #[must_use]
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.client.get(self.url(path))
+    }
+
+    ///Gets the [`NetworkRecorder`], if [`ClientConfig::record_session`] is on
+    #[must_use]
+    pub fn recorder(&self) -> Option<&NetworkRecorder> {
+        self.recorder.as_ref()
+    }
+
+    ///Sends a request started with [`ServerClient::get`] or [`ServerClient::post`], and reads the whole response. The exchange is recorded if there is a [`NetworkRecorder`].
+    ///
+    /// If there is a [`SessionPlayer`], nothing is sent and the response comes from the recording instead.
+    ///
+    /// Error statuses aren't errors here - check [`ServerResponse::is_error`]. A `304 Not Modified` for a conditional `GET` is turned back into a `200 OK` with the body kept from before. Requests with `Cache-Control: no-cache` are never made conditional.
+    ///
+    /// # Errors
+    /// - The request can't be built
+    /// - The server can't be reached, or the body can't be read
+    /// - The body is larger than [`MAX_RESPONSE_BYTES`]
+    /// - The [`SessionPlayer`] has no response for the request
+    pub fn send(&self, request: RequestBuilder) -> Result<ServerResponse> {
+        let mut request = request.build().context("building request")?;
+        let (method, url) = (request.method().to_string(), request.url().to_string());
+        let no_cache = request
+            .headers()
+            .get(CACHE_CONTROL)
+            .is_some_and(|v| v == "no-cache");
+        let conditional = *request.method() == Method::GET && self.player.is_none() && !no_cache;
+        let kept = if conditional {
+            let mut validated = self.validated.lock_panic("conditional requests");
+            let kept = validated.get(&url).cloned();
+            validated.publish_metrics();
+            kept
+        } else {
+            None
+        };
+        if let Some((etag, _)) = &kept {
+            request.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+        }
+
+        let request_body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| String::from_utf8_lossy(b).to_string());
+        if self.player.is_none() {
+            self.compress(&mut request);
+        }
+
+        let sent = Instant::now();
+        let result = match &self.player {
+            Some(player) => player.respond(&method, &url),
+            None => self
+                .client
+                .execute(request)
+                .context("sending request")
+                .and_then(|rsp| {
+                    self.clock.record_headers(rsp.headers(), sent.elapsed());
+                    let (status, etag) = (rsp.status(), rsp.headers().get(ETAG).cloned());
+                    let checksum = rsp
+                        .headers()
+                        .get(CHECKSUM_HEADER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| u64::from_str_radix(v.trim(), 16).ok());
+                    let body = read_body(rsp)?;
+                    Ok((
+                        ServerResponse {
+                            status,
+                            body,
+                            checksum,
+                        },
+                        etag,
+                    ))
+                })
+                .map(|(rsp, etag)| self.validate(&url, kept, rsp, etag.filter(|_| conditional))),
+        };
+
+        if let Some(recorder) = &self.recorder {
+            let recorded = match &result {
+                Ok(rsp) => Ok((rsp.status.as_u16(), rsp.body.as_str())),
+                Err(e) => Err(format!("{e:#}")),
+            };
+            recorder.record(method, url, request_body.as_deref(), recorded, sent);
+        }
+
+        result
+    }
+
+    ///Compresses the body of a request with gzip, if it is at least [`COMPRESS_THRESHOLD`] bytes and the active server accepts gzip
+    fn compress(&self, request: &mut Request) {
+        if !self.capabilities().is_some_and(|c| c.gzip) {
+            return;
+        }
+
+        let compressed = request
+            .body()
+            .and_then(Body::as_bytes)
+            .filter(|b| b.len() >= COMPRESS_THRESHOLD)
+            .map(|b| {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(b).and_then(|()| encoder.finish())
+            });
+
+        match compressed {
+            Some(Ok(body)) => {
+                debug!(bytes=%body.len(), "Compressed request body");
+                *request.body_mut() = Some(body.into());
+                request
+                    .headers_mut()
+                    .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+            }
+            Some(Err(e)) => warn!(%e, "Unable to compress request body, sending it as is"),
+            None => {}
+        }
+    }
+
+    ///Keeps a `GET` response with an `ETag` for the next conditional request, or swaps a `304 Not Modified` for the response kept before
+    fn validate(
+        &self,
+        url: &str,
+        kept: Option<Validated>,
+        rsp: ServerResponse,
+        etag: Option<HeaderValue>,
+    ) -> ServerResponse {
+        match (rsp.status, kept) {
+            (StatusCode::NOT_MODIFIED, Some((_, body))) => ServerResponse {
+                status: StatusCode::OK,
+                body,
+                checksum: rsp.checksum,
+            },
+            (status, _) => {
+                if let Some(etag) = etag.filter(|_| status == StatusCode::OK) {
+                    self.validated
+                        .lock_panic("conditional requests")
+                        .insert(url.to_string(), (etag, rsp.body.clone()));
+                }
+                rsp
+            }
+        }
+    }
+
+    ///Times how long the active server takes to respond to a request for its root. Any response counts, even an error status, as it shows the server can be reached.
+    ///
+    /// # Errors
+    /// - The server can't be reached within the timeout
+    pub fn ping(&self, timeout: Duration) -> Result<Duration> {
+        let start = Instant::now();
+        self.send(self.get("").timeout(timeout))
+            .context("pinging server")?;
+        Ok(start.elapsed())
+    }
+
+    ///Gets the [`ClockSync`] shared by every clone of this client
+    #[must_use]
+    pub fn clock(&self) -> Arc<ClockSync> {
+        self.clock.clone()
+    }
+
+    ///Gets what the active server supports, or `None` if it hasn't been asked yet with [`ServerClient::discover_capabilities`]
+    #[must_use]
+    pub fn capabilities(&self) -> Option<JSONCapabilities> {
+        self.capabilities
+            .lock_panic("server capabilities")
+            .get(self.active_server())
+            .copied()
+    }
+
+    ///Asks the active server what it supports, unless it already has been, and keeps the answer for [`ServerClient::capabilities`].
+    ///
+    /// Servers without the endpoint answer `404 Not Found`, and are taken to support nothing beyond the original API.
+    ///
+    /// # Errors
+    /// - The server can't be reached, or answers with any other error
+    /// - The response isn't valid [`JSONCapabilities`]
+    pub fn discover_capabilities(&self) -> Result<JSONCapabilities> {
+        if let Some(capabilities) = self.capabilities() {
+            return Ok(capabilities);
+        }
+
+        let server = self.active_server().to_string();
+        let rsp = self
+            .send(self.get("capabilities"))
+            .context("asking server for capabilities")?;
+        let capabilities = if rsp.status == StatusCode::NOT_FOUND {
+            JSONCapabilities::default()
+        } else if rsp.is_error() {
+            bail!("error code from server on capabilities: {}", rsp.status);
+        } else {
+            parse_response(&self.url("capabilities"), &rsp.body)?
+        };
+
+        self.capabilities
+            .lock_panic("server capabilities")
+            .insert(server, capabilities);
+        Ok(capabilities)
+    }
+
+    ///Does the clock-sync handshake - sends `samples` requests for the server's root and uses the times on the responses to estimate the [`ClockSkew`]. Later responses keep refining the estimate.
+    ///
+    /// Returns `None` if the server doesn't send its time, or the responses come from a [`SessionPlayer`].
+    ///
+    /// # Errors
+    /// - The server can't be reached within the timeout
+    pub fn sync_clock(&self, samples: usize, timeout: Duration) -> Result<Option<ClockSkew>> {
+        for _ in 0..samples {
+            self.send(self.get("").timeout(timeout))
+                .context("syncing clock with server")?;
+        }
+        Ok(self.clock.skew())
+    }
+
+    ///Starts a `POST` request to a path on the server
+    #[must_use]
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.client.post(self.url(path))
+    }
+}
+
+///Reads the body of a response, up to [`MAX_RESPONSE_BYTES`]
+///
+/// # Errors
+/// - The server says the body is larger than [`MAX_RESPONSE_BYTES`], or sends more than that
+/// - The body can't be read
+fn read_body(rsp: Response) -> Result<String> {
+    if let Some(length) = rsp.content_length().filter(|l| *l > MAX_RESPONSE_BYTES) {
+        bail!("response body is {length} bytes, max is {MAX_RESPONSE_BYTES}");
+    }
+
+    let mut body = Vec::new();
+    rsp.take(MAX_RESPONSE_BYTES + 1)
+        .read_to_end(&mut body)
+        .context("reading response body")?;
+    if u64::try_from(body.len()).map_or(true, |l| l > MAX_RESPONSE_BYTES) {
+        bail!("response body is over {MAX_RESPONSE_BYTES} bytes");
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
@@ -1,19 +1,46 @@
 use crate::{
-    chess::boards::board::{Board, CanMovePiece},
-    prelude::{ChessPiece, ChessPieceKind, Coords, Error, ErrorExt, Result},
+    chess::{
+        boards::board::{Board, CanMovePiece, DrawReason},
+        history::{HistoryMove, MoveHistory, STARTING_FEN},
+    },
+    net::server_client::ServerClient,
+    prelude::{ChessPiece, ChessPieceKind, Coords, Error, ErrorExt, Result, SError},
     util::error_ext::ToAnyhowNotErr,
 };
 use anyhow::Context;
+use directories::ProjectDirs;
 use epac_utils::error_ext::{ErrorExt, ToAnyhowNotErr};
-use serde::{Deserialize, Serialize};
-use strum::IntoEnumIterator;
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fmt::{Display, Formatter},
+    fs::{create_dir_all, read_dir},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+///Maximum size in bytes of a response body that will be parsed
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+///Maximum number of pieces in a [`JSONPieceList`] - one for every square on the board
+pub const MAX_PIECES: usize = 64;
+///Maximum number of games asked for in one request by [`fetch_boards`] - more are split over several requests
+pub const MAX_BULK_GAMES: usize = 16;
+///Maximum number of characters of the body kept in a [`ResponseDiagnostic`]
+const MAX_DIAGNOSTIC_BODY: usize = 4096;
+///Maximum number of pieces of one colour before a [`ValidationWarning::TooManyOfColour`] - the 16 each side starts with
+pub const MAX_PIECES_PER_COLOUR: usize = 16;
+///Maximum number of payloads kept in the [`quarantine_dir`] - the oldest are removed to make room
+const MAX_QUARANTINED: usize = 32;
 
 ///Unit struct to hold a vector of [`JSONPiece`]s.
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct JSONPieceList(pub Vec<JSONPiece>);
 
 ///A piece in JSON representation
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JSONPiece {
     ///The x position
     pub x: i32,
@@ -72,73 +99,869 @@ impl JSONPieceList {
     }
 }
 
-///Returns a Board that says Uh Oh.
+///The board sent by the server, in any version of the schema.
+///
+/// Older servers send a bare list of pieces, and newer ones send an object tagged with a `schema` field.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum JSONBoardState {
+    ///An object tagged with the version of its schema
+    Versioned(VersionedBoard),
+    ///The original flat list of pieces, from before the schema was versioned
+    Flat(JSONPieceList),
+}
+
+///What the server sent in response to a board request - either the whole board, or just the moves made since the board it last sent
+#[derive(Debug, Clone)]
+pub enum JSONBoardUpdate {
+    ///The whole board
+    Full(JSONBoardState),
+    ///The moves made since the last board, in order, from an object tagged with `"schema": "delta"`
+    Delta(Vec<JSONLastMove>),
+}
+
+///The versions of the board schema, tagged with `"schema": "v1"` or `"schema": "v2"`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "schema", rename_all = "lowercase")]
+pub enum VersionedBoard {
+    ///Just the pieces - the same as [`JSONBoardState::Flat`]
+    V1 {
+        ///The pieces on (and off) the board
+        pieces: JSONPieceList,
+    },
+    ///The pieces, along with the state of the game
+    V2(JSONBoardV2),
+}
+
+///Version 2 of the board schema. Everything other than the pieces is optional, so the server can leave out what it doesn't know.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JSONBoardV2 {
+    ///The pieces on (and off) the board
+    pub pieces: JSONPieceList,
+    ///Whose turn it is
+    #[serde(default)]
+    pub turn: Option<JSONSide>,
+    ///How much time each player has left
+    #[serde(default)]
+    pub clocks: Option<JSONClocks>,
+    ///The result of the game, if it has finished
+    #[serde(default)]
+    pub result: Option<JSONResult>,
+    ///The last move made in the game
+    #[serde(default)]
+    pub last_move: Option<JSONLastMove>,
+    ///When the last move was made, in milliseconds since the unix epoch by the server's clock
+    #[serde(default)]
+    pub moved_at: Option<u64>,
+}
+
+///One side of the board in JSON representation
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JSONSide {
+    ///The white pieces
+    White,
+    ///The black pieces
+    Black,
+}
+
+///The time each player has left, in milliseconds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JSONClocks {
+    ///Milliseconds left for white
+    pub white_ms: u64,
+    ///Milliseconds left for black
+    pub black_ms: u64,
+}
+
+///How a game finished
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JSONResult {
+    ///White won
+    WhiteWins,
+    ///Black won
+    BlackWins,
+    ///Neither player won
+    Draw,
+}
+
+impl Display for JSONResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WhiteWins => write!(f, "White wins"),
+            Self::BlackWins => write!(f, "Black wins"),
+            Self::Draw => write!(f, "Draw"),
+        }
+    }
+}
+
+///A move made in the game, without the game id that a [`JSONMove`] has - used for the last move and in a [`JSONHistory`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JSONLastMove {
+    ///Starting X position
+    pub x: u32,
+    ///Starting Y position
+    pub y: u32,
+    ///X position moved to
+    pub nx: u32,
+    ///Y position moved to
+    pub ny: u32,
+}
+
+///JSON repr of the whole history of a game, from the `games/{id}/history` endpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JSONHistory {
+    ///The starting position in Forsyth-Edwards Notation - the usual starting position if not given
+    #[serde(default)]
+    pub start: Option<String>,
+    ///Whether white made the first move - white if not given
+    #[serde(default)]
+    pub white_first: Option<bool>,
+    ///The moves made, in order
+    pub moves: Vec<JSONLastMove>,
+}
+
+impl JSONHistory {
+    ///Converts into a [`MoveHistory`], without any annotations or variations
+    ///
+    /// # Errors
+    /// - The starting position isn't valid - see [`Board::from_fen`]
+    /// - A move has coordinates which are out of bounds
+    pub fn into_history(self) -> Result<MoveHistory> {
+        let start = Board::from_fen(self.start.as_deref().unwrap_or(STARTING_FEN))
+            .context("history starting position")?;
+        let moves = self
+            .moves
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| -> Result<HistoryMove> {
+                Ok(HistoryMove::new(
+                    Coords::try_from((m.x, m.y)).with_context(|| format!("start of move {i}"))?,
+                    Coords::try_from((m.nx, m.ny)).with_context(|| format!("end of move {i}"))?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(MoveHistory::from_moves(
+            &start,
+            self.white_first.unwrap_or(true),
+            moves,
+        ))
+    }
+}
+
+impl JSONLastMove {
+    ///Turns this into a [`JSONMove`] for a game, without a promotion
+    #[must_use]
+    pub const fn with_id(self, id: u32) -> JSONMove {
+        JSONMove::new(id, self.x, self.y, self.nx, self.ny)
+    }
+}
+
+///Everything other than the pieces that the server can say about a game - only filled in by newer versions of the schema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GameInfo {
+    ///Whether it is white's turn, if known
+    pub white_to_move: Option<bool>,
+    ///How much time white and black have left, if known
+    pub clocks: Option<(Duration, Duration)>,
+    ///The result of the game, if it has finished
+    pub result: Option<JSONResult>,
+    ///The start and end of the last move, if known
+    pub last_move: Option<(Coords, Coords)>,
+    ///When the last move was made, in milliseconds since the unix epoch by the server's clock - use a [`crate::net::clock_sync::ClockSkew`] to compare it to the local time
+    pub moved_at: Option<u64>,
+}
+
+impl From<JSONPieceList> for JSONBoardState {
+    fn from(list: JSONPieceList) -> Self {
+        Self::Flat(list)
+    }
+}
+
+impl TryInto<Board<CanMovePiece>> for JSONBoardState {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Board<CanMovePiece>, Self::Error> {
+        Board::new_json(self.into_pieces())
+    }
+}
+
+impl JSONBoardState {
+    ///Gets the pieces, whichever version of the schema was used
+    #[must_use]
+    pub const fn pieces(&self) -> &JSONPieceList {
+        match self {
+            Self::Flat(pieces)
+            | Self::Versioned(
+                VersionedBoard::V1 { pieces } | VersionedBoard::V2(JSONBoardV2 { pieces, .. }),
+            ) => pieces,
+        }
+    }
+
+    ///Turns this into just the pieces, throwing away anything else
+    #[must_use]
+    pub fn into_pieces(self) -> JSONPieceList {
+        match self {
+            Self::Flat(pieces)
+            | Self::Versioned(
+                VersionedBoard::V1 { pieces } | VersionedBoard::V2(JSONBoardV2 { pieces, .. }),
+            ) => pieces,
+        }
+    }
+
+    ///Converts into a [`Board`] and a [`GameInfo`] with anything else the server sent
+    ///
+    /// # Errors
+    /// - The pieces fail [`JSONPieceList::into_game_list`]
+    /// - The last move has coordinates which are out of bounds
+    pub fn into_parts(self) -> Result<(Board<CanMovePiece>, GameInfo)> {
+        let (pieces, info) = match self {
+            Self::Flat(pieces) | Self::Versioned(VersionedBoard::V1 { pieces }) => {
+                (pieces, GameInfo::default())
+            }
+            Self::Versioned(VersionedBoard::V2(v2)) => {
+                let last_move = v2
+                    .last_move
+                    .map(|m| -> Result<(Coords, Coords)> {
+                        Ok((
+                            Coords::try_from((m.x, m.y)).context("last move start")?,
+                            Coords::try_from((m.nx, m.ny)).context("last move end")?,
+                        ))
+                    })
+                    .transpose()?;
+                let info = GameInfo {
+                    white_to_move: v2.turn.map(|t| t == JSONSide::White),
+                    clocks: v2.clocks.map(|c| {
+                        (
+                            Duration::from_millis(c.white_ms),
+                            Duration::from_millis(c.black_ms),
+                        )
+                    }),
+                    result: v2.result,
+                    last_move,
+                    moved_at: v2.moved_at,
+                };
+                (v2.pieces, info)
+            }
+        };
+
+        Ok((Board::new_json(pieces)?, info))
+    }
+}
+
+///Reasons that a response from the server can fail validation
+#[derive(Debug, Clone, Serialize)]
+pub enum ValidationError {
+    ///The body was larger than [`MAX_BODY_BYTES`]
+    TooLarge {
+        ///The size of the body
+        bytes: usize,
+    },
+    ///The body wasn't valid JSON, or didn't have the right shape
+    Malformed {
+        ///The line the error was found on
+        line: usize,
+        ///The column the error was found on
+        column: usize,
+        ///The error from `serde_json`
+        message: String,
+    },
+    ///The list had more than [`MAX_PIECES`] pieces
+    TooManyPieces {
+        ///The number of pieces in the list
+        count: usize,
+    },
+    ///A piece in the list was invalid
+    InvalidPiece {
+        ///The index of the piece in the list
+        index: usize,
+        ///The field which was invalid, if it was only one field
+        field: Option<String>,
+        ///What was wrong with it
+        message: String,
+    },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { bytes } => {
+                write!(f, "body is {bytes} bytes, max is {MAX_BODY_BYTES}")
+            }
+            Self::Malformed {
+                line,
+                column,
+                message,
+            } => write!(f, "malformed JSON at {line}:{column} - {message}"),
+            Self::TooManyPieces { count } => {
+                write!(f, "list has {count} pieces, max is {MAX_PIECES}")
+            }
+            Self::InvalidPiece {
+                index,
+                field: Some(field),
+                message,
+            } => write!(f, "piece {index} has invalid field `{field}` - {message}"),
+            Self::InvalidPiece {
+                index,
+                field: None,
+                message,
+            } => write!(f, "piece {index} is invalid - {message}"),
+        }
+    }
+}
+
+impl SError for ValidationError {}
+
+///Things wrong with a response from the server which don't stop it being used, like a piece which has to be dropped. Each one is logged, and the payload is quarantined with [`quarantine`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ValidationWarning {
+    ///Two pieces were on the same square - the later one was dropped
+    DuplicateSquare {
+        ///The index of the piece which was dropped
+        index: usize,
+        ///The index of the piece which was kept
+        kept: usize,
+        ///The x position of the square
+        x: i32,
+        ///The y position of the square
+        y: i32,
+    },
+    ///One colour had more than [`MAX_PIECES_PER_COLOUR`] pieces
+    TooManyOfColour {
+        ///Whether the pieces were white
+        is_white: bool,
+        ///The number of pieces of that colour
+        count: usize,
+    },
+}
+
+impl Display for ValidationWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateSquare { index, kept, x, y } => write!(
+                f,
+                "piece {index} is on ({x}, {y}) with piece {kept}, so was dropped"
+            ),
+            Self::TooManyOfColour { is_white, count } => write!(
+                f,
+                "{count} {} pieces, max is {MAX_PIECES_PER_COLOUR}",
+                if *is_white { "white" } else { "black" }
+            ),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ValidationError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Malformed {
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        }
+    }
+}
+
+///Typed reasons the server gives for rejecting a request, parsed from the body of an error response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    ///The move isn't legal - holds the server's explanation
+    IllegalMove(String),
+    ///The server doesn't know about the game id
+    UnknownGame,
+    ///It isn't the player's turn
+    NotYourTurn,
+    ///Any other error - holds the status code and message
+    Other(u16, String),
+}
+
+impl ServerError {
+    ///Parses the body of an error response into a `ServerError`.
+    ///
+    /// The body can either be plain text, or a JSON object with the message in an `error`, `reason` or `message` field.
+    ///
+    /// The status code decides first - `404` is always [`ServerError::UnknownGame`] and `412` always [`ServerError::IllegalMove`]. The message is only looked at for other client errors, where the status doesn't say which it is.
+    #[must_use]
+    pub fn from_response(status: u16, body: &str) -> Self {
+        let message = serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|v| {
+                ["error", "reason", "message"]
+                    .iter()
+                    .find_map(|k| v.get(k).and_then(Value::as_str).map(ToString::to_string))
+            })
+            .unwrap_or_else(|| body.trim().to_string());
+        let lower = message.to_lowercase();
+        //whole words only, so eg. `return` isn't taken as `turn`
+        let has_word = |word: &str| {
+            lower
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|w| w == word)
+        };
+
+        match StatusCode::from_u16(status) {
+            Ok(StatusCode::NOT_FOUND) => Self::UnknownGame,
+            Ok(StatusCode::PRECONDITION_FAILED) => Self::IllegalMove(message),
+            Ok(sc) if sc.is_client_error() => {
+                if has_word("turn") {
+                    Self::NotYourTurn
+                } else if lower.contains("unknown game") || lower.contains("no game") {
+                    Self::UnknownGame
+                } else if has_word("illegal") || has_word("invalid") {
+                    Self::IllegalMove(message)
+                } else {
+                    Self::Other(status, message)
+                }
+            }
+            _ => Self::Other(status, message),
+        }
+    }
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IllegalMove(m) if m.is_empty() => write!(f, "Illegal move"),
+            Self::IllegalMove(m) => write!(f, "Illegal move: {m}"),
+            Self::UnknownGame => write!(f, "Unknown game"),
+            Self::NotYourTurn => write!(f, "Not your turn"),
+            Self::Other(status, m) => write!(f, "Server error {status}: {m}"),
+        }
+    }
+}
+
+impl SError for ServerError {}
+
+///Structured diagnostic for when the server sends data which fails validation.
 ///
-/// # Panics:
-/// - Shouldn't if list is correct, but might if the list is invalid and fails [`JSONPieceList::into_game_list`]
-#[must_use]
-pub fn no_connection_list() -> Board<CanMovePiece> {
-    let p = |x, y| JSONPiece {
-        x,
-        y,
-        is_white: (x + y) % 2 == 1, //why not
-        kind: "rook".into(),
+/// Holds the (truncated) body so that it can be saved to a file and inspected later.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseDiagnostic {
+    ///The endpoint the response came from
+    pub endpoint: String,
+    ///Why the response failed validation
+    pub error: ValidationError,
+    ///The start of the body of the response
+    pub body: String,
+    ///When the response was received, in seconds since the unix epoch
+    pub received_at: u64,
+}
+
+impl Display for ResponseDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid response from {}: {}", self.endpoint, self.error)
+    }
+}
+
+impl SError for ResponseDiagnostic {}
+
+impl ResponseDiagnostic {
+    ///Creates a new `ResponseDiagnostic`, truncating the body to [`MAX_DIAGNOSTIC_BODY`] characters
+    #[must_use]
+    pub fn new(endpoint: &str, error: ValidationError, body: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            error,
+            body: body.chars().take(MAX_DIAGNOSTIC_BODY).collect(),
+            received_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+
+    ///Saves the diagnostic as JSON into a new file in `dir`, and returns the path of that file
+    ///
+    /// # Errors
+    /// - Fail to turn the diagnostic into JSON
+    /// - Fail to write to the file
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(format!("diagnostic-{}.json", self.received_at));
+        let st = serde_json::to_string_pretty(self).context("turning diagnostic to string")?;
+        std::fs::write(&path, st).with_context(|| format!("writing diagnostic to {path:?}"))?;
+        Ok(path)
+    }
+}
+
+///A response body which failed validation or had warnings, as saved by [`quarantine`]
+#[derive(Debug, Serialize)]
+struct QuarantinedPayload<'a> {
+    ///The endpoint the response came from
+    endpoint: &'a str,
+    ///Why the response failed validation, if it did
+    error: Option<&'a ValidationError>,
+    ///What was wrong with the response, if it passed validation
+    warnings: &'a [ValidationWarning],
+    ///When the response was received, in seconds since the unix epoch
+    received_at: u64,
+    ///Whether the body was cut short at [`MAX_BODY_BYTES`]
+    truncated: bool,
+    ///The body of the response
+    body: &'a str,
+}
+
+///Gets the folder payloads are quarantined in - `quarantine` in the project data directory
+///
+/// # Errors
+/// - Fail to get [`ProjectDirs`]
+pub fn quarantine_dir() -> Result<PathBuf> {
+    Ok(ProjectDirs::from("com", "jackmaguire", "async_chess")
+        .ae()
+        .context("getting project dirs")?
+        .data_dir()
+        .join("quarantine"))
+}
+
+///Saves a response body which failed validation or had warnings into the [`quarantine_dir`] for inspection, and returns the path of the file.
+///
+/// Unlike a [`ResponseDiagnostic`], the whole body is kept, up to [`MAX_BODY_BYTES`]. Files are named after a hash of the body, so a server sending the same bad payload on every refresh only fills one file, and only the newest [`MAX_QUARANTINED`] are kept.
+///
+/// # Errors
+/// - Fail to create the quarantine directory
+/// - Fail to turn the payload into JSON, or write it
+/// - Fail to list or remove old payloads
+pub fn quarantine(
+    endpoint: &str,
+    body: &str,
+    error: Option<&ValidationError>,
+    warnings: &[ValidationWarning],
+) -> Result<PathBuf> {
+    let dir = quarantine_dir()?;
+    create_dir_all(&dir).context("creating quarantine directory")?;
+
+    let mut end = body.len().min(MAX_BODY_BYTES);
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+
+    let payload = QuarantinedPayload {
+        endpoint,
+        error,
+        warnings,
+        received_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        truncated: end < body.len(),
+        body: &body[..end],
     };
-    let mut list = vec![
-        p(0, 0),
-        p(2, 0),
-        p(5, 0),
-        p(7, 0),
-        p(0, 1),
-        p(2, 1),
-        p(5, 1),
-        p(6, 1),
-        p(7, 1),
-        p(0, 2),
-        p(1, 2),
-        p(2, 2),
-        p(5, 2),
-        p(7, 2),
-        p(0, 5),
-        p(1, 5),
-        p(2, 5),
-        p(5, 5),
-        p(7, 5),
-        p(0, 6),
-        p(2, 6),
-        p(5, 6),
-        p(6, 6),
-        p(7, 6),
-        p(0, 7),
-        p(1, 7),
-        p(2, 7),
-        p(5, 7),
-        p(7, 7),
-    ];
-    for _ in 0..2 {
-        for kind in ChessPieceKind::iter() {
-            list.push(JSONPiece {
-                x: -1,
-                y: -1,
-                kind: kind.to_string(),
-                is_white: false,
-            });
-            list.push(JSONPiece {
-                x: -1,
-                y: -1,
-                kind: kind.to_string(),
-                is_white: true,
+    let path = dir.join(format!("payload-{:016x}.json", hasher.finish()));
+    let st = serde_json::to_string_pretty(&payload).context("turning payload to string")?;
+    std::fs::write(&path, st).with_context(|| format!("writing payload to {path:?}"))?;
+
+    let mut saved = read_dir(&dir)
+        .context("listing quarantine directory")?
+        .filter_map(|e| {
+            let e = e.ok()?;
+            Some((e.metadata().ok()?.modified().ok()?, e.path()))
+        })
+        .collect::<Vec<_>>();
+    if saved.len() > MAX_QUARANTINED {
+        saved.sort();
+        for (_, old) in &saved[..saved.len() - MAX_QUARANTINED] {
+            std::fs::remove_file(old).with_context(|| format!("removing old payload {old:?}"))?;
+        }
+    }
+
+    Ok(path)
+}
+
+///Logs the warnings from validating a response body, and quarantines it if it failed validation or had warnings
+fn screen<T>(
+    endpoint: &str,
+    body: &str,
+    parsed: Result<T, ValidationError>,
+    warnings: &[ValidationWarning],
+) -> Result<T, ResponseDiagnostic> {
+    for warning in warnings {
+        warn!(%endpoint, %warning, "Suspicious response from server");
+    }
+
+    let error = parsed.as_ref().err();
+    if error.is_some() || !warnings.is_empty() {
+        match quarantine(endpoint, body, error, warnings) {
+            Ok(path) => info!(?path, "Quarantined response"),
+            Err(e) => warn!(%e, "Unable to quarantine response"),
+        }
+    }
+
+    parsed.map_err(|e| ResponseDiagnostic::new(endpoint, e, body))
+}
+
+///Parses a response body from `endpoint` into a `T`, checking that it isn't larger than [`MAX_BODY_BYTES`]. Bodies which fail are [`quarantine`]d.
+///
+/// # Errors
+/// If the body is too large, or isn't valid JSON for a `T`
+pub fn parse_response<T: DeserializeOwned>(
+    endpoint: &str,
+    body: &str,
+) -> Result<T, ResponseDiagnostic> {
+    let parsed =
+        check_size(body).and_then(|()| serde_json::from_str(body).map_err(ValidationError::from));
+    screen(endpoint, body, parsed, &[])
+}
+
+///Parses a response body from `endpoint` into a [`JSONBoardUpdate`], validating each piece or move.
+///
+/// As well as the checks in [`parse_response`], this checks that there are no more than [`MAX_PIECES`], and that each piece has the right fields with a valid kind and valid coordinates. Both the flat list and the versioned schema are accepted, as well as deltas tagged with `"schema": "delta"`, whose moves must be on the board.
+///
+/// Pieces on a square which already has one are dropped, and too many pieces of one colour are allowed - both give a [`ValidationWarning`], and the body is [`quarantine`]d as it would be if it failed.
+///
+/// # Errors
+/// If any of the checks fail, with the index and field of the first invalid piece
+pub fn parse_board_update(
+    endpoint: &str,
+    body: &str,
+) -> Result<JSONBoardUpdate, ResponseDiagnostic> {
+    let mut warnings = vec![];
+    let parsed = validate_board_update(body, &mut warnings);
+    screen(endpoint, body, parsed, &warnings)
+}
+
+///Checks that a body isn't too large
+fn check_size(body: &str) -> Result<(), ValidationError> {
+    if body.len() > MAX_BODY_BYTES {
+        Err(ValidationError::TooLarge { bytes: body.len() })
+    } else {
+        Ok(())
+    }
+}
+
+///Parses a response body from the bulk endpoint into the boards of each game, keyed by id. Each board is validated like in [`parse_board_update`], but has to be a full board rather than a delta.
+///
+/// # Errors
+/// If the body isn't an object of ids to boards, or any of the boards fail validation
+pub fn parse_boards(
+    endpoint: &str,
+    body: &str,
+) -> Result<BTreeMap<u32, JSONBoardState>, ResponseDiagnostic> {
+    let mut warnings = vec![];
+    let parsed = validate_boards(body, &mut warnings);
+    screen(endpoint, body, parsed, &warnings)
+}
+
+///Fetches the boards of several games at once from `games?ids=1,2,3`, rather than polling each game separately. Asks for at most [`MAX_BULK_GAMES`] games per request.
+///
+/// Games the server doesn't know about are left out of the map. Returns `None` if the server doesn't have the bulk endpoint, so the caller can fall back to fetching each game on its own.
+///
+/// # Errors
+/// - Can't reach the server
+/// - The server responds with an error code
+/// - The response fails [`parse_boards`]
+pub fn fetch_boards(
+    client: &ServerClient,
+    ids: &[u32],
+) -> Result<Option<BTreeMap<u32, JSONBoardState>>> {
+    let mut boards = BTreeMap::new();
+    for chunk in ids.chunks(MAX_BULK_GAMES) {
+        let ids = chunk
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let rsp = client
+            .send(client.get("games").query(&[("ids", &ids)]))
+            .with_context(|| format!("fetching boards for {ids}"))?;
+
+        if rsp.status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if rsp.is_error() {
+            bail!("error code from server on fetching boards: {}", rsp.status);
+        }
+        boards.extend(parse_boards(&client.url("games"), &rsp.body)?);
+    }
+
+    Ok(Some(boards))
+}
+
+///Does the validation for [`parse_board_update`]
+fn validate_board_update(
+    body: &str,
+    warnings: &mut Vec<ValidationWarning>,
+) -> Result<JSONBoardUpdate, ValidationError> {
+    check_size(body)?;
+    validate_board_value(&serde_json::from_str(body)?, warnings)
+}
+
+///Does the validation for [`parse_boards`]
+fn validate_boards(
+    body: &str,
+    warnings: &mut Vec<ValidationWarning>,
+) -> Result<BTreeMap<u32, JSONBoardState>, ValidationError> {
+    check_size(body)?;
+
+    let value: Value = serde_json::from_str(body)?;
+    let malformed = |message: String| ValidationError::Malformed {
+        line: 1,
+        column: 1,
+        message,
+    };
+    let games = value
+        .as_object()
+        .ok_or_else(|| malformed("expected an object of game ids to boards".into()))?;
+
+    let mut boards = BTreeMap::new();
+    for (id, board) in games {
+        let id: u32 = id
+            .parse()
+            .map_err(|_| malformed(format!("expected a game id, found {id:?}")))?;
+        match validate_board_value(board, warnings)? {
+            JSONBoardUpdate::Full(state) => {
+                boards.insert(id, state);
+            }
+            JSONBoardUpdate::Delta(_) => {
+                return Err(malformed(format!("expected a full board for game {id}")))
+            }
+        }
+    }
+
+    Ok(boards)
+}
+
+///Validates one board or delta, for [`validate_board_update`] and [`validate_boards`]
+fn validate_board_value(
+    value: &Value,
+    warnings: &mut Vec<ValidationWarning>,
+) -> Result<JSONBoardUpdate, ValidationError> {
+    let malformed = |message: &str| ValidationError::Malformed {
+        line: 1,
+        column: 1,
+        message: message.into(),
+    };
+
+    if let Some(list) = value.as_array() {
+        return validate_pieces(list, warnings)
+            .map(|l| JSONBoardUpdate::Full(JSONBoardState::Flat(l)));
+    }
+    if value.get("schema").and_then(Value::as_str) == Some("delta") {
+        return validate_delta(value).map(JSONBoardUpdate::Delta);
+    }
+
+    let list = value
+        .get("pieces")
+        .and_then(Value::as_array)
+        .ok_or_else(|| {
+            malformed("expected a list of pieces, or an object with a list of pieces")
+        })?;
+    let validated = validate_pieces(list, warnings)?;
+
+    let mut board: VersionedBoard =
+        serde_json::from_value(value.clone()).map_err(|e| malformed(&e.to_string()))?;
+    //without any pieces which were dropped
+    match &mut board {
+        VersionedBoard::V1 { pieces } => *pieces = validated,
+        VersionedBoard::V2(v2) => v2.pieces = validated,
+    }
+    Ok(JSONBoardUpdate::Full(JSONBoardState::Versioned(board)))
+}
+
+///Checks a delta, for [`validate_board_value`] - each move has to start and finish on the board
+fn validate_delta(value: &Value) -> Result<Vec<JSONLastMove>, ValidationError> {
+    let moves: Vec<JSONLastMove> = value
+        .get("moves")
+        .cloned()
+        .map(serde_json::from_value)
+        .transpose()?
+        .ok_or_else(|| ValidationError::Malformed {
+            line: 1,
+            column: 1,
+            message: "expected a list of moves in the delta".into(),
+        })?;
+
+    for (index, m) in moves.iter().enumerate() {
+        if [m.x, m.y, m.nx, m.ny].iter().any(|c| *c > 7) {
+            return Err(ValidationError::Malformed {
+                line: 1,
+                column: 1,
+                message: format!("move {index} in the delta is off the board"),
             });
         }
     }
 
-    Board::new_json(JSONPieceList(list))
-        .context("turning ncl to board")
-        .unwrap_log_error()
+    Ok(moves)
+}
+
+///Checks each piece in a list, for [`validate_board_value`]. Pieces on a square which already has one are dropped, with a [`ValidationWarning`].
+fn validate_pieces(
+    list: &[Value],
+    warnings: &mut Vec<ValidationWarning>,
+) -> Result<JSONPieceList, ValidationError> {
+    if list.len() > MAX_PIECES {
+        return Err(ValidationError::TooManyPieces { count: list.len() });
+    }
+
+    let mut pieces = Vec::with_capacity(list.len());
+    //the index of the piece on each square, to find duplicates
+    let mut squares: [Option<usize>; 64] = [None; 64];
+    for (index, value) in list.iter().enumerate() {
+        let invalid = |field: Option<&str>, message: String| ValidationError::InvalidPiece {
+            index,
+            field: field.map(ToString::to_string),
+            message,
+        };
+
+        let obj = value
+            .as_object()
+            .ok_or_else(|| invalid(None, format!("expected an object, found {value}")))?;
+
+        let checks: [(&str, fn(&Value) -> bool, &str); 4] = [
+            ("x", Value::is_i64, "an integer"),
+            ("y", Value::is_i64, "an integer"),
+            ("kind", Value::is_string, "a string"),
+            ("is_white", Value::is_boolean, "a boolean"),
+        ];
+        for (field, check, expected) in checks {
+            match obj.get(field) {
+                None => return Err(invalid(Some(field), "missing".into())),
+                Some(v) if !check(v) => {
+                    return Err(invalid(Some(field), format!("expected {expected}, found {v}")))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let piece: JSONPiece = serde_json::from_value(value.clone())
+            .map_err(|e| invalid(None, e.to_string()))?;
+
+        if ChessPieceKind::try_from(piece.kind.clone()).is_err() {
+            return Err(invalid(
+                Some("kind"),
+                format!("unknown piece kind {:?}", piece.kind),
+            ));
+        }
+        let coords = Coords::try_from((piece.x, piece.y))
+            .map_err(|e| invalid(None, format!("invalid coordinates - {e}")))?;
+
+        if let Some(square) = coords.to_usize().and_then(|us| squares.get_mut(us)) {
+            if let Some(kept) = *square {
+                warnings.push(ValidationWarning::DuplicateSquare {
+                    index,
+                    kept,
+                    x: piece.x,
+                    y: piece.y,
+                });
+                continue;
+            }
+            *square = Some(index);
+        }
+
+        pieces.push(piece);
+    }
+
+    for is_white in [true, false] {
+        let count = pieces.iter().filter(|p| p.is_white == is_white).count();
+        if count > MAX_PIECES_PER_COLOUR {
+            warnings.push(ValidationWarning::TooManyOfColour { is_white, count });
+        }
+    }
+
+    Ok(JSONPieceList(pieces))
 }
 
 ///JSON repr of a chess move
-#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct JSONMove {
     ///Game ID
     pub id: u32,
@@ -150,13 +973,40 @@ pub struct JSONMove {
     pub nx: u32,
     ///Y position to be moved to
     pub ny: u32,
+    ///What a pawn becomes if the move promotes it - only sent when present, and a queen if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promotion: Option<ChessPieceKind>,
 }
 
 impl JSONMove {
-    ///Creates a new `JSONMove`
+    ///Creates a new `JSONMove`, without a promotion
     #[must_use]
     pub const fn new(id: u32, x: u32, y: u32, nx: u32, ny: u32) -> Self {
-        Self { id, x, y, nx, ny }
+        Self {
+            id,
+            x,
+            y,
+            nx,
+            ny,
+            promotion: None,
+        }
+    }
+
+    ///Sets the piece a pawn becomes if the move promotes it, for under-promotion
+    #[must_use]
+    pub const fn with_promotion(self, kind: ChessPieceKind) -> Self {
+        Self {
+            promotion: Some(kind),
+            ..self
+        }
+    }
+
+    ///Gets the piece a pawn becomes if the move promotes it - a queen, unless a valid promotion was given
+    #[must_use]
+    pub fn promotion_kind(&self) -> ChessPieceKind {
+        self.promotion
+            .filter(|k| !matches!(k, ChessPieceKind::Pawn | ChessPieceKind::King))
+            .unwrap_or(ChessPieceKind::Queen)
     }
 
     ///Gets the starting coordinates as a [`Coords`]
@@ -170,3 +1020,92 @@ impl JSONMove {
         (self.nx, self.ny).try_into().unwrap_log_error()
     }
 }
+
+///JSON repr of what a server supports beyond the original API, from the `capabilities` endpoint.
+///
+/// Anything the server leaves out is taken to be unsupported, as is everything for servers without the endpoint.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct JSONCapabilities {
+    ///Whether boards can be sent as deltas - see [`JSONBoardUpdate::Delta`]
+    pub delta: bool,
+    ///Whether players can chat
+    pub chat: bool,
+    ///Whether the server keeps the players' clocks - see [`JSONBoardV2::clocks`]
+    pub clocks: bool,
+    ///Whether the server can push updates over a websocket
+    pub websocket: bool,
+    ///Whether moves can have a [`JSONMove::promotion`]
+    pub promotion: bool,
+    ///Whether the server has the `games/{id}/history` endpoint - see [`JSONHistory`]
+    pub history: bool,
+    ///Whether the server accepts request bodies compressed with gzip
+    pub gzip: bool,
+    ///Whether the server takes draw claims at the `claimdraw` endpoint - see [`JSONDrawClaim`]
+    pub draw_claims: bool,
+}
+
+///JSON repr of a claim for a draw by repetition or the fifty-move rule
+#[derive(Serialize, Debug, Clone)]
+pub struct JSONDrawClaim {
+    ///Game ID
+    pub id: u32,
+    ///Identity of the player claiming the draw
+    pub player: String,
+    ///Why the draw can be claimed
+    pub reason: JSONDrawReason,
+}
+
+///JSON repr of why a draw is claimed - the names are part of the protocol, so they mustn't change with how [`DrawReason`] is shown
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JSONDrawReason {
+    ///The same position has come up three times - `threefold_repetition`
+    ThreefoldRepetition,
+    ///No pieces have been taken and no pawns moved for fifty moves each - `fifty_move_rule`
+    FiftyMoveRule,
+}
+
+impl TryFrom<DrawReason> for JSONDrawReason {
+    type Error = Error;
+
+    ///Only draws which have to be claimed can be sent - the others happen straight away
+    fn try_from(reason: DrawReason) -> Result<Self, Self::Error> {
+        match reason {
+            DrawReason::Repetition => Ok(Self::ThreefoldRepetition),
+            DrawReason::FiftyMoveRule => Ok(Self::FiftyMoveRule),
+            DrawReason::Stalemate | DrawReason::InsufficientMaterial => {
+                Err(anyhow!("a draw by {reason} can't be claimed"))
+            }
+        }
+    }
+}
+
+///JSON repr of a heartbeat, sent periodically so the server knows the player is still around
+#[derive(Serialize, Debug, Clone)]
+pub struct JSONHeartbeat {
+    ///Game ID
+    pub id: u32,
+    ///Identity of the player sending the heartbeat
+    pub player: String,
+}
+
+///JSON repr of the server's response to a [`JSONHeartbeat`]
+#[derive(Deserialize, Debug, Default)]
+pub struct JSONHeartbeatResponse {
+    ///The presence of the opponent, if the server knows it
+    #[serde(default)]
+    pub opponent: Option<PresenceState>,
+}
+
+///Whether or not a player is currently around
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceState {
+    ///The player has interacted with the game recently
+    Active,
+    ///The player's client is still sending heartbeats, but they haven't interacted recently
+    Idle,
+    ///The server hasn't received a heartbeat from the player recently
+    Gone,
+}
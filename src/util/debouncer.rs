@@ -0,0 +1,61 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+///Stops inputs from acting more than once when they are held down or repeated quickly, like key repeat or a double-click.
+///
+/// A press is let through if the input isn't already held down, and it wasn't last let through within the interval. Each input is tracked separately.
+#[derive(Debug, Clone)]
+pub struct Debouncer<K> {
+    ///The shortest time between presses which are let through
+    interval: Duration,
+    ///When each input was last let through
+    last_allowed: HashMap<K, Instant>,
+    ///The inputs held down after being let through, waiting for a release
+    held: HashSet<K>,
+}
+
+impl<K: Hash + Eq + Clone> Debouncer<K> {
+    ///Creates a new `Debouncer`, which lets an input through at most once per `interval`
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_allowed: HashMap::new(),
+            held: HashSet::new(),
+        }
+    }
+
+    ///Records an input being pressed, and returns whether it should act
+    pub fn press(&mut self, key: K) -> bool {
+        self.press_at(key, Instant::now())
+    }
+
+    ///Records an input being pressed at a given time, and returns whether it should act
+    pub fn press_at(&mut self, key: K, now: Instant) -> bool {
+        let recent = self
+            .last_allowed
+            .get(&key)
+            .is_some_and(|last| now.saturating_duration_since(*last) < self.interval);
+        if self.held.contains(&key) || recent {
+            return false;
+        }
+
+        self.last_allowed.insert(key.clone(), now);
+        self.held.insert(key);
+        true
+    }
+
+    ///Records an input being released, and returns whether the press it ends was let through - so that a release only acts if its press did
+    pub fn release(&mut self, key: &K) -> bool {
+        self.held.remove(key)
+    }
+
+    ///Gets whether an input is held down after being let through
+    #[must_use]
+    pub fn is_held(&self, key: &K) -> bool {
+        self.held.contains(key)
+    }
+}
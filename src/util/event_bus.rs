@@ -0,0 +1,100 @@
+use epac_utils::error_ext::MutexExt;
+use std::{
+    fmt::Debug,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+///An event which can be sent on an [`EventBus`], sorted into kinds which can be subscribed to separately
+pub trait BusEvent: Clone + Send + 'static {
+    ///The kinds of event - usually a fieldless enum with a variant for each variant of the event
+    type Kind: Copy + PartialEq + Debug + Send + 'static;
+
+    ///Gets the kind of this event
+    fn kind(&self) -> Self::Kind;
+}
+
+///One subscriber of an [`EventBus`]
+#[derive(Debug)]
+struct Subscriber<E: BusEvent> {
+    ///The kinds of event it wants
+    kinds: Vec<E::Kind>,
+    ///Where its events go
+    tx: Sender<E>,
+}
+
+///Typed publish/subscribe bus - components publish events without knowing who wants them, and subscribe to the kinds of event they want without knowing who sends them.
+///
+/// Each subscriber gets its own copy of each event, in the order they were published. Subscribers which have been dropped are forgotten the next time an event is published. Clones share subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus<E: BusEvent> {
+    ///Everyone who has subscribed
+    subscribers: Arc<Mutex<Vec<Subscriber<E>>>>,
+}
+
+//derive would require `E: Default`
+impl<E: BusEvent> Default for EventBus<E> {
+    fn default() -> Self {
+        Self {
+            subscribers: Arc::new(Mutex::new(vec![])),
+        }
+    }
+}
+
+impl<E: BusEvent> EventBus<E> {
+    ///Subscribes to some kinds of event, getting each one published from now on
+    #[must_use]
+    pub fn subscribe(&self, kinds: &[E::Kind]) -> Subscription<E> {
+        let (tx, rx) = channel();
+        self.subscribers
+            .lock_panic("event bus subscribers")
+            .push(Subscriber {
+                kinds: kinds.to_vec(),
+                tx,
+            });
+        Subscription { rx }
+    }
+
+    ///Sends an event to everyone subscribed to its kind. Returns how many subscribers it was sent to.
+    pub fn publish(&self, event: E) -> usize {
+        let kind = event.kind();
+        let mut sent = 0;
+        self.subscribers
+            .lock_panic("event bus subscribers")
+            .retain(|s| {
+                if !s.kinds.contains(&kind) {
+                    return true;
+                }
+                let alive = s.tx.send(event.clone()).is_ok();
+                if alive {
+                    sent += 1;
+                }
+                alive
+            });
+
+        trace!(?kind, %sent, "Published event");
+        sent
+    }
+}
+
+///The events an [`EventBus`] subscriber has been sent. Dropping it unsubscribes.
+#[derive(Debug)]
+pub struct Subscription<E> {
+    ///Receiver for the events
+    rx: Receiver<E>,
+}
+
+impl<E> Subscription<E> {
+    ///Gets the next event, if one has been sent
+    #[must_use]
+    pub fn try_next(&self) -> Option<E> {
+        self.rx.try_recv().ok()
+    }
+
+    ///Gets every event which has been sent and not received yet, oldest first
+    pub fn drain(&self) -> impl Iterator<Item = E> + '_ {
+        self.rx.try_iter()
+    }
+}
@@ -0,0 +1,253 @@
+use crate::metrics::metrics;
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+///Names of the gauges a [`LruCache`] publishes to the [`metrics`] registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheGauges {
+    ///Gauge for the number of lookups which found a value
+    pub hits: &'static str,
+    ///Gauge for the number of lookups which didn't find a value
+    pub misses: &'static str,
+    ///Gauge for the number of values being held
+    pub entries: &'static str,
+}
+
+///Cache which holds at most a fixed number of values, throwing away the least recently used one to make room for a new one.
+///
+/// Counts how many lookups find a value, so the hit rate can be published with [`LruCache::publish_metrics`]. Not thread-safe on its own - put it behind a [`std::sync::Mutex`] to share it.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    ///The values, with the tick they were last used at
+    entries: HashMap<K, (V, u64)>,
+    ///The keys, by the tick they were last used at - the first is the least recently used
+    order: BTreeMap<u64, K>,
+    ///Counts uses, to order the entries
+    tick: u64,
+    ///The most values which can be held - at least one
+    capacity: usize,
+    ///Number of lookups which found a value
+    hits: u64,
+    ///Number of lookups which didn't find a value
+    misses: u64,
+    ///Where [`LruCache::publish_metrics`] publishes to
+    gauges: CacheGauges,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    ///Creates a new, empty `LruCache` which holds at most `capacity` values - at least one
+    #[must_use]
+    pub fn new(capacity: usize, gauges: CacheGauges) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            capacity: capacity.max(1),
+            hits: 0,
+            misses: 0,
+            gauges,
+        }
+    }
+
+    ///Gets the value for a key, marking it as the most recently used. Counts as a hit or a miss.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+        let Some((value, used)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+
+        self.hits += 1;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, key.clone());
+        Some(value)
+    }
+
+    ///Gets the value for a key without marking it as used, or counting as a hit or a miss
+    #[must_use]
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(v, _)| v)
+    }
+
+    ///Stores a value as the most recently used, throwing away the least recently used value if the cache is full. Returns the value which was stored for the key before, if there was one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.tick += 1;
+        let old = self.remove(&key);
+        if self.entries.len() >= self.capacity {
+            if let Some((_, lru)) = self.order.pop_first() {
+                self.entries.remove(&lru);
+            }
+        }
+
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+        old
+    }
+
+    ///Removes the value for a key, returning it if there was one
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, used) = self.entries.remove(key)?;
+        self.order.remove(&used);
+        Some(value)
+    }
+
+    ///Removes every value. The hits and misses are kept.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    ///Gets the number of values being held
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    ///Gets whether no values are being held
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///Gets the most values which can be held
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    ///Gets the number of lookups which found a value
+    #[must_use]
+    pub const fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    ///Gets the number of lookups which didn't find a value
+    #[must_use]
+    pub const fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    ///Publishes the hits, misses and number of values held to the [`metrics`] registry, using the [`CacheGauges`] it was created with
+    pub fn publish_metrics(&self) {
+        let to_gauge = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+
+        let m = metrics();
+        m.set_gauge(self.gauges.hits, to_gauge(self.hits));
+        m.set_gauge(self.gauges.misses, to_gauge(self.misses));
+        m.set_gauge(
+            self.gauges.entries,
+            i64::try_from(self.entries.len()).unwrap_or(i64::MAX),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Makes an empty cache holding at most `capacity` values
+    fn cache(capacity: usize, gauges: CacheGauges) -> LruCache<&'static str, u32> {
+        LruCache::new(capacity, gauges)
+    }
+
+    #[test]
+    fn evicts_the_least_recently_inserted() {
+        let mut c = cache(
+            2,
+            CacheGauges {
+                hits: "test.lru_evict_hits",
+                misses: "test.lru_evict_misses",
+                entries: "test.lru_evict_entries",
+            },
+        );
+        c.insert("a", 1);
+        c.insert("b", 2);
+        c.insert("c", 3);
+
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.peek(&"a"), None);
+        assert_eq!(c.peek(&"b"), Some(&2));
+        assert_eq!(c.peek(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn getting_a_value_keeps_it_around() {
+        let mut c = cache(
+            2,
+            CacheGauges {
+                hits: "test.lru_recency_hits",
+                misses: "test.lru_recency_misses",
+                entries: "test.lru_recency_entries",
+            },
+        );
+        c.insert("a", 1);
+        c.insert("b", 2);
+        assert_eq!(c.get(&"a"), Some(&1));
+        c.insert("c", 3);
+
+        assert_eq!(c.peek(&"a"), Some(&1));
+        assert_eq!(c.peek(&"b"), None);
+
+        //peeking doesn't count as a use
+        c.peek(&"a");
+        c.insert("d", 4);
+        assert_eq!(c.peek(&"a"), None);
+        assert_eq!(c.peek(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn replacing_a_value_evicts_nothing() {
+        let mut c = cache(
+            2,
+            CacheGauges {
+                hits: "test.lru_replace_hits",
+                misses: "test.lru_replace_misses",
+                entries: "test.lru_replace_entries",
+            },
+        );
+        c.insert("a", 1);
+        c.insert("b", 2);
+
+        assert_eq!(c.insert("a", 10), Some(1));
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.peek(&"a"), Some(&10));
+        assert_eq!(c.peek(&"b"), Some(&2));
+
+        //and it becomes the most recently used
+        c.insert("c", 3);
+        assert_eq!(c.peek(&"a"), Some(&10));
+        assert_eq!(c.peek(&"b"), None);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let gauges = CacheGauges {
+            hits: "test.lru_count_hits",
+            misses: "test.lru_count_misses",
+            entries: "test.lru_count_entries",
+        };
+        let mut c = cache(2, gauges);
+        c.insert("a", 1);
+
+        c.get(&"a");
+        c.get(&"a");
+        c.get(&"b");
+        c.peek(&"a");
+        c.peek(&"b");
+        assert_eq!((c.hits(), c.misses()), (2, 1));
+
+        //clearing keeps the counts
+        c.clear();
+        c.get(&"a");
+        assert_eq!((c.hits(), c.misses()), (2, 2));
+
+        c.publish_metrics();
+        let m = metrics();
+        assert_eq!(m.gauge(gauges.hits), Some(2));
+        assert_eq!(m.gauge(gauges.misses), Some(2));
+        assert_eq!(m.gauge(gauges.entries), Some(0));
+    }
+}
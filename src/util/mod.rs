@@ -0,0 +1,14 @@
+///Module to hold the [`lru_cache::LruCache`], a size-bounded cache which publishes its hits and misses
+pub mod lru_cache;
+///Module to hold the [`debouncer::Debouncer`], which stops held or repeated inputs from acting more than once
+pub mod debouncer;
+///Module to hold structs which deal with time - the ones from [`epac_utils::time_based_structs`], the [`time_based_structs::stopwatch::Stopwatch`] and the [`time_based_structs::scoped_timer::ScopedTimer`]
+pub mod time_based_structs;
+///Module to hold the [`rate_counter::RateCounter`], which counts events over a sliding window of time
+pub mod rate_counter;
+///Module to hold the [`task_handle::TaskHandle`], a handle to a thread which can be asked to stop, and given up on if it takes too long
+pub mod task_handle;
+///Module to hold the [`event_bus::EventBus`], which passes events between components which don't know about each other
+pub mod event_bus;
+///Module to hold the [`typestate!`] macro and [`typestate::StateMarker`], for types which track their state in a type parameter
+pub mod typestate;
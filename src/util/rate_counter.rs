@@ -0,0 +1,64 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+///Counts events over a sliding window of time, eg. the last second, to find how often they are happening
+#[derive(Debug, Clone)]
+pub struct RateCounter {
+    ///How far back events are counted
+    window: Duration,
+    ///When each event in the window happened, oldest first
+    events: VecDeque<Instant>,
+}
+
+impl RateCounter {
+    ///Creates a new `RateCounter` which counts the events in the last `window` - which can't be zero
+    #[must_use]
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: window.max(Duration::from_millis(1)),
+            events: VecDeque::new(),
+        }
+    }
+
+    ///Records an event happening now
+    pub fn record(&mut self) {
+        self.record_at(Instant::now());
+    }
+
+    ///Records an event happening at a given time, which mustn't be before the last event recorded
+    pub fn record_at(&mut self, at: Instant) {
+        self.events.push_back(at);
+        self.forget_before(at);
+    }
+
+    ///Forgets the events which are too old to be in the window, as of `now`
+    fn forget_before(&mut self, now: Instant) {
+        while let Some(oldest) = self.events.front() {
+            if now.saturating_duration_since(*oldest) <= self.window {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+
+    ///Gets the number of events in the window
+    pub fn count(&mut self) -> usize {
+        self.forget_before(Instant::now());
+        self.events.len()
+    }
+
+    ///Gets the average number of events per second over the window
+    pub fn per_second(&mut self) -> f64 {
+        //a window holds far fewer events than f64 can count exactly
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count() as f64;
+        count / self.window.as_secs_f64()
+    }
+
+    ///Gets the average number of events per minute over the window
+    pub fn per_minute(&mut self) -> f64 {
+        self.per_second() * 60.0
+    }
+}
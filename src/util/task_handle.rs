@@ -0,0 +1,114 @@
+use anyhow::Result;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+///How often [`TaskHandle::join_timeout`] checks whether the thread has finished
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+///Shared flag which asks a [`TaskHandle`]'s thread to stop. Threads have to check it themselves - nothing is interrupted.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    ///Asks the thread to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    ///Gets whether the thread has been asked to stop
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+///Sets the completion flag of a [`TaskHandle`] when dropped - including when the thread panics
+struct DoneGuard(Arc<AtomicBool>);
+
+impl Drop for DoneGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+///Handle to a thread which can be asked to stop with a [`CancelToken`], and waited on for a limited time so that a slow thread can be given up on.
+///
+/// Dropping the handle leaves the thread running on its own.
+#[derive(Debug)]
+pub struct TaskHandle<T> {
+    ///The thread
+    handle: JoinHandle<T>,
+    ///Asks the thread to stop - the thread gets a clone
+    cancel: CancelToken,
+    ///Set once the thread has finished, or panicked
+    done: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> TaskHandle<T> {
+    ///Spawns a thread running `f`, which is given the [`CancelToken`] to check
+    #[must_use]
+    pub fn spawn(f: impl FnOnce(&CancelToken) -> T + Send + 'static) -> Self {
+        let (cancel, done) = (CancelToken::default(), Arc::new(AtomicBool::new(false)));
+        let (thread_cancel, thread_done) = (cancel.clone(), done.clone());
+        let handle = std::thread::spawn(move || {
+            let _guard = DoneGuard(thread_done);
+            f(&thread_cancel)
+        });
+
+        Self {
+            handle,
+            cancel,
+            done,
+        }
+    }
+}
+
+impl<T> TaskHandle<T> {
+    ///Asks the thread to stop - see [`CancelToken`]
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    ///Gets whether the thread has been asked to stop
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    ///Gets whether the thread has finished, so [`TaskHandle::join`] won't block
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    ///Waits for the thread to finish, and gets what it returned
+    ///
+    /// # Errors
+    /// - The thread panicked
+    pub fn join(self) -> Result<T> {
+        self.handle
+            .join()
+            .map_err(|_| anyhow!("task thread panicked"))
+    }
+
+    ///Waits at most `timeout` for the thread to finish. Returns `None` if it didn't, in which case it is left running on its own.
+    ///
+    /// # Errors
+    /// - The thread panicked
+    pub fn join_timeout(self, timeout: Duration) -> Result<Option<T>> {
+        let start = Instant::now();
+        while !self.is_finished() {
+            if start.elapsed() >= timeout {
+                return Ok(None);
+            }
+            std::thread::sleep(JOIN_POLL_INTERVAL.min(timeout.saturating_sub(start.elapsed())));
+        }
+        self.join().map(Some)
+    }
+}
@@ -0,0 +1,6 @@
+pub use epac_utils::time_based_structs::*;
+
+///Module to hold the [`scoped_timer::ScopedTimer`], which times how long a scope takes
+pub mod scoped_timer;
+///Module to hold the [`stopwatch::Stopwatch`], which measures time with pauses and laps
+pub mod stopwatch;
@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+///Times a scope - when it is dropped, how long it was alive for is passed to a callback, eg. to add it to a report
+#[must_use = "the scope ends as soon as the timer is dropped"]
+pub struct ScopedTimer<F: FnOnce(Duration)> {
+    ///When the timer was made
+    start: Instant,
+    ///What to do with the time when it is dropped - only `None` once it has been called
+    on_drop: Option<F>,
+}
+
+impl<F: FnOnce(Duration)> ScopedTimer<F> {
+    ///Starts timing, calling `on_drop` with the time taken when the timer is dropped
+    pub fn new(on_drop: F) -> Self {
+        Self {
+            start: Instant::now(),
+            on_drop: Some(on_drop),
+        }
+    }
+
+    ///Gets when the timer was made
+    #[must_use]
+    pub const fn start(&self) -> Instant {
+        self.start
+    }
+
+    ///Gets how long the timer has been running, without ending it
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    ///Stops the timer without calling the callback, eg. if the work being timed was abandoned
+    pub fn cancel(mut self) {
+        self.on_drop = None;
+    }
+}
+
+impl<F: FnOnce(Duration)> Drop for ScopedTimer<F> {
+    fn drop(&mut self) {
+        if let Some(on_drop) = self.on_drop.take() {
+            on_drop(self.start.elapsed());
+        }
+    }
+}
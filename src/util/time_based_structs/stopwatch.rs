@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+///Measures time which can be paused and resumed, like a chess clock, and split into laps.
+///
+/// Time can run faster or slower than real time - see [`Stopwatch::set_speed`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stopwatch {
+    ///Time measured before the current run
+    before: Duration,
+    ///When the current run started, if it is running
+    running_since: Option<Instant>,
+    ///What [`Stopwatch::elapsed`] was at the end of the last lap
+    lap_start: Duration,
+    ///How many times faster than real time it runs
+    speed: f64,
+}
+
+impl Default for Stopwatch {
+    fn default() -> Self {
+        Self {
+            before: Duration::ZERO,
+            running_since: None,
+            lap_start: Duration::ZERO,
+            speed: 1.0,
+        }
+    }
+}
+
+impl Stopwatch {
+    ///Creates a new `Stopwatch` at zero, which is already running
+    #[must_use]
+    pub fn started() -> Self {
+        let mut sw = Self::default();
+        sw.resume();
+        sw
+    }
+
+    ///Goes back to zero and starts running, forgetting any laps
+    pub fn start(&mut self) {
+        *self = Self {
+            speed: self.speed,
+            ..Self::started()
+        };
+    }
+
+    ///Stops running, keeping the time measured so far. Does nothing if it is already paused.
+    pub fn pause(&mut self) {
+        self.before = self.elapsed();
+        self.running_since = None;
+    }
+
+    ///Starts running again from the time measured so far. Does nothing if it is already running.
+    pub fn resume(&mut self) {
+        if self.running_since.is_none() {
+            self.running_since = Some(Instant::now());
+        }
+    }
+
+    ///Goes back to zero and stops running, forgetting any laps
+    pub fn reset(&mut self) {
+        *self = Self {
+            speed: self.speed,
+            ..Self::default()
+        };
+    }
+
+    ///Gets whether it is running
+    #[must_use]
+    pub const fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+
+    ///Gets the total time measured, not counting time spent paused
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.before
+            + self
+                .running_since
+                .map(|since| since.elapsed().mul_f64(self.speed))
+                .unwrap_or_default()
+    }
+
+    ///Ends the current lap, and returns how long it took. The first lap starts at zero.
+    pub fn lap(&mut self) -> Duration {
+        let now = self.elapsed();
+        let lap = now.saturating_sub(self.lap_start);
+        self.lap_start = now;
+        lap
+    }
+
+    ///Gets how long the current lap has taken so far, without ending it
+    #[must_use]
+    pub fn current_lap(&self) -> Duration {
+        self.elapsed().saturating_sub(self.lap_start)
+    }
+
+    ///Gets how many times faster than real time it runs
+    #[must_use]
+    pub const fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    ///Sets how many times faster than real time it runs from now on, eg. `2.0` for double speed. Time already measured isn't changed. Negative speeds count as zero.
+    pub fn set_speed(&mut self, speed: f64) {
+        let running = self.is_running();
+        self.pause();
+        self.speed = speed.max(0.0);
+        if running {
+            self.resume();
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    fmt::{Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+///A state in a typestate pattern - a zero-sized type used as a type parameter, so that only the methods which make sense in that state can be called.
+///
+/// Usually made with [`typestate!`].
+pub trait TypeState: Debug + Copy + Default + Send + Sync + 'static {
+    ///The name of the state, used in [`StateMarker`]'s [`Debug`] output
+    const NAME: &'static str;
+}
+
+///Declares a trait for a set of typestates, and a unit struct implementing it for each state.
+///
+/// The trait requires a sealing trait, so that the states can't be added to outside the crate which declares them, and [`TypeState`].
+///
+/// No transitions are declared - the type using the states writes one for each allowed transition, as a named method or a [`From`]/[`TryFrom`] conversion, so the invalid ones can't be made.
+///
+/// ```ignore
+/// typestate!(Sealed, (BoardMoveState -> "Holds the current state of moving pieces") => (CanMovePiece -> "Can move"), (NeedsMoveUpdate -> "Needs an update"));
+/// ```
+#[macro_export]
+macro_rules! typestate {
+    ($sealed:path, ($trait:ident -> $trait_doc:literal) => $(($state:ident -> $state_doc:literal)),+ $(,)?) => {
+        #[doc = $trait_doc]
+        pub trait $trait: $sealed + $crate::util::typestate::TypeState {}
+
+        $(
+            #[doc = $state_doc]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+            pub struct $state;
+
+            impl $sealed for $state {}
+            impl $crate::util::typestate::TypeState for $state {
+                const NAME: &'static str = stringify!($state);
+            }
+            impl $trait for $state {}
+        )+
+    };
+}
+
+///Marks which [`TypeState`] a value is in, without taking up any space - use it in place of a [`PhantomData`] field.
+///
+/// Unlike [`PhantomData`], its [`Debug`] output names the state, and it can be moved to another state with [`StateMarker::retype`] so the rest of a struct can be kept during a transition.
+pub struct StateMarker<S>(PhantomData<S>);
+
+impl<S> StateMarker<S> {
+    ///Creates a new `StateMarker`
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+
+    ///Moves to another state
+    #[must_use]
+    pub fn retype<T>(self) -> StateMarker<T> {
+        StateMarker(PhantomData)
+    }
+}
+
+//derives would require `S` to implement each trait, but the marker holds no `S`
+impl<S> Clone for StateMarker<S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for StateMarker<S> {}
+
+impl<S> Default for StateMarker<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> PartialEq for StateMarker<S> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<S> Eq for StateMarker<S> {}
+
+impl<S> Hash for StateMarker<S> {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl<S: TypeState> Debug for StateMarker<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(S::NAME)
+    }
+}